@@ -340,6 +340,13 @@ pub fn path_prebaked_results(name: &MapName, scenario_name: &str) -> String {
     ))
 }
 
+pub fn path_traffic_assignment(name: &MapName, scenario_name: &str) -> String {
+    path(format!(
+        "system/{}/{}/traffic_assignment/{}/{}.bin",
+        name.city.country, name.city.city, name.map, scenario_name
+    ))
+}
+
 pub fn path_scenario(name: &MapName, scenario_name: &str) -> String {
     // TODO Getting complicated. Sometimes we're trying to load, so we should look for .bin, then
     // .json. But when we're writing a custom scenario, we actually want to write a .bin.
@@ -382,9 +382,11 @@ pub fn color_for_trip_phase(app: &App, tpt: TripPhaseType) -> Color {
         TripPhaseType::Driving => app.cs.unzoomed_car,
         TripPhaseType::Walking => app.cs.unzoomed_pedestrian,
         TripPhaseType::Biking => app.cs.bike_trip,
-        TripPhaseType::Parking => app.cs.parking_trip,
+        TripPhaseType::Parking | TripPhaseType::SearchingForParking => app.cs.parking_trip,
         TripPhaseType::WaitingForBus(_, _) => app.cs.bus_layer,
         TripPhaseType::RidingBus(_, _, _) => app.cs.bus_trip,
+        TripPhaseType::WaitingForRideHail => app.cs.parking_trip,
+        TripPhaseType::RidingRideHail(_) => app.cs.unzoomed_car,
         TripPhaseType::Cancelled | TripPhaseType::Finished => unreachable!(),
         TripPhaseType::DelayedStart => Color::YELLOW,
     }
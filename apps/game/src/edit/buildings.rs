@@ -0,0 +1,124 @@
+use map_model::{BuildingID, BuildingType, EditCmd, OffstreetParking};
+use widgetry::{
+    EventCtx, GfxCtx, HorizontalAlignment, Key, Line, Outcome, Panel, Spinner, State, TextExt,
+    VerticalAlignment, Widget,
+};
+
+use crate::app::App;
+use crate::app::Transition;
+use crate::edit::apply_map_edits;
+
+pub struct BuildingEditor {
+    panel: Panel,
+    b: BuildingID,
+}
+
+impl BuildingEditor {
+    pub fn new_state(ctx: &mut EventCtx, app: &mut App, b: BuildingID) -> Box<dyn State<App>> {
+        app.primary.current_selection = None;
+
+        let bldg = app.primary.map.get_b(b);
+        let (residents, housing_units, workers) = match bldg.bldg_type {
+            BuildingType::Residential {
+                num_residents,
+                num_housing_units,
+            } => (num_residents, num_housing_units, 0),
+            BuildingType::ResidentialCommercial(residents, workers) => (residents, 0, workers),
+            BuildingType::Commercial(workers) => (0, 0, workers),
+            BuildingType::Empty => (0, 0, 0),
+        };
+        let spots = match bldg.parking {
+            OffstreetParking::PublicGarage(_, spots) | OffstreetParking::Private(spots, _) => spots,
+        };
+
+        let row = |ctx: &mut EventCtx, label: &str, name: &str, current: usize| {
+            Widget::row(vec![
+                label.text_widget(ctx).centered_vert(),
+                Spinner::widget(ctx, name, (0, 10_000), current, 1),
+            ])
+        };
+
+        Box::new(BuildingEditor {
+            panel: Panel::new_builder(Widget::col(vec![
+                Widget::row(vec![
+                    Line("Building editor").small_heading().into_widget(ctx),
+                    ctx.style().btn_close_widget(ctx),
+                ]),
+                Line(&bldg.address).into_widget(ctx),
+                row(ctx, "Residents", "residents", residents),
+                row(ctx, "Housing units", "housing units", housing_units),
+                row(ctx, "Workers", "workers", workers),
+                row(ctx, "Off-street parking spots", "parking spots", spots),
+                ctx.style()
+                    .btn_solid_primary
+                    .text("Apply")
+                    .hotkey(Key::Enter)
+                    .build_def(ctx),
+            ]))
+            .aligned(HorizontalAlignment::Center, VerticalAlignment::Top)
+            .build(ctx),
+            b,
+        })
+    }
+}
+
+impl State<App> for BuildingEditor {
+    fn event(&mut self, ctx: &mut EventCtx, app: &mut App) -> Transition {
+        ctx.canvas_movement();
+
+        if let Outcome::Clicked(x) = self.panel.event(ctx) {
+            match x.as_ref() {
+                "close" => {
+                    return Transition::Pop;
+                }
+                "Apply" => {
+                    let residents: usize = self.panel.spinner("residents");
+                    let workers: usize = self.panel.spinner("workers");
+                    let spots: usize = self.panel.spinner("parking spots");
+
+                    let old = app.primary.map.get_b_edit(self.b);
+                    let mut new = old.clone();
+                    new.bldg_type = if residents > 0 && workers > 0 {
+                        BuildingType::ResidentialCommercial(residents, workers)
+                    } else if residents > 0 {
+                        BuildingType::Residential {
+                            num_residents: residents,
+                            num_housing_units: self.panel.spinner("housing units"),
+                        }
+                    } else if workers > 0 {
+                        BuildingType::Commercial(workers)
+                    } else {
+                        BuildingType::Empty
+                    };
+                    new.parking = match new.parking {
+                        OffstreetParking::PublicGarage(name, _) => {
+                            OffstreetParking::PublicGarage(name, spots)
+                        }
+                        OffstreetParking::Private(_, garage) => {
+                            OffstreetParking::Private(spots, garage)
+                        }
+                    };
+
+                    if new != old {
+                        let mut edits = app.primary.map.get_edits().clone();
+                        edits.commands.push(EditCmd::ChangeBuilding {
+                            b: self.b,
+                            old,
+                            new,
+                        });
+                        apply_map_edits(ctx, app, edits);
+                    }
+
+                    return Transition::Pop;
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        Transition::Keep
+    }
+
+    fn draw(&self, g: &mut GfxCtx, _: &App) {
+        self.panel.draw(g);
+    }
+}
@@ -14,6 +14,7 @@ use widgetry::{
     Menu, Outcome, Panel, State, Text, TextBox, TextExt, VerticalAlignment, Widget,
 };
 
+pub use self::buildings::BuildingEditor;
 pub use self::roads::RoadEditor;
 pub use self::routes::RouteEditor;
 pub use self::stop_signs::StopSignEditor;
@@ -24,12 +25,15 @@ use crate::common::{tool_panel, CommonState, Warping};
 use crate::debug::DebugMode;
 use crate::sandbox::{GameplayMode, SandboxMode, TimeWarpScreen};
 
+mod buildings;
 mod crosswalks;
 mod multiple_roads;
+mod new_roads;
 mod roads;
 mod routes;
 mod stop_signs;
 mod traffic_signals;
+mod turn_restrictions;
 mod validate;
 mod zones;
 
@@ -757,7 +761,10 @@ pub fn apply_map_edits(ctx: &mut EventCtx, app: &mut App, edits: MapEdits) {
         let effects = app.primary.map.must_apply_edits(edits, timer);
         timer.stop("edit map");
 
-        if !effects.changed_roads.is_empty() || !effects.changed_intersections.is_empty() {
+        if !effects.changed_roads.is_empty()
+            || !effects.deleted_roads.is_empty()
+            || !effects.changed_intersections.is_empty()
+        {
             app.primary
                 .draw_map
                 .draw_all_unzoomed_roads_and_intersections = DrawMap::regenerate_unzoomed_layer(
@@ -769,11 +776,18 @@ pub fn apply_map_edits(ctx: &mut EventCtx, app: &mut App, edits: MapEdits) {
             );
         }
 
+        // Roads and intersections created by edits are removed from the end
+        for r in effects.deleted_roads.into_iter().rev() {
+            app.primary.draw_map.delete_road(r, &app.primary.map);
+        }
         for r in effects.changed_roads {
             let road = app.primary.map.get_r(r);
             app.primary.draw_map.recreate_road(road, &app.primary.map);
         }
 
+        for i in effects.deleted_intersections.into_iter().rev() {
+            app.primary.draw_map.delete_intersection(i);
+        }
         for i in effects.changed_intersections {
             app.primary
                 .draw_map
@@ -784,6 +798,13 @@ pub fn apply_map_edits(ctx: &mut EventCtx, app: &mut App, edits: MapEdits) {
             app.primary.draw_map.get_pl(pl).clear_rendering();
         }
 
+        // Amenities and parking change how buildings look
+        if !effects.changed_buildings.is_empty() {
+            app.primary
+                .draw_map
+                .recreate_buildings(ctx, &app.primary.map, &app.cs, &app.opts);
+        }
+
         if app.primary.layer.as_ref().and_then(|l| l.name()) == Some("map edits") {
             app.primary.layer = Some(Box::new(crate::layer::map::Static::edits(ctx, app)));
         }
@@ -939,7 +960,13 @@ fn cmd_to_id(cmd: &EditCmd) -> Option<ID> {
         EditCmd::ChangeRoad { r, .. } => Some(ID::Road(*r)),
         EditCmd::ChangeIntersection { i, .. } => Some(ID::Intersection(*i)),
         EditCmd::ChangeCrosswalks { i, .. } => Some(ID::Intersection(*i)),
+        EditCmd::ChangeTurnRestrictions { i, .. } => Some(ID::Intersection(*i)),
         EditCmd::ChangeRouteSchedule { .. } => None,
+        // Undoing this removes the road, but not the intersections it connects
+        EditCmd::CreateRoad { new, .. } => Some(ID::Intersection(new.src_i)),
+        EditCmd::SplitRoad { r, .. } => Some(ID::Road(*r)),
+        EditCmd::DeleteRoad { .. } => None,
+        EditCmd::ChangeBuilding { b, .. } => Some(ID::Building(*b)),
     }
 }
 
@@ -0,0 +1,121 @@
+use abstutil::Tags;
+use geom::Speed;
+use map_model::IntersectionID;
+use widgetry::mapspace::{ObjectID, World, WorldOutcome};
+use widgetry::tools::PopupMsg;
+use widgetry::{
+    Choice, Color, EventCtx, GfxCtx, HorizontalAlignment, Key, Line, Outcome, Panel, State,
+    TextExt, VerticalAlignment, Widget,
+};
+
+use crate::app::App;
+use crate::app::Transition;
+use crate::edit::apply_map_edits;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct ID(IntersectionID);
+
+impl ObjectID for ID {}
+
+/// Connects a new road from one intersection to another, in a straight line.
+pub struct NewRoadEditor {
+    src_i: IntersectionID,
+    world: World<ID>,
+    panel: Panel,
+}
+
+impl NewRoadEditor {
+    pub fn new_state(
+        ctx: &mut EventCtx,
+        app: &mut App,
+        src_i: IntersectionID,
+    ) -> Box<dyn State<App>> {
+        app.primary.current_selection = None;
+
+        let map = &app.primary.map;
+        let mut world = World::bounded(map.get_bounds());
+        for i in map.all_intersections() {
+            if i.id != src_i {
+                world
+                    .add(ID(i.id))
+                    .hitbox(i.polygon.clone())
+                    .draw_color(Color::BLUE.alpha(0.5))
+                    .hover_alpha(0.3)
+                    .clickable()
+                    .build(ctx);
+            }
+        }
+
+        Box::new(Self {
+            src_i,
+            world,
+            panel: Panel::new_builder(Widget::col(vec![
+                Line("Connect a new road").small_heading().into_widget(ctx),
+                "Click another intersection to connect to".text_widget(ctx),
+                Widget::row(vec![
+                    "Type of road".text_widget(ctx).centered_vert(),
+                    Widget::dropdown(
+                        ctx,
+                        "highway",
+                        "residential".to_string(),
+                        ["residential", "cycleway", "footway"]
+                            .into_iter()
+                            .map(|x| Choice::new(x, x.to_string()))
+                            .collect(),
+                    ),
+                ]),
+                ctx.style()
+                    .btn_outline
+                    .text("Cancel")
+                    .hotkey(Key::Escape)
+                    .build_def(ctx),
+            ]))
+            .aligned(HorizontalAlignment::Center, VerticalAlignment::Top)
+            .build(ctx),
+        })
+    }
+}
+
+impl State<App> for NewRoadEditor {
+    fn event(&mut self, ctx: &mut EventCtx, app: &mut App) -> Transition {
+        if let WorldOutcome::ClickedObject(ID(dst_i)) = self.world.event(ctx) {
+            let highway: String = self.panel.dropdown_value("highway");
+            let speed_limit = if highway == "residential" {
+                Speed::miles_per_hour(25.0)
+            } else {
+                Speed::miles_per_hour(10.0)
+            };
+            let mut tags = Tags::empty();
+            tags.insert("highway", highway);
+
+            let map = &app.primary.map;
+            return match map.create_road_cmd(self.src_i, dst_i, Vec::new(), tags, speed_limit) {
+                Ok(cmd) => {
+                    let mut edits = map.get_edits().clone();
+                    edits.commands.push(cmd);
+                    apply_map_edits(ctx, app, edits);
+                    Transition::Pop
+                }
+                Err(err) => {
+                    Transition::Push(PopupMsg::new_state(ctx, "Error", vec![err.to_string()]))
+                }
+            };
+        }
+
+        if let Outcome::Clicked(ref x) = self.panel.event(ctx) {
+            match x.as_ref() {
+                "Cancel" => {
+                    return Transition::Pop;
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        Transition::Keep
+    }
+
+    fn draw(&self, g: &mut GfxCtx, _: &App) {
+        self.panel.draw(g);
+        self.world.draw(g);
+    }
+}
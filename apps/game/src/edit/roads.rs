@@ -346,6 +346,30 @@ impl State<App> for RoadEditor {
                         apply_map_edits(ctx, app, edits);
                     }
                     return Transition::Replace(ZoneEditor::new_state(ctx, app, self.r));
+                } else if x == "Split road" || x == "Delete road" {
+                    // These change more than this one road, so stop editing it
+                    if let Some(edits) = self.compress_edits(app) {
+                        apply_map_edits(ctx, app, edits);
+                    }
+                    let map = &app.primary.map;
+                    let cmd = if x == "Split road" {
+                        map.split_road_cmd(self.r, map.get_r(self.r).length() / 2.0)
+                    } else {
+                        map.delete_road_cmd(self.r)
+                    };
+                    return match cmd {
+                        Ok(cmd) => {
+                            let mut edits = map.get_edits().clone();
+                            edits.commands.push(cmd);
+                            apply_map_edits(ctx, app, edits);
+                            Transition::Pop
+                        }
+                        Err(err) => Transition::Replace(PopupMsg::new_state(
+                            ctx,
+                            "Error",
+                            vec![err.to_string()],
+                        )),
+                    };
                 } else {
                     unreachable!()
                 }
@@ -896,6 +920,16 @@ fn make_main_panel(
             .text("Access restrictions")
             .build_def(ctx)
             .centered_vert(),
+        ctx.style()
+            .btn_outline
+            .text("Split road")
+            .build_def(ctx)
+            .centered_vert(),
+        ctx.style()
+            .btn_outline
+            .text("Delete road")
+            .build_def(ctx)
+            .centered_vert(),
     ]);
 
     Panel::new_builder(
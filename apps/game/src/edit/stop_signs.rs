@@ -18,8 +18,8 @@ use crate::common::CommonState;
 use crate::edit::{apply_map_edits, check_sidewalk_connectivity, TrafficSignalEditor};
 use crate::sandbox::GameplayMode;
 
-// TODO For now, individual turns can't be manipulated. Banning turns could be useful, but I'm not
-// sure what to do about the player orphaning a section of the map.
+// TODO For now, individual turns can't be manipulated. Movements between roads can be banned from
+// the turn restrictions editor.
 pub struct StopSignEditor {
     id: IntersectionID,
     mode: GameplayMode,
@@ -70,6 +70,15 @@ impl StopSignEditor {
                     .text("Change crosswalks")
                     .hotkey(Key::C)
                     .build_def(ctx),
+                ctx.style()
+                    .btn_outline
+                    .text("Change turn restrictions")
+                    .hotkey(Key::T)
+                    .build_def(ctx),
+                ctx.style()
+                    .btn_outline
+                    .text("Connect a new road")
+                    .build_def(ctx),
             ]),
             Widget::row(vec![
                 ctx.style()
@@ -165,6 +174,12 @@ impl SimpleState<App> for StopSignEditor {
             "Change crosswalks" => Transition::Replace(
                 super::crosswalks::CrosswalkEditor::new_state(ctx, app, self.id),
             ),
+            "Change turn restrictions" => Transition::Replace(
+                super::turn_restrictions::TurnRestrictionEditor::new_state(ctx, app, self.id),
+            ),
+            "Connect a new road" => Transition::Replace(
+                super::new_roads::NewRoadEditor::new_state(ctx, app, self.id),
+            ),
             _ => unreachable!(),
         }
     }
@@ -1,7 +1,8 @@
-use geom::Duration;
+use geom::{Duration, Time};
 use map_gui::tools::FilePicker;
 use map_model::{
-    ControlStopSign, ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, StageType,
+    ControlStopSign, ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID,
+    SignalPriority, StageType,
 };
 use widgetry::tools::{ChooseSomething, PopupMsg};
 use widgetry::{
@@ -15,6 +16,7 @@ use crate::edit::{apply_map_edits, check_sidewalk_connectivity, StopSignEditor};
 use crate::sandbox::GameplayMode;
 
 pub struct ChangeDuration {
+    plan: usize,
     idx: usize,
 }
 
@@ -23,9 +25,11 @@ impl ChangeDuration {
         ctx: &mut EventCtx,
         app: &App,
         signal: &ControlTrafficSignal,
+        plan: usize,
         idx: usize,
     ) -> Box<dyn State<App>> {
         let i = app.primary.map.get_i(signal.id);
+        let stage = &signal.plan_stages(plan)[idx];
         let panel = Panel::new_builder(Widget::col(vec![
             Widget::row(vec![
                 Line("How long should this stage last?")
@@ -38,8 +42,11 @@ impl ChangeDuration {
                 Spinner::widget(
                     ctx,
                     "duration",
-                    (signal.get_min_crossing_time(idx, i), Duration::minutes(5)),
-                    signal.stages[idx].stage_type.simple_duration(),
+                    (
+                        signal.get_min_crossing_time_in_plan(plan, idx, i),
+                        Duration::minutes(5),
+                    ),
+                    stage.stage_type.simple_duration(),
                     Duration::seconds(1.0),
                 ),
             ]),
@@ -47,7 +54,7 @@ impl ChangeDuration {
                 .secondary()
                 .into_widget(ctx),
             Widget::col(vec![
-                Text::from_all(match signal.stages[idx].stage_type {
+                Text::from_all(match stage.stage_type {
                     StageType::Fixed(_) => vec![
                         Line("Fixed timing").small_heading(),
                         Line(" (Adjust both values below to enable variable timing)"),
@@ -67,7 +74,7 @@ impl ChangeDuration {
                         ctx,
                         "additional",
                         (Duration::ZERO, Duration::minutes(5)),
-                        match signal.stages[idx].stage_type {
+                        match stage.stage_type {
                             StageType::Fixed(_) => Duration::ZERO,
                             StageType::Variable(_, _, additional) => additional,
                         },
@@ -82,7 +89,7 @@ impl ChangeDuration {
                         ctx,
                         "delay",
                         (Duration::ZERO, Duration::seconds(300.0)),
-                        match signal.stages[idx].stage_type {
+                        match stage.stage_type {
                             StageType::Fixed(_) => Duration::ZERO,
                             StageType::Variable(_, delay, _) => delay,
                         },
@@ -100,7 +107,7 @@ impl ChangeDuration {
                 .build_def(ctx),
         ]))
         .build(ctx);
-        <dyn SimpleState<_>>::new_state(panel, Box::new(ChangeDuration { idx }))
+        <dyn SimpleState<_>>::new_state(panel, Box::new(ChangeDuration { plan, idx }))
     }
 }
 
@@ -123,13 +130,14 @@ impl SimpleState<App> for ChangeDuration {
                 } else {
                     StageType::Variable(dt, delay, additional)
                 };
+                let plan = self.plan;
                 let idx = self.idx;
                 Transition::Multi(vec![
                     Transition::Pop,
                     Transition::ModifyState(Box::new(move |state, ctx, app| {
                         let editor = state.downcast_mut::<TrafficSignalEditor>().unwrap();
                         editor.add_new_edit(ctx, app, idx, |ts| {
-                            ts.stages[idx].stage_type = new_type.clone();
+                            ts.plan_stages_mut(plan)[idx].stage_type = new_type.clone();
                         });
                     })),
                 ])
@@ -176,6 +184,77 @@ impl SimpleState<App> for ChangeDuration {
     }
 }
 
+pub struct AddPlan;
+
+impl AddPlan {
+    pub fn new_state(ctx: &mut EventCtx) -> Box<dyn State<App>> {
+        let panel = Panel::new_builder(Widget::col(vec![
+            Widget::row(vec![
+                Line("When should the new timing plan start?")
+                    .small_heading()
+                    .into_widget(ctx),
+                ctx.style().btn_close_widget(ctx),
+            ]),
+            Widget::row(vec![
+                "Hours after midnight:".text_widget(ctx).centered_vert(),
+                Spinner::widget(
+                    ctx,
+                    "start time",
+                    (Duration::ZERO, Duration::hours(24) - Duration::minutes(15)),
+                    Duration::hours(7),
+                    Duration::minutes(15),
+                ),
+            ]),
+            Line("The new plan starts as a copy of whatever plan was active at that time")
+                .secondary()
+                .into_widget(ctx),
+            ctx.style()
+                .btn_solid_primary
+                .text("Add")
+                .hotkey(Key::Enter)
+                .build_def(ctx),
+        ]))
+        .build(ctx);
+        <dyn SimpleState<_>>::new_state(panel, Box::new(AddPlan))
+    }
+}
+
+impl SimpleState<App> for AddPlan {
+    fn on_click(
+        &mut self,
+        _: &mut EventCtx,
+        _: &mut App,
+        x: &str,
+        panel: &mut Panel,
+    ) -> Transition {
+        match x {
+            "close" => Transition::Pop,
+            "Add" => {
+                let start_time = Time::START_OF_DAY + panel.spinner("start time");
+                Transition::Multi(vec![
+                    Transition::Pop,
+                    Transition::ModifyState(Box::new(move |state, ctx, app| {
+                        let editor = state.downcast_mut::<TrafficSignalEditor>().unwrap();
+                        editor.add_new_plan(ctx, app, start_time);
+                    })),
+                ])
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn other_event(&mut self, ctx: &mut EventCtx, _: &mut App) -> Transition {
+        if ctx.normal_left_click() && ctx.canvas.get_cursor_in_screen_space().is_none() {
+            return Transition::Pop;
+        }
+        Transition::Keep
+    }
+
+    fn draw_baselayer(&self) -> DrawBaselayer {
+        DrawBaselayer::PreviousState
+    }
+}
+
 pub fn edit_entire_signal(
     ctx: &mut EventCtx,
     app: &App,
@@ -194,6 +273,17 @@ pub fn edit_entire_signal(
     let use_template = "use template";
     let all_walk = "add an all-walk stage at the end";
     let major_minor_timing = "use timing pattern for a major/minor intersection";
+    let priority = app.primary.map.get_traffic_signal(i).priority.clone();
+    let toggle_priority = if priority.is_some() {
+        "stop giving priority to buses, trains, and emergency vehicles"
+    } else {
+        "give priority to buses, trains, and emergency vehicles"
+    };
+    let toggle_preemption = if priority.map(|p| p.preemption).unwrap_or(false) {
+        "stop letting trains and emergency vehicles preempt the signal"
+    } else {
+        "let trains and emergency vehicles preempt the signal"
+    };
     let stop_sign = "convert to stop signs";
     let close = "close intersection for construction";
     let reset = "reset to default";
@@ -210,6 +300,8 @@ pub fn edit_entire_signal(
         choices.push(all_walk.to_string());
     }
     choices.push(major_minor_timing.to_string());
+    choices.push(toggle_priority.to_string());
+    choices.push(toggle_preemption.to_string());
     // TODO Conflating stop signs and construction here
     if mode.can_edit_stop_signs() {
         choices.push(stop_sign.to_string());
@@ -292,6 +384,31 @@ pub fn edit_entire_signal(
                     }
                 }),
             )),
+            x if x == toggle_priority => Transition::Multi(vec![
+                Transition::Pop,
+                Transition::ModifyState(Box::new(move |state, ctx, app| {
+                    let editor = state.downcast_mut::<TrafficSignalEditor>().unwrap();
+                    editor.add_new_edit(ctx, app, 0, |ts| {
+                        ts.priority = if ts.priority.is_some() {
+                            None
+                        } else {
+                            Some(SignalPriority::transit_default())
+                        };
+                    });
+                })),
+            ]),
+            x if x == toggle_preemption => Transition::Multi(vec![
+                Transition::Pop,
+                Transition::ModifyState(Box::new(move |state, ctx, app| {
+                    let editor = state.downcast_mut::<TrafficSignalEditor>().unwrap();
+                    editor.add_new_edit(ctx, app, 0, |ts| {
+                        let priority = ts
+                            .priority
+                            .get_or_insert_with(SignalPriority::transit_default);
+                        priority.preemption = !priority.preemption;
+                    });
+                })),
+            ]),
             x if x == stop_sign => {
                 original.apply(app);
 
@@ -3,12 +3,12 @@ use std::collections::{BTreeSet, VecDeque};
 use anyhow::Result;
 
 use abstutil::Timer;
-use geom::{Distance, Line, Polygon, Pt2D};
+use geom::{Distance, Line, Polygon, Pt2D, Time};
 use map_gui::options::TrafficSignalStyle;
 use map_gui::render::{traffic_signal, DrawMovement, DrawOptions};
 use map_model::{
-    ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, MovementID, Stage, StageType,
-    TurnPriority,
+    ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, MovementID, Plan, Stage,
+    StageType, TurnPriority,
 };
 use widgetry::tools::PopupMsg;
 use widgetry::{
@@ -36,6 +36,8 @@ pub struct TrafficSignalEditor {
 
     mode: GameplayMode,
     members: BTreeSet<IntersectionID>,
+    // Which time-of-day plan is being edited. See ControlTrafficSignal::plan_at.
+    current_plan: usize,
     current_stage: usize,
 
     movements: Vec<DrawMovement>,
@@ -74,9 +76,10 @@ impl TrafficSignalEditor {
         synced.apply(app);
 
         let mut editor = TrafficSignalEditor {
-            side_panel: make_side_panel(ctx, app, &members, 0),
+            side_panel: make_side_panel(ctx, app, &members, 0, 0),
             top_panel: make_top_panel(ctx, app, false, false),
             mode,
+            current_plan: 0,
             current_stage: 0,
             movements: Vec::new(),
             movement_selected: None,
@@ -95,17 +98,49 @@ impl TrafficSignalEditor {
 
     fn change_stage(&mut self, ctx: &mut EventCtx, app: &App, idx: usize) {
         if self.current_stage == idx {
-            let mut new = make_side_panel(ctx, app, &self.members, self.current_stage);
+            let mut new = make_side_panel(
+                ctx,
+                app,
+                &self.members,
+                self.current_plan,
+                self.current_stage,
+            );
             new.restore(ctx, &self.side_panel);
             self.side_panel = new;
         } else {
             self.current_stage = idx;
-            self.side_panel = make_side_panel(ctx, app, &self.members, self.current_stage);
+            self.side_panel = make_side_panel(
+                ctx,
+                app,
+                &self.members,
+                self.current_plan,
+                self.current_stage,
+            );
         }
 
         self.recalc_draw_current(ctx, app);
     }
 
+    fn change_plan(&mut self, ctx: &mut EventCtx, app: &App, plan: usize) {
+        self.current_plan = plan;
+        self.current_stage = 0;
+        self.side_panel = make_side_panel(ctx, app, &self.members, plan, 0);
+        self.recalc_draw_current(ctx, app);
+    }
+
+    fn add_new_plan(&mut self, ctx: &mut EventCtx, app: &mut App, start_time: Time) {
+        self.add_new_edit(ctx, app, 0, |ts| {
+            ts.add_plan(start_time);
+        });
+        // Every member has the same plan start times, so any of them can find the new plan
+        let plan = app
+            .primary
+            .map
+            .get_traffic_signal(*self.members.iter().next().unwrap())
+            .plan_at(start_time);
+        self.change_plan(ctx, app, plan);
+    }
+
     fn add_new_edit<F: Fn(&mut ControlTrafficSignal)>(
         &mut self,
         ctx: &mut EventCtx,
@@ -129,12 +164,17 @@ impl TrafficSignalEditor {
         let mut batch = GeomBatch::new();
         let mut movements = Vec::new();
         for i in &self.members {
-            let stage = &app.primary.map.get_traffic_signal(*i).stages[self.current_stage];
+            let stage = &app
+                .primary
+                .map
+                .get_traffic_signal(*i)
+                .plan_stages(self.current_plan)[self.current_stage];
             for (m, draw) in DrawMovement::for_i(
                 ctx.prerender,
                 &app.primary.map,
                 &app.cs,
                 *i,
+                self.current_plan,
                 self.current_stage,
             ) {
                 if self
@@ -208,7 +248,7 @@ impl State<App> for TrafficSignalEditor {
             .primary
             .map
             .get_traffic_signal(*self.members.iter().next().unwrap());
-        let num_stages = canonical_signal.stages.len();
+        let num_stages = canonical_signal.plan_stages(self.current_plan).len();
 
         match self.side_panel.event(ctx) {
             Outcome::Clicked(x) => match x.as_ref() {
@@ -226,11 +266,13 @@ impl State<App> for TrafficSignalEditor {
                         ctx,
                         app,
                         self.members.clone(),
+                        self.current_plan,
                     ));
                 }
                 "Add a new stage" => {
+                    let plan = self.current_plan;
                     self.add_new_edit(ctx, app, num_stages, |ts| {
-                        ts.stages.push(Stage::new());
+                        ts.plan_stages_mut(plan).push(Stage::new());
                     });
                     return Transition::Keep;
                 }
@@ -239,13 +281,36 @@ impl State<App> for TrafficSignalEditor {
                         ctx,
                         app,
                         canonical_signal,
+                        self.current_plan,
                         self.current_stage,
                     ));
                 }
                 "delete stage" => {
+                    let plan = self.current_plan;
                     let idx = self.current_stage;
                     self.add_new_edit(ctx, app, 0, |ts| {
-                        ts.stages.remove(idx);
+                        ts.plan_stages_mut(plan).remove(idx);
+                    });
+                    return Transition::Keep;
+                }
+                "previous plan" => {
+                    self.change_plan(ctx, app, self.current_plan - 1);
+                    return Transition::Keep;
+                }
+                "next plan" => {
+                    self.change_plan(ctx, app, self.current_plan + 1);
+                    return Transition::Keep;
+                }
+                "Add a time-of-day plan" => {
+                    return Transition::Push(edits::AddPlan::new_state(ctx));
+                }
+                "delete plan" => {
+                    // Switch to the previous plan first, so the side panel doesn't try to show
+                    // the deleted one
+                    let plan = self.current_plan;
+                    self.current_plan -= 1;
+                    self.add_new_edit(ctx, app, 0, |ts| {
+                        ts.remove_plan(plan);
                     });
                     return Transition::Keep;
                 }
@@ -268,8 +333,9 @@ impl State<App> for TrafficSignalEditor {
                 }
             },
             Outcome::DragDropReleased(_, old_idx, new_idx) => {
+                let plan = self.current_plan;
                 self.add_new_edit(ctx, app, new_idx, |ts| {
-                    ts.stages.swap(old_idx, new_idx);
+                    ts.plan_stages_mut(plan).swap(old_idx, new_idx);
                 });
             }
             _ => {}
@@ -284,7 +350,7 @@ impl State<App> for TrafficSignalEditor {
                         self.redo_stack.clear();
 
                         self.top_panel = make_top_panel(ctx, app, true, false);
-                        self.change_stage(ctx, app, 0);
+                        self.change_plan(ctx, app, 0);
 
                         return Transition::Push(PopupMsg::new_state(
                             ctx,
@@ -363,6 +429,24 @@ impl State<App> for TrafficSignalEditor {
                         *self.members.iter().next().unwrap(),
                     ));
                 }
+                "Change turn restrictions" => {
+                    // TODO Probably need to follow everything Cancel does
+                    return Transition::Replace(
+                        super::turn_restrictions::TurnRestrictionEditor::new_state(
+                            ctx,
+                            app,
+                            *self.members.iter().next().unwrap(),
+                        ),
+                    );
+                }
+                "Connect a new road" => {
+                    // TODO Probably need to follow everything Cancel does
+                    return Transition::Replace(super::new_roads::NewRoadEditor::new_state(
+                        ctx,
+                        app,
+                        *self.members.iter().next().unwrap(),
+                    ));
+                }
                 "Preview" => {
                     // Might have to do this first!
                     app.primary
@@ -381,7 +465,7 @@ impl State<App> for TrafficSignalEditor {
                         .push(BundleEdits::get_current(app, &self.members));
                     self.command_stack.pop().unwrap().apply(app);
                     self.top_panel = make_top_panel(ctx, app, !self.command_stack.is_empty(), true);
-                    self.change_stage(ctx, app, 0);
+                    self.change_plan(ctx, app, 0);
                     return Transition::Keep;
                 }
                 "redo" => {
@@ -389,7 +473,7 @@ impl State<App> for TrafficSignalEditor {
                         .push(BundleEdits::get_current(app, &self.members));
                     self.redo_stack.pop().unwrap().apply(app);
                     self.top_panel = make_top_panel(ctx, app, true, !self.redo_stack.is_empty());
-                    self.change_stage(ctx, app, 0);
+                    self.change_plan(ctx, app, 0);
                     return Transition::Keep;
                 }
                 _ => unreachable!(),
@@ -415,7 +499,7 @@ impl State<App> for TrafficSignalEditor {
                     let signal = app.primary.map.get_traffic_signal(m.id.parent);
                     let i = app.primary.map.get_i(signal.id);
                     if m.hitbox.contains_pt(pt) {
-                        let stage = &signal.stages[self.current_stage];
+                        let stage = &signal.plan_stages(self.current_plan)[self.current_stage];
                         let next_priority = match stage.get_priority_of_movement(m.id) {
                             TurnPriority::Banned => {
                                 if stage.could_be_protected(m.id, i) {
@@ -451,7 +535,9 @@ impl State<App> for TrafficSignalEditor {
             let mut txt = Text::new();
             txt.add_line(Line(format!(
                 "{} {}",
-                match signal.stages[self.current_stage].get_priority_of_movement(id) {
+                match signal.plan_stages(self.current_plan)[self.current_stage]
+                    .get_priority_of_movement(id)
+                {
                     TurnPriority::Protected => "Protected",
                     TurnPriority::Yield => "Yielding",
                     TurnPriority::Banned => "Forbidden",
@@ -474,15 +560,17 @@ impl State<App> for TrafficSignalEditor {
                 ctx,
                 format!(
                     "toggle from {:?} to {:?}",
-                    signal.stages[self.current_stage].get_priority_of_movement(id),
+                    signal.plan_stages(self.current_plan)[self.current_stage]
+                        .get_priority_of_movement(id),
                     pri
                 ),
             ) {
+                let plan = self.current_plan;
                 let idx = self.current_stage;
                 let movement = app.primary.map.get_i(id.parent).movements[&id].clone();
                 self.add_new_edit(ctx, app, idx, |ts| {
                     if ts.id == id.parent {
-                        ts.stages[idx].edit_movement(&movement, pri);
+                        ts.plan_stages_mut(plan)[idx].edit_movement(&movement, pri);
                     }
                 });
                 return Transition::KeepWithMouseover;
@@ -543,12 +631,22 @@ impl State<App> for TrafficSignalEditor {
 }
 
 fn make_top_panel(ctx: &mut EventCtx, app: &App, can_undo: bool, can_redo: bool) -> Panel {
-    let mut second_row = vec![ctx
-        .style()
-        .btn_outline
-        .text("Change crosswalks")
-        .hotkey(Key::C)
-        .build_def(ctx)];
+    let mut second_row = vec![
+        ctx.style()
+            .btn_outline
+            .text("Change crosswalks")
+            .hotkey(Key::C)
+            .build_def(ctx),
+        ctx.style()
+            .btn_outline
+            .text("Change turn restrictions")
+            .hotkey(Key::T)
+            .build_def(ctx),
+        ctx.style()
+            .btn_outline
+            .text("Connect a new road")
+            .build_def(ctx),
+    ];
     if app.opts.dev {
         second_row.push(
             ctx.style()
@@ -619,11 +717,13 @@ fn make_side_panel(
     ctx: &mut EventCtx,
     app: &App,
     members: &BTreeSet<IntersectionID>,
+    plan: usize,
     selected: usize,
 ) -> Panel {
     let map = &app.primary.map;
     // Use any member for stage duration
     let canonical_signal = map.get_traffic_signal(*members.iter().next().unwrap());
+    let stages = canonical_signal.plan_stages(plan);
 
     let mut txt = Text::new();
     if members.len() == 1 {
@@ -657,6 +757,48 @@ fn make_side_panel(
     }
     let mut col = vec![txt.into_widget(ctx)];
 
+    // Time-of-day plan controls
+    col.push(
+        Widget::row(vec![
+            ctx.style()
+                .btn_plain
+                .icon_bytes(include_labeled_bytes!(
+                    "../../../../../widgetry/icons/arrow_left.svg"
+                ))
+                .disabled(plan == 0)
+                .build_widget(ctx, "previous plan"),
+            ctx.style()
+                .btn_plain
+                .icon_bytes(include_labeled_bytes!(
+                    "../../../../../widgetry/icons/arrow_right.svg"
+                ))
+                .disabled(plan == canonical_signal.num_plans() - 1)
+                .build_widget(ctx, "next plan"),
+            format!(
+                "Plan {} of {}, starting at {}",
+                plan + 1,
+                canonical_signal.num_plans(),
+                canonical_signal.plan_start_time(plan).ampm_tostring()
+            )
+            .text_widget(ctx)
+            .centered_vert(),
+            if plan != 0 {
+                ctx.style()
+                    .btn_solid_destructive
+                    .icon("system/assets/tools/trash.svg")
+                    .build_widget(ctx, "delete plan")
+            } else {
+                Widget::nothing()
+            },
+            ctx.style()
+                .btn_plain
+                .icon("system/assets/speed/plus.svg")
+                .build_widget(ctx, "Add a time-of-day plan"),
+        ])
+        .padding(10)
+        .bg(app.cs.inner_panel_bg),
+    );
+
     // Stage controls
     col.push(
         Widget::row(vec![
@@ -672,9 +814,9 @@ fn make_side_panel(
                 .icon_bytes(include_labeled_bytes!(
                     "../../../../../widgetry/icons/arrow_right.svg"
                 ))
-                .disabled(selected == canonical_signal.stages.len() - 1)
+                .disabled(selected == stages.len() - 1)
                 .build_widget(ctx, "next stage"),
-            match canonical_signal.stages[selected].stage_type {
+            match stages[selected].stage_type {
                 StageType::Fixed(d) => format!("Stage duration: {}", d),
                 StageType::Variable(min, delay, additional) => format!(
                     "Stage duration: {}, {}, {} (variable)",
@@ -688,7 +830,7 @@ fn make_side_panel(
                 .icon("system/assets/tools/pencil.svg")
                 .hotkey(Key::X)
                 .build_widget(ctx, "change duration"),
-            if canonical_signal.stages.len() > 1 {
+            if stages.len() > 1 {
                 ctx.style()
                     .btn_solid_destructive
                     .icon("system/assets/tools/trash.svg")
@@ -713,18 +855,18 @@ fn make_side_panel(
     );
 
     let mut drag_drop = DragDrop::new(ctx, "stage cards", StackAxis::Horizontal);
-    for idx in 0..canonical_signal.stages.len() {
+    for (idx, stage) in stages.iter().enumerate() {
         let mut stack = GeomBatchStack::vertical(vec![
             Text::from(Line(format!(
                 "Stage {}: {}",
                 idx + 1,
-                match canonical_signal.stages[idx].stage_type {
+                match stage.stage_type {
                     StageType::Fixed(d) => format!("{}", d),
                     StageType::Variable(min, _, _) => format!("{} (v)", min),
                 },
             )))
             .render(ctx),
-            draw_multiple_signals(ctx, app, members, idx, &translations),
+            draw_multiple_signals(ctx, app, members, plan, idx, &translations),
         ]);
         stack.set_spacing(10.0);
         let icon_batch = stack.batch();
@@ -760,7 +902,7 @@ fn make_side_panel(
         // TODO Say "normally" to account for variable stages?
         format!(
             "One full cycle lasts {}",
-            canonical_signal.simple_cycle_duration()
+            canonical_signal.plan_cycle_duration(plan)
         )
         .text_widget(ctx)
         .centered_vert(),
@@ -819,26 +961,50 @@ impl BundleEdits {
         BundleEdits { signals }
     }
 
-    // If the intersections haven't been edited together before, the number of stages and the
-    // durations might not match up. Just initially force them to align somehow.
+    // If the intersections haven't been edited together before, the time-of-day plans, number of
+    // stages, and the durations might not match up. Just initially force them to align somehow.
     fn synchronize(app: &App, members: &BTreeSet<IntersectionID>) -> BundleEdits {
         let map = &app.primary.map;
-        // Pick one of the members with the most stages as canonical.
+        // Pick one of the members with the most plans and stages as canonical.
         let canonical = map.get_traffic_signal(
             *members
                 .iter()
-                .max_by_key(|i| map.get_traffic_signal(**i).stages.len())
+                .max_by_key(|i| {
+                    let signal = map.get_traffic_signal(**i);
+                    (signal.num_plans(), signal.stages.len())
+                })
                 .unwrap(),
         );
 
         let mut signals = Vec::new();
         for i in members {
             let mut signal = map.get_traffic_signal(*i).clone();
-            for (idx, canonical_stage) in canonical.stages.iter().enumerate() {
-                if signal.stages.len() == idx {
-                    signal.stages.push(Stage::new());
+            let start_times: Vec<Time> = signal.plans.iter().map(|p| p.start_time).collect();
+            let canonical_start_times: Vec<Time> =
+                canonical.plans.iter().map(|p| p.start_time).collect();
+            if start_times != canonical_start_times {
+                // Use whatever this signal was doing at each of the canonical start times
+                signal.plans = canonical_start_times
+                    .into_iter()
+                    .map(|start_time| {
+                        let plan = signal.plan_at(start_time);
+                        Plan {
+                            start_time,
+                            stages: signal.plan_stages(plan).clone(),
+                            offset: signal.plan_offset(plan),
+                        }
+                    })
+                    .collect();
+            }
+
+            for plan in 0..canonical.num_plans() {
+                let stages = signal.plan_stages_mut(plan);
+                for (idx, canonical_stage) in canonical.plan_stages(plan).iter().enumerate() {
+                    if stages.len() == idx {
+                        stages.push(Stage::new());
+                    }
+                    stages[idx].stage_type = canonical_stage.stage_type.clone();
                 }
-                signal.stages[idx].stage_type = canonical_stage.stage_type.clone();
             }
             signals.push(signal);
         }
@@ -849,43 +1015,54 @@ impl BundleEdits {
 
 // If None, nothing missing.
 fn check_for_missing_turns(app: &App, members: &BTreeSet<IntersectionID>) -> Option<BundleEdits> {
-    let mut all_missing = BTreeSet::new();
-    for i in members {
-        all_missing.extend(
-            app.primary
-                .map
-                .get_traffic_signal(*i)
-                .missing_turns(app.primary.map.get_i(*i)),
-        );
-    }
-    if all_missing.is_empty() {
-        return None;
-    }
-
     let mut bundle = BundleEdits::get_current(app, members);
-    // Stick all the missing turns in a new stage at the beginning.
-    for signal in &mut bundle.signals {
-        let mut stage = Stage::new();
-        // TODO Could do this more efficiently
-        for m in &all_missing {
-            if m.parent != signal.id {
-                continue;
-            }
-            if m.crosswalk {
-                stage.protected_movements.insert(*m);
-            } else {
-                stage.yield_movements.insert(*m);
+    let mut any_missing = false;
+    // The members have synchronized plans, so check each plan separately
+    let num_plans = bundle.signals[0].num_plans();
+    for plan in 0..num_plans {
+        let mut all_missing = BTreeSet::new();
+        for i in members {
+            all_missing.extend(
+                app.primary
+                    .map
+                    .get_traffic_signal(*i)
+                    .missing_turns_in_plan(plan, app.primary.map.get_i(*i)),
+            );
+        }
+        if all_missing.is_empty() {
+            continue;
+        }
+        any_missing = true;
+
+        // Stick all the missing turns in a new stage at the beginning.
+        for signal in &mut bundle.signals {
+            let mut stage = Stage::new();
+            // TODO Could do this more efficiently
+            for m in &all_missing {
+                if m.parent != signal.id {
+                    continue;
+                }
+                if m.crosswalk {
+                    stage.protected_movements.insert(*m);
+                } else {
+                    stage.yield_movements.insert(*m);
+                }
             }
+            signal.plan_stages_mut(plan).insert(0, stage);
         }
-        signal.stages.insert(0, stage);
     }
-    Some(bundle)
+    if any_missing {
+        Some(bundle)
+    } else {
+        None
+    }
 }
 
 fn draw_multiple_signals(
     ctx: &mut EventCtx,
     app: &App,
     members: &BTreeSet<IntersectionID>,
+    plan: usize,
     idx: usize,
     translations: &[(f64, f64)],
 ) -> GeomBatch {
@@ -898,7 +1075,7 @@ fn draw_multiple_signals(
         );
         traffic_signal::draw_signal_stage(
             ctx.prerender,
-            &app.primary.map.get_traffic_signal(*i).stages[idx],
+            &app.primary.map.get_traffic_signal(*i).plan_stages(plan)[idx],
             idx,
             *i,
             None,
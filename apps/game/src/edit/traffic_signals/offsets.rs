@@ -3,10 +3,11 @@ use std::collections::BTreeSet;
 use maplit::btreeset;
 
 use geom::{Distance, Duration};
-use map_model::IntersectionID;
+use map_model::{optimize_green_wave, signals_along_path, GreenWaveOptions, IntersectionID};
+use widgetry::tools::PopupMsg;
 use widgetry::{
     Color, Drawable, EventCtx, GfxCtx, HorizontalAlignment, Key, Line, Panel, RewriteColor,
-    SimpleState, Spinner, State, Text, TextExt, VerticalAlignment, Widget,
+    SimpleState, Spinner, State, Text, TextExt, Toggle, VerticalAlignment, Widget,
 };
 
 use crate::app::{App, Transition};
@@ -15,6 +16,8 @@ use crate::edit::traffic_signals::fade_irrelevant;
 
 pub struct ShowAbsolute {
     members: BTreeSet<IntersectionID>,
+    // Which timing plan's offsets to tune
+    plan: usize,
     labels: Drawable,
 }
 
@@ -23,6 +26,7 @@ impl ShowAbsolute {
         ctx: &mut EventCtx,
         app: &App,
         members: BTreeSet<IntersectionID>,
+        plan: usize,
     ) -> Box<dyn State<App>> {
         let mut batch = fade_irrelevant(app, &members);
         for i in &members {
@@ -31,7 +35,7 @@ impl ShowAbsolute {
                     app.primary
                         .map
                         .get_traffic_signal(*i)
-                        .offset
+                        .plan_offset(plan)
                         .to_string(&app.opts.units),
                 )
                 .bg(Color::PURPLE)
@@ -50,6 +54,11 @@ impl ShowAbsolute {
                 ctx.style().btn_close_widget(ctx),
             ]),
             "Select an intersection as the base".text_widget(ctx),
+            ctx.style()
+                .btn_outline
+                .text("Coordinate a green wave")
+                .hotkey(Key::G)
+                .build_def(ctx),
         ]))
         .aligned(HorizontalAlignment::Center, VerticalAlignment::Top)
         .build(ctx);
@@ -57,6 +66,7 @@ impl ShowAbsolute {
             panel,
             Box::new(ShowAbsolute {
                 members,
+                plan,
                 labels: ctx.upload(batch),
             }),
         )
@@ -64,13 +74,27 @@ impl ShowAbsolute {
 }
 
 impl SimpleState<App> for ShowAbsolute {
-    fn on_click(&mut self, _: &mut EventCtx, _: &mut App, x: &str, _: &mut Panel) -> Transition {
+    fn on_click(
+        &mut self,
+        ctx: &mut EventCtx,
+        app: &mut App,
+        x: &str,
+        _: &mut Panel,
+    ) -> Transition {
         match x {
             "close" => {
                 // TODO Bit confusing UX, because all the offset changes won't show up in the
                 // undo stack. Could maybe do ConsumeState.
                 Transition::Pop
             }
+            "Coordinate a green wave" => Transition::Replace(GreenWaveCorridor::new_state(
+                ctx,
+                app,
+                self.members.clone(),
+                self.plan,
+                None,
+                false,
+            )),
             _ => unreachable!(),
         }
     }
@@ -84,7 +108,13 @@ impl SimpleState<App> for ShowAbsolute {
     fn other_event(&mut self, ctx: &mut EventCtx, app: &mut App) -> Transition {
         ctx.canvas_movement();
         if let Some(i) = app.click_on_intersection(ctx, "select base intersection") {
-            return Transition::Replace(ShowRelative::new_state(ctx, app, i, self.members.clone()));
+            return Transition::Replace(ShowRelative::new_state(
+                ctx,
+                app,
+                i,
+                self.members.clone(),
+                self.plan,
+            ));
         }
 
         Transition::Keep
@@ -100,6 +130,7 @@ impl SimpleState<App> for ShowAbsolute {
 struct ShowRelative {
     base: IntersectionID,
     members: BTreeSet<IntersectionID>,
+    plan: usize,
     labels: Drawable,
 }
 
@@ -109,8 +140,9 @@ impl ShowRelative {
         app: &App,
         base: IntersectionID,
         members: BTreeSet<IntersectionID>,
+        plan: usize,
     ) -> Box<dyn State<App>> {
-        let base_offset = app.primary.map.get_traffic_signal(base).offset;
+        let base_offset = app.primary.map.get_traffic_signal(base).plan_offset(plan);
         let mut batch = fade_irrelevant(app, &members);
         for i in &members {
             if *i == base {
@@ -119,7 +151,7 @@ impl ShowRelative {
                     app.primary.map.get_i(*i).polygon.clone(),
                 );
             } else {
-                let offset = app.primary.map.get_traffic_signal(*i).offset - base_offset;
+                let offset = app.primary.map.get_traffic_signal(*i).plan_offset(plan) - base_offset;
                 batch.append(
                     Text::from(offset.to_string(&app.opts.units))
                         .bg(Color::PURPLE)
@@ -147,6 +179,7 @@ impl ShowRelative {
             Box::new(ShowRelative {
                 base,
                 members,
+                plan,
                 labels: ctx.upload(batch),
             }),
         )
@@ -162,7 +195,12 @@ impl SimpleState<App> for ShowRelative {
         _: &mut Panel,
     ) -> Transition {
         match x {
-            "close" => Transition::Replace(ShowAbsolute::new_state(ctx, app, self.members.clone())),
+            "close" => Transition::Replace(ShowAbsolute::new_state(
+                ctx,
+                app,
+                self.members.clone(),
+                self.plan,
+            )),
             _ => unreachable!(),
         }
     }
@@ -183,6 +221,7 @@ impl SimpleState<App> for ShowRelative {
                 self.base,
                 i,
                 self.members.clone(),
+                self.plan,
             ));
         }
 
@@ -200,6 +239,7 @@ struct TuneRelative {
     i1: IntersectionID,
     i2: IntersectionID,
     members: BTreeSet<IntersectionID>,
+    plan: usize,
     labels: Drawable,
 }
 
@@ -210,6 +250,7 @@ impl TuneRelative {
         i1: IntersectionID,
         i2: IntersectionID,
         members: BTreeSet<IntersectionID>,
+        plan: usize,
     ) -> Box<dyn State<App>> {
         let mut batch = fade_irrelevant(app, &btreeset! {i1, i2});
         let map = &app.primary.map;
@@ -230,8 +271,8 @@ impl TuneRelative {
             car_dt += r.length() / r.speed_limit;
         }
 
-        let offset1 = map.get_traffic_signal(i1).offset;
-        let offset2 = map.get_traffic_signal(i2).offset;
+        let offset1 = map.get_traffic_signal(i1).plan_offset(plan);
+        let offset2 = map.get_traffic_signal(i2).plan_offset(plan);
         let panel = Panel::new_builder(Widget::col(vec![
             Widget::row(vec![
                 Line(format!("Tuning offset between {} and {}", i1, i2))
@@ -279,6 +320,7 @@ impl TuneRelative {
                 i1,
                 i2,
                 members,
+                plan,
                 labels: ctx.upload(batch),
             }),
         )
@@ -298,8 +340,12 @@ impl SimpleState<App> for TuneRelative {
             "Update offset" => {
                 let mut ts = app.primary.map.get_traffic_signal(self.i2).clone();
                 let relative = panel.spinner("offset");
-                let offset1 = app.primary.map.get_traffic_signal(self.i1).offset;
-                ts.offset = offset1 + relative;
+                let offset1 = app
+                    .primary
+                    .map
+                    .get_traffic_signal(self.i1)
+                    .plan_offset(self.plan);
+                *ts.plan_offset_mut(self.plan) = offset1 + relative;
                 app.primary.map.incremental_edit_traffic_signal(ts);
                 Transition::Multi(vec![
                     Transition::Pop,
@@ -308,6 +354,7 @@ impl SimpleState<App> for TuneRelative {
                         app,
                         self.i1,
                         self.members.clone(),
+                        self.plan,
                     )),
                 ])
             }
@@ -324,3 +371,178 @@ impl SimpleState<App> for TuneRelative {
         g.redraw(&self.labels);
     }
 }
+
+// Pick both ends of a corridor, then calculate offsets for all the signals along it.
+struct GreenWaveCorridor {
+    members: BTreeSet<IntersectionID>,
+    plan: usize,
+    first: Option<IntersectionID>,
+    change_cycle_length: bool,
+    labels: Drawable,
+}
+
+impl GreenWaveCorridor {
+    fn new_state(
+        ctx: &mut EventCtx,
+        app: &App,
+        members: BTreeSet<IntersectionID>,
+        plan: usize,
+        first: Option<IntersectionID>,
+        change_cycle_length: bool,
+    ) -> Box<dyn State<App>> {
+        let mut batch = fade_irrelevant(app, &members);
+        if let Some(i) = first {
+            batch.push(
+                Color::BLUE.alpha(0.8),
+                app.primary.map.get_i(i).polygon.clone(),
+            );
+        }
+
+        let panel = Panel::new_builder(Widget::col(vec![
+            Widget::row(vec![
+                Line("Coordinate a green wave")
+                    .small_heading()
+                    .into_widget(ctx),
+                ctx.style().btn_close_widget(ctx),
+            ]),
+            if first.is_some() {
+                "Select the signal at the end of the corridor".text_widget(ctx)
+            } else {
+                "Select the signal at the start of the corridor".text_widget(ctx)
+            },
+            Toggle::checkbox(
+                ctx,
+                "also pick a common cycle length between 60s and 150s",
+                None,
+                change_cycle_length,
+            ),
+        ]))
+        .aligned(HorizontalAlignment::Center, VerticalAlignment::Top)
+        .build(ctx);
+        <dyn SimpleState<_>>::new_state(
+            panel,
+            Box::new(GreenWaveCorridor {
+                members,
+                plan,
+                first,
+                change_cycle_length,
+                labels: ctx.upload(batch),
+            }),
+        )
+    }
+
+    fn coordinate(&self, ctx: &mut EventCtx, app: &mut App, last: IntersectionID) -> Transition {
+        let corridor = match signals_along_path(&app.primary.map, self.first.unwrap(), last) {
+            Ok(corridor) => corridor,
+            Err(err) => {
+                return Transition::Push(PopupMsg::new_state(ctx, "Error", vec![err.to_string()]));
+            }
+        };
+        if let Some(i) = corridor.iter().find(|i| !self.members.contains(i)) {
+            return Transition::Push(PopupMsg::new_state(
+                ctx,
+                "Error",
+                vec![format!(
+                    "{} is along the corridor, but it's not being edited. Use \"Edit multiple \
+                     signals\" to include it.",
+                    i
+                )],
+            ));
+        }
+
+        let opts = GreenWaveOptions {
+            plan: self.plan,
+            cycle_length: if self.change_cycle_length {
+                Some((Duration::seconds(60.0), Duration::seconds(150.0)))
+            } else {
+                None
+            },
+        };
+        match optimize_green_wave(&app.primary.map, &corridor, &opts) {
+            Ok(wave) => {
+                let summary = vec![
+                    format!("Coordinated {} signals", wave.signals.len()),
+                    format!("Cycle length: {}", wave.cycle_length),
+                    format!("Outbound green band: {}", wave.outbound_band),
+                    format!("Inbound green band: {}", wave.inbound_band),
+                ];
+                for ts in wave.signals {
+                    app.primary.map.incremental_edit_traffic_signal(ts);
+                }
+                Transition::Multi(vec![
+                    Transition::Replace(ShowAbsolute::new_state(
+                        ctx,
+                        app,
+                        self.members.clone(),
+                        self.plan,
+                    )),
+                    Transition::Push(PopupMsg::new_state(ctx, "Green wave", summary)),
+                ])
+            }
+            Err(err) => Transition::Push(PopupMsg::new_state(ctx, "Error", vec![err.to_string()])),
+        }
+    }
+}
+
+impl SimpleState<App> for GreenWaveCorridor {
+    fn on_click(
+        &mut self,
+        ctx: &mut EventCtx,
+        app: &mut App,
+        x: &str,
+        _: &mut Panel,
+    ) -> Transition {
+        match x {
+            "close" => Transition::Replace(ShowAbsolute::new_state(
+                ctx,
+                app,
+                self.members.clone(),
+                self.plan,
+            )),
+            _ => unreachable!(),
+        }
+    }
+
+    fn panel_changed(
+        &mut self,
+        _: &mut EventCtx,
+        _: &mut App,
+        panel: &mut Panel,
+    ) -> Option<Transition> {
+        self.change_cycle_length =
+            panel.is_checked("also pick a common cycle length between 60s and 150s");
+        None
+    }
+
+    fn on_mouseover(&mut self, ctx: &mut EventCtx, app: &mut App) {
+        app.primary.current_selection = app.mouseover_unzoomed_intersections(ctx).filter(|id| {
+            let i = id.as_intersection();
+            self.members.contains(&i) && Some(i) != self.first
+        });
+    }
+
+    fn other_event(&mut self, ctx: &mut EventCtx, app: &mut App) -> Transition {
+        ctx.canvas_movement();
+        if let Some(i) = app.click_on_intersection(ctx, "select signal") {
+            if self.first.is_none() {
+                return Transition::Replace(GreenWaveCorridor::new_state(
+                    ctx,
+                    app,
+                    self.members.clone(),
+                    self.plan,
+                    Some(i),
+                    self.change_cycle_length,
+                ));
+            }
+            return self.coordinate(ctx, app, i);
+        }
+
+        Transition::Keep
+    }
+
+    fn draw(&self, g: &mut GfxCtx, app: &App) {
+        CommonState::draw_osd(g, app);
+
+        g.redraw(&self.labels);
+    }
+}
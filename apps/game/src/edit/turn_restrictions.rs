@@ -0,0 +1,132 @@
+use std::collections::BTreeSet;
+
+use map_model::{EditCmd, IntersectionID, RoadID};
+use widgetry::{
+    EventCtx, GfxCtx, HorizontalAlignment, Key, Line, Outcome, Panel, State, TextExt, Toggle,
+    VerticalAlignment, Widget,
+};
+
+use crate::app::App;
+use crate::app::Transition;
+use crate::edit::{apply_map_edits, check_blackholes};
+
+pub struct TurnRestrictionEditor {
+    id: IntersectionID,
+    panel: Panel,
+    // The label of each checkbox, and the (from, to) movement it bans
+    movements: Vec<(String, (RoadID, RoadID))>,
+}
+
+impl TurnRestrictionEditor {
+    pub fn new_state(ctx: &mut EventCtx, app: &mut App, id: IntersectionID) -> Box<dyn State<App>> {
+        app.primary.current_selection = None;
+
+        let map = &app.primary.map;
+        let i = map.get_i(id);
+        let mut from_roads = BTreeSet::new();
+        for l in &i.incoming_lanes {
+            if map.get_l(*l).lane_type.is_for_moving_vehicles() {
+                from_roads.insert(l.road);
+            }
+        }
+        let mut to_roads = BTreeSet::new();
+        for l in &i.outgoing_lanes {
+            if map.get_l(*l).lane_type.is_for_moving_vehicles() {
+                to_roads.insert(l.road);
+            }
+        }
+        let name = |r: RoadID| {
+            format!(
+                "{} ({})",
+                map.get_r(r).get_name(app.opts.language.as_ref()),
+                r
+            )
+        };
+
+        let banned = map.get_i_turn_restrictions_edit(id).0;
+        let mut movements = Vec::new();
+        let mut col = vec![
+            Line("Turn restrictions editor")
+                .small_heading()
+                .into_widget(ctx),
+            "Check a movement to ban vehicles from making it".text_widget(ctx),
+        ];
+        for from in &from_roads {
+            for to in &to_roads {
+                let label = if from == to {
+                    format!("U-turn on {}", name(*from))
+                } else {
+                    format!("{} to {}", name(*from), name(*to))
+                };
+                col.push(Toggle::checkbox(
+                    ctx,
+                    &label,
+                    None,
+                    banned.contains(&(*from, *to)),
+                ));
+                movements.push((label, (*from, *to)));
+            }
+        }
+        col.push(
+            ctx.style()
+                .btn_solid_primary
+                .text("Finish")
+                .hotkey(Key::Escape)
+                .build_def(ctx),
+        );
+
+        Box::new(Self {
+            id,
+            panel: Panel::new_builder(Widget::col(col))
+                .aligned(HorizontalAlignment::Center, VerticalAlignment::Top)
+                .build(ctx),
+            movements,
+        })
+    }
+}
+
+impl State<App> for TurnRestrictionEditor {
+    fn event(&mut self, ctx: &mut EventCtx, app: &mut App) -> Transition {
+        match self.panel.event(ctx) {
+            Outcome::Clicked(ref x) => match x.as_ref() {
+                "Finish" => {
+                    return Transition::Pop;
+                }
+                _ => unreachable!(),
+            },
+            Outcome::Changed(ref x) => {
+                let (_, movement) = self.movements.iter().find(|(label, _)| label == x).unwrap();
+                let old = app.primary.map.get_i_turn_restrictions_edit(self.id);
+                let mut new = old.clone();
+                if self.panel.is_checked(x) {
+                    new.0.insert(*movement);
+                } else {
+                    new.0.remove(movement);
+                }
+                let cmd = EditCmd::ChangeTurnRestrictions {
+                    i: self.id,
+                    old,
+                    new,
+                };
+                // Banning a movement could strand vehicles somewhere
+                if let Some(err) = check_blackholes(ctx, app, cmd.clone()) {
+                    return Transition::Multi(vec![
+                        Transition::Replace(Self::new_state(ctx, app, self.id)),
+                        Transition::Push(err),
+                    ]);
+                }
+                let mut edits = app.primary.map.get_edits().clone();
+                edits.commands.push(cmd);
+                apply_map_edits(ctx, app, edits);
+                return Transition::Replace(Self::new_state(ctx, app, self.id));
+            }
+            _ => {}
+        }
+
+        Transition::Keep
+    }
+
+    fn draw(&self, g: &mut GfxCtx, _: &App) {
+        self.panel.draw(g);
+    }
+}
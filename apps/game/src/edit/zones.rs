@@ -3,13 +3,15 @@ use std::collections::BTreeSet;
 use enumset::EnumSet;
 use maplit::btreeset;
 
+use geom::{Duration, Time};
 use map_gui::tools::{checkbox_per_mode, intersections_from_roads, ColorDiscrete};
-use map_model::{AccessRestrictions, CommonEndpoint, PathConstraints, RoadID};
+use map_model::{AccessRestrictions, CommonEndpoint, PathConstraints, RoadID, TimeWindow};
 use synthpop::TripMode;
 use widgetry::mapspace::ToggleZoomed;
+use widgetry::tools::PopupMsg;
 use widgetry::{
-    Color, EventCtx, GfxCtx, HorizontalAlignment, Key, Line, Outcome, Panel, State, Text,
-    VerticalAlignment, Widget,
+    Color, EventCtx, GfxCtx, HorizontalAlignment, Key, Line, Outcome, Panel, Spinner, State, Text,
+    TextExt, Toggle, VerticalAlignment, Widget,
 };
 
 use crate::app::{App, Transition};
@@ -35,12 +37,23 @@ impl ZoneEditor {
             // Starting a new zone
             btreeset! { start.id }
         };
-        let allow_through_traffic = start
-            .access_restrictions
-            .allow_through_traffic
+        // The editor only handles one time window. When there is one, the checkboxes describe who
+        // may pass through during it, and everyone may pass through otherwise.
+        let window = start.access_restrictions.time_windows.first();
+        let allow_through_traffic = window
+            .map(|w| w.allow_through_traffic)
+            .unwrap_or(start.access_restrictions.allow_through_traffic)
             .into_iter()
             .map(TripMode::from_constraints)
             .collect();
+        let (window_start, window_end) = window
+            .map(|w| (w.start - Time::START_OF_DAY, w.end - Time::START_OF_DAY))
+            .unwrap_or((
+                Duration::hours(8),
+                Duration::hours(9) + Duration::minutes(30),
+            ));
+
+        let cap = start.access_restrictions.cap_vehicles_per_hour;
 
         let (draw, legend) = draw_zone(ctx, app, &members);
         let orig_members = members.clone();
@@ -53,8 +66,19 @@ impl ZoneEditor {
                     .into_widget(ctx),
                 selector.make_controls(ctx).named("selector"),
                 legend,
-                make_instructions(ctx, &allow_through_traffic).named("instructions"),
+                make_instructions(ctx, &allow_through_traffic, window.is_some())
+                    .named("instructions"),
                 checkbox_per_mode(ctx, app, &allow_through_traffic),
+                Toggle::checkbox(ctx, "only restrict between", None, window.is_some()),
+                Widget::row(vec![
+                    time_of_day_spinner(ctx, "window start", window_start),
+                    "and".text_widget(ctx).centered_vert(),
+                    time_of_day_spinner(ctx, "window end", window_end),
+                ]),
+                Widget::row(vec![
+                    Toggle::checkbox(ctx, "cap vehicles per hour at", None, cap.is_some()),
+                    Spinner::widget(ctx, "cap", (0, 10_000), cap.unwrap_or(100), 10),
+                ]),
                 Widget::custom_row(vec![
                     ctx.style()
                         .btn_solid_primary
@@ -104,8 +128,42 @@ impl State<App> for ZoneEditor {
                     // The original allow_through_traffic always includes this, and there's no way
                     // to exclude it, so stay consistent.
                     allow_through_traffic.insert(PathConstraints::Train);
-                    let new_access_restrictions = AccessRestrictions {
-                        allow_through_traffic,
+                    // Delivery vehicles follow the same rules as cars
+                    if allow_through_traffic.contains(PathConstraints::Car) {
+                        allow_through_traffic.insert(PathConstraints::Truck);
+                    }
+                    let cap_vehicles_per_hour = if self.panel.is_checked("cap vehicles per hour at")
+                    {
+                        Some(self.panel.spinner("cap"))
+                    } else {
+                        None
+                    };
+                    let new_access_restrictions = if self.panel.is_checked("only restrict between")
+                    {
+                        let start: Duration = self.panel.spinner("window start");
+                        let end: Duration = self.panel.spinner("window end");
+                        if start == end {
+                            return Transition::Push(PopupMsg::new_state(
+                                ctx,
+                                "Invalid time window",
+                                vec!["The restrictions must start and end at different times"],
+                            ));
+                        }
+                        AccessRestrictions {
+                            allow_through_traffic: EnumSet::all(),
+                            time_windows: vec![TimeWindow {
+                                start: Time::START_OF_DAY + start,
+                                end: Time::START_OF_DAY + end,
+                                allow_through_traffic,
+                            }],
+                            cap_vehicles_per_hour,
+                        }
+                    } else {
+                        AccessRestrictions {
+                            allow_through_traffic,
+                            time_windows: Vec::new(),
+                            cap_vehicles_per_hour,
+                        }
                     };
                     for r in &self.selector.roads {
                         let old_access_restrictions =
@@ -140,7 +198,11 @@ impl State<App> for ZoneEditor {
                         new_allow_through_traffic.insert(m);
                     }
                 }
-                let instructions = make_instructions(ctx, &new_allow_through_traffic);
+                let instructions = make_instructions(
+                    ctx,
+                    &new_allow_through_traffic,
+                    self.panel.is_checked("only restrict between"),
+                );
                 self.panel.replace(ctx, "instructions", instructions);
                 self.allow_through_traffic = new_allow_through_traffic;
             }
@@ -191,7 +253,11 @@ fn draw_zone(ctx: &mut EventCtx, app: &App, members: &BTreeSet<RoadID>) -> (Togg
     colorer.build(ctx)
 }
 
-fn make_instructions(ctx: &mut EventCtx, allow_through_traffic: &BTreeSet<TripMode>) -> Widget {
+fn make_instructions(
+    ctx: &mut EventCtx,
+    allow_through_traffic: &BTreeSet<TripMode>,
+    during_window: bool,
+) -> Widget {
     if allow_through_traffic == &TripMode::all().into_iter().collect() {
         Text::from(
             "Through-traffic is allowed for everyone, meaning this is just a normal public road. \
@@ -199,8 +265,26 @@ fn make_instructions(ctx: &mut EventCtx, allow_through_traffic: &BTreeSet<TripMo
         )
         .wrap_to_pct(ctx, 30)
         .into_widget(ctx)
+    } else if during_window {
+        Text::from(
+            "Trips may start or end in this zone, but during these hours, through-traffic is only \
+             allowed for:",
+        )
+        .wrap_to_pct(ctx, 30)
+        .into_widget(ctx)
     } else {
         Line("Trips may start or end in this zone, but through-traffic is only allowed for:")
             .into_widget(ctx)
     }
 }
+
+fn time_of_day_spinner(ctx: &EventCtx, label: &str, current: Duration) -> Widget {
+    Spinner::widget_with_custom_rendering(
+        ctx,
+        label,
+        (Duration::ZERO, Duration::hours(24)),
+        current,
+        Duration::minutes(15),
+        Box::new(|d| (Time::START_OF_DAY + d).ampm_tostring()),
+    )
+}
@@ -5,7 +5,7 @@ use map_gui::render::DrawPedestrian;
 use map_model::{BuildingID, LaneID, OffstreetParking, Traversable, SIDEWALK_THICKNESS};
 use sim::{DrawPedestrianInput, PedestrianID, PersonID, TripResult, VehicleType};
 use synthpop::TripMode;
-use widgetry::{Color, EventCtx, Line, Text, TextExt, Widget};
+use widgetry::{Color, EventCtx, Key, Line, Text, TextExt, Widget};
 
 use crate::app::App;
 use crate::info::{header_btns, make_table, make_tabs, Details, Tab};
@@ -113,6 +113,14 @@ fn info_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BuildingI
         rows.push(txt.into_widget(ctx))
     }
 
+    rows.push(
+        ctx.style()
+            .btn_outline
+            .text("Edit land use")
+            .hotkey(Key::E)
+            .build_widget(ctx, format!("edit {}", b.id)),
+    );
+
     if app.opts.dev {
         rows.push(
             ctx.style()
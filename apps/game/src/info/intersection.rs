@@ -316,13 +316,23 @@ fn traffic_signal_body(ctx: &mut EventCtx, app: &App, id: IntersectionID) -> Wid
     let bbox = Polygon::rectangle(zoom * bounds.width(), zoom * bounds.height());
 
     let signal = app.primary.map.get_traffic_signal(id);
+    let plan = signal.plan_at(app.primary.sim.time());
+    let stages = signal.plan_stages(plan);
     {
         let mut txt = Text::new();
-        txt.add_line(Line(format!("{} stages", signal.stages.len())).small_heading());
-        txt.add_line(format!("Signal offset: {}", signal.offset));
+        txt.add_line(Line(format!("{} stages", stages.len())).small_heading());
+        if signal.num_plans() > 1 {
+            txt.add_line(format!(
+                "Timing plan {} of {}, starting at {}",
+                plan + 1,
+                signal.num_plans(),
+                signal.plan_start_time(plan).ampm_tostring()
+            ));
+        }
+        txt.add_line(format!("Signal offset: {}", signal.plan_offset(plan)));
         {
             let mut total = Duration::ZERO;
-            for s in &signal.stages {
+            for s in stages {
                 total += s.stage_type.simple_duration();
             }
             // TODO Say "normally" or something?
@@ -331,7 +341,7 @@ fn traffic_signal_body(ctx: &mut EventCtx, app: &App, id: IntersectionID) -> Wid
         rows.push(txt.into_widget(ctx));
     }
 
-    for (idx, stage) in signal.stages.iter().enumerate() {
+    for (idx, stage) in stages.iter().enumerate() {
         rows.push(
             match stage.stage_type {
                 StageType::Fixed(d) => Line(format!("Stage {}: {}", idx + 1, d)),
@@ -1,3 +1,5 @@
+use enumset::EnumSet;
+
 use abstutil::prettyprint_usize;
 use map_model::{LaneID, PathConstraints};
 use widgetry::{EventCtx, Line, LinePlot, PlotOptions, Series, Text, TextExt, Widget};
@@ -25,34 +27,84 @@ fn info_body(ctx: &EventCtx, app: &App, id: LaneID) -> Widget {
     let mut kv = Vec::new();
 
     if !l.is_walkable() {
-        kv.push(("Type", l.lane_type.describe().to_string()));
+        kv.push(("Type".to_string(), l.lane_type.describe().to_string()));
     }
     if r.is_private() {
-        let mut ban = Vec::new();
-        for p in PathConstraints::all() {
-            if !r.access_restrictions.allow_through_traffic.contains(p) {
-                ban.push(format!("{:?}", p).to_ascii_lowercase());
+        let banned = |allow: EnumSet<PathConstraints>| {
+            PathConstraints::all()
+                .into_iter()
+                .filter(|p| !allow.contains(*p))
+                .map(|p| format!("{:?}", p).to_ascii_lowercase())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let ban = banned(r.access_restrictions.allow_through_traffic);
+        if !ban.is_empty() {
+            kv.push(("No through-traffic for".to_string(), ban));
+        }
+        for window in &r.access_restrictions.time_windows {
+            let ban = banned(window.allow_through_traffic);
+            if !ban.is_empty() {
+                kv.push((
+                    format!(
+                        "From {} to {}, no through-traffic for",
+                        window.start.ampm_tostring(),
+                        window.end.ampm_tostring()
+                    ),
+                    ban,
+                ));
             }
         }
-        if !ban.is_empty() {
-            kv.push(("No through-traffic for", ban.join(", ")));
+        if let Some(cap) = r.access_restrictions.cap_vehicles_per_hour {
+            kv.push(("Vehicle cap".to_string(), format!("{} per hour", cap)));
+            let (rerouted, cancelled) = app
+                .primary
+                .sim
+                .get_analytics()
+                .capped_trips_in_zone(r.get_zone(map).unwrap());
+            kv.push((
+                "Cars turned away so far".to_string(),
+                format!(
+                    "{} rerouted, {} cancelled",
+                    prettyprint_usize(rerouted),
+                    prettyprint_usize(cancelled)
+                ),
+            ));
         }
     }
 
     if l.is_parking() {
         kv.push((
-            "Parking",
+            "Parking".to_string(),
             format!(
                 "{} / {} spots available",
                 app.primary.sim.get_free_onstreet_spots(l.id).len(),
                 l.number_parking_spots(app.primary.map.get_config())
             ),
         ));
+        if let Some(price) = app.primary.sim.current_parking_price(r.id) {
+            kv.push(("Price".to_string(), format!("${:.2} / hour", price)));
+            let revenue = app
+                .primary
+                .sim
+                .get_analytics()
+                .parking_revenue_per_block()
+                .get(&r.id)
+                .cloned()
+                .unwrap_or(0.0);
+            kv.push((
+                "Revenue on this block so far".to_string(),
+                format!("${:.2}", revenue),
+            ));
+        }
     } else {
-        kv.push(("Speed limit", r.speed_limit.to_string(&app.opts.units)));
+        kv.push((
+            "Speed limit".to_string(),
+            r.speed_limit.to_string(&app.opts.units),
+        ));
     }
 
-    kv.push(("Length", l.length().to_string(&app.opts.units)));
+    kv.push(("Length".to_string(), l.length().to_string(&app.opts.units)));
 
     rows.extend(make_table(ctx, kv));
 
@@ -21,7 +21,7 @@ use widgetry::{
 use crate::app::{App, Transition};
 use crate::common::{color_for_agent_type, Warping};
 use crate::debug::path_counter::PathCounter;
-use crate::edit::{EditMode, RouteEditor};
+use crate::edit::{BuildingEditor, EditMode, RouteEditor};
 use crate::layer::PANEL_PLACEMENT;
 use crate::sandbox::{dashboards, GameplayMode, SandboxMode, TimeWarpScreen};
 
@@ -602,6 +602,22 @@ impl InfoPanel {
                             )),
                         ])),
                     )
+                } else if let Some(x) = action.strip_prefix("edit Building #") {
+                    (
+                        false,
+                        Some(Transition::Multi(vec![
+                            Transition::Push(EditMode::new_state(
+                                ctx,
+                                app,
+                                ctx_actions.gameplay_mode(),
+                            )),
+                            Transition::Push(BuildingEditor::new_state(
+                                ctx,
+                                app,
+                                BuildingID(x.parse::<usize>().unwrap()),
+                            )),
+                        ])),
+                    )
                 } else if action == "Explore demand across all traffic signals" {
                     (
                         false,
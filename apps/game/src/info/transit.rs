@@ -129,10 +129,13 @@ fn bus_status_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: Car
         Tab::TransitRoute(route.id),
     );
 
+    let capacity = app.primary.sim.transit_capacity(id.vehicle_type).unwrap();
     rows.push(
         Line(format!(
-            "Currently has {} passengers",
+            "Currently has {} passengers ({} seats, {} total capacity)",
             app.primary.sim.num_transit_passengers(id),
+            capacity.seated,
+            capacity.total(),
         ))
         .into_widget(ctx),
     );
@@ -219,6 +222,7 @@ fn route_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: TransitR
     let mut boardings: Counter<TransitStopID> = Counter::new();
     let mut alightings: Counter<TransitStopID> = Counter::new();
     let mut waiting: Counter<TransitStopID> = Counter::new();
+    let mut denied: Counter<TransitStopID> = Counter::new();
     for ts in &route.stops {
         if let Some(list) = app.primary.sim.get_analytics().passengers_boarding.get(ts) {
             for (_, r, _) in list {
@@ -235,6 +239,14 @@ fn route_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: TransitR
             }
         }
 
+        if let Some(list) = app.primary.sim.get_analytics().denied_boardings.get(ts) {
+            for (_, r, count) in list {
+                if *r == id {
+                    denied.add(*ts, *count);
+                }
+            }
+        }
+
         for (_, r, _, _) in app.primary.sim.get_people_waiting_at_stop(*ts) {
             if *r == id {
                 waiting.inc(*ts);
@@ -246,10 +258,11 @@ fn route_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: TransitR
         Text::from_all(vec![
             Line("Total"),
             Line(format!(
-                ": {} boardings, {} alightings, {} currently waiting",
+                ": {} boardings, {} alightings, {} currently waiting, {} denied boardings",
                 prettyprint_usize(boardings.sum()),
                 prettyprint_usize(alightings.sum()),
-                prettyprint_usize(waiting.sum())
+                prettyprint_usize(waiting.sum()),
+                prettyprint_usize(denied.sum())
             ))
             .secondary(),
         ])
@@ -270,6 +283,7 @@ fn route_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: TransitR
         ]));
         details.warpers.insert(name, ID::Intersection(i.id));
     }
+    let max_loads = app.primary.sim.get_analytics().max_load_per_segment(id);
     for (idx, ts) in route.stops.iter().enumerate() {
         let ts = map.get_ts(*ts);
         let name = format!("Stop {}: {}", idx + 1, ts.name);
@@ -281,10 +295,13 @@ fn route_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: TransitR
             Text::from_all(vec![
                 Line(&ts.name),
                 Line(format!(
-                    ": {} boardings, {} alightings, {} currently waiting",
+                    ": {} boardings, {} alightings, {} currently waiting, {} denied boardings, \
+                     peak load {}",
                     prettyprint_usize(boardings.get(ts.id)),
                     prettyprint_usize(alightings.get(ts.id)),
-                    prettyprint_usize(waiting.get(ts.id))
+                    prettyprint_usize(waiting.get(ts.id)),
+                    prettyprint_usize(denied.get(ts.id)),
+                    prettyprint_usize(max_loads.get(&ts.id).cloned().unwrap_or(0))
                 ))
                 .secondary(),
             ])
@@ -316,6 +333,20 @@ fn route_body(ctx: &mut EventCtx, app: &App, details: &mut Details, id: TransitR
                 .build_widget(ctx, format!("edit {}", route.id)),
         );
         rows.push(describe_schedule(route).into_widget(ctx));
+        if let Some(pct) = app
+            .primary
+            .sim
+            .get_analytics()
+            .on_time_performance(route.id)
+        {
+            rows.push(
+                Line(format!(
+                    "{}% of departures on time so far",
+                    (pct * 100.0).round()
+                ))
+                .into_widget(ctx),
+            );
+        }
     }
 
     // Draw the route, label stops, and show location of buses
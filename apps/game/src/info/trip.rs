@@ -434,7 +434,7 @@ fn describe_problems(
                 txt.into_widget(ctx),
             ])
         }
-        TripMode::Bike => {
+        TripMode::Bike | TripMode::Micromobility => {
             let mut count_complex_intersections = 0;
             let mut count_overtakes = 0;
             let empty = Vec::new();
@@ -716,11 +716,17 @@ fn make_timeline(
                     TripPhaseType::Driving => "system/assets/timeline/driving.svg",
                     TripPhaseType::Walking => "system/assets/timeline/walking.svg",
                     TripPhaseType::Biking => "system/assets/timeline/biking.svg",
-                    TripPhaseType::Parking => "system/assets/timeline/parking.svg",
+                    TripPhaseType::Parking | TripPhaseType::SearchingForParking => {
+                        "system/assets/timeline/parking.svg"
+                    }
                     TripPhaseType::WaitingForBus(_, _) => {
                         "system/assets/timeline/waiting_for_bus.svg"
                     }
                     TripPhaseType::RidingBus(_, _, _) => "system/assets/timeline/riding_bus.svg",
+                    TripPhaseType::WaitingForRideHail => {
+                        "system/assets/timeline/waiting_for_bus.svg"
+                    }
+                    TripPhaseType::RidingRideHail(_) => "system/assets/timeline/driving.svg",
                     TripPhaseType::Cancelled | TripPhaseType::Finished => unreachable!(),
                     TripPhaseType::DelayedStart => "system/assets/timeline/delayed_start.svg",
                 },
@@ -0,0 +1,170 @@
+use abstutil::Counter;
+use geom::Time;
+use map_gui::tools::{ColorLegend, ColorNetwork};
+use map_gui::ID;
+use sim::Emissions;
+use widgetry::mapspace::ToggleZoomed;
+use widgetry::{Choice, EventCtx, GfxCtx, Line, Outcome, Panel, Text, TextExt, Widget};
+
+use crate::app::App;
+use crate::layer::{header, Layer, LayerOutcome, PANEL_PLACEMENT};
+
+pub struct VehicleEmissions {
+    time: Time,
+    pollutant: Pollutant,
+    tooltip: Option<Text>,
+    draw: ToggleZoomed,
+    panel: Panel,
+}
+
+impl Layer for VehicleEmissions {
+    fn name(&self) -> Option<&'static str> {
+        Some("emissions")
+    }
+    fn event(&mut self, ctx: &mut EventCtx, app: &mut App) -> Option<LayerOutcome> {
+        let mut recalc_tooltip = false;
+        if app.primary.sim.time() != self.time {
+            *self = VehicleEmissions::new(ctx, app, self.pollutant);
+            recalc_tooltip = true;
+        }
+
+        // Show a tooltip with the amount, only when unzoomed
+        if ctx.canvas.is_unzoomed() {
+            if ctx.redo_mouseover() || recalc_tooltip {
+                self.tooltip = None;
+                let analytics = app.primary.sim.get_analytics();
+                let emissions = match app.mouseover_unzoomed_roads_and_intersections(ctx) {
+                    Some(ID::Road(r)) => analytics.road_emissions.get(&r),
+                    Some(ID::Intersection(i)) => analytics.intersection_emissions.get(&i),
+                    _ => None,
+                };
+                if let Some(emissions) = emissions {
+                    self.tooltip = Some(Text::from(self.pollutant.describe(emissions)));
+                }
+            }
+        } else {
+            self.tooltip = None;
+        }
+
+        match self.panel.event(ctx) {
+            Outcome::Clicked(x) => match x.as_ref() {
+                "close" => {
+                    return Some(LayerOutcome::Close);
+                }
+                _ => unreachable!(),
+            },
+            Outcome::Changed(_) => {
+                return Some(LayerOutcome::Replace(Box::new(VehicleEmissions::new(
+                    ctx,
+                    app,
+                    self.panel.dropdown_value("pollutant"),
+                ))));
+            }
+            _ => {}
+        }
+        None
+    }
+    fn draw(&self, g: &mut GfxCtx, _: &App) {
+        self.panel.draw(g);
+        self.draw.draw(g);
+        if let Some(ref txt) = self.tooltip {
+            g.draw_mouse_tooltip(txt.clone());
+        }
+    }
+    fn draw_minimap(&self, g: &mut GfxCtx) {
+        g.redraw(&self.draw.unzoomed);
+    }
+}
+
+impl VehicleEmissions {
+    pub fn new(ctx: &mut EventCtx, app: &App, pollutant: Pollutant) -> VehicleEmissions {
+        let analytics = app.primary.sim.get_analytics();
+
+        let mut txt = Text::from(Line("This counts all vehicles since midnight").secondary());
+        txt.add_line(format!(
+            "Total: {}",
+            pollutant.describe(&analytics.total_emissions())
+        ));
+        if app.has_prebaked().is_some() {
+            txt.add_line(format!(
+                "Before proposal: {}",
+                pollutant.describe(
+                    &app.prebaked()
+                        .total_emissions_by_time(app.primary.sim.time())
+                )
+            ));
+        }
+
+        let panel = Panel::new_builder(Widget::col(vec![
+            header(ctx, "Vehicle emissions"),
+            txt.wrap_to_pct(ctx, 15).into_widget(ctx),
+            Widget::row(vec![
+                "Show:".text_widget(ctx).centered_vert(),
+                Widget::dropdown(
+                    ctx,
+                    "pollutant",
+                    pollutant,
+                    vec![
+                        Choice::new("CO2", Pollutant::CO2),
+                        Choice::new("NOx", Pollutant::NOx),
+                        Choice::new("fuel", Pollutant::Fuel),
+                        Choice::new("electricity", Pollutant::Electricity),
+                    ],
+                ),
+            ]),
+            ColorLegend::gradient(ctx, &app.cs.good_to_bad_red, vec!["0", "highest"]),
+        ]))
+        .aligned_pair(PANEL_PLACEMENT)
+        .build(ctx);
+
+        let mut roads = Counter::new();
+        for (r, emissions) in &analytics.road_emissions {
+            roads.add(*r, pollutant.rank(emissions));
+        }
+        let mut intersections = Counter::new();
+        for (i, emissions) in &analytics.intersection_emissions {
+            intersections.add(*i, pollutant.rank(emissions));
+        }
+        let mut colorer = ColorNetwork::new(app);
+        colorer.ranked_roads(roads, &app.cs.good_to_bad_red);
+        colorer.ranked_intersections(intersections, &app.cs.good_to_bad_red);
+
+        VehicleEmissions {
+            time: app.primary.sim.time(),
+            pollutant,
+            tooltip: None,
+            draw: colorer.build(ctx),
+            panel,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pollutant {
+    CO2,
+    NOx,
+    Fuel,
+    Electricity,
+}
+
+impl Pollutant {
+    fn describe(self, emissions: &Emissions) -> String {
+        match self {
+            Pollutant::CO2 => format!("{:.1} kg CO2", emissions.co2_grams / 1000.0),
+            Pollutant::NOx => format!("{:.1} g NOx", emissions.nox_grams),
+            Pollutant::Fuel => format!("{:.1} L of fuel", emissions.fuel_liters),
+            Pollutant::Electricity => format!("{:.1} kWh", emissions.energy_kwh),
+        }
+    }
+
+    // Only the ordering matters for coloring, so round to a fine enough integer unit
+    fn rank(self, emissions: &Emissions) -> usize {
+        let amount = match self {
+            Pollutant::CO2 => emissions.co2_grams,
+            Pollutant::NOx => emissions.nox_grams * 1000.0,
+            Pollutant::Fuel => emissions.fuel_liters * 1000.0,
+            Pollutant::Electricity => emissions.energy_kwh * 1000.0,
+        };
+        amount.max(0.0).round() as usize
+    }
+}
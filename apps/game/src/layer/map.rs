@@ -203,7 +203,10 @@ impl Static {
     pub fn edits(ctx: &mut EventCtx, app: &App) -> Static {
         let mut colorer = ColorDiscrete::new(
             app,
-            vec![("modified road/intersection", app.cs.edits_layer)],
+            vec![
+                ("modified road/intersection", app.cs.edits_layer),
+                ("modified building", app.cs.edits_layer),
+            ],
         );
 
         let edits = app.primary.map.get_edits();
@@ -217,6 +220,9 @@ impl Static {
         for i in edits.original_intersections.keys() {
             colorer.add_i(*i, "modified road/intersection");
         }
+        for b in edits.original_buildings.keys() {
+            colorer.add_b(*b, "modified building");
+        }
 
         Static::new(
             ctx,
@@ -229,6 +235,10 @@ impl Static {
                     "{} intersections changed",
                     edits.original_intersections.len()
                 )),
+                Line(format!(
+                    "{} buildings changed",
+                    edits.original_buildings.len()
+                )),
             ])
             .into_widget(ctx),
         )
@@ -9,8 +9,10 @@ use crate::app::{App, Transition};
 use crate::sandbox::dashboards;
 
 pub mod elevation;
+mod emissions;
 pub mod favorites;
 pub mod map;
+mod noise;
 mod pandemic;
 mod parking;
 mod population;
@@ -110,6 +112,8 @@ impl PickLayer {
                     btn("traffic jams", Key::J),
                     btn("cycling activity", Key::B),
                     btn("pedestrian crowding", Key::C),
+                    btn("emissions", Key::I),
+                    btn("noise", Key::W),
                 ]),
                 Widget::col(vec![
                     "Map".text_widget(ctx),
@@ -133,6 +137,7 @@ impl PickLayer {
                     btn("steep streets", Key::V),
                     btn("elevation", Key::G),
                     btn("parking efficiency", Key::O),
+                    btn("cruising for parking", Key::Q),
                     btn("blackholes", Key::L),
                     btn("problem map", Key::K),
                     btn("high stress", Key::H),
@@ -182,6 +187,17 @@ impl State<App> for PickLayer {
                 "pedestrian crowding" => {
                     app.primary.layer = Some(Box::new(traffic::PedestrianCrowding::new(ctx, app)));
                 }
+                "emissions" => {
+                    app.primary.layer = Some(Box::new(emissions::VehicleEmissions::new(
+                        ctx,
+                        app,
+                        emissions::Pollutant::CO2,
+                    )));
+                }
+                "noise" => {
+                    app.primary.layer =
+                        Some(Box::new(noise::Noise::new(ctx, app, noise::Metric::Lden)));
+                }
                 "steep streets" => {
                     app.primary.layer = Some(Box::new(elevation::SteepStreets::new(ctx, app)));
                 }
@@ -221,6 +237,9 @@ impl State<App> for PickLayer {
                 "parking efficiency" => {
                     app.primary.layer = Some(Box::new(parking::Efficiency::new(ctx, app)));
                 }
+                "cruising for parking" => {
+                    app.primary.layer = Some(Box::new(parking::Cruising::new(ctx, app)));
+                }
                 "population map" => {
                     app.primary.layer = Some(Box::new(population::PopulationMap::new(
                         ctx,
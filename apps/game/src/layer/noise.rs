@@ -0,0 +1,140 @@
+use abstutil::prettyprint_usize;
+use geom::Time;
+use map_gui::tools::{ColorLegend, ColorNetwork};
+use sim::{NoiseEstimate, LDEN_THRESHOLD, LNIGHT_THRESHOLD};
+use widgetry::mapspace::ToggleZoomed;
+use widgetry::{Choice, EventCtx, GfxCtx, Line, Outcome, Panel, Text, TextExt, Widget};
+
+use crate::app::App;
+use crate::layer::{header, Layer, LayerOutcome, PANEL_PLACEMENT};
+
+// Buildings are colored on a scale between these levels
+const QUIETEST: f64 = 40.0;
+const LOUDEST: f64 = 80.0;
+
+pub struct Noise {
+    time: Time,
+    metric: Metric,
+    draw: ToggleZoomed,
+    panel: Panel,
+}
+
+impl Layer for Noise {
+    fn name(&self) -> Option<&'static str> {
+        Some("noise")
+    }
+    fn event(&mut self, ctx: &mut EventCtx, app: &mut App) -> Option<LayerOutcome> {
+        if app.primary.sim.time() != self.time {
+            *self = Noise::new(ctx, app, self.metric);
+        }
+
+        match self.panel.event(ctx) {
+            Outcome::Clicked(x) => match x.as_ref() {
+                "close" => {
+                    return Some(LayerOutcome::Close);
+                }
+                _ => unreachable!(),
+            },
+            Outcome::Changed(_) => {
+                return Some(LayerOutcome::Replace(Box::new(Noise::new(
+                    ctx,
+                    app,
+                    self.panel.dropdown_value("metric"),
+                ))));
+            }
+            _ => {}
+        }
+        None
+    }
+    fn draw(&self, g: &mut GfxCtx, _: &App) {
+        self.panel.draw(g);
+        self.draw.draw(g);
+    }
+    fn draw_minimap(&self, g: &mut GfxCtx) {
+        g.redraw(&self.draw.unzoomed);
+    }
+}
+
+impl Noise {
+    pub fn new(ctx: &mut EventCtx, app: &App, metric: Metric) -> Noise {
+        let map = &app.primary.map;
+        let noise = NoiseEstimate::new(map, app.primary.sim.get_analytics());
+        let exposure = noise.exposure(map);
+
+        let mut colorer = ColorNetwork::new(app);
+        let hour = app.primary.sim.time().get_hours() % 24;
+        for (b, levels) in &noise.buildings {
+            let level = match metric {
+                Metric::Lden => levels.lden,
+                Metric::Lnight => levels.lnight,
+                Metric::CurrentHour => levels.hourly[hour],
+            };
+            let pct = ((level - QUIETEST) / (LOUDEST - QUIETEST)).clamp(0.0, 1.0);
+            colorer.add_b(*b, app.cs.good_to_bad_red.eval(pct));
+        }
+
+        let mut txt = Text::from(
+            Line("Estimated from vehicle traffic since midnight, ignoring screening by buildings")
+                .secondary(),
+        );
+        txt.add_line(format!(
+            "{} of {} residents exposed above {} dB Lden",
+            prettyprint_usize(exposure.residents_above_lden_threshold),
+            prettyprint_usize(exposure.residents),
+            LDEN_THRESHOLD
+        ));
+        txt.add_line(format!(
+            "{} of {} residents exposed above {} dB Lnight",
+            prettyprint_usize(exposure.residents_above_lnight_threshold),
+            prettyprint_usize(exposure.residents),
+            LNIGHT_THRESHOLD
+        ));
+        txt.add_line(format!(
+            "Average Lden at home: {:.1} dB",
+            exposure.mean_lden_per_resident
+        ));
+
+        let panel = Panel::new_builder(Widget::col(vec![
+            header(ctx, "Traffic noise"),
+            txt.wrap_to_pct(ctx, 15).into_widget(ctx),
+            Widget::row(vec![
+                "Show:".text_widget(ctx).centered_vert(),
+                Widget::dropdown(
+                    ctx,
+                    "metric",
+                    metric,
+                    vec![
+                        Choice::new("day-evening-night level", Metric::Lden),
+                        Choice::new("night level", Metric::Lnight),
+                        Choice::new("current hour", Metric::CurrentHour),
+                    ],
+                ),
+            ]),
+            ColorLegend::gradient(
+                ctx,
+                &app.cs.good_to_bad_red,
+                vec![
+                    format!("{} dB", QUIETEST),
+                    format!("{} dB", (QUIETEST + LOUDEST) / 2.0),
+                    format!("{} dB", LOUDEST),
+                ],
+            ),
+        ]))
+        .aligned_pair(PANEL_PLACEMENT)
+        .build(ctx);
+
+        Noise {
+            time: app.primary.sim.time(),
+            metric,
+            draw: colorer.build(ctx),
+            panel,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Metric {
+    Lden,
+    Lnight,
+    CurrentHour,
+}
@@ -400,3 +400,92 @@ impl Efficiency {
         }
     }
 }
+
+pub struct Cruising {
+    time: Time,
+    draw: ToggleZoomed,
+    panel: Panel,
+}
+
+impl Layer for Cruising {
+    fn name(&self) -> Option<&'static str> {
+        Some("cruising for parking")
+    }
+    fn event(&mut self, ctx: &mut EventCtx, app: &mut App) -> Option<LayerOutcome> {
+        if app.primary.sim.time() != self.time {
+            *self = Cruising::new(ctx, app);
+        }
+
+        if let Outcome::Clicked(x) = self.panel.event(ctx) {
+            match x.as_ref() {
+                "close" => {
+                    return Some(LayerOutcome::Close);
+                }
+                _ => unreachable!(),
+            }
+        }
+        None
+    }
+    fn draw(&self, g: &mut GfxCtx, _: &App) {
+        self.panel.draw(g);
+        self.draw.draw(g);
+    }
+    fn draw_minimap(&self, g: &mut GfxCtx) {
+        g.redraw(&self.draw.unzoomed);
+    }
+}
+
+impl Cruising {
+    pub fn new(ctx: &mut EventCtx, app: &App) -> Cruising {
+        let analytics = app.primary.sim.get_analytics();
+
+        let mut total_time = Duration::ZERO;
+        let mut total_dist = Distance::ZERO;
+        for (_, _, _, duration, dist) in &analytics.parking_searches {
+            total_time += *duration;
+            total_dist += *dist;
+        }
+        let mut txt = Text::from(
+            Line("This counts all drivers who parked after searching, since midnight").secondary(),
+        );
+        txt.add_line(format!(
+            "{} searches so far: {} and {} total",
+            prettyprint_usize(analytics.parking_searches.len()),
+            total_time,
+            total_dist.to_string(&app.opts.units)
+        ));
+
+        let panel = Panel::new_builder(Widget::col(vec![
+            header(ctx, "Cruising for parking"),
+            txt.wrap_to_pct(ctx, 15).into_widget(ctx),
+            Line("Distance cruised per road").into_widget(ctx),
+            ColorLegend::gradient(ctx, &app.cs.good_to_bad_red, vec!["0", "highest"]),
+            Line("Average search near destinations (minutes)").into_widget(ctx),
+            ColorLegend::gradient(ctx, &app.cs.good_to_bad_red, vec!["0", "3", "6", "10+"]),
+        ]))
+        .aligned_pair(PANEL_PLACEMENT)
+        .build(ctx);
+
+        let mut roads = Counter::new();
+        for (r, dist) in &analytics.cruising_per_road {
+            roads.add(*r, dist.inner_meters() as usize);
+        }
+        let mut colorer = ColorNetwork::new(app);
+        colorer.ranked_roads(roads, &app.cs.good_to_bad_red);
+        for (b, (count, duration, _)) in analytics.parking_search_per_building() {
+            let avg = duration / (count as f64);
+            colorer.add_b(
+                b,
+                app.cs
+                    .good_to_bad_red
+                    .eval((avg / Duration::minutes(10)).min(1.0)),
+            );
+        }
+
+        Cruising {
+            time: app.primary.sim.time(),
+            draw: colorer.build(ctx),
+            panel,
+        }
+    }
+}
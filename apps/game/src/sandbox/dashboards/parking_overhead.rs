@@ -145,7 +145,7 @@ fn produce_raw_data(app: &App) -> Vec<Entry> {
                     TripPhaseType::Walking => {
                         walking_duration += dt;
                     }
-                    TripPhaseType::Parking => {
+                    TripPhaseType::Parking | TripPhaseType::SearchingForParking => {
                         parking_duration += dt;
                     }
                     _ => {}
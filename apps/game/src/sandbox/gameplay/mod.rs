@@ -177,7 +177,11 @@ impl GameplayMode {
     pub fn allows(&self, edits: &MapEdits) -> bool {
         for cmd in &edits.commands {
             match cmd {
-                EditCmd::ChangeRoad { .. } => {
+                EditCmd::ChangeRoad { .. }
+                | EditCmd::ChangeTurnRestrictions { .. }
+                | EditCmd::CreateRoad { .. }
+                | EditCmd::SplitRoad { .. }
+                | EditCmd::DeleteRoad { .. } => {
                     if !self.can_edit_roads() {
                         return false;
                     }
@@ -197,7 +201,7 @@ impl GameplayMode {
                         return false;
                     }
                 }
-                EditCmd::ChangeRouteSchedule { .. } => {}
+                EditCmd::ChangeRouteSchedule { .. } | EditCmd::ChangeBuilding { .. } => {}
             }
         }
         true
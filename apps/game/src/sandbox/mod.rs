@@ -8,7 +8,7 @@ use map_gui::options::OptionsPanel;
 use map_gui::render::{unzoomed_agent_radius, UnzoomedAgents};
 use map_gui::tools::{Minimap, TurnExplorer};
 use map_gui::{AppLike, ID};
-use sim::Analytics;
+use sim::{Analytics, ScenarioGenerator};
 use synthpop::Scenario;
 use widgetry::tools::{ChooseSomething, FileLoader, FutureLoader, URLManager};
 use widgetry::{lctrl, Choice, EventCtx, GfxCtx, Key, Outcome, Panel, State, UpdateType};
@@ -561,6 +561,12 @@ impl State<App> for SandboxLoader {
                                 scenario = m.apply(&app.primary.map, scenario, &mut rng);
                             }
                         }
+                        scenario = ScenarioGenerator::match_building_edits(
+                            &app.primary.map,
+                            scenario,
+                            &mut rng,
+                            timer,
+                        );
 
                         app.primary
                             .sim
@@ -224,6 +224,24 @@ enum Command {
         #[structopt()]
         scenario_path: String,
     },
+    /// Repeatedly simulate a scenario, rerouting some driving trips each time to avoid observed
+    /// congestion, until reaching an approximate user equilibrium. The converged costs are saved,
+    /// and can be used in later simulations with `--traffic_assignment`.
+    #[structopt(name = "assign-traffic")]
+    AssignTraffic {
+        /// The path to a scenario file
+        #[structopt()]
+        scenario_path: String,
+        /// Give up after simulating this many days
+        #[structopt(long, default_value = "10")]
+        max_iterations: usize,
+        /// After each iteration, this fraction of driving trips switch routes
+        #[structopt(long, default_value = "0.2")]
+        reroute_fraction: f64,
+        /// Stop once the relative gap drops to this
+        #[structopt(long, default_value = "0.01")]
+        target_relative_gap: f64,
+    },
 }
 
 // See https://github.com/TeXitoi/structopt/issues/94
@@ -335,6 +353,19 @@ async fn main() -> Result<()> {
         Command::RegenerateEverythingExternally => regenerate_everything_externally()?,
         Command::Import { job } => job.run(&mut Timer::new("import one city")).await,
         Command::PrebakeScenario { scenario_path } => prebake_scenario(scenario_path),
+        Command::AssignTraffic {
+            scenario_path,
+            max_iterations,
+            reroute_fraction,
+            target_relative_gap,
+        } => assign_traffic(
+            scenario_path,
+            sim::AssignmentOptions {
+                max_iterations,
+                reroute_fraction,
+                target_relative_gap,
+            },
+        )?,
     }
     Ok(())
 }
@@ -418,6 +449,20 @@ fn prebake_scenario(path: String) {
     sim::prebake::prebake(&map, scenario, &mut timer);
 }
 
+fn assign_traffic(path: String, opts: sim::AssignmentOptions) -> Result<()> {
+    let mut timer = Timer::new("assign traffic");
+    let scenario: synthpop::Scenario = abstio::must_read_object(path, &mut timer);
+    let map = map_model::Map::load_synchronously(scenario.map_name.path(), &mut timer);
+    let assignment = sim::TrafficAssignment::new(&map, &scenario, &opts, &mut timer)?;
+    assignment.save();
+    println!(
+        "Relative gap after {} iterations: {:.4}",
+        assignment.relative_gaps.len(),
+        assignment.relative_gap().unwrap_or(0.0)
+    );
+    Ok(())
+}
+
 fn driving_side(drive_on_left: bool) -> map_model::DrivingSide {
     if drive_on_left {
         map_model::DrivingSide::Left
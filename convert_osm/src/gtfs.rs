@@ -1,13 +1,15 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use fs_err::File;
 use serde::Deserialize;
 
 use abstutil::MultiMap;
-use geom::{LonLat, PolyLine, Pt2D};
+use geom::{LonLat, PolyLine, Pt2D, Time};
 use kml::{ExtraShape, ExtraShapes};
-use raw_map::{RawMap, RawTransitRoute, RawTransitStop, RawTransitType};
+use raw_map::{
+    RawMap, RawStopTime, RawTransitRoute, RawTransitRun, RawTransitStop, RawTransitType,
+};
 
 pub fn import(map: &mut RawMap) -> Result<()> {
     // Collect metadata about routes
@@ -34,9 +36,18 @@ pub fn import(map: &mut RawMap) -> Result<()> {
             shape: PolyLine::dummy(),
             stops: Vec::new(),
             route_type,
+            schedule: Vec::new(),
         });
     }
 
+    // Only keep trips running on a typical weekday
+    let weekday_services = read_weekday_services(map)?;
+    if let Some(ref services) = weekday_services {
+        if services.is_empty() {
+            warn!("No services run on the chosen weekday, so no transit will be imported");
+        }
+    }
+
     // Map route_id to shape_id
     let mut route_to_shapes = MultiMap::new();
     // Map (route_id, shape_id) to trip_id
@@ -45,6 +56,11 @@ pub fn import(map: &mut RawMap) -> Result<()> {
         .deserialize()
     {
         let rec: Trip = rec?;
+        if let Some(ref services) = weekday_services {
+            if !services.contains(&rec.service_id) {
+                continue;
+            }
+        }
         route_to_shapes.insert(rec.route_id.clone(), rec.shape_id.clone());
         route_and_shape_to_trips.insert((rec.route_id, rec.shape_id), rec.trip_id);
     }
@@ -72,7 +88,7 @@ pub fn import(map: &mut RawMap) -> Result<()> {
     for mut route in map.transit_routes.drain(..) {
         let shape_ids = route_to_shapes.get(RouteID(route.gtfs_id.clone()));
         if shape_ids.is_empty() {
-            warn!("Route {} has no shape", route.gtfs_id);
+            warn!("Route {} has no weekday trips with a shape", route.gtfs_id);
             continue;
         }
         if shape_ids.len() > 1 {
@@ -105,36 +121,68 @@ pub fn import(map: &mut RawMap) -> Result<()> {
     }
     map.transit_routes = transit_routes;
 
-    // For now, every route uses exactly one trip ID, and there's no schedule. Just pick an
-    // arbitrary trip per route.
+    // Every route uses the stops from one arbitrary trip. Other trips with the same shape become
+    // the schedule, as long as they serve exactly the same stops.
     let mut route_to_trip = HashMap::new();
+    let mut scheduled_trips = HashSet::new();
     for (route_id, shape_id) in &route_to_shape {
         let trips = route_and_shape_to_trips.get((route_id.clone(), shape_id.clone()));
-        if let Some(trip_id) = trips.into_iter().next() {
-            route_to_trip.insert(route_id.clone(), trip_id);
+        if let Some(trip_id) = trips.iter().next() {
+            route_to_trip.insert(route_id.clone(), trip_id.clone());
         }
+        scheduled_trips.extend(trips.iter().cloned());
     }
 
-    // Scrape the trip ID -> (stop ID, sequence number)
-    let mut trip_to_stops: HashMap<TripID, Vec<(StopID, usize)>> = HashMap::new();
+    // Scrape the trip ID -> stop times. This file is huge, so only keep the trips we need.
+    let mut trip_to_stops: HashMap<TripID, Vec<StopTime>> = HashMap::new();
     for rec in
         csv::Reader::from_reader(File::open(map.name.city.input_path("gtfs/stop_times.txt"))?)
             .deserialize()
     {
         let rec: StopTime = rec?;
-        trip_to_stops
-            .entry(rec.trip_id)
-            .or_insert_with(Vec::new)
-            .push((rec.stop_id, rec.stop_sequence));
+        if scheduled_trips.contains(&rec.trip_id) {
+            trip_to_stops
+                .entry(rec.trip_id.clone())
+                .or_insert_with(Vec::new)
+                .push(rec);
+        }
+    }
+    for stops in trip_to_stops.values_mut() {
+        stops.sort_by_key(|st| st.stop_sequence);
     }
 
-    // Assign the stops for every route
+    // Assign the stops and schedule for every route
     let mut stop_ids = HashSet::new();
     for route in &mut map.transit_routes {
-        let trip_id = route_to_trip[&RouteID(route.gtfs_id.clone())];
-        let mut stops = trip_to_stops.remove(&trip_id).unwrap_or_else(Vec::new);
-        stops.sort_by_key(|(_, seq)| *seq);
-        for (stop_id, _) in stops {
+        let route_id = RouteID(route.gtfs_id.clone());
+        let stops: Vec<StopID> = trip_to_stops
+            .get(&route_to_trip[&route_id])
+            .map(|times| times.iter().map(|st| st.stop_id.clone()).collect())
+            .unwrap_or_else(Vec::new);
+
+        for trip_id in
+            route_and_shape_to_trips.get((route_id.clone(), route_to_shape[&route_id].clone()))
+        {
+            let times = match trip_to_stops.get(trip_id) {
+                Some(times) => times,
+                None => continue,
+            };
+            if !times.iter().map(|st| &st.stop_id).eq(stops.iter()) {
+                continue;
+            }
+            match make_run(trip_id, times) {
+                Ok(run) => route.schedule.push(run),
+                Err(err) => warn!(
+                    "Skipping part of route {}'s schedule: {}",
+                    route.gtfs_id, err
+                ),
+            }
+        }
+        route
+            .schedule
+            .sort_by_key(|run| run.stop_times[0].departure);
+
+        for stop_id in stops {
             route.stops.push(stop_id.0.clone());
             stop_ids.insert(stop_id);
         }
@@ -163,10 +211,21 @@ pub fn import(map: &mut RawMap) -> Result<()> {
     // Make sure all of the stops are valid and used by some route
     let mut used_stops = HashSet::new();
     for route in &mut map.transit_routes {
-        route.stops.retain(|stop_id| {
-            used_stops.insert(stop_id.clone());
-            map.transit_stops.contains_key(stop_id)
-        });
+        let keep: Vec<bool> = route
+            .stops
+            .iter()
+            .map(|stop_id| {
+                used_stops.insert(stop_id.clone());
+                map.transit_stops.contains_key(stop_id)
+            })
+            .collect();
+        // Keep the schedule lined up with the stops
+        let mut iter = keep.iter();
+        route.stops.retain(|_| *iter.next().unwrap());
+        for run in &mut route.schedule {
+            let mut iter = keep.iter();
+            run.stop_times.retain(|_| *iter.next().unwrap());
+        }
     }
     map.transit_routes.retain(|route| !route.stops.is_empty());
     map.transit_stops
@@ -179,6 +238,183 @@ pub fn import(map: &mut RawMap) -> Result<()> {
     Ok(())
 }
 
+/// Returns the services that run on one typical weekday: the first Wednesday on or after the feed
+/// begins. Both the regular calendar and the exceptions in calendar_dates.txt are used, and either
+/// file may be missing. If both are, returns None, meaning every trip runs.
+fn read_weekday_services(map: &RawMap) -> Result<Option<HashSet<ServiceID>>> {
+    let calendar_path = map.name.city.input_path("gtfs/calendar.txt");
+    let exceptions_path = map.name.city.input_path("gtfs/calendar_dates.txt");
+    if !abstio::file_exists(&calendar_path) && !abstio::file_exists(&exceptions_path) {
+        warn!(
+            "{} and {} are missing, so assuming every trip runs every day",
+            calendar_path, exceptions_path
+        );
+        return Ok(None);
+    }
+
+    let mut calendars = Vec::new();
+    if abstio::file_exists(&calendar_path) {
+        for rec in csv::Reader::from_reader(File::open(calendar_path)?).deserialize() {
+            let rec: Calendar = rec?;
+            calendars.push(rec);
+        }
+    }
+    let mut exceptions = Vec::new();
+    if abstio::file_exists(&exceptions_path) {
+        for rec in csv::Reader::from_reader(File::open(exceptions_path)?).deserialize() {
+            let rec: CalendarDate = rec?;
+            exceptions.push(rec);
+        }
+    }
+
+    let feed_start = calendars
+        .iter()
+        .map(|rec| &rec.start_date)
+        .chain(exceptions.iter().map(|rec| &rec.date))
+        .map(|date| parse_date(date))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .min();
+    let day = match feed_start {
+        Some(day) => day + (WEDNESDAY + 7 - weekday(day)) % 7,
+        None => {
+            return Ok(Some(HashSet::new()));
+        }
+    };
+    Ok(Some(services_on(day, &calendars, &exceptions)?))
+}
+
+/// The services running on a day, counted from the Unix epoch
+fn services_on(
+    day: i64,
+    calendars: &[Calendar],
+    exceptions: &[CalendarDate],
+) -> Result<HashSet<ServiceID>> {
+    let mut services = HashSet::new();
+    for rec in calendars {
+        let runs_this_weekday = [
+            rec.monday,
+            rec.tuesday,
+            rec.wednesday,
+            rec.thursday,
+            rec.friday,
+            rec.saturday,
+            rec.sunday,
+        ][weekday(day) as usize]
+            == 1;
+        if runs_this_weekday
+            && parse_date(&rec.start_date)? <= day
+            && day <= parse_date(&rec.end_date)?
+        {
+            services.insert(rec.service_id.clone());
+        }
+    }
+    for rec in exceptions {
+        if parse_date(&rec.date)? != day {
+            continue;
+        }
+        // See https://developers.google.com/transit/gtfs/reference#calendar_datestxt
+        match rec.exception_type {
+            1 => {
+                services.insert(rec.service_id.clone());
+            }
+            2 => {
+                services.remove(&rec.service_id);
+            }
+            x => bail!("Unknown exception_type {} for {:?}", x, rec.service_id),
+        }
+    }
+    Ok(services)
+}
+
+const WEDNESDAY: i64 = 2;
+
+/// Parses a GTFS date like "20230104" into the number of days since 1970-01-01.
+fn parse_date(x: &str) -> Result<i64> {
+    let x = x.trim();
+    if x.len() != 8 || !x.chars().all(|c| c.is_ascii_digit()) {
+        bail!("Bad date {}", x);
+    }
+    let year: i64 = x[0..4].parse()?;
+    let month: i64 = x[4..6].parse()?;
+    let day: i64 = x[6..8].parse()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        bail!("Bad date {}", x);
+    }
+    // From http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Ok(era * 146097 + day_of_era - 719468)
+}
+
+/// Monday is 0
+fn weekday(day: i64) -> i64 {
+    // 1970-01-01 was a Thursday
+    (day + 3).rem_euclid(7)
+}
+
+/// Stops with blank times get times interpolated between their neighbors, and they aren't
+/// timepoints.
+fn make_run(trip_id: &TripID, times: &[StopTime]) -> Result<RawTransitRun> {
+    let mut stop_times = Vec::new();
+    // Stops that have times
+    let mut known = Vec::new();
+    for (idx, st) in times.iter().enumerate() {
+        let arrival = parse_time(&st.arrival_time)?;
+        let departure = parse_time(&st.departure_time)?;
+        // Only one of the two might be specified
+        if let (Some(arrival), Some(departure)) = (arrival.or(departure), departure.or(arrival)) {
+            known.push(idx);
+            stop_times.push(RawStopTime {
+                arrival,
+                departure,
+                // Blank means exact times
+                timepoint: st.timepoint != Some(0),
+            });
+        } else {
+            stop_times.push(RawStopTime {
+                arrival: Time::START_OF_DAY,
+                departure: Time::START_OF_DAY,
+                timepoint: false,
+            });
+        }
+    }
+    if known.first() != Some(&0) || known.last() != Some(&(times.len() - 1)) {
+        bail!("trip {} has no times for its first or last stop", trip_id.0);
+    }
+
+    for pair in known.windows(2) {
+        let (i1, i2) = (pair[0], pair[1]);
+        let t1 = stop_times[i1].departure;
+        let t2 = stop_times[i2].arrival;
+        if t2 < t1 {
+            bail!("trip {} goes back in time", trip_id.0);
+        }
+        for idx in i1 + 1..i2 {
+            let t = t1 + (t2 - t1) * ((idx - i1) as f64 / (i2 - i1) as f64);
+            stop_times[idx].arrival = t;
+            stop_times[idx].departure = t;
+        }
+    }
+
+    Ok(RawTransitRun {
+        gtfs_trip_id: trip_id.0.clone(),
+        stop_times,
+    })
+}
+
+/// GTFS times may be past 24:00:00 for service running after midnight.
+fn parse_time(x: &str) -> Result<Option<Time>> {
+    let x = x.trim();
+    if x.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Time::parse(x)?))
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
 struct ShapeID(String);
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
@@ -187,6 +423,8 @@ struct TripID(String);
 struct StopID(String);
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
 struct RouteID(String);
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+struct ServiceID(String);
 
 #[derive(Deserialize)]
 struct Route {
@@ -204,6 +442,28 @@ struct Trip {
     route_id: RouteID,
     shape_id: ShapeID,
     trip_id: TripID,
+    service_id: ServiceID,
+}
+
+#[derive(Deserialize)]
+struct Calendar {
+    service_id: ServiceID,
+    monday: usize,
+    tuesday: usize,
+    wednesday: usize,
+    thursday: usize,
+    friday: usize,
+    saturday: usize,
+    sunday: usize,
+    start_date: String,
+    end_date: String,
+}
+
+#[derive(Deserialize)]
+struct CalendarDate {
+    service_id: ServiceID,
+    date: String,
+    exception_type: usize,
 }
 
 #[derive(Deserialize)]
@@ -227,6 +487,11 @@ struct StopTime {
     trip_id: TripID,
     stop_id: StopID,
     stop_sequence: usize,
+    arrival_time: String,
+    departure_time: String,
+    // Optional in the spec
+    #[serde(default)]
+    timepoint: Option<usize>,
 }
 
 fn dump_kml(map: &RawMap) {
@@ -260,3 +525,122 @@ fn dump_kml(map: &RawMap) {
         &ExtraShapes { shapes },
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_make_run() {
+        let at = |time: &str| Time::parse(time).unwrap();
+        let trip = TripID("trip".to_string());
+        let stop = |arrival: &str, departure: &str, timepoint: Option<usize>| StopTime {
+            trip_id: trip.clone(),
+            stop_id: StopID("stop".to_string()),
+            stop_sequence: 0,
+            arrival_time: arrival.to_string(),
+            departure_time: departure.to_string(),
+            timepoint,
+        };
+
+        let run = make_run(
+            &trip,
+            &[
+                stop("7:00:00", "7:01:00", None),
+                stop("", "", None),
+                stop("", "", Some(0)),
+                stop("", "7:10:00", Some(1)),
+                stop("25:00:00", "25:00:00", Some(0)),
+            ],
+        )
+        .unwrap();
+        let times: Vec<(Time, Time, bool)> = run
+            .stop_times
+            .iter()
+            .map(|st| (st.arrival, st.departure, st.timepoint))
+            .collect();
+        assert_eq!(
+            times,
+            vec![
+                (at("7:00:00"), at("7:01:00"), true),
+                // Blank stops are spread evenly between their neighbors
+                (at("7:04:00"), at("7:04:00"), false),
+                (at("7:07:00"), at("7:07:00"), false),
+                (at("7:10:00"), at("7:10:00"), true),
+                (at("25:00:00"), at("25:00:00"), false),
+            ]
+        );
+
+        assert!(make_run(
+            &trip,
+            &[stop("", "", None), stop("7:00:00", "7:00:00", None)]
+        )
+        .is_err());
+        assert!(make_run(
+            &trip,
+            &[
+                stop("7:00:00", "7:00:00", None),
+                stop("6:00:00", "6:00:00", None)
+            ]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_services_on() {
+        let calendar = |id: &str, weekdays: [usize; 7], start: &str, end: &str| Calendar {
+            service_id: ServiceID(id.to_string()),
+            monday: weekdays[0],
+            tuesday: weekdays[1],
+            wednesday: weekdays[2],
+            thursday: weekdays[3],
+            friday: weekdays[4],
+            saturday: weekdays[5],
+            sunday: weekdays[6],
+            start_date: start.to_string(),
+            end_date: end.to_string(),
+        };
+        let exception = |id: &str, date: &str, exception_type: usize| CalendarDate {
+            service_id: ServiceID(id.to_string()),
+            date: date.to_string(),
+            exception_type,
+        };
+        let ids = |services: HashSet<ServiceID>| {
+            let mut ids: Vec<String> = services.into_iter().map(|id| id.0).collect();
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(parse_date("19700101").unwrap(), 0);
+        assert_eq!(parse_date("20000229").unwrap(), 11016);
+        assert!(parse_date("2023-01-04").is_err());
+        let wednesday = parse_date("20230104").unwrap();
+        assert_eq!(weekday(wednesday), WEDNESDAY);
+
+        let calendars = vec![
+            calendar("weekday", [1, 1, 1, 1, 1, 0, 0], "20230101", "20231231"),
+            calendar("weekend", [0, 0, 0, 0, 0, 1, 1], "20230101", "20231231"),
+            calendar("expired", [1, 1, 1, 1, 1, 0, 0], "20220101", "20221231"),
+        ];
+        assert_eq!(
+            ids(services_on(wednesday, &calendars, &[]).unwrap()),
+            vec!["weekday"]
+        );
+
+        let exceptions = vec![
+            exception("weekday", "20230104", 2),
+            exception("holiday", "20230104", 1),
+            exception("extra", "20230105", 1),
+        ];
+        assert_eq!(
+            ids(services_on(wednesday, &calendars, &exceptions).unwrap()),
+            vec!["holiday"]
+        );
+        // Feeds may only use calendar_dates.txt
+        assert_eq!(
+            ids(services_on(wednesday, &[], &exceptions).unwrap()),
+            vec!["holiday"]
+        );
+        assert!(services_on(wednesday, &[], &[exception("bad", "20230104", 3)]).is_err());
+    }
+}
@@ -144,6 +144,12 @@ impl Time {
     pub fn round_seconds(self, s: f64) -> Time {
         Time::seconds_since_midnight(s * (self.0 / s).round())
     }
+
+    /// Simulations can run past midnight. This maps a time on any day to the same time on the
+    /// first day, so 25:30 becomes 1:30.
+    pub fn time_of_day(self) -> Time {
+        Time::seconds_since_midnight(self.0 % (24.0 * 3600.0))
+    }
 }
 
 // 24-hour format by default
@@ -223,4 +229,15 @@ mod tests {
             6
         );
     }
+
+    #[test]
+    fn time_of_day() {
+        let t = Time::START_OF_DAY + Duration::hours(7);
+        assert_eq!(t.time_of_day(), t);
+        assert_eq!((t + Duration::hours(24)).time_of_day(), t);
+        assert_eq!(
+            (Time::START_OF_DAY + Duration::hours(24)).time_of_day(),
+            Time::START_OF_DAY
+        );
+    }
 }
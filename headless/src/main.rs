@@ -8,6 +8,28 @@
 //! it's now 01:01:00.0
 //! > curl http://localhost:1234/data/get-road-thruput
 //! ... huge JSON blob
+//!
+//! To receive events as newline-delimited JSON while the simulation advances, keep a subscription
+//! open in another connection. The optional `kinds`, `intersections`, and `roads` parameters are
+//! comma-separated lists used to filter events.
+//!
+//! > curl -N http://localhost:1234/events/subscribe?kinds=TripFinished,Alert&intersections=42
+//!
+//! Many independent simulations can run at once, each in a named session owning its own map, edits,
+//! and simulation. Every command accepts an optional `session` parameter; if it's missing, the
+//! "default" session created at startup is used.
+//!
+//! > curl http://localhost:1234/sessions/fork?from=default&name=experiment
+//! > curl http://localhost:1234/sim/goto-time?t=02:00:00&session=experiment
+//! > curl http://localhost:1234/sessions/list
+//!
+//! Within a session, snapshots of the current map and simulation can be saved in memory, then
+//! restored or forked into a new session. This is useful for comparing different edits from the
+//! same starting point.
+//!
+//! > curl http://localhost:1234/snapshots/save?name=7am
+//! > curl http://localhost:1234/sessions/fork?from=default&snapshot=7am&name=branch
+//! > curl http://localhost:1234/sessions/compare?before=default&after=branch
 
 #[macro_use]
 extern crate anyhow;
@@ -15,9 +37,10 @@ extern crate anyhow;
 extern crate log;
 
 use std::collections::{BTreeMap, BTreeSet, HashMap};
-use std::sync::RwLock;
+use std::sync::{Arc, Mutex, RwLock};
 
 use anyhow::Result;
+use hyper::header::CONTENT_TYPE;
 use hyper::{Body, Request, Response, Server, StatusCode};
 use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
@@ -25,21 +48,24 @@ use serde::{Deserialize, Serialize};
 use structopt::StructOpt;
 
 use abstio::MapName;
-use abstutil::{serialize_btreemap, Timer};
-use geom::{Distance, Duration, FindClosest, LonLat, Time};
+use abstutil::{serialize_btreemap, Tags, Timer};
+use geom::{Distance, Duration, FindClosest, LonLat, Speed, Time};
 use map_model::{
-    CompressedMovementID, ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, Map,
-    MovementID, PermanentMapEdits, RoadID, TurnID,
+    optimize_green_wave, BuildingID, CompressedMovementID, ControlTrafficSignal, EditCmd,
+    EditIntersection, GreenWaveOptions, IntersectionID, Map, MovementID, PermanentMapEdits, RoadID,
+    TurnID,
 };
 use sim::{
-    AgentID, AgentType, DelayCause, PersonID, Sim, SimFlags, SimOptions, TripID, VehicleType,
+    AgentID, AgentType, Analytics, BuildingNoise, DelayCause, Emissions, Event, NoiseEstimate,
+    NoiseExposure, PersonID, ScenarioGenerator, Sim, SimFlags, SimOptions, TripID, VehicleType,
 };
 use synthpop::{ExternalPerson, Scenario, ScenarioModifier, TripMode};
 
 lazy_static::lazy_static! {
-    static ref MAP: RwLock<Map> = RwLock::new(Map::blank());
-    static ref SIM: RwLock<Sim> = RwLock::new(Sim::new(&Map::blank(), SimOptions::new("tmp")));
-    static ref LOAD: RwLock<LoadSim> = RwLock::new({
+    static ref SESSIONS: RwLock<BTreeMap<String, Arc<Mutex<Session>>>> =
+        RwLock::new(BTreeMap::new());
+    /// New sessions are created from this, unless the caller overrides the scenario.
+    static ref DEFAULT_LOAD: RwLock<LoadSim> = RwLock::new({
         LoadSim {
             scenario: abstio::path_scenario(&MapName::seattle("montlake"), "weekday"),
             modifiers: Vec::new(),
@@ -48,8 +74,14 @@ lazy_static::lazy_static! {
             opts: SimOptions::default(),
         }
     });
+    static ref SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
 }
 
+const DEFAULT_SESSION: &str = "default";
+
+/// While the simulation advances, publish events to subscribers at least this often.
+const PUBLISH_EVENTS_FREQUENCY: Duration = Duration::const_seconds(60.0);
+
 #[derive(StructOpt)]
 #[structopt(
     name = "headless",
@@ -74,13 +106,19 @@ async fn main() {
     let args = Args::from_args();
 
     {
-        let mut load = LOAD.write().unwrap();
+        let mut load = DEFAULT_LOAD.write().unwrap();
         load.rng_seed = args.rng_seed;
         load.opts = args.opts;
 
         let (map, sim) = load.setup(&mut Timer::new("setup headless"));
-        *MAP.write().unwrap() = map;
-        *SIM.write().unwrap() = sim;
+        insert_session(Session {
+            name: DEFAULT_SESSION.to_string(),
+            map,
+            sim,
+            load: load.clone(),
+            snapshots: BTreeMap::new(),
+        })
+        .unwrap();
     }
 
     let addr = std::net::SocketAddr::from(([127, 0, 0, 1], args.port));
@@ -104,40 +142,211 @@ async fn serve_req(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
             .collect();
     let body = hyper::body::to_bytes(req).await?.to_vec();
     info!("Handling {}", path);
-    Ok(
-        match handle_command(
-            &path,
-            &params,
-            &body,
-            &mut SIM.write().unwrap(),
-            &mut MAP.write().unwrap(),
-            &mut LOAD.write().unwrap(),
-        ) {
-            Ok(resp) => Response::new(Body::from(resp)),
-            Err(err) => {
-                error!("{}: {}", path, err);
-                Response::builder()
-                    .status(StatusCode::BAD_REQUEST)
-                    .body(Body::from(format!("Bad command {}: {}", path, err)))
-                    .unwrap()
-            }
-        },
+
+    // Subscriptions stay open, so handle them without locking the simulation.
+    if path == "/events/subscribe" {
+        return Ok(match subscribe(&params) {
+            Ok(resp) => resp,
+            Err(err) => bad_request(&path, err),
+        });
+    }
+
+    let result = if path.starts_with("/sessions/") {
+        handle_session_command(&path, &params, &body)
+    } else {
+        match get_session(&params) {
+            Ok(session) => handle_command(&path, &params, &body, &mut session.lock().unwrap()),
+            Err(err) => Err(err),
+        }
+    };
+    Ok(match result {
+        Ok(resp) => Response::new(Body::from(resp)),
+        Err(err) => bad_request(&path, err),
+    })
+}
+
+fn bad_request(path: &str, err: anyhow::Error) -> Response<Body> {
+    error!("{}: {}", path, err);
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .body(Body::from(format!("Bad command {}: {}", path, err)))
+        .unwrap()
+}
+
+/// One independent simulation
+struct Session {
+    name: String,
+    map: Map,
+    sim: Sim,
+    load: LoadSim,
+    snapshots: BTreeMap<String, Snapshot>,
+}
+
+/// The map and simulation at some point in time, kept in memory. Since the map may be edited
+/// after the snapshot is taken, it's copied too.
+#[derive(Clone)]
+struct Snapshot {
+    map: Map,
+    sim: Sim,
+}
+
+fn get_session(params: &HashMap<String, String>) -> Result<Arc<Mutex<Session>>> {
+    lookup_session(
+        params
+            .get("session")
+            .map(|x| x.as_str())
+            .unwrap_or(DEFAULT_SESSION),
     )
 }
 
+fn lookup_session(name: &str) -> Result<Arc<Mutex<Session>>> {
+    SESSIONS
+        .read()
+        .unwrap()
+        .get(name)
+        .cloned()
+        .ok_or_else(|| anyhow!("no session named {}", name))
+}
+
+fn insert_session(session: Session) -> Result<()> {
+    let mut sessions = SESSIONS.write().unwrap();
+    if session.name.is_empty() {
+        bail!("session names can't be empty");
+    }
+    if sessions.contains_key(&session.name) {
+        bail!("a session named {} already exists", session.name);
+    }
+    sessions.insert(session.name.clone(), Arc::new(Mutex::new(session)));
+    Ok(())
+}
+
+fn handle_session_command(
+    path: &str,
+    params: &HashMap<String, String>,
+    body: &[u8],
+) -> Result<String> {
+    let get = |key: &str| {
+        params
+            .get(key)
+            .ok_or_else(|| anyhow!("missing GET parameter {}", key))
+    };
+
+    match path {
+        "/sessions/list" => {
+            let mut list = Vec::new();
+            for session in SESSIONS.read().unwrap().values() {
+                let session = session.lock().unwrap();
+                list.push(SessionInfo {
+                    name: session.name.clone(),
+                    map: session.map.get_name().clone(),
+                    edits: session.map.get_edits().edits_name.clone(),
+                    time: session.sim.time(),
+                });
+            }
+            Ok(abstutil::to_json(&list))
+        }
+        "/sessions/create" => {
+            let name = get("name")?.to_string();
+            if SESSIONS.read().unwrap().contains_key(&name) {
+                bail!("a session named {} already exists", name);
+            }
+            // The body is optional; by default, use the same scenario as the initial session.
+            let mut load = DEFAULT_LOAD.read().unwrap().clone();
+            if !body.is_empty() {
+                let args: LoadSim = abstutil::from_json(body)?;
+                load.scenario = args.scenario;
+                load.modifiers = args.modifiers;
+                load.edits = args.edits;
+            }
+            let (map, sim) = load.setup(&mut Timer::new(format!("create session {}", name)));
+            insert_session(Session {
+                name: name.clone(),
+                map,
+                sim,
+                load,
+                snapshots: BTreeMap::new(),
+            })?;
+            Ok(format!("session {} created", name))
+        }
+        "/sessions/fork" => {
+            let name = get("name")?.to_string();
+            let session = {
+                let from = lookup_session(get("from")?)?;
+                let from = from.lock().unwrap();
+                // Fork from the current state, unless a snapshot is specified
+                let snapshot = if let Some(snapshot) = params.get("snapshot") {
+                    from.snapshots
+                        .get(snapshot)
+                        .cloned()
+                        .ok_or_else(|| anyhow!("{} has no snapshot {}", from.name, snapshot))?
+                } else {
+                    Snapshot {
+                        map: from.map.clone(),
+                        sim: from.sim.clone(),
+                    }
+                };
+                Session {
+                    name: name.clone(),
+                    map: snapshot.map,
+                    sim: snapshot.sim,
+                    load: from.load.clone(),
+                    snapshots: BTreeMap::new(),
+                }
+            };
+            insert_session(session)?;
+            Ok(format!("session {} created", name))
+        }
+        "/sessions/compare" => {
+            // Don't lock both sessions at the same time, to avoid deadlocks
+            let (before_time, before) = {
+                let session = lookup_session(get("before")?)?;
+                let session = session.lock().unwrap();
+                (session.sim.time(), session.sim.get_analytics().clone())
+            };
+            let (after_time, after) = {
+                let session = lookup_session(get("after")?)?;
+                let session = session.lock().unwrap();
+                (session.sim.time(), session.sim.get_analytics().clone())
+            };
+            Ok(abstutil::to_json(&compare_analytics(
+                before_time.min(after_time),
+                &before,
+                &after,
+            )))
+        }
+        "/sessions/delete" => {
+            let name = get("name")?;
+            if SESSIONS.write().unwrap().remove(name).is_none() {
+                bail!("no session named {}", name);
+            }
+            SUBSCRIBERS
+                .lock()
+                .unwrap()
+                .retain(|sub| sub.session != *name);
+            Ok(format!("session {} deleted", name))
+        }
+        _ => Err(anyhow!("Unknown command")),
+    }
+}
+
 fn handle_command(
     path: &str,
     params: &HashMap<String, String>,
     body: &[u8],
-    sim: &mut Sim,
-    map: &mut Map,
-    load: &mut LoadSim,
+    session: &mut Session,
 ) -> Result<String> {
     let get = |key: &str| {
         params
             .get(key)
             .ok_or_else(|| anyhow!("missing GET parameter {}", key))
     };
+    let Session {
+        name,
+        map,
+        sim,
+        load,
+        snapshots,
+    } = session;
 
     match path {
         // Controlling the simulation
@@ -173,8 +382,7 @@ fn handle_command(
             if t <= sim.time() {
                 bail!("{} is in the past. call /sim/reset first?", t)
             } else {
-                let dt = t - sim.time();
-                sim.timed_step(map, dt, &mut None, &mut Timer::new("goto-time"));
+                goto_time(name, sim, map, t);
                 Ok(format!("it's now {}", t))
             }
         }
@@ -222,9 +430,49 @@ fn handle_command(
             });
             map.must_apply_edits(edits, &mut Timer::throwaway());
             map.recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+            sim.handle_live_edited_traffic_signals(map);
 
             Ok(format!("{} has been updated", id))
         }
+        "/traffic-signals/green-wave" => {
+            // Coordinate the signals along a corridor, given in order
+            let mut corridor = Vec::new();
+            for i in get("intersections")?.split(',') {
+                corridor.push(IntersectionID(i.parse::<usize>()?));
+            }
+            let opts = GreenWaveOptions {
+                plan: match params.get("plan") {
+                    Some(plan) => plan.parse::<usize>()?,
+                    None => 0,
+                },
+                cycle_length: match (params.get("min_cycle"), params.get("max_cycle")) {
+                    (Some(min), Some(max)) => Some((
+                        Duration::seconds(min.parse::<f64>()?),
+                        Duration::seconds(max.parse::<f64>()?),
+                    )),
+                    (None, None) => None,
+                    _ => bail!("Specify both min_cycle and max_cycle, or neither"),
+                },
+            };
+            let wave = optimize_green_wave(map, &corridor, &opts)?;
+
+            let mut edits = map.get_edits().clone();
+            edits.commands.extend(wave.to_edit_cmds(map));
+            map.must_apply_edits(edits, &mut Timer::throwaway());
+            map.recalculate_pathfinding_after_edits(&mut Timer::throwaway());
+            sim.handle_live_edited_traffic_signals(map);
+
+            Ok(abstutil::to_json(&GreenWaveSummary {
+                cycle_length: wave.cycle_length,
+                outbound_band: wave.outbound_band,
+                inbound_band: wave.inbound_band,
+                offsets: wave
+                    .signals
+                    .iter()
+                    .map(|ts| (ts.id, ts.plan_offset(opts.plan)))
+                    .collect(),
+            }))
+        }
         "/traffic-signals/get-delays" => {
             let i = map.get_i(IntersectionID(get("id")?.parse::<usize>()?));
             let t1 = Time::parse(get("t1")?)?;
@@ -275,6 +523,13 @@ fn handle_command(
             }
             Ok(abstutil::to_json(&thruput))
         }
+        "/traffic-signals/get-detectors" => {
+            let i = IntersectionID(get("id")?.parse::<usize>()?);
+            if !map.get_i(i).is_traffic_signal() {
+                bail!("{} isn't a traffic signal", i);
+            }
+            Ok(abstutil::to_json(&sim.get_signal_detectors(map, i)))
+        }
         "/traffic-signals/get-all-current-state" => {
             let mut all_state = BTreeMap::new();
             for i in map.all_intersections() {
@@ -286,6 +541,7 @@ fn handle_command(
                 all_state.insert(
                     i.id,
                     TrafficSignalState {
+                        current_plan_idx: map.get_traffic_signal(i.id).plan_at(sim.time()),
                         current_stage_idx,
                         remaining_time,
                         accepted: sim
@@ -342,6 +598,56 @@ fn handle_command(
                 .map(|((r, a, hr), cnt)| (*r, *a, *hr, *cnt))
                 .collect(),
         })),
+        "/data/get-emissions" => {
+            let analytics = sim.get_analytics();
+            Ok(abstutil::to_json(&EmissionsSummary {
+                total: analytics.total_emissions(),
+                per_hour: analytics.emissions_per_hour.clone(),
+                per_road: analytics
+                    .road_emissions
+                    .iter()
+                    .map(|(r, e)| (*r, *e))
+                    .collect(),
+                per_intersection: analytics
+                    .intersection_emissions
+                    .iter()
+                    .map(|(i, e)| (*i, *e))
+                    .collect(),
+                per_trip: analytics
+                    .trip_emissions
+                    .iter()
+                    .map(|(t, e)| (*t, *e))
+                    .collect(),
+            }))
+        }
+        "/data/get-parking-search" => {
+            let analytics = sim.get_analytics();
+            Ok(abstutil::to_json(&ParkingSearchSummary {
+                per_trip: analytics
+                    .parking_searches
+                    .iter()
+                    .map(|(_, trip, _, duration, dist)| (*trip, *duration, *dist))
+                    .collect(),
+                per_road: analytics
+                    .cruising_per_road
+                    .iter()
+                    .map(|(r, dist)| (*r, *dist))
+                    .collect(),
+                per_building: analytics
+                    .parking_search_per_building()
+                    .into_iter()
+                    .map(|(b, (count, duration, dist))| (b, count, duration, dist))
+                    .collect(),
+            }))
+        }
+        "/data/get-noise" => {
+            let noise = NoiseEstimate::new(map, sim.get_analytics());
+            Ok(abstutil::to_json(&NoiseReport {
+                exposure: noise.exposure(map),
+                roads: noise.roads.into_iter().collect(),
+                buildings: noise.buildings.into_iter().collect(),
+            }))
+        }
         "/data/get-blocked-by-graph" => Ok(abstutil::to_json(&BlockedByGraph {
             blocked_by: sim
                 .get_blocked_by_graph(map)
@@ -375,10 +681,61 @@ fn handle_command(
                 .collect();
             Ok(abstutil::to_json(&results))
         }
+        // Snapshots
+        "/snapshots/save" => {
+            let name = get("name")?.to_string();
+            let snapshot = Snapshot {
+                map: map.clone(),
+                sim: sim.clone(),
+            };
+            snapshots.insert(name.clone(), snapshot);
+            Ok(format!("snapshot {} saved at {}", name, sim.time()))
+        }
+        "/snapshots/restore" => {
+            let name = get("name")?;
+            let snapshot = snapshots
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("no snapshot named {}", name))?;
+            *map = snapshot.map;
+            *sim = snapshot.sim;
+            Ok(format!(
+                "snapshot {} restored, it's now {}",
+                name,
+                sim.time()
+            ))
+        }
+        "/snapshots/list" => Ok(abstutil::to_json(
+            &snapshots
+                .iter()
+                .map(|(name, snapshot)| (name.clone(), snapshot.sim.time()))
+                .collect::<Vec<_>>(),
+        )),
+        "/snapshots/delete" => {
+            let name = get("name")?;
+            if snapshots.remove(name).is_none() {
+                bail!("no snapshot named {}", name);
+            }
+            Ok(format!("snapshot {} deleted", name))
+        }
         // Controlling the map
+        "/map/set-edits" => {
+            // Unlike /sim/load, this keeps the simulation running, interrupting any trips affected
+            // by the edits.
+            let perma: PermanentMapEdits = abstutil::from_json(body)?;
+            let edits = perma.into_edits(map)?;
+            let mut timer = Timer::new("apply live edits");
+            map.must_apply_edits(edits, &mut timer);
+            map.recalculate_pathfinding_after_edits(&mut timer);
+            sim.handle_live_edited_traffic_signals(map);
+            let (trips, parked_cars) = sim.handle_live_edits(map, &mut timer);
+            Ok(format!(
+                "edits applied, interrupting {} trips and displacing {} parked cars",
+                trips, parked_cars
+            ))
+        }
         "/map/get-edits" => {
             let mut edits = map.get_edits().clone();
-            edits.commands.clear();
             edits.compress(map);
             Ok(abstutil::to_json(&edits.to_permanent(map)))
         }
@@ -388,6 +745,37 @@ fn handle_command(
                 &map.edit_road_cmd(r, |_| {}).to_perma(map),
             ))
         }
+        // These return commands to append to the edits from /map/get-edits. Road and intersection
+        // IDs change after applying them.
+        "/map/get-create-road-command" => {
+            let i1 = IntersectionID(get("i1")?.parse::<usize>()?);
+            let i2 = IntersectionID(get("i2")?.parse::<usize>()?);
+            let mut tags = Tags::empty();
+            tags.insert(
+                "highway",
+                params
+                    .get("highway")
+                    .cloned()
+                    .unwrap_or_else(|| "residential".to_string()),
+            );
+            let speed_limit = match params.get("speed_limit_mph") {
+                Some(x) => Speed::miles_per_hour(x.parse::<f64>()?),
+                None => Speed::miles_per_hour(25.0),
+            };
+            let cmd = map.create_road_cmd(i1, i2, Vec::new(), tags, speed_limit)?;
+            Ok(abstutil::to_json(&cmd.to_perma(map)))
+        }
+        "/map/get-split-road-command" => {
+            let r = RoadID(get("id")?.parse::<usize>()?);
+            let dist = Distance::meters(get("dist_meters")?.parse::<f64>()?);
+            Ok(abstutil::to_json(
+                &map.split_road_cmd(r, dist)?.to_perma(map),
+            ))
+        }
+        "/map/get-delete-road-command" => {
+            let r = RoadID(get("id")?.parse::<usize>()?);
+            Ok(abstutil::to_json(&map.delete_road_cmd(r)?.to_perma(map)))
+        }
         "/map/get-intersection-geometry" => {
             let i = IntersectionID(get("id")?.parse::<usize>()?);
             Ok(abstutil::to_json(&export_geometry(map, i)))
@@ -409,8 +797,239 @@ fn handle_command(
     }
 }
 
+/// Advance the simulation to the given time. If anybody is subscribed to events, periodically
+/// publish what's happened so far.
+fn goto_time(session: &str, sim: &mut Sim, map: &Map, t: Time) {
+    if !SUBSCRIBERS
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|sub| sub.session == session)
+    {
+        let dt = t - sim.time();
+        sim.timed_step(map, dt, &mut None, &mut Timer::new("goto-time"));
+        return;
+    }
+
+    sim.start_capturing_events();
+    while sim.time() < t {
+        let before = sim.time();
+        let dt = (t - before).min(PUBLISH_EVENTS_FREQUENCY);
+        sim.timed_step(map, dt, &mut None, &mut Timer::throwaway());
+        publish_events(session, sim.drain_captured_events());
+        // With --alerts=block, the simulation may not make progress
+        if sim.time() == before {
+            break;
+        }
+    }
+    sim.stop_capturing_events();
+}
+
+struct Subscriber {
+    session: String,
+    filter: EventFilter,
+    tx: tokio::sync::mpsc::UnboundedSender<String>,
+}
+
+/// Describes which events a subscriber wants to receive. An empty set means no filtering on that
+/// property.
+struct EventFilter {
+    kinds: BTreeSet<String>,
+    intersections: BTreeSet<IntersectionID>,
+    roads: BTreeSet<RoadID>,
+}
+
+impl EventFilter {
+    fn from_params(params: &HashMap<String, String>) -> Result<EventFilter> {
+        let list = |key: &str| -> Vec<String> {
+            params
+                .get(key)
+                .map(|x| {
+                    x.split(',')
+                        .filter(|x| !x.is_empty())
+                        .map(|x| x.to_string())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let mut filter = EventFilter {
+            kinds: list("kinds").into_iter().collect(),
+            intersections: BTreeSet::new(),
+            roads: BTreeSet::new(),
+        };
+        for i in list("intersections") {
+            filter
+                .intersections
+                .insert(IntersectionID(i.parse::<usize>()?));
+        }
+        for r in list("roads") {
+            filter.roads.insert(RoadID(r.parse::<usize>()?));
+        }
+        Ok(filter)
+    }
+
+    /// If both intersections and roads are specified, the event only has to happen at one of
+    /// them.
+    fn matches(&self, ev: &Event) -> bool {
+        if !self.kinds.is_empty() && !self.kinds.contains(ev.kind()) {
+            return false;
+        }
+        if self.intersections.is_empty() && self.roads.is_empty() {
+            return true;
+        }
+        ev.intersection()
+            .map(|i| self.intersections.contains(&i))
+            .unwrap_or(false)
+            || ev.road().map(|r| self.roads.contains(&r)).unwrap_or(false)
+    }
+}
+
+fn subscribe(params: &HashMap<String, String>) -> Result<Response<Body>> {
+    let session = params
+        .get("session")
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_SESSION.to_string());
+    if !SESSIONS.read().unwrap().contains_key(&session) {
+        bail!("no session named {}", session);
+    }
+    let filter = EventFilter::from_params(params)?;
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+    let (mut body_tx, body) = Body::channel();
+    tokio::spawn(async move {
+        while let Some(line) = rx.recv().await {
+            if body_tx.send_data(line.into()).await.is_err() {
+                // The client disconnected. Dropping rx will unsubscribe them.
+                break;
+            }
+        }
+    });
+    SUBSCRIBERS.lock().unwrap().push(Subscriber {
+        session,
+        filter,
+        tx,
+    });
+
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, "application/x-ndjson")
+        .body(body)
+        .unwrap())
+}
+
+fn publish_events(session: &str, events: Vec<(Time, Event)>) {
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    subscribers.retain(|sub| {
+        if sub.session != session {
+            return true;
+        }
+        for (time, event) in &events {
+            if sub.filter.matches(event) {
+                let line = format!(
+                    "{}\n",
+                    abstutil::to_json_terse(&PublishedEvent { time: *time, event })
+                );
+                if sub.tx.send(line).is_err() {
+                    return false;
+                }
+            }
+        }
+        // Also notice subscribers that disconnected while nothing matched
+        !sub.tx.is_closed()
+    });
+}
+
+fn compare_analytics(now: Time, before: &Analytics, after: &Analytics) -> AnalyticsComparison {
+    let count_trips = |analytics: &Analytics| {
+        let mut finished = 0;
+        let mut cancelled = 0;
+        for (t, _, _, maybe_dt) in &analytics.finished_trips {
+            if *t > now {
+                break;
+            }
+            if maybe_dt.is_some() {
+                finished += 1;
+            } else {
+                cancelled += 1;
+            }
+        }
+        (finished, cancelled)
+    };
+    let agent_types: BTreeSet<AgentType> = AgentType::all().into_iter().collect();
+
+    AnalyticsComparison {
+        now,
+        trips_finished_and_cancelled: (count_trips(before), count_trips(after)),
+        trip_times: after
+            .both_finished_trips(now, before)
+            .into_iter()
+            .map(|(id, before, after, _)| (id, before, after))
+            .collect(),
+        road_thruput: before
+            .road_thruput
+            .all_total_counts_by_time(&agent_types, now)
+            .compare(
+                after
+                    .road_thruput
+                    .all_total_counts_by_time(&agent_types, now),
+            )
+            .into_iter()
+            .filter(|(_, before, after)| before != after)
+            .collect(),
+        total_emissions: (before.total_emissions(), after.total_emissions()),
+        road_emissions: before
+            .road_emissions
+            .keys()
+            .chain(after.road_emissions.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter_map(|r| {
+                let before = before.road_emissions.get(r).cloned().unwrap_or_default();
+                let after = after.road_emissions.get(r).cloned().unwrap_or_default();
+                if before == after {
+                    None
+                } else {
+                    Some((*r, before, after))
+                }
+            })
+            .collect(),
+    }
+}
+
 // TODO I think specifying the API with protobufs or similar will be a better idea.
 
+#[derive(Serialize)]
+struct AnalyticsComparison {
+    /// Results are only compared up to this time, the earlier of the two simulations
+    now: Time,
+    /// ((finished, cancelled) before, (finished, cancelled) after)
+    trips_finished_and_cancelled: ((usize, usize), (usize, usize)),
+    /// Trips that finished in both simulations: (trip, duration before, duration after)
+    trip_times: Vec<(TripID, Duration, Duration)>,
+    /// Roads whose total throughput differs: (road, count before, count after). Throughput is
+    /// counted per hour, so this includes the whole hour containing `now`.
+    road_thruput: Vec<(RoadID, usize, usize)>,
+    /// Emissions from all vehicles, (before, after). Unlike the rest of the comparison, these are
+    /// cumulative up to the current time of each simulation.
+    total_emissions: (Emissions, Emissions),
+    /// Roads whose cumulative emissions differ: (road, before, after)
+    road_emissions: Vec<(RoadID, Emissions, Emissions)>,
+}
+
+#[derive(Serialize)]
+struct SessionInfo {
+    name: String,
+    map: MapName,
+    edits: String,
+    time: Time,
+}
+
+#[derive(Serialize)]
+struct PublishedEvent<'a> {
+    time: Time,
+    event: &'a Event,
+}
+
 #[derive(Serialize)]
 struct FinishedTrip {
     id: TripID,
@@ -469,8 +1088,49 @@ struct RoadThroughput {
     counts: Vec<(RoadID, AgentType, usize, usize)>,
 }
 
+#[derive(Serialize)]
+struct EmissionsSummary {
+    total: Emissions,
+    /// Indexed by hour since midnight
+    per_hour: Vec<Emissions>,
+    per_road: Vec<(RoadID, Emissions)>,
+    per_intersection: Vec<(IntersectionID, Emissions)>,
+    /// Buses and trains aren't part of any trip, so this doesn't sum to the total.
+    per_trip: Vec<(TripID, Emissions)>,
+}
+
+#[derive(Serialize)]
+struct ParkingSearchSummary {
+    /// How long and how far each driver searched for parking, once they reached the last road of
+    /// their original route
+    per_trip: Vec<(TripID, Duration, Distance)>,
+    /// Distance cruised along each road after the spot drivers were heading for was taken
+    per_road: Vec<(RoadID, Distance)>,
+    /// Per destination, the number of searches and their total time and distance
+    per_building: Vec<(BuildingID, usize, Duration, Distance)>,
+}
+
+#[derive(Serialize)]
+struct NoiseReport {
+    exposure: NoiseExposure,
+    /// Hourly levels in dBA, 10m from the center of each road with traffic
+    roads: Vec<(RoadID, Vec<Option<f64>>)>,
+    buildings: Vec<(BuildingID, BuildingNoise)>,
+}
+
+#[derive(Serialize)]
+struct GreenWaveSummary {
+    cycle_length: Duration,
+    outbound_band: Duration,
+    inbound_band: Duration,
+    offsets: Vec<(IntersectionID, Duration)>,
+}
+
 #[derive(Serialize)]
 struct TrafficSignalState {
+    /// Which timing plan is active. 0 is the base plan, and higher numbers index into the signal's
+    /// time-of-day `plans`, starting from 1.
+    current_plan_idx: usize,
     current_stage_idx: usize,
     remaining_time: Duration,
     accepted: BTreeSet<AgentID>,
@@ -487,7 +1147,7 @@ struct BlockedByGraph {
     blocked_by: BTreeMap<AgentID, (Duration, DelayCause, Option<TripID>, Option<PersonID>)>,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 struct LoadSim {
     scenario: String,
     modifiers: Vec<ScenarioModifier>,
@@ -514,6 +1174,7 @@ impl LoadSim {
         for m in &self.modifiers {
             scenario = m.apply(&map, scenario, &mut rng);
         }
+        scenario = ScenarioGenerator::match_building_edits(&map, scenario, &mut rng, timer);
 
         let mut sim = Sim::new(&map, self.opts.clone());
         sim.instantiate(&scenario, &map, &mut rng, timer);
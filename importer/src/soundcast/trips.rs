@@ -202,8 +202,8 @@ fn clip_trips(
                 borders.for_mode(orig.mode),
                 match orig.mode {
                     TripMode::Walk | TripMode::Transit => PathConstraints::Pedestrian,
-                    TripMode::Drive => PathConstraints::Car,
-                    TripMode::Bike => PathConstraints::Bike,
+                    TripMode::Drive | TripMode::RideHail => PathConstraints::Car,
+                    TripMode::Bike | TripMode::Micromobility => PathConstraints::Bike,
                 },
                 maybe_huge_map.as_ref(),
                 only_passthrough_trips,
@@ -293,6 +293,7 @@ pub fn make_scenario(
         map_name: map.get_name().clone(),
         people,
         only_seed_buses: None,
+        micromobility: Vec::new(),
     }
     .remove_weird_schedules(true)
 }
@@ -203,7 +203,10 @@ impl UnzoomedAgents {
 
     fn color(&self, agent: &UnzoomedAgent, color_scheme: &ColorScheme) -> Option<Color> {
         match agent.id.to_vehicle_type() {
-            Some(VehicleType::Car) => {
+            Some(VehicleType::Car)
+            | Some(VehicleType::RideHail)
+            | Some(VehicleType::Freight)
+            | Some(VehicleType::Emergency) => {
                 if self.cars {
                     Some(color_scheme.unzoomed_car)
                 } else {
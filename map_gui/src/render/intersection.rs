@@ -238,10 +238,11 @@ impl DrawIntersection {
                 .unwrap_or(true);
             if recalc {
                 let (idx, remaining) = app.current_stage_and_remaining_time(self.id);
+                let plan = signal.plan_at(app.sim_time());
                 let mut batch = GeomBatch::new();
                 traffic_signal::draw_signal_stage(
                     g.prerender,
-                    &signal.stages[idx],
+                    &signal.plan_stages(plan)[idx],
                     idx,
                     self.id,
                     Some(remaining),
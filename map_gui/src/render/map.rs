@@ -136,6 +136,9 @@ impl DrawMap {
         let mut quadtree_ids = HashMap::new();
         // TODO use iter chain if everything was boxed as a renderable...
         for obj in &roads {
+            if map.get_r(obj.id).is_deleted() {
+                continue;
+            }
             let item_id =
                 quadtree.insert_with_box(obj.get_id(), obj.get_outline(map).get_bounds().as_bbox());
             quadtree_ids.insert(obj.get_id(), item_id);
@@ -205,6 +208,9 @@ impl DrawMap {
         let mut unzoomed_pieces: Vec<(isize, Fill, Polygon)> = Vec::new();
 
         for r in map.all_roads() {
+            if r.is_deleted() {
+                continue;
+            }
             let width = r.get_width();
 
             unzoomed_pieces.push((
@@ -489,7 +495,9 @@ impl DrawMap {
         }
 
         for r in map.all_roads() {
-            batch.append(DrawRoad::new(r).render(ctx, app));
+            if !r.is_deleted() {
+                batch.append(DrawRoad::new(r).render(ctx, app));
+            }
         }
 
         for i in map.all_intersections() {
@@ -515,28 +523,91 @@ impl DrawMap {
         batch
     }
 
+    /// Also handles intersections newly created by map edits.
     pub fn recreate_intersection(&mut self, i: IntersectionID, map: &Map) {
-        let item_id = self.quadtree_ids.remove(&ID::Intersection(i)).unwrap();
-        self.quadtree.remove(item_id).unwrap();
+        if let Some(item_id) = self.quadtree_ids.remove(&ID::Intersection(i)) {
+            self.quadtree.remove(item_id).unwrap();
+        }
 
         let draw = DrawIntersection::new(map.get_i(i), map);
         let item_id = self
             .quadtree
             .insert_with_box(draw.get_id(), draw.get_outline(map).get_bounds().as_bbox());
         self.quadtree_ids.insert(draw.get_id(), item_id);
-        self.intersections[i.0] = draw;
+        if i.0 == self.intersections.len() {
+            self.intersections.push(draw);
+        } else {
+            self.intersections[i.0] = draw;
+        }
     }
 
-    pub fn recreate_road(&mut self, road: &Road, map: &Map) {
-        let item_id = self.quadtree_ids.remove(&ID::Road(road.id)).unwrap();
+    /// Only the last intersection can be deleted, when undoing the edit that created it.
+    pub fn delete_intersection(&mut self, i: IntersectionID) {
+        assert_eq!(i.0, self.intersections.len() - 1);
+        let item_id = self.quadtree_ids.remove(&ID::Intersection(i)).unwrap();
         self.quadtree.remove(item_id).unwrap();
+        self.intersections.pop();
+    }
+
+    /// Also handles roads newly created by map edits.
+    pub fn recreate_road(&mut self, road: &Road, map: &Map) {
+        if let Some(item_id) = self.quadtree_ids.remove(&ID::Road(road.id)) {
+            self.quadtree.remove(item_id).unwrap();
+        }
 
         let draw = DrawRoad::new(road);
         let item_id = self
             .quadtree
             .insert_with_box(draw.get_id(), draw.get_outline(map).get_bounds().as_bbox());
         self.quadtree_ids.insert(draw.get_id(), item_id);
-        self.roads[road.id.0] = draw;
+        if road.id.0 == self.roads.len() {
+            self.roads.push(draw);
+        } else {
+            self.roads[road.id.0] = draw;
+        }
+    }
+
+    /// Handles roads that map edits deleted, and roads that no longer exist at all, because the
+    /// edit creating them was undone. Only the last road can stop existing.
+    pub fn delete_road(&mut self, r: RoadID, map: &Map) {
+        if let Some(item_id) = self.quadtree_ids.remove(&ID::Road(r)) {
+            self.quadtree.remove(item_id).unwrap();
+        }
+        if r.0 < map.all_roads().len() {
+            self.roads[r.0] = DrawRoad::new(map.get_r(r));
+        } else {
+            assert_eq!(r.0, self.roads.len() - 1);
+            self.roads.pop();
+        }
+    }
+
+    /// Buildings are drawn in one batch, so redraw all of them when edits change any.
+    pub fn recreate_buildings(
+        &mut self,
+        ctx: &EventCtx,
+        map: &Map,
+        cs: &ColorScheme,
+        opts: &Options,
+    ) {
+        let mut all_buildings = GeomBatch::new();
+        let mut all_building_outlines = GeomBatch::new();
+        self.buildings = map
+            .all_buildings()
+            .iter()
+            .map(|b| {
+                DrawBuilding::new(
+                    ctx,
+                    b,
+                    map,
+                    cs,
+                    opts,
+                    &mut all_buildings,
+                    &mut all_building_outlines,
+                )
+            })
+            .collect();
+        self.draw_all_buildings = all_buildings.upload(ctx);
+        self.draw_all_building_outlines = all_building_outlines.upload(ctx);
     }
 
     pub fn free_memory(&mut self) {
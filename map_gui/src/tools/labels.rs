@@ -99,7 +99,7 @@ impl DrawRoadLabels {
         let mut quadtree = QuadTree::default(map.get_bounds().as_bbox());
 
         'ROAD: for r in map.all_roads() {
-            if !(self.include_roads)(r) || r.length() < Distance::meters(30.0) {
+            if r.is_deleted() || !(self.include_roads)(r) || r.length() < Distance::meters(30.0) {
                 continue;
             }
 
@@ -27,6 +27,7 @@ impl Navigator {
                     app.map()
                         .all_roads()
                         .iter()
+                        .filter(|r| !r.is_deleted())
                         .map(|r| (r.get_name(app.opts().language.as_ref()), r.id))
                         .collect(),
                     10,
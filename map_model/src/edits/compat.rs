@@ -1,6 +1,7 @@
 use std::collections::BTreeMap;
 
 use anyhow::Result;
+use enumset::EnumSet;
 use serde::Deserialize;
 use serde_json::Value;
 
@@ -10,7 +11,7 @@ use geom::Speed;
 use crate::raw::OriginalRoad;
 use crate::{
     osm, AccessRestrictions, Direction, EditCmd, EditRoad, LaneSpec, LaneType, Map,
-    PermanentMapEdits, RoadID,
+    PathConstraints, PermanentMapEdits, RoadID,
 };
 
 /// When the PermanentMapEdits format changes, add a transformation here to automatically convert
@@ -20,6 +21,9 @@ use crate::{
 /// usually winds up with permanent legacy fields, unless the changes are purely additive. For
 /// example, protobufs wouldn't have helped with the fix_intersection_ids problem. Explicit
 /// transformation is easier!
+/// Edits saved with an older version may still deserialize directly, but need to be upgraded.
+pub const LATEST_VERSION: usize = 14;
+
 pub fn upgrade(mut value: Value, map: &Map) -> Result<PermanentMapEdits> {
     // c46a74f10f4f1976a48aa8642ac11717d74b262c added an explicit version field. There are a few
     // changes before that.
@@ -111,6 +115,27 @@ pub fn upgrade(mut value: Value, map: &Map) -> Result<PermanentMapEdits> {
             .unwrap()
             .insert("version".to_string(), Value::Number(11.into()));
     }
+    if value["version"] == Value::Number(11.into()) {
+        add_time_windows(&mut value);
+        value
+            .as_object_mut()
+            .unwrap()
+            .insert("version".to_string(), Value::Number(12.into()));
+    }
+    if value["version"] == Value::Number(12.into()) {
+        add_vehicle_caps(&mut value);
+        value
+            .as_object_mut()
+            .unwrap()
+            .insert("version".to_string(), Value::Number(13.into()));
+    }
+    if value["version"] == Value::Number(13.into()) {
+        add_truck_access(&mut value);
+        value
+            .as_object_mut()
+            .unwrap()
+            .insert("version".to_string(), Value::Number(14.into()));
+    }
 
     abstutil::from_json(&value.to_string().into_bytes())
 }
@@ -244,10 +269,10 @@ fn fix_old_lane_cmds(value: &mut Value, map: &Map) -> Result<()> {
             let obj: ChangeAccessRestrictions = serde_json::from_value(obj).unwrap();
             let r = map.find_r_by_osm_id(obj.id)?;
             let road = modified.entry(r).or_insert_with(|| map.get_r_edit(r));
-            if road.access_restrictions != obj.old {
+            if road.access_restrictions != obj.old.upgrade() {
                 bail!("{:?} access restrictions have changed", obj);
             }
-            road.access_restrictions = obj.new.clone();
+            road.access_restrictions = obj.new.upgrade();
         } else {
             commands.push(orig);
         }
@@ -442,6 +467,48 @@ fn remove_vehicle_caps(value: &mut Value) {
     });
 }
 
+// Access restrictions gained time windows, when through-traffic is restricted further
+fn add_time_windows(value: &mut Value) {
+    walk(value, &|map| {
+        // TimeWindow also has this field, but also a start
+        if map.contains_key("allow_through_traffic")
+            && !map.contains_key("start")
+            && !map.contains_key("time_windows")
+        {
+            map.insert("time_windows".to_string(), Value::Array(Vec::new()));
+        }
+        false
+    });
+}
+
+// Congestion capping came back, counting every motor vehicle
+fn add_vehicle_caps(value: &mut Value) {
+    walk(value, &|map| {
+        if map.contains_key("time_windows") && !map.contains_key("cap_vehicles_per_hour") {
+            map.insert("cap_vehicles_per_hour".to_string(), Value::Null);
+        }
+        false
+    });
+}
+
+// PathConstraints gained Truck. Wherever cars were allowed through, trucks are too.
+fn add_truck_access(value: &mut Value) {
+    let car = EnumSet::only(PathConstraints::Car).as_u64();
+    let truck = EnumSet::only(PathConstraints::Truck).as_u64();
+    walk(value, &|map| {
+        // Both AccessRestrictions and TimeWindow have this field
+        if let Some(bits) = map.get("allow_through_traffic").and_then(|x| x.as_u64()) {
+            if bits & car != 0 {
+                map.insert(
+                    "allow_through_traffic".to_string(),
+                    Value::Number((bits | truck).into()),
+                );
+            }
+        }
+        false
+    });
+}
+
 // These're old structs used in fix_old_lane_cmds.
 #[derive(Debug, Deserialize)]
 struct OriginalLane {
@@ -472,8 +539,21 @@ struct ChangeSpeedLimit {
 #[derive(Debug, Deserialize)]
 struct ChangeAccessRestrictions {
     id: OriginalRoad,
-    new: AccessRestrictions,
-    old: AccessRestrictions,
+    new: OldAccessRestrictions,
+    old: OldAccessRestrictions,
+}
+#[derive(Debug, Deserialize)]
+struct OldAccessRestrictions {
+    allow_through_traffic: EnumSet<PathConstraints>,
+}
+
+impl OldAccessRestrictions {
+    fn upgrade(&self) -> AccessRestrictions {
+        AccessRestrictions {
+            allow_through_traffic: self.allow_through_traffic,
+            ..AccessRestrictions::new()
+        }
+    }
 }
 
 impl OriginalLane {
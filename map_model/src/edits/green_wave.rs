@@ -0,0 +1,388 @@
+//! Coordinate the offsets of traffic signals along a corridor, so that platoons of vehicles
+//! travelling at the speed limit in both directions hit as many green lights as possible. This is
+//! a simple search for the widest two-way green band, in the spirit of MAXBAND.
+
+use anyhow::Result;
+
+use geom::Duration;
+
+use crate::{
+    ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, Map, RoadID, StageType,
+    TurnPriority,
+};
+
+/// How to search for a green wave.
+#[derive(Clone, Debug)]
+pub struct GreenWaveOptions {
+    /// Which time-of-day plan of every signal to coordinate. See `ControlTrafficSignal::plan_at`.
+    pub plan: usize,
+    /// If specified, also pick a common cycle length between these bounds, rescaling the stages
+    /// of every signal to fit. Otherwise, all signals must already share the same cycle length.
+    pub cycle_length: Option<(Duration, Duration)>,
+}
+
+/// The result of coordinating a corridor.
+#[derive(Clone, Debug)]
+pub struct GreenWave {
+    pub cycle_length: Duration,
+    /// Per cycle, the window of time that a vehicle can leave the first signal and pass through
+    /// every other signal without stopping.
+    pub outbound_band: Duration,
+    /// Likewise, starting from the last signal and heading back to the first.
+    pub inbound_band: Duration,
+    /// The signals along the corridor, in order, with new offsets and maybe new stage durations
+    pub signals: Vec<ControlTrafficSignal>,
+}
+
+// When picking a cycle length, try lengths between the bounds in steps of this
+const CYCLE_LENGTH_STEP: Duration = Duration::const_seconds(5.0);
+// Give up on improving offsets after this many passes over the whole corridor
+const MAX_PASSES: usize = 10;
+
+impl GreenWave {
+    /// Express the changed signals as map edits.
+    pub fn to_edit_cmds(&self, map: &Map) -> Vec<EditCmd> {
+        self.signals
+            .iter()
+            .map(|ts| EditCmd::ChangeIntersection {
+                i: ts.id,
+                old: map.get_i_edit(ts.id),
+                new: EditIntersection::TrafficSignal(ts.export(map)),
+            })
+            .collect()
+    }
+}
+
+/// Find all traffic signals along the shortest path between two intersections, in order. The
+/// endpoints are included if they're signals.
+pub fn signals_along_path(
+    map: &Map,
+    i1: IntersectionID,
+    i2: IntersectionID,
+) -> Result<Vec<IntersectionID>> {
+    let (_, intersections) = map
+        .simple_path_btwn(i1, i2)
+        .ok_or_else(|| anyhow!("No path between {} and {}", i1, i2))?;
+    Ok(intersections
+        .into_iter()
+        .filter(|i| map.get_i(*i).is_traffic_signal())
+        .collect())
+}
+
+/// Calculate offsets (and maybe a common cycle length) for a sequence of traffic signals along a
+/// route, maximizing the sum of the green bands in both directions. The first signal keeps its
+/// offset; everything else is relative to it.
+pub fn optimize_green_wave(
+    map: &Map,
+    corridor: &[IntersectionID],
+    opts: &GreenWaveOptions,
+) -> Result<GreenWave> {
+    if corridor.len() < 2 {
+        bail!("A corridor needs at least 2 traffic signals");
+    }
+    let mut signals = Vec::new();
+    for i in corridor {
+        let ts = map
+            .maybe_get_traffic_signal(*i)
+            .ok_or_else(|| anyhow!("{} isn't a traffic signal", i))?;
+        if opts.plan >= ts.num_plans() {
+            bail!("{} doesn't have plan {}", i, opts.plan);
+        }
+        signals.push(ts.clone());
+    }
+
+    // Find the roads connecting the signals, and how long it takes to drive between them at the
+    // speed limit.
+    let mut links: Vec<(RoadID, RoadID)> = Vec::new();
+    let mut travel_times = vec![Duration::ZERO];
+    for pair in corridor.windows(2) {
+        let (roads, _) = map
+            .simple_path_btwn(pair[0], pair[1])
+            .ok_or_else(|| anyhow!("No path between {} and {}", pair[0], pair[1]))?;
+        if roads.is_empty() {
+            bail!("{} is repeated in the corridor", pair[0]);
+        }
+        let mut dt = Duration::ZERO;
+        for r in &roads {
+            let r = map.get_r(*r);
+            dt += r.length() / r.speed_limit;
+        }
+        travel_times.push(*travel_times.last().unwrap() + dt);
+        links.push((roads[0], *roads.last().unwrap()));
+    }
+
+    let candidates = match opts.cycle_length {
+        Some((min, max)) => {
+            if min > max {
+                bail!(
+                    "Minimum cycle length {} is more than the maximum {}",
+                    min,
+                    max
+                );
+            }
+            let mut cycles = Vec::new();
+            let mut cycle = min;
+            while cycle <= max {
+                cycles.push(cycle);
+                cycle += CYCLE_LENGTH_STEP;
+            }
+            cycles
+        }
+        None => {
+            let cycle = signals[0].plan_cycle_duration(opts.plan);
+            for ts in &signals {
+                if ts.plan_cycle_duration(opts.plan) != cycle {
+                    bail!(
+                        "{} has a cycle length of {}, but {} has {}. Pick a common cycle length.",
+                        signals[0].id,
+                        cycle,
+                        ts.id,
+                        ts.plan_cycle_duration(opts.plan)
+                    );
+                }
+            }
+            vec![cycle]
+        }
+    };
+
+    let mut best: Option<(f64, GreenWave)> = None;
+    for cycle in candidates {
+        let mut candidate_signals = signals.clone();
+        if opts.cycle_length.is_some()
+            && !candidate_signals
+                .iter_mut()
+                .all(|ts| rescale_cycle(ts, opts.plan, cycle, map))
+        {
+            continue;
+        }
+        let wave = coordinate(
+            map,
+            candidate_signals,
+            &links,
+            &travel_times,
+            cycle,
+            opts.plan,
+        )?;
+        // Longer cycles trivially have wider bands, so compare the fraction of the cycle that's
+        // green. Only switch to a longer cycle for a real improvement.
+        let efficiency = (wave.outbound_band + wave.inbound_band) / cycle;
+        if best
+            .as_ref()
+            .map(|(score, _)| efficiency > *score + 0.001)
+            .unwrap_or(true)
+        {
+            best = Some((efficiency, wave));
+        }
+    }
+    best.map(|(_, wave)| wave)
+        .ok_or_else(|| anyhow!("No cycle length in range gives every stage enough time"))
+}
+
+// Search for the best offsets, given a common cycle length.
+fn coordinate(
+    map: &Map,
+    mut signals: Vec<ControlTrafficSignal>,
+    links: &[(RoadID, RoadID)],
+    travel_times: &[Duration],
+    cycle: Duration,
+    plan: usize,
+) -> Result<GreenWave> {
+    let n = signals.len();
+    // Work in whole seconds
+    let cycle_secs = cycle.inner_seconds().round() as usize;
+    if cycle_secs == 0 {
+        bail!("The cycle length can't be 0");
+    }
+
+    let mut outbound = Vec::new();
+    let mut inbound = Vec::new();
+    for (idx, ts) in signals.iter().enumerate() {
+        // Outbound traffic arrives from the previous link, or just departs onto the first one.
+        let outbound_movement = if idx == 0 {
+            Corridor::To(links[0].0)
+        } else {
+            Corridor::From(links[idx - 1].1)
+        };
+        let inbound_movement = if idx == n - 1 {
+            Corridor::To(links[idx - 1].1)
+        } else {
+            Corridor::From(links[idx].0)
+        };
+        outbound.push(green_window(ts, plan, outbound_movement, map)?);
+        inbound.push(green_window(ts, plan, inbound_movement, map)?);
+    }
+    let to_secs = |dt: Duration| dt.inner_seconds().round() as usize;
+    let outbound_arrivals: Vec<usize> = travel_times.iter().map(|dt| to_secs(*dt)).collect();
+    let total = *outbound_arrivals.last().unwrap();
+    let inbound_arrivals: Vec<usize> = outbound_arrivals.iter().map(|t| total - t).collect();
+
+    let score = |offsets: &[usize]| {
+        band(&outbound, &outbound_arrivals, offsets, cycle_secs)
+            + band(&inbound, &inbound_arrivals, offsets, cycle_secs)
+    };
+
+    // Start from the current offsets and from a one-way progression in the outbound direction,
+    // then improve one signal at a time. Keep whichever ends up better.
+    let current: Vec<usize> = signals
+        .iter()
+        .map(|ts| to_secs(ts.plan_offset(plan)) % cycle_secs)
+        .collect();
+    let mut progression = vec![current[0]];
+    for idx in 1..n {
+        // Line up the start of green with the arrival of the front of the outbound platoon
+        let target = current[0] + outbound[idx].0 + cycle_secs * (outbound_arrivals[idx] + 1)
+            - outbound[0].0
+            - outbound_arrivals[idx];
+        progression.push(target % cycle_secs);
+    }
+
+    let mut best_offsets = Vec::new();
+    let mut best_score = 0;
+    for mut offsets in [current, progression] {
+        let mut current_score = score(&offsets);
+        for _ in 0..MAX_PASSES {
+            let mut improved = false;
+            for idx in 1..n {
+                let orig = offsets[idx];
+                let mut best_here = (current_score, orig);
+                for candidate in 0..cycle_secs {
+                    offsets[idx] = candidate;
+                    let s = score(&offsets);
+                    if s > best_here.0 {
+                        best_here = (s, candidate);
+                    }
+                }
+                offsets[idx] = best_here.1;
+                if best_here.0 > current_score {
+                    current_score = best_here.0;
+                    improved = true;
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+        if best_offsets.is_empty() || current_score > best_score {
+            best_score = current_score;
+            best_offsets = offsets;
+        }
+    }
+
+    for (ts, offset) in signals.iter_mut().zip(best_offsets.iter()) {
+        *ts.plan_offset_mut(plan) = Duration::seconds(*offset as f64);
+    }
+    Ok(GreenWave {
+        cycle_length: Duration::seconds(cycle_secs as f64),
+        outbound_band: Duration::seconds(band(
+            &outbound,
+            &outbound_arrivals,
+            &best_offsets,
+            cycle_secs,
+        ) as f64),
+        inbound_band: Duration::seconds(
+            band(&inbound, &inbound_arrivals, &best_offsets, cycle_secs) as f64,
+        ),
+        signals,
+    })
+}
+
+// Which through movement at a signal belongs to the corridor
+#[derive(Clone, Copy)]
+enum Corridor {
+    From(RoadID),
+    To(RoadID),
+}
+
+// Returns (start, duration) in seconds, relative to the start of the cycle, of the longest stage
+// serving the corridor. Prefer stages where the movement is protected.
+fn green_window(
+    ts: &ControlTrafficSignal,
+    plan: usize,
+    corridor: Corridor,
+    map: &Map,
+) -> Result<(usize, usize)> {
+    let i = map.get_i(ts.id);
+    let mut best: Option<(TurnPriority, usize, usize)> = None;
+    let mut start = 0;
+    for stage in ts.plan_stages(plan) {
+        let dt = stage.stage_type.simple_duration().inner_seconds().round() as usize;
+        let mut priority = TurnPriority::Banned;
+        for m in i.movements.keys() {
+            if m.crosswalk {
+                continue;
+            }
+            let matches = match corridor {
+                Corridor::From(r) => m.from.road == r,
+                Corridor::To(r) => m.to.road == r,
+            };
+            let movement_priority = stage.get_priority_of_movement(*m);
+            if matches && movement_priority > priority {
+                priority = movement_priority;
+            }
+        }
+        if priority != TurnPriority::Banned
+            && best
+                .map(|(p, _, d)| (priority, dt) > (p, d))
+                .unwrap_or(true)
+        {
+            best = Some((priority, start, dt));
+        }
+        start += dt;
+    }
+    best.map(|(_, start, dt)| (start, dt))
+        .ok_or_else(|| anyhow!("No stage at {} serves the corridor", ts.id))
+}
+
+// How many seconds per cycle can a platoon leave the first signal and pass through all of them
+// without stopping? The platoon reaches each signal `arrivals` seconds after leaving the first.
+fn band(windows: &[(usize, usize)], arrivals: &[usize], offsets: &[usize], cycle: usize) -> usize {
+    (0..cycle)
+        .filter(|depart| {
+            windows.iter().zip(arrivals).zip(offsets).all(
+                |(((start, duration), arrival), offset)| {
+                    // The signal is in the middle of its cycle by its offset
+                    (depart + arrival + offset + cycle - start % cycle) % cycle < *duration
+                },
+            )
+        })
+        .count()
+}
+
+// Scale every stage of one plan proportionally to fit a new cycle length. Returns false if the
+// signal can't be retimed, because it has actuated stages or the new stages would be too short
+// for pedestrians to cross.
+fn rescale_cycle(ts: &mut ControlTrafficSignal, plan: usize, cycle: Duration, map: &Map) -> bool {
+    let old_cycle = ts.plan_cycle_duration(plan);
+    if old_cycle == Duration::ZERO {
+        return false;
+    }
+    let target = cycle.inner_seconds().round();
+    let mut total = 0.0;
+    for stage in ts.plan_stages_mut(plan).iter_mut() {
+        let dt = match stage.stage_type {
+            StageType::Fixed(dt) => dt,
+            StageType::Variable(_, _, _) => {
+                return false;
+            }
+        };
+        let scaled = (dt * (cycle / old_cycle)).inner_seconds().round().max(1.0);
+        total += scaled;
+        stage.stage_type = StageType::Fixed(Duration::seconds(scaled));
+    }
+    // Rounding may leave the cycle a few seconds off. Absorb the difference in the longest stage.
+    let longest = ts
+        .plan_stages(plan)
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, s)| s.stage_type.simple_duration())
+        .map(|(idx, _)| idx)
+        .unwrap();
+    let stage = &mut ts.plan_stages_mut(plan)[longest];
+    let adjusted = stage.stage_type.simple_duration().inner_seconds() + target - total;
+    if adjusted < 1.0 {
+        return false;
+    }
+    stage.stage_type = StageType::Fixed(Duration::seconds(adjusted));
+
+    ts.validate(map.get_i(ts.id)).is_ok()
+}
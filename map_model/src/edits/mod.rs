@@ -7,19 +7,23 @@ use std::collections::{BTreeMap, BTreeSet, HashSet};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use abstutil::Timer;
-use geom::{Distance, HashablePt2D, Line, Speed, Time};
-use raw_map::{get_lane_specs_ltr, InputRoad};
+use abstutil::{Tags, Timer};
+use geom::{Circle, Distance, HashablePt2D, Line, PolyLine, Pt2D, Speed, Time};
+use raw_map::{get_lane_specs_ltr, Amenity, InputRoad};
 
+pub use self::green_wave::{optimize_green_wave, signals_along_path, GreenWave, GreenWaveOptions};
 pub use self::perma::PermanentMapEdits;
 use crate::make::{match_points_to_lanes, snap_driveway, trim_path};
+use crate::raw::OriginalRoad;
 use crate::{
-    connectivity, AccessRestrictions, BuildingID, ControlStopSign, ControlTrafficSignal,
-    IntersectionID, IntersectionType, LaneID, LaneSpec, Map, MapConfig, Movement, ParkingLotID,
-    PathConstraints, Pathfinder, Road, RoadID, TransitRouteID, TurnID, TurnType, Zone,
+    connectivity, osm, AccessRestrictions, BuildingID, BuildingType, ControlStopSign,
+    ControlTrafficSignal, Intersection, IntersectionID, IntersectionType, LaneID, LaneSpec, Map,
+    MapConfig, Movement, OffstreetParking, ParkingLotID, PathConstraints, Pathfinder, Road, RoadID,
+    TransitRouteID, TurnID, TurnType,
 };
 
 mod compat;
+mod green_wave;
 mod perma;
 
 /// Represents changes to a map. Note this isn't serializable -- that's what `PermanentMapEdits`
@@ -37,9 +41,16 @@ pub struct MapEdits {
 
     /// Derived from commands, kept up to date by update_derived
     pub changed_roads: BTreeSet<RoadID>,
+    /// Roads that don't exist in the basemap. These aren't included in changed_roads.
+    pub created_roads: BTreeSet<RoadID>,
+    /// Roads from the basemap or created by edits that've since been deleted. These aren't
+    /// included in changed_roads.
+    pub deleted_roads: BTreeSet<RoadID>,
     pub original_intersections: BTreeMap<IntersectionID, EditIntersection>,
     pub original_crosswalks: BTreeMap<IntersectionID, EditCrosswalks>,
+    pub original_turn_restrictions: BTreeMap<IntersectionID, EditTurnRestrictions>,
     pub changed_routes: BTreeSet<TransitRouteID>,
+    pub original_buildings: BTreeMap<BuildingID, EditBuilding>,
 
     /// Some edits are included in the game by default, in data/system/proposals, as "community
     /// proposals." They require a description and may have a link to a write-up.
@@ -68,6 +79,32 @@ pub struct EditRoad {
 #[derive(Debug, Clone, PartialEq)]
 pub struct EditCrosswalks(pub BTreeMap<TurnID, TurnType>);
 
+/// The land use of a building. The number of residents and workers is part of the type.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EditBuilding {
+    pub bldg_type: BuildingType,
+    pub amenities: Vec<Amenity>,
+    pub parking: OffstreetParking,
+}
+
+/// A road that doesn't exist in the basemap, connecting two existing intersections
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewRoad {
+    pub src_i: IntersectionID,
+    pub dst_i: IntersectionID,
+    /// From the center of src_i to the center of dst_i, before trimming to the intersection
+    /// polygons
+    pub center_pts: PolyLine,
+    /// Describes the kind of road and its name, as if it came from OSM
+    pub osm_tags: Tags,
+    pub road: EditRoad,
+}
+
+/// Every banned movement between roads at one intersection, as (from, to). A U-turn goes from a
+/// road back onto itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EditTurnRestrictions(pub BTreeSet<(RoadID, RoadID)>);
+
 impl EditRoad {
     pub fn get_orig_from_osm(r: &Road, cfg: &MapConfig) -> EditRoad {
         EditRoad {
@@ -120,6 +157,22 @@ impl EditRoad {
     }
 }
 
+impl EditBuilding {
+    fn diff(&self, other: &EditBuilding) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.bldg_type != other.bldg_type {
+            changes.push("land use".to_string());
+        }
+        if self.amenities != other.amenities {
+            changes.push("amenities".to_string());
+        }
+        if self.parking != other.parking {
+            changes.push("off-street parking".to_string());
+        }
+        changes
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum EditCmd {
     ChangeRoad {
@@ -142,19 +195,66 @@ pub enum EditCmd {
         old: EditCrosswalks,
         new: EditCrosswalks,
     },
+    ChangeTurnRestrictions {
+        i: IntersectionID,
+        old: EditTurnRestrictions,
+        new: EditTurnRestrictions,
+    },
+    /// Road and intersection IDs are indices, so new ones always get the next ID. Undoing this
+    /// or SplitRoad removes them from the end.
+    CreateRoad { r: RoadID, new: NewRoad },
+    /// Splits a road in two at a new intersection. The road keeps the part before `dist`, and the
+    /// new road continues from the new intersection to the original end.
+    SplitRoad {
+        r: RoadID,
+        /// Along the road's center, before trimming it to the intersection polygons
+        dist: Distance,
+        new_i: IntersectionID,
+        new_r: RoadID,
+    },
+    /// A deleted road keeps its ID, but loses all of its lanes and isn't connected to any
+    /// intersection.
+    DeleteRoad { r: RoadID, old: EditRoad },
+    ChangeBuilding {
+        b: BuildingID,
+        old: EditBuilding,
+        new: EditBuilding,
+    },
 }
 
 pub struct EditEffects {
     pub changed_roads: BTreeSet<RoadID>,
+    /// Includes roads that no longer exist, because undoing their creation removed them
+    pub deleted_roads: BTreeSet<RoadID>,
     pub deleted_lanes: BTreeSet<LaneID>,
     pub changed_intersections: BTreeSet<IntersectionID>,
+    /// Intersections that no longer exist, because undoing a split removed them
+    pub deleted_intersections: BTreeSet<IntersectionID>,
     // TODO Will we need modified turns?
     pub added_turns: BTreeSet<TurnID>,
     pub deleted_turns: BTreeSet<TurnID>,
     pub changed_parking_lots: BTreeSet<ParkingLotID>,
+    pub changed_buildings: BTreeSet<BuildingID>,
     modified_lanes: BTreeSet<LaneID>,
 }
 
+impl EditEffects {
+    fn new() -> EditEffects {
+        EditEffects {
+            changed_roads: BTreeSet::new(),
+            deleted_roads: BTreeSet::new(),
+            deleted_lanes: BTreeSet::new(),
+            changed_intersections: BTreeSet::new(),
+            deleted_intersections: BTreeSet::new(),
+            added_turns: BTreeSet::new(),
+            deleted_turns: BTreeSet::new(),
+            changed_parking_lots: BTreeSet::new(),
+            changed_buildings: BTreeSet::new(),
+            modified_lanes: BTreeSet::new(),
+        }
+    }
+}
+
 impl MapEdits {
     pub(crate) fn new() -> MapEdits {
         MapEdits {
@@ -165,9 +265,13 @@ impl MapEdits {
             merge_zones: true,
 
             changed_roads: BTreeSet::new(),
+            created_roads: BTreeSet::new(),
+            deleted_roads: BTreeSet::new(),
             original_intersections: BTreeMap::new(),
             original_crosswalks: BTreeMap::new(),
+            original_turn_restrictions: BTreeMap::new(),
             changed_routes: BTreeSet::new(),
+            original_buildings: BTreeMap::new(),
         }
     }
 
@@ -176,8 +280,8 @@ impl MapEdits {
     /// the edits likely don't cover this map at all.
     pub fn load_from_file(map: &Map, path: String, timer: &mut Timer) -> Result<MapEdits> {
         let perma = match abstio::maybe_read_json::<PermanentMapEdits>(path.clone(), timer) {
-            Ok(perma) => perma,
-            Err(_) => {
+            Ok(perma) if perma.version == compat::LATEST_VERSION => perma,
+            _ => {
                 // The JSON format may have changed, so attempt backwards compatibility.
                 let bytes = abstio::slurp_file(path)?;
                 let value = serde_json::from_slice(&bytes)?;
@@ -208,8 +312,8 @@ impl MapEdits {
     /// failure -- the edits likely don't cover this map at all.
     pub fn load_from_bytes(map: &Map, bytes: Vec<u8>) -> Result<MapEdits> {
         let perma = match abstutil::from_json::<PermanentMapEdits>(&bytes) {
-            Ok(perma) => perma,
-            Err(_) => {
+            Ok(perma) if perma.version == compat::LATEST_VERSION => perma,
+            _ => {
                 // The JSON format may have changed, so attempt backwards compatibility.
                 let contents = std::str::from_utf8(&bytes)?;
                 let value = serde_json::from_str(contents)?;
@@ -237,9 +341,13 @@ impl MapEdits {
 
     fn update_derived(&mut self, map: &Map) {
         self.changed_roads.clear();
+        self.created_roads.clear();
+        self.deleted_roads.clear();
         self.original_intersections.clear();
         self.original_crosswalks.clear();
+        self.original_turn_restrictions.clear();
         self.changed_routes.clear();
+        self.original_buildings.clear();
 
         for cmd in &self.commands {
             match cmd {
@@ -256,34 +364,119 @@ impl MapEdits {
                         self.original_crosswalks.insert(*i, old.clone());
                     }
                 }
+                EditCmd::ChangeTurnRestrictions { i, ref old, .. } => {
+                    if !self.original_turn_restrictions.contains_key(i) {
+                        self.original_turn_restrictions.insert(*i, old.clone());
+                    }
+                }
                 EditCmd::ChangeRouteSchedule { id, .. } => {
                     self.changed_routes.insert(*id);
                 }
+                EditCmd::CreateRoad { r, ref new } => {
+                    self.created_roads.insert(*r);
+                    self.forget_clobbered_intersections(vec![new.src_i, new.dst_i]);
+                }
+                EditCmd::SplitRoad {
+                    r, new_i, new_r, ..
+                } => {
+                    self.created_roads.insert(*new_r);
+                    self.forget_clobbered_intersections(vec![
+                        map.get_r(*r).src_i,
+                        *new_i,
+                        map.get_r(*new_r).dst_i,
+                    ]);
+                }
+                EditCmd::DeleteRoad { r, .. } => {
+                    self.deleted_roads.insert(*r);
+                    let road = map.get_r(*r);
+                    self.forget_clobbered_intersections(vec![road.src_i, road.dst_i]);
+                }
+                EditCmd::ChangeBuilding { b, ref old, .. } => {
+                    if !self.original_buildings.contains_key(b) {
+                        self.original_buildings.insert(*b, old.clone());
+                    }
+                }
             }
         }
 
+        let deleted_roads = &self.deleted_roads;
+        self.created_roads.retain(|r| !deleted_roads.contains(r));
+        let created_roads = &self.created_roads;
         self.changed_roads.retain(|r| {
-            map.get_r_edit(*r) != EditRoad::get_orig_from_osm(map.get_r(*r), &map.config)
+            !created_roads.contains(r)
+                && !deleted_roads.contains(r)
+                && map.get_r_edit(*r) != EditRoad::get_orig_from_osm(map.get_r(*r), &map.config)
         });
         self.original_intersections
             .retain(|i, orig| map.get_i_edit(*i) != orig.clone());
         self.original_crosswalks
             .retain(|i, orig| map.get_i_crosswalks_edit(*i) != orig.clone());
+        self.original_turn_restrictions
+            .retain(|i, orig| map.get_i_turn_restrictions_edit(*i) != orig.clone());
         self.changed_routes.retain(|br| {
             let r = map.get_tr(*br);
             r.spawn_times != r.orig_spawn_times
         });
+        self.original_buildings
+            .retain(|b, orig| map.get_b_edit(*b) != orig.clone());
+    }
+
+    // Creating, splitting, or deleting a road regenerates the stop signs, traffic signals, and
+    // crosswalks at its ends, so earlier edits to them no longer matter.
+    fn forget_clobbered_intersections(&mut self, intersections: Vec<IntersectionID>) {
+        for i in intersections {
+            self.original_intersections.remove(&i);
+            self.original_crosswalks.remove(&i);
+        }
     }
 
-    /// Assumes update_derived has been called.
+    /// Replaces the commands with an equivalent, shorter list. Assumes update_derived has been
+    /// called.
     pub fn compress(&mut self, map: &Map) {
+        // New road and intersection IDs depend on the order roads were created and split, and the
+        // new half of a split road starts with the lanes the road had then. Keep those edits and
+        // any changes to the roads involved as they are, in order. Everything else describes the
+        // current state, so it goes afterwards.
+        let mut network_roads = BTreeSet::new();
+        for cmd in &self.commands {
+            match cmd {
+                EditCmd::CreateRoad { r, .. } | EditCmd::DeleteRoad { r, .. } => {
+                    network_roads.insert(*r);
+                }
+                EditCmd::SplitRoad { r, new_r, .. } => {
+                    network_roads.insert(*r);
+                    network_roads.insert(*new_r);
+                }
+                _ => {}
+            }
+        }
+        self.commands.retain(|cmd| match cmd {
+            EditCmd::CreateRoad { .. } | EditCmd::SplitRoad { .. } | EditCmd::DeleteRoad { .. } => {
+                true
+            }
+            EditCmd::ChangeRoad { r, .. } => network_roads.contains(r),
+            _ => false,
+        });
+
         for r in &self.changed_roads {
+            if network_roads.contains(r) {
+                continue;
+            }
             self.commands.push(EditCmd::ChangeRoad {
                 r: *r,
                 old: EditRoad::get_orig_from_osm(map.get_r(*r), &map.config),
                 new: map.get_r_edit(*r),
             });
         }
+        // A traffic signal only matches the movements left after banning or allowing turns, so
+        // change the restrictions first
+        for (i, old) in &self.original_turn_restrictions {
+            self.commands.push(EditCmd::ChangeTurnRestrictions {
+                i: *i,
+                old: old.clone(),
+                new: map.get_i_turn_restrictions_edit(*i),
+            });
+        }
         for (i, old) in &self.original_intersections {
             self.commands.push(EditCmd::ChangeIntersection {
                 i: *i,
@@ -306,6 +499,13 @@ impl MapEdits {
                 old: r.orig_spawn_times.clone(),
             });
         }
+        for (b, old) in &self.original_buildings {
+            self.commands.push(EditCmd::ChangeBuilding {
+                b: *b,
+                old: old.clone(),
+                new: map.get_b_edit(*b),
+            });
+        }
     }
 
     /// Pick apart changed_roads and figure out if an entire road was edited, or just a few lanes.
@@ -332,6 +532,15 @@ impl MapEdits {
                 }
             }
         }
+        // Everything about a new road is a change, and splitting a road changes its length
+        roads.extend(self.created_roads.iter().cloned());
+        for cmd in &self.commands {
+            if let EditCmd::SplitRoad { r, .. } = cmd {
+                if !self.deleted_roads.contains(r) {
+                    roads.insert(*r);
+                }
+            }
+        }
         (lanes, roads)
     }
 
@@ -376,14 +585,38 @@ impl EditCmd {
                 EditIntersection::Closed => format!("close {}", i),
             },
             EditCmd::ChangeCrosswalks { i, .. } => format!("crosswalks at {}", i),
+            EditCmd::ChangeTurnRestrictions { i, old, new } => {
+                for (from, to) in new.0.difference(&old.0) {
+                    details.push(format!("ban {} to {}", from, to));
+                }
+                for (from, to) in old.0.difference(&new.0) {
+                    details.push(format!("allow {} to {}", from, to));
+                }
+                format!("turn restrictions at {}", i)
+            }
+            EditCmd::CreateRoad { r, new } => {
+                details.push(format!("between {} and {}", new.src_i, new.dst_i));
+                format!("new road #{}", r.0)
+            }
+            EditCmd::SplitRoad {
+                r, new_i, new_r, ..
+            } => {
+                details.push(format!("new road #{} from {}", new_r.0, new_i));
+                format!("split road #{}", r.0)
+            }
+            EditCmd::DeleteRoad { r, .. } => format!("delete road #{}", r.0),
             EditCmd::ChangeRouteSchedule { id, .. } => {
                 format!("reschedule route {}", map.get_tr(*id).short_name)
             }
+            EditCmd::ChangeBuilding { b, old, new } => {
+                details = new.diff(old);
+                format!("building #{}", b.0)
+            }
         };
         (summary, details)
     }
 
-    // Must be idempotent
+    // Must be idempotent, except for creating and splitting roads, which always add new IDs
     fn apply(&self, effects: &mut EditEffects, map: &mut Map) {
         match self {
             EditCmd::ChangeRoad { r, ref new, .. } => {
@@ -398,22 +631,7 @@ impl EditCmd {
 
                 effects.changed_roads.insert(road.id);
                 for i in [road.src_i, road.dst_i] {
-                    effects.changed_intersections.insert(i);
-                    let i = &mut map.intersections[i.0];
-                    i.outgoing_lanes.clear();
-                    i.incoming_lanes.clear();
-                    for r in &i.roads {
-                        for lane in &map.roads[r.0].lanes {
-                            if lane.src_i == i.id {
-                                i.outgoing_lanes.push(lane.id);
-                            } else {
-                                assert_eq!(lane.dst_i, i.id);
-                                i.incoming_lanes.push(lane.id);
-                            }
-                        }
-                    }
-
-                    recalculate_turns(i.id, map, effects);
+                    reconnect_intersection(i, map, effects);
                 }
             }
             EditCmd::ChangeIntersection {
@@ -461,14 +679,58 @@ impl EditCmd {
                     map.mut_turn(*turn).turn_type = *turn_type;
                 }
             }
+            EditCmd::ChangeTurnRestrictions { i, ref new, .. } => {
+                if map.get_i_turn_restrictions_edit(*i) == new.clone() {
+                    return;
+                }
+                effects.changed_intersections.insert(*i);
+                // Only override OSM if the restrictions differ, so undoing every edit leaves the
+                // intersection like it started
+                map.intersections[i.0].edited_turn_restrictions = None;
+                if map.get_i_turn_restrictions_edit(*i) != new.clone() {
+                    map.intersections[i.0].edited_turn_restrictions = Some(new.0.clone());
+                }
+                recalculate_turns_keeping_control(*i, map, effects);
+            }
             EditCmd::ChangeRouteSchedule { id, new, .. } => {
                 map.transit_routes[id.0].spawn_times = new.clone();
             }
+            EditCmd::CreateRoad { r, ref new } => {
+                assert_eq!(r.0, map.roads.len());
+                create_road(map, *r, new, effects);
+            }
+            EditCmd::SplitRoad {
+                r,
+                dist,
+                new_i,
+                new_r,
+            } => {
+                assert_eq!(new_r.0, map.roads.len());
+                assert_eq!(new_i.0, map.intersections.len());
+                split_road(map, *r, *dist, *new_i, *new_r, effects);
+            }
+            EditCmd::DeleteRoad { r, .. } => {
+                if map.get_r(*r).is_deleted() {
+                    return;
+                }
+                delete_road(map, *r, effects);
+            }
+            EditCmd::ChangeBuilding { b, ref new, .. } => {
+                if map.get_b_edit(*b) == new.clone() {
+                    return;
+                }
+
+                let bldg = &mut map.buildings[b.0];
+                bldg.bldg_type = new.bldg_type.clone();
+                bldg.amenities = new.amenities.clone();
+                bldg.parking = new.parking.clone();
+                effects.changed_buildings.insert(*b);
+            }
         }
     }
 
-    fn undo(self) -> EditCmd {
-        match self {
+    fn undo(self, effects: &mut EditEffects, map: &mut Map) {
+        let inverse = match self {
             EditCmd::ChangeRoad { r, old, new } => EditCmd::ChangeRoad {
                 r,
                 old: new,
@@ -484,11 +746,300 @@ impl EditCmd {
                 old: new,
                 new: old,
             },
+            EditCmd::ChangeTurnRestrictions { i, old, new } => EditCmd::ChangeTurnRestrictions {
+                i,
+                old: new,
+                new: old,
+            },
             EditCmd::ChangeRouteSchedule { id, old, new } => EditCmd::ChangeRouteSchedule {
                 id,
                 old: new,
                 new: old,
             },
+            EditCmd::CreateRoad { r, .. } => {
+                delete_road(map, r, effects);
+                assert_eq!(map.roads.pop().unwrap().id, r);
+                return;
+            }
+            EditCmd::SplitRoad {
+                r, new_i, new_r, ..
+            } => {
+                unsplit_road(map, r, new_i, new_r, effects);
+                return;
+            }
+            EditCmd::DeleteRoad { r, old } => {
+                connect_road(map, r, &old, effects);
+                return;
+            }
+            EditCmd::ChangeBuilding { b, old, new } => EditCmd::ChangeBuilding {
+                b,
+                old: new,
+                new: old,
+            },
+        };
+        inverse.apply(effects, map);
+    }
+}
+
+// After the roads or lanes of an intersection change, refresh its lanes and turns.
+fn reconnect_intersection(id: IntersectionID, map: &mut Map, effects: &mut EditEffects) {
+    effects.changed_intersections.insert(id);
+    let i = &mut map.intersections[id.0];
+    i.outgoing_lanes.clear();
+    i.incoming_lanes.clear();
+    for r in &i.roads {
+        for lane in &map.roads[r.0].lanes {
+            if lane.src_i == i.id {
+                i.outgoing_lanes.push(lane.id);
+            } else {
+                assert_eq!(lane.dst_i, i.id);
+                i.incoming_lanes.push(lane.id);
+            }
+        }
+    }
+
+    recalculate_turns(id, map, effects);
+}
+
+fn create_road(map: &mut Map, id: RoadID, new: &NewRoad, effects: &mut EditEffects) {
+    let src_i = map.get_i(new.src_i);
+    let dst_i = map.get_i(new.dst_i);
+    let orig_id = OriginalRoad {
+        osm_way_id: map.new_osm_way_id(),
+        i1: src_i.orig_id,
+        i2: dst_i.orig_id,
+    };
+    let percent_incline = (dst_i.elevation - src_i.elevation) / new.center_pts.length();
+    map.roads.push(Road {
+        id,
+        osm_tags: new.osm_tags.clone(),
+        turn_restrictions: Vec::new(),
+        complicated_turn_restrictions: Vec::new(),
+        orig_id,
+        speed_limit: new.road.speed_limit,
+        access_restrictions: new.road.access_restrictions.clone(),
+        zorder: 0,
+        percent_incline,
+        lanes: Vec::new(),
+        // Trimmed when the intersection polygons are recalculated
+        center_pts: new.center_pts.clone(),
+        untrimmed_center_pts: new.center_pts.clone(),
+        src_i: new.src_i,
+        dst_i: new.dst_i,
+        crosswalk_forward: true,
+        crosswalk_backward: true,
+        transit_stops: BTreeSet::new(),
+        barrier_nodes: Vec::new(),
+    });
+    connect_road(map, id, &new.road, effects);
+}
+
+// Attaches a road without lanes to its intersections, then gives it lanes.
+fn connect_road(map: &mut Map, id: RoadID, edit: &EditRoad, effects: &mut EditEffects) {
+    let road = &mut map.roads[id.0];
+    road.speed_limit = edit.speed_limit;
+    road.access_restrictions = edit.access_restrictions.clone();
+    let (src_i, dst_i) = (road.src_i, road.dst_i);
+    for i in [src_i, dst_i] {
+        map.intersections[i.0].roads.insert(id);
+    }
+
+    modify_lanes(map, id, edit.lanes_ltr.clone(), effects);
+    effects.changed_roads.insert(id);
+    for i in [src_i, dst_i] {
+        reconnect_intersection(i, map, effects);
+    }
+}
+
+// The road keeps its ID, but loses its lanes and is detached from its intersections.
+fn delete_road(map: &mut Map, id: RoadID, effects: &mut EditEffects) {
+    effects.deleted_roads.insert(id);
+    let road = &mut map.roads[id.0];
+    for lane in std::mem::take(&mut road.lanes) {
+        effects.deleted_lanes.insert(lane.id);
+        effects.modified_lanes.insert(lane.id);
+    }
+
+    let (src_i, dst_i) = (road.src_i, road.dst_i);
+    for i in [src_i, dst_i] {
+        map.intersections[i.0].roads.remove(&id);
+        // The other roads can extend back into the space the deleted road used
+        for r in recalculate_intersection_polygon(map, id, Distance::ZERO, i) {
+            effects.changed_roads.insert(r);
+            let lane_specs = map.get_r(r).lane_specs();
+            let other = &mut map.roads[r.0];
+            other.recreate_lanes(lane_specs);
+            for lane in &other.lanes {
+                effects.modified_lanes.insert(lane.id);
+            }
+        }
+        reconnect_intersection(i, map, effects);
+    }
+}
+
+fn split_road(
+    map: &mut Map,
+    r: RoadID,
+    dist: Distance,
+    new_i: IntersectionID,
+    new_r: RoadID,
+    effects: &mut EditEffects,
+) {
+    let road = map.get_r(r);
+    let (src_i, dst_i) = (road.src_i, road.dst_i);
+    let length = road.untrimmed_center_pts.length();
+    let first = road.untrimmed_center_pts.exact_slice(Distance::ZERO, dist);
+    let second = road.untrimmed_center_pts.exact_slice(dist, length);
+    let src_elevation = map.get_i(src_i).elevation;
+    let dst_elevation = map.get_i(dst_i).elevation;
+    let elevation = src_elevation + (dst_elevation - src_elevation) * (dist / length);
+    let lane_specs = road.lane_specs();
+    let half_width = road.get_half_width();
+    // Barriers are measured along the trimmed center
+    let split_at = road
+        .center_pts
+        .dist_along_of_point(first.last_pt())
+        .map(|(d, _)| d)
+        .unwrap_or_else(|| road.length() / 2.0);
+
+    let orig_node = map.new_osm_node_id();
+    let new_road = Road {
+        id: new_r,
+        osm_tags: road.osm_tags.clone(),
+        // A restriction only applies where the road it refers to is, so the new road can share
+        // the ones at the far end
+        turn_restrictions: road.turn_restrictions.clone(),
+        complicated_turn_restrictions: road.complicated_turn_restrictions.clone(),
+        orig_id: OriginalRoad {
+            osm_way_id: map.new_osm_way_id(),
+            i1: orig_node,
+            i2: map.get_i(dst_i).orig_id,
+        },
+        speed_limit: road.speed_limit,
+        access_restrictions: road.access_restrictions.clone(),
+        zorder: road.zorder,
+        percent_incline: (dst_elevation - elevation) / second.length(),
+        lanes: Vec::new(),
+        // Trimmed when the intersection polygons are recalculated
+        center_pts: second.clone(),
+        untrimmed_center_pts: second,
+        src_i: new_i,
+        dst_i,
+        crosswalk_forward: road.crosswalk_forward,
+        crosswalk_backward: road.crosswalk_backward,
+        transit_stops: BTreeSet::new(),
+        barrier_nodes: road
+            .barrier_nodes
+            .iter()
+            .filter(|d| **d > split_at)
+            .map(|d| *d - split_at)
+            .collect(),
+    };
+    map.intersections.push(Intersection {
+        id: new_i,
+        // Calculated below
+        polygon: Circle::new(first.last_pt(), half_width).to_polygon(),
+        turns: Vec::new(),
+        elevation,
+        intersection_type: IntersectionType::StopSign,
+        orig_id: orig_node,
+        incoming_lanes: Vec::new(),
+        outgoing_lanes: Vec::new(),
+        roads: [r, new_r].into_iter().collect(),
+        merged: false,
+        edited_turn_restrictions: None,
+        movements: BTreeMap::new(),
+    });
+    map.roads.push(new_road);
+
+    let road = &mut map.roads[r.0];
+    road.percent_incline = (elevation - src_elevation) / first.length();
+    road.center_pts = first.clone();
+    road.untrimmed_center_pts = first;
+    road.dst_i = new_i;
+    road.barrier_nodes.retain(|d| *d < split_at);
+    replace_road_at_intersection(map, dst_i, r, new_r);
+
+    for id in [r, new_r] {
+        modify_lanes(map, id, lane_specs.clone(), effects);
+        effects.changed_roads.insert(id);
+    }
+    for i in [src_i, new_i, dst_i] {
+        reconnect_intersection(i, map, effects);
+    }
+}
+
+// Undoes split_road. The new road and intersection must be the newest ones.
+fn unsplit_road(
+    map: &mut Map,
+    r: RoadID,
+    new_i: IntersectionID,
+    new_r: RoadID,
+    effects: &mut EditEffects,
+) {
+    let new_road = map.roads.pop().unwrap();
+    assert_eq!(new_road.id, new_r);
+    effects.deleted_roads.insert(new_r);
+    for lane in &new_road.lanes {
+        effects.deleted_lanes.insert(lane.id);
+        effects.modified_lanes.insert(lane.id);
+    }
+
+    let intersection = map.intersections.pop().unwrap();
+    assert_eq!(intersection.id, new_i);
+    effects.deleted_intersections.insert(new_i);
+    for t in intersection.turns {
+        effects.deleted_turns.insert(t.id);
+    }
+    map.stop_signs.remove(&new_i);
+    map.traffic_signals.remove(&new_i);
+
+    let dst_i = new_road.dst_i;
+    replace_road_at_intersection(map, dst_i, new_r, r);
+    let src_elevation = map.get_i(map.get_r(r).src_i).elevation;
+    let dst_elevation = map.get_i(dst_i).elevation;
+    let road = &mut map.roads[r.0];
+    let split_at = road.length();
+    road.untrimmed_center_pts = road
+        .untrimmed_center_pts
+        .clone()
+        .must_extend(new_road.untrimmed_center_pts);
+    road.center_pts = road.untrimmed_center_pts.clone();
+    road.percent_incline = (dst_elevation - src_elevation) / road.untrimmed_center_pts.length();
+    road.dst_i = dst_i;
+    road.barrier_nodes
+        .extend(new_road.barrier_nodes.into_iter().map(|d| d + split_at));
+
+    let lane_specs = map.get_r(r).lane_specs();
+    modify_lanes(map, r, lane_specs, effects);
+    effects.changed_roads.insert(r);
+    let src_i = map.get_r(r).src_i;
+    for i in [src_i, dst_i] {
+        reconnect_intersection(i, map, effects);
+    }
+}
+
+// Makes everything at an intersection that refers to one road refer to another: the road list,
+// turn restrictions from other roads, and edited turn restrictions.
+fn replace_road_at_intersection(map: &mut Map, i: IntersectionID, from: RoadID, to: RoadID) {
+    let swap = |r: RoadID| if r == from { to } else { r };
+    let intersection = &mut map.intersections[i.0];
+    intersection.roads.remove(&from);
+    intersection.roads.insert(to);
+    if let Some(banned) = intersection.edited_turn_restrictions.take() {
+        intersection.edited_turn_restrictions = Some(
+            banned
+                .into_iter()
+                .map(|(r1, r2)| (swap(r1), swap(r2)))
+                .collect(),
+        );
+    }
+    for r in intersection.roads.clone() {
+        if r == to {
+            continue;
+        }
+        for (_, restricted_to) in &mut map.roads[r.0].turn_restrictions {
+            *restricted_to = swap(*restricted_to);
         }
     }
 }
@@ -497,21 +1048,62 @@ impl EditCmd {
 // TODO Step 1: Detect and warn about that
 // TODO Step 2: Avoid when possible
 fn recalculate_turns(id: IntersectionID, map: &mut Map, effects: &mut EditEffects) {
+    if !regenerate_turns(id, map, effects) {
+        return;
+    }
+
+    let i = &map.intersections[id.0];
+    match i.intersection_type {
+        IntersectionType::StopSign => {
+            // Stop sign policy usually doesn't depend on incoming lane types, except when changing
+            // to/from construction. To be safe, always regenerate. Edits to stop signs are rare
+            // anyway. And when we're smarter about preserving traffic signal changes in the face
+            // of lane changes, we can do the same here.
+            map.stop_signs.insert(id, ControlStopSign::new(map, id));
+        }
+        IntersectionType::TrafficSignal => {
+            map.traffic_signals
+                .insert(id, ControlTrafficSignal::new(map, id));
+        }
+        IntersectionType::Border | IntersectionType::Construction => unreachable!(),
+    }
+}
+
+// Banning or allowing a movement doesn't change the roads at the intersection, so the stop sign
+// stays as it is, and a traffic signal keeps its timing, plans, and priority.
+fn recalculate_turns_keeping_control(id: IntersectionID, map: &mut Map, effects: &mut EditEffects) {
+    if !regenerate_turns(id, map, effects) {
+        return;
+    }
+
+    if let Some(mut signal) = map.traffic_signals.remove(&id) {
+        if !signal.update_movements(map.get_i(id)) {
+            warn!(
+                "Couldn't keep the traffic signal at {} after changing turn restrictions",
+                id
+            );
+            signal = ControlTrafficSignal::new(map, id);
+        }
+        map.traffic_signals.insert(id, signal);
+    }
+}
+
+// Replaces all turns and movements at an intersection. Returns false for borders and closed
+// intersections, which have no turns to control.
+fn regenerate_turns(id: IntersectionID, map: &mut Map, effects: &mut EditEffects) -> bool {
     let i = &mut map.intersections[id.0];
 
     if i.is_border() {
         assert!(i.turns.is_empty());
-        return;
+        return false;
     }
 
-    let mut old_turns = Vec::new();
     for t in std::mem::take(&mut i.turns) {
         effects.deleted_turns.insert(t.id);
-        old_turns.push(t);
     }
 
     if i.is_closed() {
-        return;
+        return false;
     }
 
     {
@@ -523,23 +1115,8 @@ fn recalculate_turns(id: IntersectionID, map: &mut Map, effects: &mut EditEffect
         }
     }
     let movements = Movement::for_i(id, map);
-    let i = &mut map.intersections[id.0];
-    i.movements = movements;
-
-    match i.intersection_type {
-        IntersectionType::StopSign => {
-            // Stop sign policy usually doesn't depend on incoming lane types, except when changing
-            // to/from construction. To be safe, always regenerate. Edits to stop signs are rare
-            // anyway. And when we're smarter about preserving traffic signal changes in the face
-            // of lane changes, we can do the same here.
-            map.stop_signs.insert(id, ControlStopSign::new(map, id));
-        }
-        IntersectionType::TrafficSignal => {
-            map.traffic_signals
-                .insert(id, ControlTrafficSignal::new(map, id));
-        }
-        IntersectionType::Border | IntersectionType::Construction => unreachable!(),
-    }
+    map.intersections[id.0].movements = movements;
+    true
 }
 
 fn modify_lanes(map: &mut Map, r: RoadID, lanes_ltr: Vec<LaneSpec>, effects: &mut EditEffects) {
@@ -801,11 +1378,166 @@ impl Map {
         EditCrosswalks(turns)
     }
 
+    pub fn get_i_turn_restrictions_edit(&self, i: IntersectionID) -> EditTurnRestrictions {
+        EditTurnRestrictions(self.get_i(i).banned_turns_between_roads(self))
+    }
+
+    pub fn get_b_edit(&self, b: BuildingID) -> EditBuilding {
+        let b = self.get_b(b);
+        EditBuilding {
+            bldg_type: b.bldg_type.clone(),
+            amenities: b.amenities.clone(),
+            parking: b.parking.clone(),
+        }
+    }
+
+    /// Create a road between two intersections, passing through some points along the way. The
+    /// lanes are guessed from the OSM tags.
+    pub fn create_road_cmd(
+        &self,
+        src_i: IntersectionID,
+        dst_i: IntersectionID,
+        via: Vec<Pt2D>,
+        osm_tags: Tags,
+        speed_limit: Speed,
+    ) -> Result<EditCmd> {
+        let mut pts = vec![self.get_i(src_i).polygon.center()];
+        pts.extend(via);
+        pts.push(self.get_i(dst_i).polygon.center());
+        let new = NewRoad {
+            src_i,
+            dst_i,
+            center_pts: PolyLine::new(pts)?,
+            road: EditRoad {
+                lanes_ltr: get_lane_specs_ltr(&osm_tags, &self.config),
+                speed_limit,
+                access_restrictions: AccessRestrictions::new(),
+            },
+            osm_tags,
+        };
+        self.validate_new_road(&new)?;
+        Ok(EditCmd::CreateRoad {
+            r: RoadID(self.roads.len()),
+            new,
+        })
+    }
+
+    /// Split a road in two at a new intersection, some distance along its center.
+    pub fn split_road_cmd(&self, r: RoadID, dist: Distance) -> Result<EditCmd> {
+        let road = self.get_r(r);
+        let (pt, _) = road.center_pts.dist_along(dist)?;
+        let (dist, _) = road
+            .untrimmed_center_pts
+            .dist_along_of_point(pt)
+            .ok_or_else(|| anyhow!("{} isn't along the center of {}", pt, r))?;
+        self.validate_split_road(r, dist)?;
+        Ok(EditCmd::SplitRoad {
+            r,
+            dist,
+            new_i: IntersectionID(self.intersections.len()),
+            new_r: RoadID(self.roads.len()),
+        })
+    }
+
+    /// Delete any road, whether it's from the basemap or created by edits. Roads with transit
+    /// stops, or that're the only road at one of their intersections, can't be deleted.
+    pub fn delete_road_cmd(&self, r: RoadID) -> Result<EditCmd> {
+        let road = self.get_r(r);
+        if road.is_deleted() {
+            bail!("{} was already deleted", r);
+        }
+        if !road.transit_stops.is_empty() {
+            bail!("{} has transit stops, so it can't be deleted", r);
+        }
+        for i in [road.src_i, road.dst_i] {
+            let i = self.get_i(i);
+            if i.is_closed() {
+                bail!("Reopen {} before deleting {}", i.id, r);
+            }
+            if i.roads.len() == 1 {
+                bail!("Deleting {} would leave nothing at {}", r, i.id);
+            }
+        }
+        Ok(EditCmd::DeleteRoad {
+            r,
+            old: self.get_r_edit(r),
+        })
+    }
+
+    pub(crate) fn validate_new_road(&self, new: &NewRoad) -> Result<()> {
+        if new.src_i == new.dst_i {
+            bail!("A new road can't start and end at {}", new.src_i);
+        }
+        for i in [new.src_i, new.dst_i] {
+            let i = self.get_i(i);
+            if i.is_border() {
+                bail!("A new road can't connect to the map border at {}", i.id);
+            }
+            if i.is_closed() {
+                bail!("A new road can't connect to {}, because it's closed", i.id);
+            }
+        }
+        if new.road.lanes_ltr.is_empty() {
+            bail!("A new road needs at least one lane");
+        }
+        Ok(())
+    }
+
+    /// `dist` is along the road's center, before trimming it to the intersection polygons.
+    pub(crate) fn validate_split_road(&self, r: RoadID, dist: Distance) -> Result<()> {
+        let road = self.get_r(r);
+        if road.is_deleted() {
+            bail!("{} was deleted", r);
+        }
+        if !road.transit_stops.is_empty() {
+            bail!("{} has transit stops, so it can't be split", r);
+        }
+        for i in [road.src_i, road.dst_i] {
+            if self.get_i(i).is_closed() {
+                bail!("Reopen {} before splitting {}", i, r);
+            }
+        }
+        // Leave room for the new intersection, away from the existing ones
+        let (pt, _) = road.untrimmed_center_pts.dist_along(dist)?;
+        let buffer = road.get_width();
+        match road.center_pts.dist_along_of_point(pt) {
+            Some((trimmed_dist, _))
+                if trimmed_dist >= buffer && trimmed_dist <= road.length() - buffer =>
+            {
+                Ok(())
+            }
+            _ => bail!("{} can only be split at least {} from its ends", r, buffer),
+        }
+    }
+
+    /// A placeholder OSM node ID for an intersection created by edits. It's deterministic, like
+    /// new_osm_way_id.
+    fn new_osm_node_id(&self) -> osm::NodeID {
+        let lowest = self
+            .all_intersections()
+            .iter()
+            .map(|i| i.orig_id.0)
+            .min()
+            .unwrap_or(0);
+        osm::NodeID(lowest.min(0) - 1)
+    }
+
+    /// A placeholder OSM way ID for a road created by edits. It's deterministic, so that edits
+    /// referring to the new road can be loaded later.
+    fn new_osm_way_id(&self) -> osm::WayID {
+        let lowest = self
+            .all_roads()
+            .iter()
+            .map(|r| r.orig_id.osm_way_id.0)
+            .min()
+            .unwrap_or(0);
+        osm::WayID(lowest.min(0) - 1)
+    }
+
     pub fn save_edits(&self) {
         // Don't overwrite the current edits with the compressed first. Otherwise, undo/redo order
         // in the UI gets messed up.
         let mut edits = self.edits.clone();
-        edits.commands.clear();
         edits.compress(self);
         edits.save(self);
     }
@@ -835,15 +1567,7 @@ impl Map {
     ) -> EditEffects {
         self.edits_generation += 1;
 
-        let mut effects = EditEffects {
-            changed_roads: BTreeSet::new(),
-            deleted_lanes: BTreeSet::new(),
-            changed_intersections: BTreeSet::new(),
-            added_turns: BTreeSet::new(),
-            deleted_turns: BTreeSet::new(),
-            changed_parking_lots: BTreeSet::new(),
-            modified_lanes: BTreeSet::new(),
-        };
+        let mut effects = EditEffects::new();
 
         // Short-circuit to avoid marking pathfinder_dirty
         if self.edits == new_edits {
@@ -866,12 +1590,7 @@ impl Map {
         timer.start_iter("undo old edits", self.edits.commands.len() - start_at_idx);
         for _ in start_at_idx..self.edits.commands.len() {
             timer.next();
-            self.edits
-                .commands
-                .pop()
-                .unwrap()
-                .undo()
-                .apply(&mut effects, self);
+            self.edits.commands.pop().unwrap().undo(&mut effects, self);
         }
 
         timer.start_iter("apply new edits", new_edits.commands.len() - start_at_idx);
@@ -880,6 +1599,15 @@ impl Map {
             cmd.apply(&mut effects, self);
         }
 
+        // Roads might've been created and then deleted, or the other way around
+        let roads = &self.roads;
+        effects
+            .changed_roads
+            .retain(|r| roads.get(r.0).map(|r| !r.is_deleted()).unwrap_or(false));
+        effects
+            .deleted_roads
+            .retain(|r| roads.get(r.0).map(|r| r.is_deleted()).unwrap_or(true));
+
         timer.start("re-snap buildings");
         let mut recalc_buildings = Vec::new();
         for b in self.all_buildings() {
@@ -929,8 +1657,11 @@ impl Map {
         self.pathfinder_dirty = true;
 
         // Update zones after setting the new edits, since it'll pull merge_zones from there
-        if !effects.changed_roads.is_empty() || merge_zones_changed {
-            self.zones = Zone::make_all(self);
+        if !effects.changed_roads.is_empty()
+            || !effects.deleted_roads.is_empty()
+            || merge_zones_changed
+        {
+            self.recalculate_zones();
         }
 
         // Some of these might've been added, then later deleted.
@@ -949,6 +1680,14 @@ impl Map {
         effects
             .changed_intersections
             .extend(more_changed_intersections);
+        // Intersections made by splitting roads might've been removed again
+        let num_intersections = self.intersections.len();
+        effects
+            .changed_intersections
+            .retain(|i| i.0 < num_intersections);
+        effects
+            .deleted_intersections
+            .retain(|i| i.0 >= num_intersections);
 
         self.recalculate_road_to_buildings();
 
@@ -4,12 +4,15 @@ use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
 use abstio::MapName;
-use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::Time;
+use abstutil::{deserialize_btreemap, serialize_btreemap, Tags, Timer};
+use geom::{Distance, LonLat, PolyLine, Time};
 
-use crate::edits::{EditCmd, EditCrosswalks, EditIntersection, EditRoad, MapEdits};
+use crate::edits::{
+    compat, EditBuilding, EditCmd, EditCrosswalks, EditEffects, EditIntersection, EditRoad,
+    EditTurnRestrictions, MapEdits, NewRoad,
+};
 use crate::raw::OriginalRoad;
-use crate::{osm, ControlStopSign, IntersectionID, Map, MovementID, TurnType};
+use crate::{osm, ControlStopSign, IntersectionID, Map, MovementID, RoadID, TurnType};
 
 // Manually change this to attempt to preserve edits after major OSM updates.
 const IGNORE_OLD_LANES: bool = false;
@@ -56,6 +59,20 @@ pub struct PermanentEditCrosswalks {
     turns: BTreeMap<traffic_signal_data::Turn, TurnType>,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PermanentEditTurnRestrictions {
+    banned: BTreeSet<(OriginalRoad, OriginalRoad)>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PermanentNewRoad {
+    i1: osm::NodeID,
+    i2: osm::NodeID,
+    center_pts: Vec<LonLat>,
+    osm_tags: Tags,
+    road: EditRoad,
+}
+
 #[allow(clippy::enum_variant_names)]
 #[derive(Serialize, Deserialize, Clone)]
 pub enum PermanentEditCmd {
@@ -74,11 +91,33 @@ pub enum PermanentEditCmd {
         new: PermanentEditCrosswalks,
         old: PermanentEditCrosswalks,
     },
+    ChangeTurnRestrictions {
+        i: osm::NodeID,
+        new: PermanentEditTurnRestrictions,
+        old: PermanentEditTurnRestrictions,
+    },
     ChangeRouteSchedule {
         gtfs_id: String,
         old: Vec<Time>,
         new: Vec<Time>,
     },
+    /// The new road's ID and OSM ID are assigned when the command is loaded
+    CreateRoad {
+        new: PermanentNewRoad,
+    },
+    /// The new intersection and road's IDs and OSM IDs are assigned when the command is loaded
+    SplitRoad {
+        r: OriginalRoad,
+        dist: Distance,
+    },
+    DeleteRoad {
+        r: OriginalRoad,
+    },
+    ChangeBuilding {
+        b: osm::OsmID,
+        new: EditBuilding,
+        old: EditBuilding,
+    },
 }
 
 impl EditCmd {
@@ -99,6 +138,13 @@ impl EditCmd {
                 new: new.to_permanent(map),
                 old: old.to_permanent(map),
             },
+            EditCmd::ChangeTurnRestrictions { i, new, old } => {
+                PermanentEditCmd::ChangeTurnRestrictions {
+                    i: map.get_i(*i).orig_id,
+                    new: new.to_permanent(map),
+                    old: old.to_permanent(map),
+                }
+            }
             EditCmd::ChangeRouteSchedule { id, old, new } => {
                 PermanentEditCmd::ChangeRouteSchedule {
                     gtfs_id: map.get_tr(*id).gtfs_id.clone(),
@@ -106,6 +152,21 @@ impl EditCmd {
                     new: new.clone(),
                 }
             }
+            EditCmd::CreateRoad { new, .. } => PermanentEditCmd::CreateRoad {
+                new: new.to_permanent(map),
+            },
+            EditCmd::SplitRoad { r, dist, .. } => PermanentEditCmd::SplitRoad {
+                r: map.get_r(*r).orig_id,
+                dist: *dist,
+            },
+            EditCmd::DeleteRoad { r, .. } => PermanentEditCmd::DeleteRoad {
+                r: map.get_r(*r).orig_id,
+            },
+            EditCmd::ChangeBuilding { b, new, old } => PermanentEditCmd::ChangeBuilding {
+                b: map.get_b(*b).orig_id,
+                new: new.clone(),
+                old: old.clone(),
+            },
         }
     }
 }
@@ -161,12 +222,48 @@ impl PermanentEditCmd {
                         .with_context(|| format!("old ChangeCrosswalks of {} invalid", i))?,
                 })
             }
+            PermanentEditCmd::ChangeTurnRestrictions { i, new, old } => {
+                let id = map.find_i_by_osm_id(i)?;
+                Ok(EditCmd::ChangeTurnRestrictions {
+                    i: id,
+                    new: new
+                        .with_permanent(id, map)
+                        .with_context(|| format!("new ChangeTurnRestrictions of {} invalid", i))?,
+                    old: old
+                        .with_permanent(id, map)
+                        .with_context(|| format!("old ChangeTurnRestrictions of {} invalid", i))?,
+                })
+            }
             PermanentEditCmd::ChangeRouteSchedule { gtfs_id, old, new } => {
                 let id = map
                     .find_tr_by_gtfs(&gtfs_id)
                     .ok_or_else(|| anyhow!("can't find {}", gtfs_id))?;
                 Ok(EditCmd::ChangeRouteSchedule { id, old, new })
             }
+            PermanentEditCmd::CreateRoad { new } => Ok(EditCmd::CreateRoad {
+                r: RoadID(map.all_roads().len()),
+                new: new.with_permanent(map).context("invalid CreateRoad")?,
+            }),
+            PermanentEditCmd::SplitRoad { r, dist } => {
+                let id = map.find_r_by_osm_id(r)?;
+                map.validate_split_road(id, dist)
+                    .with_context(|| format!("invalid SplitRoad of {}", r))?;
+                Ok(EditCmd::SplitRoad {
+                    r: id,
+                    dist,
+                    new_i: IntersectionID(map.all_intersections().len()),
+                    new_r: RoadID(map.all_roads().len()),
+                })
+            }
+            PermanentEditCmd::DeleteRoad { r } => map
+                .delete_road_cmd(map.find_r_by_osm_id(r)?)
+                .with_context(|| format!("invalid DeleteRoad of {}", r)),
+            PermanentEditCmd::ChangeBuilding { b, new, old } => {
+                let id = map
+                    .find_b_by_osm_id(b)
+                    .ok_or_else(|| anyhow!("can't find {}", b))?;
+                Ok(EditCmd::ChangeBuilding { b: id, new, old })
+            }
         }
     }
 }
@@ -178,7 +275,7 @@ impl MapEdits {
             map_name: map.get_name().clone(),
             edits_name: self.edits_name.clone(),
             // Increase this every time there's a schema change
-            version: 11,
+            version: compat::LATEST_VERSION,
             proposal_description: self.proposal_description.clone(),
             proposal_link: self.proposal_link.clone(),
             commands: self.commands.iter().map(|cmd| cmd.to_perma(map)).collect(),
@@ -188,66 +285,95 @@ impl MapEdits {
 }
 
 impl PermanentMapEdits {
+    /// Deserialize edits from JSON, upgrading anything saved in an older version of the format.
+    pub fn from_json(value: serde_json::Value, map: &Map) -> Result<PermanentMapEdits> {
+        compat::upgrade(value, map)
+    }
+
     /// Transform permanent edits to MapEdits, looking up the map IDs by the hopefully stabler OSM
     /// IDs. Validate that the basemap hasn't changed in important ways.
     pub fn into_edits(self, map: &Map) -> Result<MapEdits> {
-        let mut edits = MapEdits {
-            edits_name: self.edits_name,
-            proposal_description: self.proposal_description,
-            proposal_link: self.proposal_link,
-            commands: self
-                .commands
-                .into_iter()
-                .map(|cmd| cmd.into_cmd(map))
-                .collect::<Result<Vec<EditCmd>>>()?,
-            merge_zones: self.merge_zones,
-
-            changed_roads: BTreeSet::new(),
-            original_intersections: BTreeMap::new(),
-            original_crosswalks: BTreeMap::new(),
-            changed_routes: BTreeSet::new(),
-        };
-        edits.update_derived(map);
-        Ok(edits)
+        self.into_edits_impl(map, false)
     }
 
     /// Transform permanent edits to MapEdits, looking up the map IDs by the hopefully stabler OSM
     /// IDs. Strip out commands that're broken, but log warnings.
     pub fn into_edits_permissive(self, map: &Map) -> MapEdits {
+        // Broken commands are skipped, so this never fails
+        self.into_edits_impl(map, true).unwrap()
+    }
+
+    /// Get the human-friendly of these edits. If they have a descrption, the first line is the
+    /// title. Otherwise we use the filename.
+    pub fn get_title(&self) -> &str {
+        if self.proposal_description.is_empty() {
+            &self.edits_name
+        } else {
+            &self.proposal_description[0]
+        }
+    }
+
+    /// If `permissive`, skip broken commands with a warning.
+    fn into_edits_impl(self, map: &Map, permissive: bool) -> Result<MapEdits> {
         let mut edits = MapEdits {
             edits_name: self.edits_name,
             proposal_description: self.proposal_description,
             proposal_link: self.proposal_link,
-            commands: self
-                .commands
-                .into_iter()
-                .filter_map(|cmd| match cmd.into_cmd(map) {
-                    Ok(cmd) => Some(cmd),
-                    Err(err) => {
-                        warn!("Skipping broken command: {}", err);
-                        None
-                    }
-                })
-                .collect(),
+            commands: Vec::new(),
             merge_zones: self.merge_zones,
 
             changed_roads: BTreeSet::new(),
+            created_roads: BTreeSet::new(),
+            deleted_roads: BTreeSet::new(),
             original_intersections: BTreeMap::new(),
             original_crosswalks: BTreeMap::new(),
+            original_turn_restrictions: BTreeMap::new(),
             changed_routes: BTreeSet::new(),
+            original_buildings: BTreeMap::new(),
         };
-        edits.update_derived(map);
-        edits
-    }
 
-    /// Get the human-friendly of these edits. If they have a descrption, the first line is the
-    /// title. Otherwise we use the filename.
-    pub fn get_title(&self) -> &str {
-        if self.proposal_description.is_empty() {
-            &self.edits_name
+        // Commands may refer to roads and intersections created by earlier commands, and whether
+        // a road can be split or deleted depends on the earlier commands. So look things up in a
+        // copy of the map, applying each command after converting it. Copying is slow, so only do
+        // it when needed.
+        let mut copy = if self.commands.iter().any(|cmd| {
+            matches!(
+                cmd,
+                PermanentEditCmd::CreateRoad { .. }
+                    | PermanentEditCmd::SplitRoad { .. }
+                    | PermanentEditCmd::DeleteRoad { .. }
+            )
+        }) || !map.get_edits().created_roads.is_empty()
+            || !map.get_edits().deleted_roads.is_empty()
+        {
+            let mut copy = map.clone();
+            copy.try_apply_edits(MapEdits::new(), &mut Timer::throwaway());
+            Some(copy)
         } else {
-            &self.proposal_description[0]
+            None
+        };
+
+        for cmd in self.commands {
+            match cmd.into_cmd(copy.as_ref().unwrap_or(map)) {
+                Ok(cmd) => {
+                    if let Some(ref mut copy) = copy {
+                        // Later commands only need the roads, intersections, and turns to be
+                        // updated, so skip the rest of what applying edits normally does
+                        cmd.apply(&mut EditEffects::new(), copy);
+                    }
+                    edits.commands.push(cmd);
+                }
+                Err(err) => {
+                    if !permissive {
+                        return Err(err);
+                    }
+                    warn!("Skipping broken command: {}", err);
+                }
+            }
         }
+
+        edits.update_derived(copy.as_ref().unwrap_or(map));
+        Ok(edits)
     }
 }
 
@@ -339,3 +465,57 @@ impl PermanentEditCrosswalks {
         Ok(EditCrosswalks(turns))
     }
 }
+
+impl EditTurnRestrictions {
+    fn to_permanent(&self, map: &Map) -> PermanentEditTurnRestrictions {
+        PermanentEditTurnRestrictions {
+            banned: self
+                .0
+                .iter()
+                .map(|(from, to)| (map.get_r(*from).orig_id, map.get_r(*to).orig_id))
+                .collect(),
+        }
+    }
+}
+
+impl PermanentEditTurnRestrictions {
+    fn with_permanent(self, i: IntersectionID, map: &Map) -> Result<EditTurnRestrictions> {
+        let roads = &map.get_i(i).roads;
+        let mut banned = BTreeSet::new();
+        for (from, to) in self.banned {
+            let from = map.find_r_by_osm_id(from)?;
+            let to = map.find_r_by_osm_id(to)?;
+            if !roads.contains(&from) || !roads.contains(&to) {
+                bail!("{} doesn't connect {} and {}", i, from, to);
+            }
+            banned.insert((from, to));
+        }
+        Ok(EditTurnRestrictions(banned))
+    }
+}
+
+impl NewRoad {
+    fn to_permanent(&self, map: &Map) -> PermanentNewRoad {
+        PermanentNewRoad {
+            i1: map.get_i(self.src_i).orig_id,
+            i2: map.get_i(self.dst_i).orig_id,
+            center_pts: map.get_gps_bounds().convert_back(self.center_pts.points()),
+            osm_tags: self.osm_tags.clone(),
+            road: self.road.clone(),
+        }
+    }
+}
+
+impl PermanentNewRoad {
+    fn with_permanent(self, map: &Map) -> Result<NewRoad> {
+        let new = NewRoad {
+            src_i: map.find_i_by_osm_id(self.i1)?,
+            dst_i: map.find_i_by_osm_id(self.i2)?,
+            center_pts: PolyLine::new(map.get_gps_bounds().convert(&self.center_pts))?,
+            osm_tags: self.osm_tags,
+            road: self.road,
+        };
+        map.validate_new_road(&new)?;
+        Ok(new)
+    }
+}
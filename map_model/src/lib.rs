@@ -28,13 +28,13 @@ extern crate anyhow;
 #[macro_use]
 extern crate log;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use serde::{Deserialize, Serialize};
 
 use abstio::MapName;
 use abstutil::{deserialize_btreemap, serialize_btreemap, MultiMap};
-use geom::{Bounds, GPSBounds, Polygon};
+use geom::{Bounds, GPSBounds, Polygon, Time};
 pub use raw_map as raw;
 pub use raw_map::{
     osm, Amenity, AmenityType, AreaType, BufferType, Direction, DrivingSide, IntersectionType,
@@ -43,7 +43,8 @@ pub use raw_map::{
 
 pub use crate::city::City;
 pub use crate::edits::{
-    EditCmd, EditEffects, EditIntersection, EditRoad, MapEdits, PermanentMapEdits,
+    optimize_green_wave, signals_along_path, EditBuilding, EditCmd, EditEffects, EditIntersection,
+    EditRoad, GreenWave, GreenWaveOptions, MapEdits, NewRoad, PermanentMapEdits,
 };
 pub use crate::make::RawToMapOptions;
 pub use crate::objects::area::{Area, AreaID};
@@ -52,19 +53,25 @@ pub use crate::objects::building::{Building, BuildingID, BuildingType, Offstreet
 pub use crate::objects::intersection::{Intersection, IntersectionID};
 pub use crate::objects::lane::{CommonEndpoint, Lane, LaneID, PARKING_LOT_SPOT_LENGTH};
 pub use crate::objects::movement::{CompressedMovementID, Movement, MovementID};
-pub use crate::objects::parking_lot::{ParkingLot, ParkingLotID};
+pub use crate::objects::parking_lot::{ParkingLot, ParkingLotID, ParkingRate};
 pub use crate::objects::road::{DirectedRoadID, Road, RoadID, RoadSideID, SideOfRoad};
 pub use crate::objects::stop_signs::{ControlStopSign, RoadWithStopSign};
-pub use crate::objects::traffic_signals::{ControlTrafficSignal, Stage, StageType};
-pub use crate::objects::transit::{TransitRoute, TransitRouteID, TransitStop, TransitStopID};
+pub use crate::objects::traffic_signals::{
+    ControlTrafficSignal, Plan, SignalPriority, Stage, StageType,
+};
+pub use crate::objects::transit::{
+    ScheduledStop, TransitRoute, TransitRouteID, TransitRun, TransitStop, TransitStopID,
+};
 pub use crate::objects::turn::{Turn, TurnID, TurnPriority, TurnType};
-pub use crate::objects::zone::{AccessRestrictions, Zone};
+pub use crate::objects::zone::{AccessRestrictions, TimeWindow, Zone};
 pub use crate::pathfind::uber_turns::{IntersectionCluster, UberTurn};
 pub use crate::pathfind::{
     Path, PathConstraints, PathRequest, PathStep, PathStepV2, PathV2, Pathfinder, PathfinderCache,
     PathfinderCaching, RoutingParams,
 };
-pub use crate::traversable::{Position, Traversable, MAX_BIKE_SPEED, MAX_WALKING_SPEED};
+pub use crate::traversable::{
+    Position, Traversable, MAX_BIKE_SPEED, MAX_TRUCK_SPEED, MAX_WALKING_SPEED,
+};
 
 mod city;
 pub mod connectivity;
@@ -105,6 +112,7 @@ pub struct Map {
     routing_params: RoutingParams,
     // Not the source of truth, just cached.
     zones: Vec<Zone>,
+    zone_window_changes: BTreeSet<Time>,
 
     name: MapName,
 
@@ -10,7 +10,7 @@ use crate::make::{match_points_to_lanes, trim_path};
 use crate::raw::RawBuilding;
 use crate::{
     osm, Amenity, Building, BuildingID, BuildingType, LaneID, Map, NamePerLanguage,
-    OffstreetParking,
+    OffstreetParking, ParkingRate,
 };
 
 /// Finalize importing of buildings, mostly by matching them to the nearest sidewalk.
@@ -105,6 +105,11 @@ pub fn make_all_buildings(
                         b.osm_tags.is("building", "parking") || b.osm_tags.is("amenity", "parking"),
                     )
                 },
+                garage_rate: if b.public_garage_name.is_some() {
+                    ParkingRate::from_osm(&b.osm_tags)
+                } else {
+                    None
+                },
                 osm_tags: if keep_bldg_tags {
                     b.osm_tags.clone()
                 } else {
@@ -17,7 +17,7 @@ use crate::raw::{OriginalRoad, RawMap};
 use crate::{
     connectivity, osm, AccessRestrictions, Area, AreaID, ControlStopSign, ControlTrafficSignal,
     Intersection, IntersectionID, IntersectionType, Lane, LaneID, Map, MapEdits, PathConstraints,
-    Position, Road, RoadID, RoutingParams, Zone,
+    Position, Road, RoadID, RoutingParams,
 };
 
 mod bridges;
@@ -63,6 +63,7 @@ impl Map {
             areas: Vec::new(),
             parking_lots: Vec::new(),
             zones: Vec::new(),
+            zone_window_changes: BTreeSet::new(),
             boundary_polygon: raw.streets.boundary_polygon.clone(),
             stop_signs: BTreeMap::new(),
             traffic_signals: BTreeMap::new(),
@@ -103,6 +104,7 @@ impl Map {
                 merged: !raw.streets.intersections[&i.id]
                     .trim_roads_for_merging
                     .is_empty(),
+                edited_turn_restrictions: None,
             });
             intersection_id_mapping.insert(i.id, id);
         }
@@ -245,7 +247,7 @@ impl Map {
             timer,
         );
 
-        map.zones = Zone::make_all(&map);
+        map.recalculate_zones();
 
         for a in &raw.areas {
             map.areas.push(Area {
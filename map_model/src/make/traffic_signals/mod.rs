@@ -107,6 +107,8 @@ fn new(id: IntersectionID) -> ControlTrafficSignal {
         id,
         stages: Vec::new(),
         offset: Duration::ZERO,
+        plans: Vec::new(),
+        priority: None,
     }
 }
 
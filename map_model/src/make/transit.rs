@@ -8,8 +8,8 @@ use geom::{Distance, Duration, FindClosest, HashablePt2D, Time};
 use crate::make::match_points_to_lanes;
 use crate::raw::{RawMap, RawTransitRoute, RawTransitStop, RawTransitType};
 use crate::{
-    LaneID, Map, PathConstraints, Position, TransitRoute, TransitRouteID, TransitStop,
-    TransitStopID,
+    LaneID, Map, PathConstraints, Position, ScheduledStop, TransitRoute, TransitRouteID,
+    TransitRun, TransitStop, TransitStopID,
 };
 
 pub fn finalize_transit(map: &mut Map, raw: &RawMap, timer: &mut Timer) {
@@ -148,11 +148,14 @@ fn create_route(
     snapper: &BorderSnapper,
 ) -> Result<()> {
     // TODO At least warn about stops that failed to snap
-    let stops: Vec<TransitStopID> = route
+    // Remember the index of each raw stop, to look up the schedule
+    let snapped: Vec<(usize, TransitStopID)> = route
         .stops
         .iter()
-        .filter_map(|gtfs_id| gtfs_to_stop_id.get(gtfs_id).cloned())
+        .enumerate()
+        .filter_map(|(idx, gtfs_id)| gtfs_to_stop_id.get(gtfs_id).map(|id| (idx, *id)))
         .collect();
+    let stops: Vec<TransitStopID> = snapped.iter().map(|(_, id)| *id).collect();
     if stops.is_empty() {
         bail!("No valid stops");
     }
@@ -221,12 +224,12 @@ fn create_route(
         }
     };
 
-    // TODO This'll come from the RawTransitRoute eventually. For now, every 30 minutes.
+    // Without a timetable, run every 30 minutes.
     let spawn_times: Vec<Time> = (0..48)
         .map(|i| Time::START_OF_DAY + (i as f64) * Duration::minutes(30))
         .collect();
 
-    let result = TransitRoute {
+    let mut result = TransitRoute {
         id: TransitRouteID(map.transit_routes.len()),
         long_name: route.long_name.clone(),
         short_name: route.short_name.clone(),
@@ -240,10 +243,48 @@ fn create_route(
         },
         spawn_times: spawn_times.clone(),
         orig_spawn_times: spawn_times,
+        timetable: Vec::new(),
     };
 
     // Check that the paths are valid
-    result.all_paths(map)?;
+    let paths = result.all_paths(map)?;
+
+    if !route.schedule.is_empty() {
+        let to_first_stop = paths[0].estimate_duration(map, None);
+        let mut timetable: Vec<TransitRun> = route
+            .schedule
+            .iter()
+            .map(|run| TransitRun {
+                spawn_time: run.stop_times[snapped[0].0]
+                    .arrival
+                    .clamped_sub(to_first_stop),
+                stops: snapped
+                    .iter()
+                    .map(|(idx, _)| {
+                        let st = &run.stop_times[*idx];
+                        ScheduledStop {
+                            arrival: st.arrival,
+                            departure: st.departure,
+                            timepoint: st.timepoint,
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+        // Runs are identified by their spawn time, so when several share one, like two trips
+        // departing together or a few clamped to midnight, stagger them by a second each.
+        timetable.sort_by_key(|run| run.spawn_time);
+        for idx in 1..timetable.len() {
+            let earliest = timetable[idx - 1].spawn_time + Duration::seconds(1.0);
+            if timetable[idx].spawn_time < earliest {
+                timetable[idx].spawn_time = earliest;
+            }
+        }
+
+        result.spawn_times = timetable.iter().map(|run| run.spawn_time).collect();
+        result.orig_spawn_times = result.spawn_times.clone();
+        result.timetable = timetable;
+    }
 
     map.transit_routes.push(result);
     Ok(())
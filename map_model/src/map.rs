@@ -135,6 +135,7 @@ impl Map {
             areas: Vec::new(),
             parking_lots: Vec::new(),
             zones: Vec::new(),
+            zone_window_changes: BTreeSet::new(),
             boundary_polygon: Ring::must_new(vec![
                 Pt2D::new(0.0, 0.0),
                 Pt2D::new(1.0, 0.0),
@@ -204,7 +205,7 @@ impl Map {
     pub fn maybe_get_t(&self, id: TurnID) -> Option<&Turn> {
         // Looking up the intersection is fast. Linearly scanning through all of the turns to find
         // this one actually turns out to be fast too; thanks cache locality.
-        for turn in &self.intersections[id.parent.0].turns {
+        for turn in &self.intersections.get(id.parent.0)?.turns {
             if turn.id == id {
                 return Some(turn);
             }
@@ -546,6 +547,11 @@ impl Map {
         &self.boundary_polygon
     }
 
+    pub(crate) fn recalculate_zones(&mut self) {
+        self.zones = Zone::make_all(self);
+        self.zone_window_changes = Zone::window_changes(&self.zones);
+    }
+
     pub fn get_pathfinder(&self) -> &Pathfinder {
         &self.pathfinder
     }
@@ -562,6 +568,29 @@ impl Map {
         self.pathfind_v2_with_params(req, params, cache_custom)?
             .into_v1(self)
     }
+    /// Like `pathfind`, but honors the time windows of access-restricted zones as of some time.
+    pub fn pathfind_at(&self, req: PathRequest, time: Time) -> Result<Path> {
+        self.pathfind_with_params_at(req, &self.routing_params, time, PathfinderCaching::CacheCH)
+    }
+    /// Like `pathfind_with_params`, but honors the time windows of access-restricted zones as of
+    /// some time. The restrictions only change at a few times of day, so with caching, a
+    /// pathfinder is built once per change.
+    pub fn pathfind_with_params_at(
+        &self,
+        req: PathRequest,
+        params: &RoutingParams,
+        time: Time,
+        cache_custom: PathfinderCaching,
+    ) -> Result<Path> {
+        match Zone::restrictions_changed_at(self, time) {
+            Some(changed_at) => {
+                let mut params = params.clone();
+                params.access_restrictions_at = Some(changed_at);
+                self.pathfind_with_params(req, &params, cache_custom)
+            }
+            None => self.pathfind_with_params(req, params, cache_custom),
+        }
+    }
     pub fn pathfind_v2(&self, req: PathRequest) -> Result<PathV2> {
         assert!(!self.pathfinder_dirty);
         self.pathfinder
@@ -613,7 +642,7 @@ impl Map {
 
     pub fn find_r_by_osm_id(&self, id: OriginalRoad) -> Result<RoadID> {
         for r in self.all_roads() {
-            if r.orig_id == id {
+            if r.orig_id == id && !r.is_deleted() {
                 return Ok(r.id);
             }
         }
@@ -720,7 +749,7 @@ impl Map {
     ) -> Option<(Vec<RoadID>, Vec<IntersectionID>)> {
         let mut graph: UnGraphMap<IntersectionID, RoadID> = UnGraphMap::new();
         for r in self.all_roads() {
-            if !r.is_light_rail() {
+            if !r.is_light_rail() && !r.is_deleted() {
                 graph.add_edge(r.src_i, r.dst_i, r.id);
             }
         }
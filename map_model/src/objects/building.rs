@@ -7,7 +7,7 @@ use abstutil::{deserialize_usize, serialize_usize, Tags};
 use geom::{Distance, PolyLine, Polygon, Pt2D};
 use raw_map::{Amenity, AmenityType, NamePerLanguage};
 
-use crate::{osm, LaneID, Map, PathConstraints, Position};
+use crate::{osm, LaneID, Map, ParkingRate, PathConstraints, Position};
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct BuildingID(
@@ -40,6 +40,8 @@ pub struct Building {
     pub amenities: Vec<Amenity>,
     pub bldg_type: BuildingType,
     pub parking: OffstreetParking,
+    /// What it costs to park in a public garage, if anything
+    pub garage_rate: Option<ParkingRate>,
     /// Depending on options while importing, these might be empty, to save file space.
     pub osm_tags: Tags,
 
@@ -59,7 +61,7 @@ pub enum OffstreetParking {
     Private(usize, bool),
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum BuildingType {
     Residential {
         num_residents: usize,
@@ -8,6 +8,7 @@ use abstutil::{deserialize_usize, serialize_usize};
 use geom::{Distance, Polygon};
 use raw_map::IntersectionType;
 
+use crate::raw::RestrictionType;
 use crate::{
     osm, CompressedMovementID, DirectedRoadID, LaneID, Map, Movement, MovementID, PathConstraints,
     Road, RoadID, RoadSideID, SideOfRoad, Turn, TurnID,
@@ -54,6 +55,9 @@ pub struct Intersection {
 
     /// Was a short road adjacent to this intersection merged?
     pub merged: bool,
+    /// Map edits can replace the OSM turn restrictions here with this list of banned movements
+    /// between roads, as (from, to). A U-turn goes from a road back onto itself.
+    pub edited_turn_restrictions: Option<BTreeSet<(RoadID, RoadID)>>,
     // These increase the map file size, so instead, just use `recalculate_all_movements` after
     // deserializing.
     #[serde(skip_serializing, skip_deserializing)]
@@ -87,6 +91,47 @@ impl Intersection {
         self.roads.iter().all(|r| map.get_r(*r).is_light_rail())
     }
 
+    /// Can vehicles go from one road to another here? This follows OSM turn restrictions, unless
+    /// map edits override them.
+    pub fn allows_turn_between(&self, from: RoadID, to: RoadID, map: &Map) -> bool {
+        if let Some(ref banned) = self.edited_turn_restrictions {
+            return !banned.contains(&(from, to));
+        }
+
+        for (restriction, restricted_to) in &map.get_r(from).turn_restrictions {
+            // The restriction only applies to one direction of the road.
+            if !self.roads.contains(restricted_to) {
+                continue;
+            }
+            match restriction {
+                RestrictionType::BanTurns => {
+                    if to == *restricted_to {
+                        return false;
+                    }
+                }
+                RestrictionType::OnlyAllowTurns => {
+                    if to != *restricted_to {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Every movement between roads that isn't allowed here, as (from, to)
+    pub fn banned_turns_between_roads(&self, map: &Map) -> BTreeSet<(RoadID, RoadID)> {
+        let mut banned = BTreeSet::new();
+        for from in &self.roads {
+            for to in &self.roads {
+                if !self.allows_turn_between(*from, *to, map) {
+                    banned.insert((*from, *to));
+                }
+            }
+        }
+        banned
+    }
+
     pub fn is_private(&self, map: &Map) -> bool {
         self.roads.iter().all(|r| map.get_r(*r).is_private())
     }
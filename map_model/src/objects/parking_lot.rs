@@ -1,9 +1,10 @@
 use std::fmt;
 
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use abstutil::{deserialize_usize, serialize_usize};
-use geom::{Angle, Line, PolyLine, Polygon, Pt2D};
+use abstutil::{deserialize_usize, serialize_usize, Tags};
+use geom::{Angle, Duration, Line, PolyLine, Polygon, Pt2D, Time};
 
 use crate::{osm, Position};
 
@@ -47,6 +48,9 @@ pub struct ParkingLot {
     /// Lot to sidewalk
     pub sidewalk_line: Line,
     pub sidewalk_pos: Position,
+
+    /// What it costs to park here, if anything
+    pub rate: Option<ParkingRate>,
 }
 
 impl ParkingLot {
@@ -54,3 +58,167 @@ impl ParkingLot {
         self.spots.len() + self.extra_spots
     }
 }
+
+/// What parking costs through the day, and how long people may stay. Lots and public garages may
+/// have one from OSM.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParkingRate {
+    /// (Starting at this time, dollars per hour), sorted by time. Parking is free before the first
+    /// entry.
+    pub hourly_price: Vec<(Time, f64)>,
+    /// Drivers planning to stay longer than this won't park here.
+    pub max_stay: Option<Duration>,
+}
+
+impl ParkingRate {
+    /// Understands simple hourly `charge` tags, like "2.50 USD/hour", and `maxstay` tags, like "2
+    /// hours". None if parking is free and there's no time limit, or the tags can't be parsed.
+    pub fn from_osm(tags: &Tags) -> Option<ParkingRate> {
+        let price = tags.get("charge").and_then(|x| parse_hourly_charge(x));
+        let max_stay = tags.get("maxstay").and_then(|x| parse_max_stay(x));
+        if price.is_none() && max_stay.is_none() {
+            return None;
+        }
+        Some(ParkingRate {
+            hourly_price: price
+                .map(|p| vec![(Time::START_OF_DAY, p)])
+                .unwrap_or_default(),
+            max_stay,
+        })
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for pair in self.hourly_price.windows(2) {
+            if pair[0].0 >= pair[1].0 {
+                bail!("hourly_price isn't sorted by time: {:?}", self.hourly_price);
+            }
+        }
+        if self.hourly_price.iter().any(|(_, price)| *price < 0.0) {
+            bail!("hourly_price can't be negative: {:?}", self.hourly_price);
+        }
+        if self.max_stay.map(|d| d <= Duration::ZERO).unwrap_or(false) {
+            bail!("max_stay must be positive");
+        }
+        Ok(())
+    }
+
+    /// Dollars per hour at some time, before any adjustment
+    pub fn price_at(&self, time: Time) -> f64 {
+        self.hourly_price
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= time)
+            .map(|(_, price)| *price)
+            .unwrap_or(0.0)
+    }
+
+    /// The fee for parking from `start` until `end`, adding `adjustment` dollars to every hourly
+    /// price. Prices are never adjusted below free.
+    pub fn fee(&self, start: Time, end: Time, adjustment: f64) -> f64 {
+        let mut total = 0.0;
+        for (idx, (from, price)) in self.hourly_price.iter().enumerate() {
+            // The last price lasts for the rest of the day
+            let to = self
+                .hourly_price
+                .get(idx + 1)
+                .map(|(t, _)| *t)
+                .unwrap_or(end);
+            let overlap = to.min(end) - (*from).max(start);
+            if overlap > Duration::ZERO {
+                total += (price + adjustment).max(0.0) * overlap.inner_seconds() / 3600.0;
+            }
+        }
+        total
+    }
+}
+
+// "2.50 USD/hour", "1 EUR/h"
+fn parse_hourly_charge(x: &str) -> Option<f64> {
+    let (amount, per) = x.split_once('/')?;
+    if !matches!(per.trim(), "hour" | "h" | "1 hour") {
+        return None;
+    }
+    amount.split_whitespace().next()?.parse::<f64>().ok()
+}
+
+// "2 hours", "90 minutes", "30 min"
+fn parse_max_stay(x: &str) -> Option<Duration> {
+    let mut parts = x.split_whitespace();
+    let n = parts.next()?.parse::<f64>().ok()?;
+    let duration = match parts.next()? {
+        "hour" | "hours" | "h" => Duration::seconds(n * 3600.0),
+        "minute" | "minutes" | "min" => Duration::seconds(n * 60.0),
+        _ => {
+            return None;
+        }
+    };
+    if duration > Duration::ZERO {
+        Some(duration)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parking_rate() {
+        let at = |time: &str| Time::parse(time).unwrap();
+        let rate = ParkingRate {
+            hourly_price: vec![(at("8:00:00"), 2.0), (at("18:00:00"), 1.0)],
+            max_stay: None,
+        };
+
+        assert_eq!(rate.price_at(at("7:59:59")), 0.0);
+        assert_eq!(rate.price_at(at("8:00:00")), 2.0);
+        assert_eq!(rate.price_at(at("17:00:00")), 2.0);
+        assert_eq!(rate.price_at(at("20:00:00")), 1.0);
+
+        // Free before the first price starts
+        assert_eq!(rate.fee(at("6:00:00"), at("8:00:00"), 0.0), 0.0);
+        assert_eq!(rate.fee(at("7:00:00"), at("9:30:00"), 0.0), 3.0);
+        // Spanning a change in price
+        assert_eq!(rate.fee(at("17:00:00"), at("19:00:00"), 0.0), 3.0);
+        // The last price lasts for the rest of the stay
+        assert_eq!(rate.fee(at("18:00:00"), at("22:00:00"), 0.0), 4.0);
+        // Adjustments apply to every priced hour, but never make parking cheaper than free
+        assert_eq!(rate.fee(at("17:00:00"), at("19:00:00"), 0.5), 4.0);
+        assert_eq!(rate.fee(at("17:00:00"), at("19:00:00"), -1.5), 0.5);
+    }
+
+    #[test]
+    fn test_parking_rate_from_osm() {
+        let tags = |pairs: Vec<(&str, &str)>| {
+            Tags::new(
+                pairs
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            )
+        };
+        assert_eq!(
+            ParkingRate::from_osm(&tags(vec![
+                ("charge", "2.50 USD/hour"),
+                ("maxstay", "2 hours")
+            ])),
+            Some(ParkingRate {
+                hourly_price: vec![(Time::START_OF_DAY, 2.5)],
+                max_stay: Some(Duration::hours(2)),
+            })
+        );
+        assert_eq!(
+            ParkingRate::from_osm(&tags(vec![("maxstay", "30 min")])),
+            Some(ParkingRate {
+                hourly_price: Vec::new(),
+                max_stay: Some(Duration::minutes(30)),
+            })
+        );
+        // Daily charges aren't understood
+        assert_eq!(
+            ParkingRate::from_osm(&tags(vec![("fee", "yes"), ("charge", "20 USD/day")])),
+            None
+        );
+    }
+}
@@ -171,8 +171,9 @@ pub struct Road {
     /// positive is uphill from src_i -> dst_i, negative is downhill.
     pub percent_incline: f64,
 
-    /// Invariant: A road must contain at least one child. These are ordered from the left side of
-    /// the road to the right, with that orientation determined by the direction of `center_pts`.
+    /// Invariant: A road must contain at least one child, unless map edits deleted it. These are
+    /// ordered from the left side of the road to the right, with that orientation determined by
+    /// the direction of `center_pts`.
     pub lanes: Vec<Lane>,
 
     /// The physical center of the road, including sidewalks, after trimming to account for the
@@ -195,6 +196,12 @@ pub struct Road {
 }
 
 impl Road {
+    /// Map edits can delete a road. It keeps its ID, but has no lanes and isn't connected to any
+    /// intersection.
+    pub fn is_deleted(&self) -> bool {
+        self.lanes.is_empty()
+    }
+
     pub(crate) fn lane_specs(&self) -> Vec<LaneSpec> {
         self.lanes
             .iter()
@@ -483,6 +490,8 @@ impl Road {
         };
         AccessRestrictions {
             allow_through_traffic,
+            time_windows: Vec::new(),
+            cap_vehicles_per_hour: None,
         }
     }
 
@@ -3,7 +3,7 @@ use std::collections::{BTreeMap, BTreeSet};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use geom::{Distance, Duration, Speed};
+use geom::{Distance, Duration, Speed, Time};
 
 use crate::make::traffic_signals::get_possible_policies;
 use crate::{
@@ -17,11 +17,55 @@ const CROSSWALK_PACE: Speed = Speed::const_meters_per_second(1.4);
 /// A traffic signal consists of a sequence of Stages that repeat in a cycle. Most Stages last for a
 /// fixed duration. During a single Stage, some movements are protected (can proceed with the
 /// highest priority), while others are permitted (have to yield before proceeding).
+///
+/// A signal may switch between different timing plans over the course of a day. `stages` and
+/// `offset` form the plan used from midnight until the first of `plans` begins.
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct ControlTrafficSignal {
     pub id: IntersectionID,
     pub stages: Vec<Stage>,
     pub offset: Duration,
+    /// Additional plans that take over at some time of day, sorted by start time. Most signals
+    /// have none.
+    pub plans: Vec<Plan>,
+    /// If present, the signal gives priority to approaching buses, trains, and emergency
+    /// vehicles. This applies to every plan.
+    pub priority: Option<SignalPriority>,
+}
+
+/// Transit signal priority (TSP) and preemption. A bus, train, or emergency vehicle checks in with
+/// the signal once it's within the detection distance. If its movement is green but the stage is
+/// about to end, the green is extended. If it's waiting for the next stage, the current stage ends
+/// early. With preemption, trains and emergency vehicles instead switch the signal straight to a
+/// stage that serves them, like at a railroad crossing or for an ambulance.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SignalPriority {
+    pub detection_distance: Distance,
+    pub max_green_extension: Duration,
+    pub max_early_green: Duration,
+    pub preemption: bool,
+}
+
+impl SignalPriority {
+    /// Reasonable settings for transit signal priority, without preemption
+    pub fn transit_default() -> SignalPriority {
+        SignalPriority {
+            detection_distance: Distance::meters(100.0),
+            max_green_extension: Duration::seconds(10.0),
+            max_early_green: Duration::seconds(10.0),
+            preemption: false,
+        }
+    }
+}
+
+/// An alternate set of stages and offset, like an AM or PM peak plan. It's active from its start
+/// time until the next plan begins.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Plan {
+    /// Time of day, between midnight and the end of the day
+    pub start_time: Time,
+    pub stages: Vec<Stage>,
+    pub offset: Duration,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -81,8 +125,17 @@ impl ControlTrafficSignal {
     }
 
     pub fn get_min_crossing_time(&self, idx: usize, i: &Intersection) -> Duration {
+        self.get_min_crossing_time_in_plan(0, idx, i)
+    }
+
+    pub fn get_min_crossing_time_in_plan(
+        &self,
+        plan: usize,
+        idx: usize,
+        i: &Intersection,
+    ) -> Duration {
         let mut max_distance = Distance::meters(0.0);
-        for movement in &self.stages[idx].protected_movements {
+        for movement in &self.plan_stages(plan)[idx].protected_movements {
             if movement.crosswalk {
                 max_distance = max_distance.max(i.movements[movement].geom.length());
             }
@@ -94,10 +147,35 @@ impl ControlTrafficSignal {
     }
 
     pub fn validate(&self, i: &Intersection) -> Result<()> {
+        for plan in 0..self.num_plans() {
+            self.validate_plan(plan, i)?;
+        }
+        let mut last_start = Time::START_OF_DAY;
+        for plan in &self.plans {
+            if plan.start_time < last_start || plan.start_time >= Time::START_OF_DAY + DAY {
+                bail!(
+                    "Traffic signal {} has plans out of order or outside of one day",
+                    self.id
+                );
+            }
+            last_start = plan.start_time;
+        }
+        if let Some(ref priority) = self.priority {
+            if priority.detection_distance <= Distance::ZERO
+                || priority.max_green_extension < Duration::ZERO
+                || priority.max_early_green < Duration::ZERO
+            {
+                bail!("Traffic signal {} has invalid priority settings", self.id);
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_plan(&self, plan: usize, i: &Intersection) -> Result<()> {
         // Does the assignment cover the correct set of movements?
         let expected_movements: BTreeSet<MovementID> = i.movements.keys().cloned().collect();
         let mut actual_movements: BTreeSet<MovementID> = BTreeSet::new();
-        for stage in &self.stages {
+        for stage in self.plan_stages(plan) {
             actual_movements.extend(stage.protected_movements.iter());
             actual_movements.extend(stage.yield_movements.iter());
         }
@@ -115,7 +193,7 @@ impl ControlTrafficSignal {
                     .collect::<Vec<_>>()
             );
         }
-        for (stage_index, stage) in self.stages.iter().enumerate() {
+        for (stage_index, stage) in self.plan_stages(plan).iter().enumerate() {
             // Do any of the priority movements in one stage conflict?
             for m1 in stage.protected_movements.iter().map(|m| &i.movements[m]) {
                 for m2 in stage.protected_movements.iter().map(|m| &i.movements[m]) {
@@ -136,7 +214,7 @@ impl ControlTrafficSignal {
                 assert!(!m.turn_type.pedestrian_crossing())
             }
             // Is there enough time in each stage to walk across the crosswalk
-            let min_crossing_time = self.get_min_crossing_time(stage_index, i);
+            let min_crossing_time = self.get_min_crossing_time_in_plan(plan, stage_index, i);
             if stage.stage_type.simple_duration() < min_crossing_time {
                 bail!(
                     "Traffic signal does not allow enough time in stage to complete the \
@@ -269,9 +347,61 @@ impl ControlTrafficSignal {
         Ok(())
     }
 
+    /// After the movements at the intersection change, keep the existing timing, plans, and
+    /// priority, but drop movements that no longer exist, along with any stages left empty. New
+    /// movements yield whenever another movement from the same road goes. Returns false if the
+    /// result isn't valid, in which case the signal should be regenerated.
+    pub(crate) fn update_movements(&mut self, i: &Intersection) -> bool {
+        for plan in 0..self.num_plans() {
+            let stages = self.plan_stages_mut(plan);
+            for stage in stages.iter_mut() {
+                stage
+                    .protected_movements
+                    .retain(|m| i.movements.contains_key(m));
+                stage
+                    .yield_movements
+                    .retain(|m| i.movements.contains_key(m));
+            }
+            stages.retain(|s| !s.protected_movements.is_empty() || !s.yield_movements.is_empty());
+            if stages.is_empty() {
+                return false;
+            }
+
+            let missing = self.missing_turns_in_plan(plan, i);
+            let stages = self.plan_stages_mut(plan);
+            for m in missing {
+                let mut placed = false;
+                for stage in stages.iter_mut() {
+                    if stage
+                        .protected_movements
+                        .iter()
+                        .chain(stage.yield_movements.iter())
+                        .any(|other| other.from == m.from && !other.crosswalk)
+                    {
+                        stage.yield_movements.insert(m);
+                        placed = true;
+                    }
+                }
+                if !placed {
+                    stages[0].yield_movements.insert(m);
+                }
+            }
+        }
+        self.validate(i).is_ok()
+    }
+
+    /// Movements that aren't covered by some plan.
     pub fn missing_turns(&self, i: &Intersection) -> BTreeSet<MovementID> {
+        let mut missing = BTreeSet::new();
+        for plan in 0..self.num_plans() {
+            missing.extend(self.missing_turns_in_plan(plan, i));
+        }
+        missing
+    }
+
+    pub fn missing_turns_in_plan(&self, plan: usize, i: &Intersection) -> BTreeSet<MovementID> {
         let mut missing: BTreeSet<MovementID> = i.movements.keys().cloned().collect();
-        for stage in &self.stages {
+        for stage in self.plan_stages(plan) {
             for m in &stage.protected_movements {
                 missing.remove(m);
             }
@@ -284,14 +414,122 @@ impl ControlTrafficSignal {
 
     /// How long a full cycle of the signal lasts, assuming no actuated timings.
     pub fn simple_cycle_duration(&self) -> Duration {
+        self.plan_cycle_duration(0)
+    }
+
+    /// How long a full cycle of one plan lasts, assuming no actuated timings.
+    pub fn plan_cycle_duration(&self, plan: usize) -> Duration {
         let mut total = Duration::ZERO;
-        for s in &self.stages {
+        for s in self.plan_stages(plan) {
             total += s.stage_type.simple_duration();
         }
         total
     }
 }
 
+// Plans are numbered so that 0 is the base plan, stored directly in `stages` and `offset`, and
+// `idx > 0` refers to `plans[idx - 1]`.
+impl ControlTrafficSignal {
+    /// The number of plans, including the base plan.
+    pub fn num_plans(&self) -> usize {
+        1 + self.plans.len()
+    }
+
+    /// Which plan is active at some time. Every day repeats the same plans.
+    pub fn plan_at(&self, time: Time) -> usize {
+        let time_of_day = time.time_of_day();
+        let mut idx = 0;
+        for (i, plan) in self.plans.iter().enumerate() {
+            if plan.start_time <= time_of_day {
+                idx = i + 1;
+            }
+        }
+        idx
+    }
+
+    /// When the plan active at `time` will be replaced by another. None if there's only one plan.
+    pub fn next_plan_change(&self, time: Time) -> Option<Time> {
+        if self.plans.is_empty() {
+            return None;
+        }
+        let start_of_day = time - (time.time_of_day() - Time::START_OF_DAY);
+        let next = match self.plans.get(self.plan_at(time)) {
+            Some(plan) => plan.start_time - Time::START_OF_DAY,
+            // Wrap around to the next day
+            None => DAY,
+        };
+        Some(start_of_day + next)
+    }
+
+    pub fn plan_start_time(&self, plan: usize) -> Time {
+        if plan == 0 {
+            Time::START_OF_DAY
+        } else {
+            self.plans[plan - 1].start_time
+        }
+    }
+
+    pub fn plan_stages(&self, plan: usize) -> &Vec<Stage> {
+        if plan == 0 {
+            &self.stages
+        } else {
+            &self.plans[plan - 1].stages
+        }
+    }
+
+    pub fn plan_stages_mut(&mut self, plan: usize) -> &mut Vec<Stage> {
+        if plan == 0 {
+            &mut self.stages
+        } else {
+            &mut self.plans[plan - 1].stages
+        }
+    }
+
+    pub fn plan_offset(&self, plan: usize) -> Duration {
+        if plan == 0 {
+            self.offset
+        } else {
+            self.plans[plan - 1].offset
+        }
+    }
+
+    pub fn plan_offset_mut(&mut self, plan: usize) -> &mut Duration {
+        if plan == 0 {
+            &mut self.offset
+        } else {
+            &mut self.plans[plan - 1].offset
+        }
+    }
+
+    /// Add a new plan starting at some time of day, copying whatever plan was previously active
+    /// then. Returns the index of the new plan.
+    pub fn add_plan(&mut self, start_time: Time) -> usize {
+        let start_time = start_time.time_of_day();
+        let previous = self.plan_at(start_time);
+        let plan = Plan {
+            start_time,
+            stages: self.plan_stages(previous).clone(),
+            offset: self.plan_offset(previous),
+        };
+        // Insert after any plans starting at the same time, so the new one is active
+        let idx = self
+            .plans
+            .iter()
+            .take_while(|p| p.start_time <= start_time)
+            .count();
+        self.plans.insert(idx, plan);
+        idx + 1
+    }
+
+    /// Remove a plan. The base plan can't be removed.
+    pub fn remove_plan(&mut self, plan: usize) {
+        assert_ne!(plan, 0);
+        self.plans.remove(plan - 1);
+    }
+}
+
+const DAY: Duration = Duration::const_seconds(24.0 * 3600.0);
+
 impl Stage {
     pub fn new() -> Stage {
         Stage {
@@ -380,38 +618,27 @@ impl ControlTrafficSignal {
     pub fn export(&self, map: &Map) -> traffic_signal_data::TrafficSignal {
         traffic_signal_data::TrafficSignal {
             intersection_osm_node_id: map.get_i(self.id).orig_id.0,
-            plans: vec![traffic_signal_data::Plan {
-                start_time_seconds: 0,
-                stages: self
-                    .stages
-                    .iter()
-                    .map(|s| traffic_signal_data::Stage {
-                        protected_turns: s
-                            .protected_movements
-                            .iter()
-                            .map(|mvmnt| mvmnt.to_permanent(map))
-                            .collect(),
-                        permitted_turns: s
-                            .yield_movements
-                            .iter()
-                            .map(|mvmnt| mvmnt.to_permanent(map))
-                            .collect(),
-                        stage_type: match s.stage_type {
-                            StageType::Fixed(d) => {
-                                traffic_signal_data::StageType::Fixed(d.inner_seconds() as usize)
-                            }
-                            StageType::Variable(min, delay, additional) => {
-                                traffic_signal_data::StageType::Variable(
-                                    min.inner_seconds() as usize,
-                                    delay.inner_seconds() as usize,
-                                    additional.inner_seconds() as usize,
-                                )
-                            }
-                        },
-                    })
-                    .collect(),
-                offset_seconds: self.offset.inner_seconds() as usize,
-            }],
+            plans: (0..self.num_plans())
+                .map(|plan| traffic_signal_data::Plan {
+                    start_time_seconds: (self.plan_start_time(plan) - Time::START_OF_DAY)
+                        .inner_seconds() as usize,
+                    stages: self
+                        .plan_stages(plan)
+                        .iter()
+                        .map(|s| export_stage(s, map))
+                        .collect(),
+                    offset_seconds: self.plan_offset(plan).inner_seconds() as usize,
+                })
+                .collect(),
+            priority: self
+                .priority
+                .as_ref()
+                .map(|p| traffic_signal_data::SignalPriority {
+                    detection_distance_meters: p.detection_distance.inner_meters() as usize,
+                    max_green_extension_seconds: p.max_green_extension.inner_seconds() as usize,
+                    max_early_green_seconds: p.max_early_green.inner_seconds() as usize,
+                    preemption: p.preemption,
+                }),
         }
     }
 
@@ -420,60 +647,150 @@ impl ControlTrafficSignal {
         id: IntersectionID,
         map: &Map,
     ) -> Result<ControlTrafficSignal> {
-        // TODO Only import the first plan. Will import all of them later.
-        let plan = raw.plans.remove(0);
-        let mut stages = Vec::new();
-        for s in plan.stages {
-            let mut errors = Vec::new();
-            let mut protected_movements = BTreeSet::new();
-            for t in s.protected_turns {
-                match MovementID::from_permanent(t, map) {
-                    Ok(mvmnt) => {
-                        protected_movements.insert(mvmnt);
-                    }
-                    Err(err) => {
-                        errors.push(err.to_string());
-                    }
-                }
-            }
-            let mut permitted_movements = BTreeSet::new();
-            for t in s.permitted_turns {
-                match MovementID::from_permanent(t, map) {
-                    Ok(mvmnt) => {
-                        permitted_movements.insert(mvmnt);
-                    }
-                    Err(err) => {
-                        errors.push(err.to_string());
-                    }
-                }
-            }
-            if errors.is_empty() {
-                stages.push(Stage {
-                    protected_movements,
-                    yield_movements: permitted_movements,
-                    stage_type: match s.stage_type {
-                        traffic_signal_data::StageType::Fixed(d) => {
-                            StageType::Fixed(Duration::seconds(d as f64))
-                        }
-                        traffic_signal_data::StageType::Variable(min, delay, additional) => {
-                            StageType::Variable(
-                                Duration::seconds(min as f64),
-                                Duration::seconds(delay as f64),
-                                Duration::seconds(additional as f64),
-                            )
-                        }
-                    },
-                });
-            } else {
-                bail!("{}", errors.join("; "));
+        if raw.plans.is_empty() {
+            bail!("Traffic signal {} has no plans", id);
+        }
+        raw.plans.sort_by_key(|p| p.start_time_seconds);
+        let mut plans = Vec::new();
+        for plan in raw.plans {
+            let mut stages = Vec::new();
+            for s in plan.stages {
+                stages.push(import_stage(s, map)?);
             }
+            plans.push(Plan {
+                start_time: Time::START_OF_DAY
+                    + Duration::seconds(plan.start_time_seconds as f64) % DAY,
+                stages,
+                offset: Duration::seconds(plan.offset_seconds as f64),
+            });
         }
+
+        // If the first plan doesn't start at midnight, then the last plan from the previous day
+        // is still active until then.
+        let base = if plans[0].start_time == Time::START_OF_DAY {
+            plans.remove(0)
+        } else {
+            plans.last().cloned().unwrap()
+        };
         let ts = ControlTrafficSignal {
             id,
-            stages,
-            offset: Duration::seconds(plan.offset_seconds as f64),
+            stages: base.stages,
+            offset: base.offset,
+            plans,
+            priority: raw.priority.map(|p| SignalPriority {
+                detection_distance: Distance::meters(p.detection_distance_meters as f64),
+                max_green_extension: Duration::seconds(p.max_green_extension_seconds as f64),
+                max_early_green: Duration::seconds(p.max_early_green_seconds as f64),
+                preemption: p.preemption,
+            }),
         };
         ts.validate(map.get_i(id))?;
         Ok(ts)
     }
 }
+
+fn export_stage(s: &Stage, map: &Map) -> traffic_signal_data::Stage {
+    traffic_signal_data::Stage {
+        protected_turns: s
+            .protected_movements
+            .iter()
+            .map(|mvmnt| mvmnt.to_permanent(map))
+            .collect(),
+        permitted_turns: s
+            .yield_movements
+            .iter()
+            .map(|mvmnt| mvmnt.to_permanent(map))
+            .collect(),
+        stage_type: match s.stage_type {
+            StageType::Fixed(d) => {
+                traffic_signal_data::StageType::Fixed(d.inner_seconds() as usize)
+            }
+            StageType::Variable(min, delay, additional) => {
+                traffic_signal_data::StageType::Variable(
+                    min.inner_seconds() as usize,
+                    delay.inner_seconds() as usize,
+                    additional.inner_seconds() as usize,
+                )
+            }
+        },
+    }
+}
+
+fn import_stage(s: traffic_signal_data::Stage, map: &Map) -> Result<Stage> {
+    let mut errors = Vec::new();
+    let mut protected_movements = BTreeSet::new();
+    for t in s.protected_turns {
+        match MovementID::from_permanent(t, map) {
+            Ok(mvmnt) => {
+                protected_movements.insert(mvmnt);
+            }
+            Err(err) => {
+                errors.push(err.to_string());
+            }
+        }
+    }
+    let mut permitted_movements = BTreeSet::new();
+    for t in s.permitted_turns {
+        match MovementID::from_permanent(t, map) {
+            Ok(mvmnt) => {
+                permitted_movements.insert(mvmnt);
+            }
+            Err(err) => {
+                errors.push(err.to_string());
+            }
+        }
+    }
+    if !errors.is_empty() {
+        bail!("{}", errors.join("; "));
+    }
+    Ok(Stage {
+        protected_movements,
+        yield_movements: permitted_movements,
+        stage_type: match s.stage_type {
+            traffic_signal_data::StageType::Fixed(d) => {
+                StageType::Fixed(Duration::seconds(d as f64))
+            }
+            traffic_signal_data::StageType::Variable(min, delay, additional) => {
+                StageType::Variable(
+                    Duration::seconds(min as f64),
+                    Duration::seconds(delay as f64),
+                    Duration::seconds(additional as f64),
+                )
+            }
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_at() {
+        let mut ts = ControlTrafficSignal {
+            id: IntersectionID(0),
+            stages: vec![Stage::new()],
+            offset: Duration::ZERO,
+            plans: Vec::new(),
+            priority: None,
+        };
+        let hours = |h: usize| Time::START_OF_DAY + Duration::hours(h);
+        assert_eq!(ts.plan_at(hours(8)), 0);
+        assert_eq!(ts.next_plan_change(hours(8)), None);
+
+        assert_eq!(ts.add_plan(hours(16)), 1);
+        assert_eq!(ts.add_plan(hours(7)), 1);
+        assert_eq!(ts.plan_start_time(2), hours(16));
+
+        assert_eq!(ts.plan_at(hours(3)), 0);
+        assert_eq!(ts.plan_at(hours(7)), 1);
+        assert_eq!(ts.plan_at(hours(17)), 2);
+        // The next day repeats the same plans
+        assert_eq!(ts.plan_at(hours(24 + 8)), 1);
+
+        assert_eq!(ts.next_plan_change(hours(3)), Some(hours(7)));
+        assert_eq!(ts.next_plan_change(hours(8)), Some(hours(16)));
+        assert_eq!(ts.next_plan_change(hours(17)), Some(hours(24)));
+        assert_eq!(ts.next_plan_change(hours(24 + 1)), Some(hours(24 + 7)));
+    }
+}
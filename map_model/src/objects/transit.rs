@@ -69,6 +69,29 @@ pub struct TransitRoute {
     /// Explicitly store whatever the original was, since this can't be reconstructed without side
     /// input.
     pub orig_spawn_times: Vec<Time>,
+    /// When each vehicle is scheduled at every stop, if the route has a timetable. Vehicles
+    /// spawning at a time without a run here don't follow any schedule. Every run has a distinct
+    /// spawn time.
+    ///
+    /// This changed the map format, so maps imported before it must be regenerated.
+    pub timetable: Vec<TransitRun>,
+}
+
+/// One vehicle's schedule along a route.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TransitRun {
+    /// When the vehicle begins at the route's start, early enough to reach the first stop on time
+    pub spawn_time: Time,
+    /// Lines up with the route's stops
+    pub stops: Vec<ScheduledStop>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ScheduledStop {
+    pub arrival: Time,
+    pub departure: Time,
+    /// Vehicles running early wait at timepoints until the scheduled departure.
+    pub timepoint: bool,
 }
 
 impl TransitRoute {
@@ -138,6 +161,14 @@ impl TransitRoute {
         Ok(paths)
     }
 
+    /// The schedule for the vehicle spawning at this time, if there is one. Runs keep their
+    /// schedule as long as an edit doesn't change their spawn time.
+    pub fn get_run(&self, spawn_time: Time) -> Option<&TransitRun> {
+        self.timetable
+            .iter()
+            .find(|run| run.spawn_time == spawn_time)
+    }
+
     pub fn plural_noun(&self) -> &'static str {
         if self.route_type == PathConstraints::Bus {
             "buses"
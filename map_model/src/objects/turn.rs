@@ -4,7 +4,6 @@ use serde::{Deserialize, Serialize};
 
 use geom::{Angle, Line, PolyLine};
 
-use crate::raw::RestrictionType;
 use crate::{
     DirectedRoadID, Direction, Intersection, IntersectionID, LaneID, Map, MovementID,
     PathConstraints,
@@ -238,29 +237,7 @@ impl Turn {
             return true;
         }
 
-        let src = map.get_parent(self.id.src);
-        let dst = self.id.dst.road;
-
-        for (restriction, to) in &src.turn_restrictions {
-            // The restriction only applies to one direction of the road.
-            if !i.roads.contains(to) {
-                continue;
-            }
-            match restriction {
-                RestrictionType::BanTurns => {
-                    if dst == *to {
-                        return false;
-                    }
-                }
-                RestrictionType::OnlyAllowTurns => {
-                    if dst != *to {
-                        return false;
-                    }
-                }
-            }
-        }
-
-        true
+        i.allows_turn_between(self.id.src.road, self.id.dst.road, map)
     }
 
     /// If this turn is a crosswalk over a single road, return that road and which end of the road
@@ -3,24 +3,71 @@
 //!    use any of the private roads
 //! 2) Stay Healthy Streets, where most car traffic is banned, except for trips beginning/ending in
 //!    the zone
-//! 3) Congestion capping, where only so many cars per hour can enter the zone
+//! 3) Congestion capping, where only so many vehicles per hour can enter the zone
+//! 4) School streets and bus gates, where through-traffic is only banned during some times of day
 
 use std::collections::BTreeSet;
 
 use enumset::EnumSet;
 use serde::{Deserialize, Serialize};
 
+use geom::Time;
+
 use crate::{CommonEndpoint, IntersectionID, Map, PathConstraints, RoadID};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct AccessRestrictions {
     pub allow_through_traffic: EnumSet<PathConstraints>,
+    /// During each of these windows, through-traffic is restricted further. Every day repeats the
+    /// same windows.
+    pub time_windows: Vec<TimeWindow>,
+    /// If present, only this many motor vehicles per hour may enter the zone. Pathfinding ignores
+    /// this; the simulation enforces it.
+    pub cap_vehicles_per_hour: Option<usize>,
+}
+
+/// Part of every day when only some types of through-traffic are allowed
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TimeWindow {
+    /// Inclusive, as a time of day
+    pub start: Time,
+    /// Exclusive, as a time of day. If this is before `start`, the window wraps past midnight.
+    pub end: Time,
+    pub allow_through_traffic: EnumSet<PathConstraints>,
 }
 
 impl AccessRestrictions {
     pub fn new() -> AccessRestrictions {
         AccessRestrictions {
             allow_through_traffic: EnumSet::all(),
+            time_windows: Vec::new(),
+            cap_vehicles_per_hour: None,
+        }
+    }
+
+    /// Can this type of through-traffic enter? If `time` is None, time windows are ignored.
+    pub fn allows_through_traffic(&self, constraints: PathConstraints, time: Option<Time>) -> bool {
+        if !self.allow_through_traffic.contains(constraints) {
+            return false;
+        }
+        if let Some(time) = time {
+            for window in &self.time_windows {
+                if window.contains(time) && !window.allow_through_traffic.contains(constraints) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+impl TimeWindow {
+    pub fn contains(&self, time: Time) -> bool {
+        let time = time.time_of_day();
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
         }
     }
 }
@@ -38,7 +85,7 @@ impl Zone {
     pub fn make_all(map: &Map) -> Vec<Zone> {
         let mut queue = Vec::new();
         for r in map.all_roads() {
-            if r.is_private() {
+            if r.is_private() && !r.is_deleted() {
                 queue.push(r.id);
             }
         }
@@ -57,6 +104,33 @@ impl Zone {
 
         zones
     }
+
+    /// Every time of day when some zone's time window starts or ends. The map caches this
+    /// alongside the zones.
+    pub(crate) fn window_changes(zones: &[Zone]) -> BTreeSet<Time> {
+        let mut changes = BTreeSet::new();
+        for zone in zones {
+            for window in &zone.restrictions.time_windows {
+                changes.insert(window.start.time_of_day());
+                changes.insert(window.end.time_of_day());
+            }
+        }
+        changes
+    }
+
+    /// Time windows only change which restrictions are in effect when they start or end. Returns
+    /// the latest such time of day at or before `time`, so that all times in between map to the
+    /// same value. None if no zone has any time windows.
+    pub fn restrictions_changed_at(map: &Map, time: Time) -> Option<Time> {
+        let changes = &map.zone_window_changes;
+        // Before the first change of the day, the restrictions from the end of the previous day
+        // still apply
+        changes
+            .range(..=time.time_of_day())
+            .next_back()
+            .or_else(|| changes.iter().next_back())
+            .cloned()
+    }
 }
 
 fn floodfill(map: &Map, start: RoadID) -> Zone {
@@ -91,3 +165,41 @@ fn floodfill(map: &Map, start: RoadID) -> Zone {
         restrictions: match_constraints,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_window_contains() {
+        let at = |time: &str| Time::parse(time).unwrap();
+        let school_run = TimeWindow {
+            start: at("8:00:00"),
+            end: at("9:00:00"),
+            allow_through_traffic: EnumSet::only(PathConstraints::Pedestrian),
+        };
+        assert!(!school_run.contains(at("7:59:59")));
+        assert!(school_run.contains(at("8:00:00")));
+        assert!(school_run.contains(at("8:30:00")));
+        assert!(!school_run.contains(at("9:00:00")));
+        // Every day repeats the same windows
+        assert!(school_run.contains(at("32:30:00")));
+        assert!(!school_run.contains(at("33:00:00")));
+
+        let overnight = TimeWindow {
+            start: at("22:00:00"),
+            end: at("6:00:00"),
+            allow_through_traffic: EnumSet::only(PathConstraints::Pedestrian),
+        };
+        assert!(!overnight.contains(at("21:59:59")));
+        assert!(overnight.contains(at("22:00:00")));
+        assert!(overnight.contains(at("23:59:59")));
+        assert!(overnight.contains(at("0:00:00")));
+        assert!(overnight.contains(at("5:59:59")));
+        assert!(!overnight.contains(at("6:00:00")));
+        assert!(!overnight.contains(at("12:00:00")));
+        // Past midnight of the first day
+        assert!(overnight.contains(at("24:30:00")));
+        assert!(!overnight.contains(at("30:00:00")));
+    }
+}
@@ -108,6 +108,15 @@ impl PathfindEngine {
         }
     }
 
+    /// Create the same type of engine from scratch. Needed when the number of nodes changes.
+    pub fn recreate(&self) -> CreateEngine {
+        match self {
+            PathfindEngine::Empty => unreachable!(),
+            PathfindEngine::Dijkstra { .. } => CreateEngine::Dijkstra,
+            PathfindEngine::CH { .. } => CreateEngine::CH,
+        }
+    }
+
     pub fn is_dijkstra(&self) -> bool {
         matches!(self, PathfindEngine::Dijkstra { .. })
     }
@@ -1,11 +1,11 @@
 //! Everything related to pathfinding through a map for different types of agents.
 
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
 use enumset::EnumSetType;
 use serde::{Deserialize, Serialize};
 
-use geom::Duration;
+use geom::{Duration, Time};
 
 pub use self::engine::CreateEngine;
 pub use self::pathfinder::{Pathfinder, PathfinderCache, PathfinderCaching};
@@ -13,7 +13,7 @@ pub use self::v1::{Path, PathRequest, PathStep};
 pub use self::v2::{PathStepV2, PathV2};
 pub use self::vehicles::vehicle_cost;
 pub use self::walking::WalkingNode;
-use crate::{osm, Lane, LaneID, LaneType, Map, MovementID, Road, RoadID, TurnType};
+use crate::{osm, DirectedRoadID, Lane, LaneID, LaneType, Map, MovementID, Road, RoadID, TurnType};
 
 mod engine;
 mod node_map;
@@ -34,6 +34,8 @@ pub enum PathConstraints {
     Bike,
     Bus,
     Train,
+    /// Delivery vans and lorries
+    Truck,
 }
 
 impl PathConstraints {
@@ -44,6 +46,7 @@ impl PathConstraints {
             PathConstraints::Bike,
             PathConstraints::Bus,
             PathConstraints::Train,
+            PathConstraints::Truck,
         ]
     }
 
@@ -87,12 +90,15 @@ impl PathConstraints {
             PathConstraints::Train => {
                 return lane.is_light_rail();
             }
+            PathConstraints::Truck => {
+                lane.is_driving() && !map.get_r(lane.id.road).osm_tags.is("hgv", "no")
+            }
         };
         if result {
             return true;
         }
-        // Second chance for cars and bikes trying to use a bus-only lane that also happens to be a
-        // turn lane.
+        // Second chance for cars, trucks, and bikes trying to use a bus-only lane that also happens
+        // to be a turn lane.
         //
         // TODO This check could be made stricter in two ways:
         // 1) Verify that the bus-only lanes are the ONLY way to make this movement; if there's a
@@ -138,19 +144,23 @@ impl PathConstraints {
     }
 }
 
-/// Heavily penalize crossing into an access-restricted zone that doesn't allow this mode.
-pub(crate) fn zone_cost(mvmnt: MovementID, constraints: PathConstraints, map: &Map) -> Duration {
+/// Heavily penalize crossing into an access-restricted zone that doesn't allow this mode. If
+/// `time` is specified, also consider the zone's time windows.
+pub(crate) fn zone_cost(
+    mvmnt: MovementID,
+    constraints: PathConstraints,
+    time: Option<Time>,
+    map: &Map,
+) -> Duration {
     // Detect when we cross into a new zone that doesn't allow constraints.
     if map
         .get_r(mvmnt.from.road)
         .access_restrictions
-        .allow_through_traffic
-        .contains(constraints)
+        .allows_through_traffic(constraints, time)
         && !map
             .get_r(mvmnt.to.road)
             .access_restrictions
-            .allow_through_traffic
-            .contains(constraints)
+            .allows_through_traffic(constraints, time)
     {
         // This should be high enough to achieve the desired effect of somebody not entering
         // the zone unless absolutely necessary. Someone would violate that and cut through anyway
@@ -196,6 +206,15 @@ pub struct RoutingParams {
     /// Don't allow movements between these roads at all. Only affects vehicle routing, not
     /// pedestrian.
     pub avoid_movements_between: BTreeSet<(RoadID, RoadID)>,
+
+    /// Extra time to cross some roads, added to the cost for all vehicles. This is usually the
+    /// congestion delay observed in a previous simulation.
+    pub road_delays: BTreeMap<DirectedRoadID, Duration>,
+
+    /// Honor the access restrictions of zones with time windows as of this time of day. If `None`,
+    /// time windows are ignored. Usually set by `Map::pathfind_with_params_at`, so that the same
+    /// pathfinder can be reused until the restrictions change.
+    pub access_restrictions_at: Option<Time>,
 }
 
 impl Default for RoutingParams {
@@ -216,6 +235,10 @@ impl Default for RoutingParams {
 
             avoid_roads: BTreeSet::new(),
             avoid_movements_between: BTreeSet::new(),
+
+            road_delays: BTreeMap::new(),
+
+            access_restrictions_at: None,
         }
     }
 }
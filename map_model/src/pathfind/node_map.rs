@@ -43,6 +43,10 @@ impl<T: Copy + Ord + Debug + Serialize> NodeMap<T> {
         }
     }
 
+    pub fn contains(&self, node: T) -> bool {
+        self.node_to_id.contains_key(&node)
+    }
+
     pub fn translate_id(&self, id: usize) -> T {
         self.id_to_node[id]
     }
@@ -21,6 +21,9 @@ pub struct Pathfinder {
     bike_graph: VehiclePathfinder,
     bus_graph: VehiclePathfinder,
     train_graph: VehiclePathfinder,
+    /// Few simulations have delivery vehicles, so this isn't built with the map. When it's None,
+    /// it's built the first time it's needed; see `with_truck_graph`.
+    truck_graph: Option<VehiclePathfinder>,
     walking_graph: SidewalkPathfinder,
     walking_with_transit_graph: SidewalkPathfinder,
 
@@ -54,6 +57,7 @@ impl Clone for Pathfinder {
             bike_graph: self.bike_graph.clone(),
             bus_graph: self.bus_graph.clone(),
             train_graph: self.train_graph.clone(),
+            truck_graph: self.truck_graph.clone(),
             walking_graph: self.walking_graph.clone(),
             walking_with_transit_graph: self.walking_with_transit_graph.clone(),
             params: self.params.clone(),
@@ -71,6 +75,7 @@ impl Pathfinder {
             bike_graph: VehiclePathfinder::empty(),
             bus_graph: VehiclePathfinder::empty(),
             train_graph: VehiclePathfinder::empty(),
+            truck_graph: None,
             walking_graph: SidewalkPathfinder::empty(),
             walking_with_transit_graph: SidewalkPathfinder::empty(),
             params: RoutingParams::default(),
@@ -115,7 +120,8 @@ impl Pathfinder {
         timer.stop("prepare pathfinding for trains");
 
         timer.start("prepare pathfinding for pedestrians");
-        let walking_graph = SidewalkPathfinder::new(map, None, engine);
+        let walking_graph =
+            SidewalkPathfinder::new(map, None, params.access_restrictions_at, engine);
         timer.stop("prepare pathfinding for pedestrians");
 
         // Transit routes haven't been created yet, so defer this step
@@ -126,6 +132,7 @@ impl Pathfinder {
             bike_graph,
             bus_graph,
             train_graph,
+            truck_graph: None,
             walking_graph,
             walking_with_transit_graph,
 
@@ -169,7 +176,8 @@ impl Pathfinder {
             timer.start(format!("prepare pathfinding for just {:?}", constraints));
             match constraints {
                 PathConstraints::Pedestrian => {
-                    p.walking_graph = SidewalkPathfinder::new(map, None, &engine);
+                    p.walking_graph =
+                        SidewalkPathfinder::new(map, None, params.access_restrictions_at, &engine);
                 }
                 PathConstraints::Car => {
                     p.car_graph = VehiclePathfinder::new(map, constraints, &params, &engine);
@@ -183,6 +191,10 @@ impl Pathfinder {
                 PathConstraints::Train => {
                     p.train_graph = VehiclePathfinder::new(map, constraints, &params, &engine);
                 }
+                PathConstraints::Truck => {
+                    p.truck_graph =
+                        Some(VehiclePathfinder::new(map, constraints, &params, &engine));
+                }
             }
             timer.stop(format!("prepare pathfinding for just {:?}", constraints));
         }
@@ -191,8 +203,12 @@ impl Pathfinder {
     }
 
     pub(crate) fn finalize_transit(&mut self, map: &Map, engine: &CreateEngine) {
-        self.walking_with_transit_graph =
-            SidewalkPathfinder::new(map, Some((&self.bus_graph, &self.train_graph)), engine);
+        self.walking_with_transit_graph = SidewalkPathfinder::new(
+            map,
+            Some((&self.bus_graph, &self.train_graph)),
+            self.params.access_restrictions_at,
+            engine,
+        );
     }
 
     /// Finds a path from a start to an end for a certain type of agent.
@@ -209,6 +225,7 @@ impl Pathfinder {
             PathConstraints::Bike => self.bike_graph.pathfind(req, map),
             PathConstraints::Bus => self.bus_graph.pathfind(req, map),
             PathConstraints::Train => self.train_graph.pathfind(req, map),
+            PathConstraints::Truck => self.with_truck_graph(map, |g| g.pathfind(req, map)),
         }
     }
 
@@ -231,6 +248,7 @@ impl Pathfinder {
                 PathConstraints::Bike => self.bike_graph.pathfind(req, map),
                 PathConstraints::Bus => self.bus_graph.pathfind(req, map),
                 PathConstraints::Train => self.train_graph.pathfind(req, map),
+                PathConstraints::Truck => self.with_truck_graph(map, |g| g.pathfind(req, map)),
             };
         }
 
@@ -281,11 +299,39 @@ impl Pathfinder {
             PathConstraints::Pedestrian => self.walking_graph.all_costs_from(req.start, map),
             PathConstraints::Car => self.car_graph.all_costs_from(req.start, map),
             PathConstraints::Bike => self.bike_graph.all_costs_from(req.start, map),
+            PathConstraints::Truck => {
+                self.with_truck_graph(map, |g| g.all_costs_from(req.start, map))
+            }
             PathConstraints::Bus | PathConstraints::Train => unreachable!(),
         };
         Some((req_cost, all_costs))
     }
 
+    /// Uses the truck graph, building it the first time it's needed on each thread. It's a
+    /// Dijkstra graph, since it's only worth paying for a contraction hierarchy when there are
+    /// many trucks.
+    fn with_truck_graph<T, F: FnOnce(&VehiclePathfinder) -> T>(&self, map: &Map, f: F) -> T {
+        if let Some(ref graph) = self.truck_graph {
+            return f(graph);
+        }
+        let key = (PathConstraints::Truck, self.params.clone());
+        let cache = self
+            .cached_alternatives
+            .get_or(|| RefCell::new(VecMap::new()));
+        if cache.borrow().get(&key).is_none() {
+            let pathfinder = Pathfinder::new_limited(
+                map,
+                self.params.clone(),
+                CreateEngine::Dijkstra,
+                vec![PathConstraints::Truck],
+                &mut Timer::throwaway(),
+            );
+            cache.borrow_mut().push(key.clone(), pathfinder);
+        }
+        let cache = cache.borrow();
+        f(cache.get(&key).unwrap().truck_graph.as_ref().unwrap())
+    }
+
     // TODO Consider returning the walking-only path in the failure case, to avoid wasting work
     pub fn should_use_transit(
         &self,
@@ -314,6 +360,14 @@ impl Pathfinder {
         self.train_graph.apply_edits(map);
         timer.stop("apply edits to train pathfinding");
 
+        if let Some(ref mut truck_graph) = self.truck_graph {
+            timer.start("apply edits to truck pathfinding");
+            truck_graph.apply_edits(map);
+            timer.stop("apply edits to truck pathfinding");
+        }
+        // Lazily built graphs, like the one for trucks, no longer match the map
+        self.cached_alternatives.clear();
+
         timer.start("apply edits to pedestrian pathfinding");
         self.walking_graph.apply_edits(map, None);
         timer.stop("apply edits to pedestrian pathfinding");
@@ -350,6 +350,28 @@ impl Path {
         }
     }
 
+    /// Replaces everything after the current step with a different route. The new path must start
+    /// with the current step and end at the same place as the original request. Progress along the
+    /// original path is preserved.
+    pub fn reroute(&mut self, new_path: Path, map: &Map) {
+        assert!(self.currently_inside_ut.is_none());
+        assert_eq!(self.steps[0], new_path.steps[0]);
+        assert_eq!(
+            self.orig_req.end.lane(),
+            new_path.last_step().as_traversable().as_lane()
+        );
+
+        for step in self.steps.iter().skip(1) {
+            self.total_length -= self.dist_crossed_from_step(map, step);
+        }
+        self.steps.truncate(1);
+        for step in new_path.steps.into_iter().skip(1) {
+            self.total_length += self.dist_crossed_from_step(map, &step);
+            self.steps.push_back(step);
+        }
+        self.uber_turns = new_path.uber_turns;
+    }
+
     pub fn current_step(&self) -> PathStep {
         self.steps[0]
     }
@@ -601,7 +623,7 @@ impl PathRequest {
         let (start, end) = match constraints {
             PathConstraints::Pedestrian => (from.sidewalk_pos, to.sidewalk_pos),
             PathConstraints::Bike => (from.biking_connection(map)?.0, to.biking_connection(map)?.0),
-            PathConstraints::Car => (
+            PathConstraints::Car | PathConstraints::Truck => (
                 from.driving_connection(map)?.0,
                 to.driving_connection(map)?.0,
             ),
@@ -142,10 +142,21 @@ impl VehiclePathfinder {
             return;
         }
 
-        // The NodeMap is just all roads and uber-turns -- it won't change. So we can also reuse
-        // the node ordering.
+        // The NodeMap is just all roads and uber-turns -- it only changes when edits create new
+        // roads. Deleted roads keep their nodes; they just won't have any edges. If nothing was
+        // added, we can also reuse the node ordering.
         // TODO Make sure the result of this is deterministic and equivalent to computing from
         // scratch.
+        let mut added_nodes = false;
+        for r in map.all_roads() {
+            for dr in r.id.both_directions() {
+                if !self.nodes.contains(Node::Road(dr)) {
+                    self.nodes.get_or_insert(Node::Road(dr));
+                    added_nodes = true;
+                }
+            }
+        }
+
         let input_graph = make_input_graph(
             self.constraints,
             &self.nodes,
@@ -153,7 +164,11 @@ impl VehiclePathfinder {
             &self.params,
             map,
         );
-        let engine = self.engine.reuse_ordering().create(input_graph);
+        let engine = if added_nodes {
+            self.engine.recreate().create(input_graph)
+        } else {
+            self.engine.reuse_ordering().create(input_graph)
+        };
         self.engine = engine;
     }
 
@@ -291,6 +306,7 @@ pub fn vehicle_cost(
     let max_speed = match constraints {
         PathConstraints::Car | PathConstraints::Bus | PathConstraints::Train => None,
         PathConstraints::Bike => Some(crate::MAX_BIKE_SPEED),
+        PathConstraints::Truck => Some(crate::MAX_TRUCK_SPEED),
         PathConstraints::Pedestrian => unreachable!(),
     };
     let t1 = road.length() / Traversable::max_speed_along_road(dr, max_speed, constraints, map).0;
@@ -299,7 +315,7 @@ pub fn vehicle_cost(
         / Traversable::max_speed_along_movement(mvmnt, max_speed, constraints, map);
 
     let base = match constraints {
-        PathConstraints::Car | PathConstraints::Train => t1 + t2,
+        PathConstraints::Car | PathConstraints::Train | PathConstraints::Truck => t1 + t2,
         PathConstraints::Bike => {
             // TODO If we're on a driving lane, higher speed limit is worse.
             // TODO Bike lanes next to parking is dangerous.
@@ -356,11 +372,14 @@ pub fn vehicle_cost(
         return None;
     }
 
-    let mut extra = zone_cost(mvmnt, constraints, map);
+    let mut extra = zone_cost(mvmnt, constraints, params.access_restrictions_at, map);
     // Penalize unprotected turns at a stop sign from smaller to larger roads.
     if map.is_unprotected_turn(dr.road, mvmnt.to.road, movement.turn_type) {
         extra += params.unprotected_turn_penalty
     }
+    if let Some(delay) = params.road_delays.get(&dr) {
+        extra += *delay;
+    }
 
     if (params.main_road_penalty - 1.0).abs() > f64::EPSILON
         && road.get_rank() != osm::RoadRank::Local
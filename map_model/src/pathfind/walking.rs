@@ -5,7 +5,7 @@ use std::collections::HashMap;
 use fast_paths::InputGraph;
 use serde::{Deserialize, Serialize};
 
-use geom::{Distance, Duration};
+use geom::{Distance, Duration, Time};
 
 use crate::pathfind::engine::{CreateEngine, PathfindEngine};
 use crate::pathfind::node_map::{deserialize_nodemap, NodeMap};
@@ -22,6 +22,8 @@ pub struct SidewalkPathfinder {
     #[serde(deserialize_with = "deserialize_nodemap")]
     nodes: NodeMap<WalkingNode>,
     use_transit: bool,
+    /// See `RoutingParams::access_restrictions_at`
+    access_restrictions_at: Option<Time>,
     engine: PathfindEngine,
 }
 
@@ -59,6 +61,7 @@ impl SidewalkPathfinder {
         SidewalkPathfinder {
             nodes: NodeMap::new(),
             use_transit: false,
+            access_restrictions_at: None,
             engine: PathfindEngine::Empty,
         }
     }
@@ -66,6 +69,7 @@ impl SidewalkPathfinder {
     pub fn new(
         map: &Map,
         use_transit: Option<(&VehiclePathfinder, &VehiclePathfinder)>,
+        access_restrictions_at: Option<Time>,
         engine: &CreateEngine,
     ) -> SidewalkPathfinder {
         let mut nodes = NodeMap::new();
@@ -89,12 +93,13 @@ impl SidewalkPathfinder {
             }
         }
 
-        let input_graph = make_input_graph(&nodes, use_transit, map);
+        let input_graph = make_input_graph(&nodes, use_transit, access_restrictions_at, map);
         let engine = engine.create(input_graph);
 
         SidewalkPathfinder {
             nodes,
             use_transit: use_transit.is_some(),
+            access_restrictions_at,
             engine,
         }
     }
@@ -108,8 +113,27 @@ impl SidewalkPathfinder {
             return;
         }
 
-        let input_graph = make_input_graph(&self.nodes, use_transit, map);
-        let engine = self.engine.reuse_ordering().create(input_graph);
+        // Roads created by edits need new nodes, and then the node ordering can't be reused
+        let mut added_nodes = false;
+        for r in map.all_roads() {
+            for dr in r.id.both_directions() {
+                for endpt in [true, false] {
+                    let node = WalkingNode::SidewalkEndpoint(dr, endpt);
+                    if !self.nodes.contains(node) {
+                        self.nodes.get_or_insert(node);
+                        added_nodes = true;
+                    }
+                }
+            }
+        }
+
+        let input_graph =
+            make_input_graph(&self.nodes, use_transit, self.access_restrictions_at, map);
+        let engine = if added_nodes {
+            self.engine.recreate().create(input_graph)
+        } else {
+            self.engine.reuse_ordering().create(input_graph)
+        };
         self.engine = engine;
     }
 
@@ -241,7 +265,7 @@ impl SidewalkPathfinder {
             self.engine.all_costs_from(start)
         } else {
             // The CH engine doesn't support this!
-            let input_graph = make_input_graph(&self.nodes, None, map);
+            let input_graph = make_input_graph(&self.nodes, None, self.access_restrictions_at, map);
             CreateEngine::Dijkstra
                 .create(input_graph)
                 .all_costs_from(start)
@@ -264,6 +288,7 @@ impl SidewalkPathfinder {
 fn make_input_graph(
     nodes: &NodeMap<WalkingNode>,
     use_transit: Option<(&VehiclePathfinder, &VehiclePathfinder)>,
+    access_restrictions_at: Option<Time>,
     map: &Map,
 ) -> InputGraph {
     let max_speed = Some(crate::MAX_WALKING_SPEED);
@@ -309,7 +334,12 @@ fn make_input_graph(
 
             let mut cost = t.geom.length()
                 / PathStep::Turn(t.id).max_speed_along(max_speed, PathConstraints::Pedestrian, map)
-                + zone_cost(t.id.to_movement(map), PathConstraints::Pedestrian, map);
+                + zone_cost(
+                    t.id.to_movement(map),
+                    PathConstraints::Pedestrian,
+                    access_restrictions_at,
+                    map,
+                );
 
             if t.turn_type == TurnType::UnmarkedCrossing {
                 // TODO Add to RoutingParams
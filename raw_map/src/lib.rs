@@ -8,7 +8,7 @@ use serde::{Deserialize, Serialize};
 
 use abstio::{CityName, MapName};
 use abstutil::{deserialize_btreemap, serialize_btreemap, Tags};
-use geom::{PolyLine, Polygon, Pt2D};
+use geom::{PolyLine, Polygon, Pt2D, Time};
 
 // Re-export everything for refactoring convenience
 pub use street_network::*;
@@ -119,7 +119,26 @@ pub struct RawTransitRoute {
     /// Entries into transit_stops
     pub stops: Vec<String>,
     pub route_type: RawTransitType,
-    // TODO Schedule
+    /// Every vehicle run over one weekday, in order of departure. Each run serves exactly the
+    /// stops above. Empty if the feed has no usable stop times.
+    pub schedule: Vec<RawTransitRun>,
+}
+
+/// One vehicle following a route, from GTFS stop_times.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RawTransitRun {
+    pub gtfs_trip_id: String,
+    /// Lines up with the route's stops
+    pub stop_times: Vec<RawStopTime>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RawStopTime {
+    pub arrival: Time,
+    pub departure: Time,
+    /// Vehicles running early wait here until the scheduled departure. If the feed leaves times
+    /// blank for a stop, they're interpolated and the stop isn't a timepoint.
+    pub timepoint: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
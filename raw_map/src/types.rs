@@ -6,7 +6,7 @@ use abstutil::Tags;
 use street_network::NamePerLanguage;
 
 /// A business located inside a building.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Amenity {
     pub names: NamePerLanguage,
     /// This is the specific amenity listed in OSM, not the more general `AmenityType` category.
@@ -4,14 +4,17 @@ use std::fmt::Write;
 use serde::{Deserialize, Serialize};
 
 use abstutil::Counter;
-use geom::{Duration, Pt2D, Time};
+use geom::{Distance, Duration, Pt2D, Time};
 use map_model::{
-    CompressedMovementID, IntersectionID, LaneID, Map, MovementID, ParkingLotID, Path, PathRequest,
-    RoadID, TransitRouteID, TransitStopID, Traversable, TurnID,
+    BuildingID, CompressedMovementID, IntersectionID, LaneID, Map, MovementID, ParkingLotID, Path,
+    PathRequest, RoadID, TransitRouteID, TransitStopID, Traversable, TurnID, Zone,
 };
 use synthpop::TripMode;
 
-use crate::{AgentID, AgentType, AlertLocation, CarID, Event, ParkingSpot, TripID, TripPhaseType};
+use crate::parking_pricing::parking_block;
+use crate::{
+    AgentID, AgentType, AlertLocation, CarID, Emissions, Event, ParkingSpot, TripID, TripPhaseType,
+};
 
 /// As a simulation runs, different pieces emit Events. The Analytics object listens to these,
 /// organizing and storing some information from them. The UI queries Analytics to draw time-series
@@ -39,6 +42,17 @@ pub struct Analytics {
     /// For each passenger boarding, how long did they wait at the stop?
     pub passengers_boarding: BTreeMap<TransitStopID, Vec<(Time, TransitRouteID, Duration)>>,
     pub passengers_alighting: BTreeMap<TransitStopID, Vec<(Time, TransitRouteID)>>,
+    /// How many people were left waiting at a stop because a vehicle on their route was full
+    pub denied_boardings: BTreeMap<TransitStopID, Vec<(Time, TransitRouteID, usize)>>,
+    /// For every route, how many passengers were aboard vehicles leaving each stop, so the load
+    /// on the segment to the next stop
+    pub transit_loads: BTreeMap<TransitRouteID, Vec<(Time, CarID, TransitStopID, usize)>>,
+    /// Every time a traffic signal extended (positive) or cut short (negative) a stage for an
+    /// approaching bus or train
+    pub signal_priority: Vec<(Time, CarID, IntersectionID, Duration)>,
+    /// For vehicles following a timetable, how late (positive) or early (negative) they departed
+    /// each stop
+    pub schedule_deviation: Vec<(Time, CarID, TransitRouteID, TransitStopID, Duration)>,
 
     pub started_trips: BTreeMap<TripID, Time>,
     /// Finish time, ID, mode, trip duration if successful (or None if cancelled)
@@ -57,6 +71,50 @@ pub struct Analytics {
     /// Per parking lane or lot, when does a spot become filled (true) or free (false)
     pub parking_lane_changes: BTreeMap<LaneID, Vec<(Time, bool)>>,
     pub parking_lot_changes: BTreeMap<ParkingLotID, Vec<(Time, bool)>>,
+    /// Per block, when did somebody pay how many dollars to park? Lots and garages count towards
+    /// the road their driveway connects to.
+    pub parking_revenue: BTreeMap<RoadID, Vec<(Time, f64)>>,
+    /// Per block, when did demand-responsive pricing change the hourly price of on-street parking?
+    pub parking_prices: BTreeMap<RoadID, Vec<(Time, f64)>>,
+    /// Every time a driver parked after looking for a spot near their destination, how long and
+    /// how far did they search? This starts once they reach the last road of their original route.
+    pub parking_searches: Vec<(Time, TripID, BuildingID, Duration, Distance)>,
+    /// How far drivers have cruised along each road, after the spot they were heading for was
+    /// taken. Whole lanes count, even if the driver parked partway along.
+    pub cruising_per_road: BTreeMap<RoadID, Distance>,
+    /// Trips currently cruising for parking
+    searching_for_parking: BTreeSet<TripID>,
+
+    /// Cumulative emissions by vehicles moving along or idling on each road
+    pub road_emissions: BTreeMap<RoadID, Emissions>,
+    /// Cumulative emissions by vehicles turning through or waiting at each intersection
+    pub intersection_emissions: BTreeMap<IntersectionID, Emissions>,
+    /// Cumulative emissions per trip. Buses and trains aren't part of a trip, so they only count
+    /// towards roads and intersections.
+    pub trip_emissions: BTreeMap<TripID, Emissions>,
+    /// Emissions from all vehicles, bucketed by hour
+    pub emissions_per_hour: Vec<Emissions>,
+
+    /// Distance driven by ride-hail vehicles, bucketed by hour. The first is with a passenger, the
+    /// second is empty, driving to a pickup or returning to base.
+    pub ride_hail_distance_per_hour: Vec<(Distance, Distance)>,
+    /// Every time a ride-hail vehicle stops in a driving lane to pick up or drop off somebody
+    pub ride_hail_curb_stops: Vec<(Time, CarID, LaneID)>,
+
+    /// Every stop a delivery vehicle makes, with how long it stays and whether it double-parked
+    pub delivery_stops: Vec<(Time, CarID, LaneID, Duration, bool)>,
+
+    /// How many shared scooters and bikes are waiting in front of each building, recorded every
+    /// time it changes. Before the first change, stations have their initial vehicles.
+    pub micromobility_availability: BTreeMap<BuildingID, Vec<(Time, usize)>>,
+    /// Micromobility trips that walked instead, because no vehicle or dock was available near the
+    /// building
+    pub micromobility_unmet_demand: Vec<(Time, TripID, BuildingID)>,
+
+    /// Every time a car was turned away from a zone that reached its hourly cap, with the road its
+    /// route would've entered the zone through. True if the car routed around the zone, false if
+    /// the trip was cancelled.
+    pub capped_trips: Vec<(Time, TripID, RoadID, bool)>,
 
     pub(crate) alerts: Vec<(Time, AlertLocation, String)>,
 
@@ -160,7 +218,11 @@ impl Analytics {
             demand: BTreeMap::new(),
             bus_arrivals: Vec::new(),
             passengers_boarding: BTreeMap::new(),
+            schedule_deviation: Vec::new(),
             passengers_alighting: BTreeMap::new(),
+            denied_boardings: BTreeMap::new(),
+            transit_loads: BTreeMap::new(),
+            signal_priority: Vec::new(),
             started_trips: BTreeMap::new(),
             finished_trips: Vec::new(),
             problems_per_trip: BTreeMap::new(),
@@ -168,6 +230,21 @@ impl Analytics {
             intersection_delays: BTreeMap::new(),
             parking_lane_changes: BTreeMap::new(),
             parking_lot_changes: BTreeMap::new(),
+            parking_revenue: BTreeMap::new(),
+            parking_prices: BTreeMap::new(),
+            parking_searches: Vec::new(),
+            cruising_per_road: BTreeMap::new(),
+            searching_for_parking: BTreeSet::new(),
+            road_emissions: BTreeMap::new(),
+            intersection_emissions: BTreeMap::new(),
+            trip_emissions: BTreeMap::new(),
+            emissions_per_hour: Vec::new(),
+            ride_hail_distance_per_hour: Vec::new(),
+            ride_hail_curb_stops: Vec::new(),
+            delivery_stops: Vec::new(),
+            micromobility_availability: BTreeMap::new(),
+            micromobility_unmet_demand: Vec::new(),
+            capped_trips: Vec::new(),
             alerts: Vec::new(),
             record_anything,
         }
@@ -232,6 +309,25 @@ impl Analytics {
             self.bus_arrivals.push((time, bus, route, stop));
         }
 
+        if let Event::PassengersDeniedBoarding(_, route, stop, count) = ev {
+            self.denied_boardings
+                .entry(stop)
+                .or_insert_with(Vec::new)
+                .push((time, route, count));
+        }
+        if let Event::BusDepartedFromStop(bus, route, stop, load) = ev {
+            self.transit_loads
+                .entry(route)
+                .or_insert_with(Vec::new)
+                .push((time, bus, stop, load));
+        }
+        if let Event::SignalPriorityGranted(car, i, dt) = ev {
+            self.signal_priority.push((time, car, i, dt));
+        }
+        if let Event::TransitScheduleDeviation(bus, route, stop, dt) = ev {
+            self.schedule_deviation.push((time, bus, route, stop, dt));
+        }
+
         // Passengers boarding/alighting
         if let Event::PassengerBoardsTransit(_, _, route, stop, waiting) = ev {
             self.passengers_boarding
@@ -318,6 +414,97 @@ impl Analytics {
                     .push((time, false));
             }
         }
+        if let Event::ParkingFeePaid(_, spot, fee) = ev {
+            self.parking_revenue
+                .entry(parking_block(spot, map))
+                .or_insert_with(Vec::new)
+                .push((time, fee));
+        }
+        if let Event::ParkingPriceChanged(r, price) = ev {
+            self.parking_prices
+                .entry(r)
+                .or_insert_with(Vec::new)
+                .push((time, price));
+        }
+
+        // Cruising for parking
+        if let Event::TripPhaseStarting(trip, _, _, TripPhaseType::SearchingForParking) = ev {
+            self.searching_for_parking.insert(trip);
+        }
+        if let Event::AgentEntersTraversable(_, Some(trip), Traversable::Lane(l), _) = ev {
+            if self.searching_for_parking.contains(&trip) {
+                *self
+                    .cruising_per_road
+                    .entry(l.road)
+                    .or_insert(Distance::ZERO) += map.get_l(l).length();
+            }
+        }
+        if let Event::ParkingSearchFinished(trip, b, duration, dist) = ev {
+            self.searching_for_parking.remove(&trip);
+            self.parking_searches.push((time, trip, b, duration, dist));
+        }
+        if let Event::TripCancelled(trip, _) = ev {
+            self.searching_for_parking.remove(&trip);
+        }
+
+        // Emissions
+        if let Event::VehicleEmissions(_, maybe_trip, on, emissions) = ev {
+            match on {
+                Traversable::Lane(l) => {
+                    *self.road_emissions.entry(l.road).or_default() += emissions;
+                }
+                Traversable::Turn(t) => {
+                    *self.intersection_emissions.entry(t.parent).or_default() += emissions;
+                }
+            }
+            if let Some(trip) = maybe_trip {
+                *self.trip_emissions.entry(trip).or_default() += emissions;
+            }
+            let hour = time.get_hours();
+            if self.emissions_per_hour.len() <= hour {
+                self.emissions_per_hour.resize(hour + 1, Emissions::ZERO);
+            }
+            self.emissions_per_hour[hour] += emissions;
+        }
+
+        // Ride-hail
+        if let Event::RideHailLegFinished(_, passenger, dist) = ev {
+            let hour = time.get_hours();
+            if self.ride_hail_distance_per_hour.len() <= hour {
+                self.ride_hail_distance_per_hour
+                    .resize(hour + 1, (Distance::ZERO, Distance::ZERO));
+            }
+            if passenger.is_some() {
+                self.ride_hail_distance_per_hour[hour].0 += dist;
+            } else {
+                self.ride_hail_distance_per_hour[hour].1 += dist;
+            }
+        }
+        if let Event::RideHailArrivedAtCurb(car, lane) = ev {
+            self.ride_hail_curb_stops.push((time, car, lane));
+        }
+
+        // Freight
+        if let Event::DeliveryStop(car, _, lane, dwell, double_parked) = ev {
+            self.delivery_stops
+                .push((time, car, lane, dwell, double_parked));
+        }
+
+        // Micromobility
+        if let Event::SharedVehiclesAvailable(b, count) = ev {
+            self.micromobility_availability
+                .entry(b)
+                .or_insert_with(Vec::new)
+                .push((time, count));
+        }
+        if let Event::NoSharedVehicleAvailable(trip, b) = ev {
+            self.micromobility_unmet_demand.push((time, trip, b));
+        }
+
+        // Congestion capping
+        if let Event::ZoneCapReached(trip, zone, rerouted) = ev {
+            self.capped_trips.push((time, trip, zone, rerouted));
+        }
 
         // Safety metrics
         if let Event::AgentEntersTraversable(a, Some(trip), Traversable::Turn(t), _) = ev {
@@ -358,6 +545,16 @@ impl Analytics {
             Event::PathAmended(path) => {
                 self.record_demand(&path, map);
             }
+            Event::VehicleRerouted(_, _, old_path, new_path) => {
+                for step in old_path.get_steps() {
+                    if let Traversable::Turn(t) = step.as_traversable() {
+                        if let Some((id, _)) = map.get_movement_for_traffic_signal(t) {
+                            *self.demand.entry(id).or_insert(0) -= 1;
+                        }
+                    }
+                }
+                self.record_demand(&new_path, map);
+            }
             Event::Alert(loc, msg) => {
                 self.alerts.push((time, loc, msg));
             }
@@ -384,6 +581,86 @@ impl Analytics {
     // TODO If these ever need to be speeded up, just cache the histogram and index in the events
     // list.
 
+    /// Emissions from all vehicles so far.
+    pub fn total_emissions(&self) -> Emissions {
+        let mut total = Emissions::ZERO;
+        for emissions in &self.emissions_per_hour {
+            total += *emissions;
+        }
+        total
+    }
+
+    /// Emissions from all vehicles up to some time. Only hourly totals are kept, so this assumes
+    /// emissions are spread evenly through the last hour. Meant for comparing a live simulation
+    /// against prebaked results.
+    pub fn total_emissions_by_time(&self, now: Time) -> Emissions {
+        let hour = now.get_hours();
+        let mut total = Emissions::ZERO;
+        for (idx, emissions) in self.emissions_per_hour.iter().enumerate() {
+            if idx < hour {
+                total += *emissions;
+            } else if idx == hour {
+                let into_hour = now - (Time::START_OF_DAY + Duration::hours(hour));
+                total += *emissions * (into_hour / Duration::hours(1));
+            }
+        }
+        total
+    }
+
+    /// Total distance driven by ride-hail vehicles so far, split into (with a passenger, empty).
+    pub fn ride_hail_mileage(&self) -> (Distance, Distance) {
+        let mut occupied = Distance::ZERO;
+        let mut empty = Distance::ZERO;
+        for (with_passenger, without) in &self.ride_hail_distance_per_hour {
+            occupied += *with_passenger;
+            empty += *without;
+        }
+        (occupied, empty)
+    }
+
+    /// For each stop along a route, the most passengers aboard any vehicle leaving it.
+    pub fn max_load_per_segment(&self, route: TransitRouteID) -> BTreeMap<TransitStopID, usize> {
+        let mut per_stop = BTreeMap::new();
+        for (_, _, stop, load) in self.transit_loads.get(&route).into_iter().flatten() {
+            let max = per_stop.entry(*stop).or_insert(0);
+            *max = (*max).max(*load);
+        }
+        per_stop
+    }
+
+    /// The fraction of departures from stops along a route that were on time, using the common
+    /// definition of no more than 1 minute early or 5 minutes late. None if no vehicles on the
+    /// route follow a timetable yet.
+    pub fn on_time_performance(&self, route: TransitRouteID) -> Option<f64> {
+        let mut total = 0;
+        let mut on_time = 0;
+        for (_, _, r, _, dt) in &self.schedule_deviation {
+            if *r == route {
+                total += 1;
+                if *dt >= -Duration::minutes(1) && *dt <= Duration::minutes(5) {
+                    on_time += 1;
+                }
+            }
+        }
+        if total == 0 {
+            None
+        } else {
+            Some((on_time as f64) / (total as f64))
+        }
+    }
+
+    /// For each lane, the total time delivery vehicles have spent double-parked on it. Stops that
+    /// haven't finished yet count in full.
+    pub fn double_parking_per_lane(&self) -> BTreeMap<LaneID, Duration> {
+        let mut per_lane = BTreeMap::new();
+        for (_, _, lane, dwell, double_parked) in &self.delivery_stops {
+            if *double_parked {
+                *per_lane.entry(*lane).or_insert(Duration::ZERO) += *dwell;
+            }
+        }
+        per_lane
+    }
+
     /// Ignores the current time. Returns None for cancelled trips.
     pub fn finished_trip_time(&self, trip: TripID) -> Option<Duration> {
         // TODO This is so inefficient!
@@ -545,6 +822,46 @@ impl Analytics {
         }
     }
 
+    /// Total dollars paid to park on each block so far
+    pub fn parking_revenue_per_block(&self) -> BTreeMap<RoadID, f64> {
+        self.parking_revenue
+            .iter()
+            .map(|(r, fees)| (*r, fees.iter().map(|(_, fee)| fee).sum()))
+            .collect()
+    }
+
+    /// How many cars routed around a capped zone and how many trips were cancelled because it was
+    /// full
+    pub fn capped_trips_in_zone(&self, zone: &Zone) -> (usize, usize) {
+        let mut rerouted = 0;
+        let mut cancelled = 0;
+        for (_, _, r, ok) in &self.capped_trips {
+            if zone.members.contains(r) {
+                if *ok {
+                    rerouted += 1;
+                } else {
+                    cancelled += 1;
+                }
+            }
+        }
+        (rerouted, cancelled)
+    }
+
+    /// Per destination building, how many drivers searched for parking nearby, and the total time
+    /// and distance they spent
+    pub fn parking_search_per_building(&self) -> BTreeMap<BuildingID, (usize, Duration, Distance)> {
+        let mut per_building: BTreeMap<BuildingID, (usize, Duration, Distance)> = BTreeMap::new();
+        for (_, _, b, duration, dist) in &self.parking_searches {
+            let entry = per_building
+                .entry(*b)
+                .or_insert((0, Duration::ZERO, Distance::ZERO));
+            entry.0 += 1;
+            entry.1 += *duration;
+            entry.2 += *dist;
+        }
+        per_building
+    }
+
     fn parking_spot_availability(
         now: Time,
         changes: &[(Time, bool)],
@@ -782,6 +1099,22 @@ impl<X: Ord + Clone> TimeSeriesCount<X> {
         cnt
     }
 
+    /// Like `all_total_counts`, but only counting hours up to and including the one containing
+    /// `now`.
+    pub fn all_total_counts_by_time(
+        &self,
+        agent_types: &BTreeSet<AgentType>,
+        now: Time,
+    ) -> Counter<X> {
+        let mut cnt = Counter::new();
+        for ((id, agent_type, hour), value) in &self.counts {
+            if *hour <= now.get_hours() && agent_types.contains(agent_type) {
+                cnt.add(id.clone(), *value);
+            }
+        }
+        cnt
+    }
+
     pub fn count_per_hour(&self, id: X, time: Time) -> Vec<(AgentType, Vec<(Time, usize)>)> {
         let hour = time.get_hours();
         let mut results = Vec::new();
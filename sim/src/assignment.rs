@@ -0,0 +1,354 @@
+//! Iterative dynamic traffic assignment. Driving trips normally pick their route using static
+//! costs, as if the map were empty, so everybody piles onto the same shortest routes. Instead,
+//! repeatedly simulate the full day, measure the congestion delay along every road, and move some
+//! trips onto routes that account for it. Stop once the relative gap is small, meaning few drivers
+//! could still save time by switching routes -- an approximate user equilibrium.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Result;
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
+
+use abstio::MapName;
+use abstutil::{deserialize_btreemap, serialize_btreemap, Timer};
+use geom::{Duration, Time};
+use map_model::connectivity::vehicle_cost;
+use map_model::{
+    DirectedRoadID, Map, MovementID, PathConstraints, PathRequest, PathfinderCaching,
+    RoutingParams, Traversable,
+};
+use synthpop::Scenario;
+
+use crate::{AgentID, AlertHandler, Event, SimOptions, TripID, TripPhaseType, VehicleType};
+
+/// Roads with a smaller average delay than this are treated as uncongested. Pathfinding costs are
+/// rounded to seconds anyway.
+const MIN_DELAY: Duration = Duration::const_seconds(1.0);
+
+/// Controls how an equilibrium is found.
+#[derive(Clone, Debug)]
+pub struct AssignmentOptions {
+    /// Give up after simulating this many days, even if the relative gap hasn't converged.
+    pub max_iterations: usize,
+    /// After each iteration, this fraction of driving trips (between 0 and 1) switch to routes
+    /// using the most recently observed costs.
+    pub reroute_fraction: f64,
+    /// Stop once the relative gap drops to this.
+    pub target_relative_gap: f64,
+}
+
+impl Default for AssignmentOptions {
+    fn default() -> AssignmentOptions {
+        AssignmentOptions {
+            max_iterations: 10,
+            reroute_fraction: 0.2,
+            target_relative_gap: 0.01,
+        }
+    }
+}
+
+/// The result of a traffic assignment for one scenario. Use it through
+/// `SimOptions::traffic_assignment` to route driving trips with the converged costs.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrafficAssignment {
+    pub map_name: MapName,
+    pub scenario_name: String,
+    /// The routing costs derived from each iteration, including the observed congestion delay
+    /// along each road. The last is the most recent.
+    pub costs: Vec<RoutingParams>,
+    /// Rerouted driving trips use the costs from this iteration. Other trips use the map's usual
+    /// costs.
+    pub trip_costs: BTreeMap<TripID, usize>,
+    /// The relative gap measured in each iteration: the fraction of total driving time that could
+    /// be saved if every driver switched to their fastest route.
+    pub relative_gaps: Vec<f64>,
+}
+
+impl TrafficAssignment {
+    /// Repeatedly simulates a scenario until the relative gap converges.
+    pub fn new(
+        map: &Map,
+        scenario: &Scenario,
+        opts: &AssignmentOptions,
+        timer: &mut Timer,
+    ) -> Result<TrafficAssignment> {
+        if !(0.0..=1.0).contains(&opts.reroute_fraction) {
+            bail!(
+                "The reroute fraction {} must be between 0 and 1",
+                opts.reroute_fraction
+            );
+        }
+
+        let mut assignment = TrafficAssignment {
+            map_name: scenario.map_name.clone(),
+            scenario_name: scenario.scenario_name.clone(),
+            costs: Vec::new(),
+            trip_costs: BTreeMap::new(),
+            relative_gaps: Vec::new(),
+        };
+        let mut rng = XorShiftRng::seed_from_u64(42);
+
+        for iteration in 1..=opts.max_iterations {
+            timer.start(format!("traffic assignment iteration {}", iteration));
+            let observed = assignment.simulate(map, scenario, timer);
+            let costs = observed.routing_params(map);
+            let gap = observed.relative_gap(&costs, map);
+            timer.stop(format!("traffic assignment iteration {}", iteration));
+            info!(
+                "After iteration {}, the relative gap is {:.4}",
+                iteration, gap
+            );
+
+            assignment.costs.push(costs);
+            assignment.relative_gaps.push(gap);
+            if gap <= opts.target_relative_gap {
+                break;
+            }
+
+            let newest = assignment.costs.len() - 1;
+            for trip in observed.trips.keys() {
+                if rng.gen_bool(opts.reroute_fraction) {
+                    assignment.trip_costs.insert(*trip, newest);
+                }
+            }
+        }
+        Ok(assignment)
+    }
+
+    /// Loads a previously saved assignment.
+    pub fn load(path: &str) -> Result<TrafficAssignment> {
+        abstio::maybe_read_binary(path.to_string(), &mut Timer::throwaway())
+    }
+
+    /// Saves the assignment alongside the scenario's other system data.
+    pub fn save(&self) {
+        abstio::write_binary(
+            abstio::path_traffic_assignment(&self.map_name, &self.scenario_name),
+            self,
+        );
+    }
+
+    /// The latest relative gap, or None if nothing has been simulated yet.
+    pub fn relative_gap(&self) -> Option<f64> {
+        self.relative_gaps.last().cloned()
+    }
+
+    /// Just the costs that a simulation needs to route the rerouted trips.
+    pub(crate) fn assigned_routing(&self) -> AssignedRouting {
+        let mut costs = Vec::new();
+        let mut old_to_new = BTreeMap::new();
+        let mut trips = BTreeMap::new();
+        for (trip, idx) in &self.trip_costs {
+            let new_idx = *old_to_new.entry(*idx).or_insert_with(|| {
+                costs.push(self.costs[*idx].clone());
+                costs.len() - 1
+            });
+            trips.insert(*trip, new_idx);
+        }
+        AssignedRouting {
+            scenario_name: self.scenario_name.clone(),
+            costs,
+            trips,
+        }
+    }
+
+    /// Simulates a full day with the current assignment and watches every car.
+    fn simulate(&self, map: &Map, scenario: &Scenario, timer: &mut Timer) -> Observations {
+        let mut opts = SimOptions::new("traffic_assignment");
+        opts.alerts = AlertHandler::Silence;
+        opts.traffic_assignment = Some(self.clone());
+        let mut sim = crate::prebake::instantiate(map, scenario, opts, timer);
+        sim.start_capturing_events();
+
+        // Like prebaking, keep going a few hours after the end of the day. Step an hour at a time
+        // to avoid holding onto too many events.
+        let end_time = sim.get_end_of_day() + Duration::hours(3);
+        let mut observed = Observations::default();
+        while sim.time() < end_time {
+            let dt = Duration::hours(1).min(end_time - sim.time());
+            sim.timed_step(map, dt, &mut None, timer);
+            for (time, ev) in sim.drain_captured_events() {
+                observed.handle_event(time, ev, map);
+            }
+        }
+        observed
+    }
+}
+
+/// The part of a traffic assignment that's kept while simulating: the routing costs for each
+/// rerouted trip. The other iterations and the relative gaps aren't needed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct AssignedRouting {
+    pub scenario_name: String,
+    /// Only the iterations that some trip was rerouted with
+    costs: Vec<RoutingParams>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    trips: BTreeMap<TripID, usize>,
+}
+
+impl AssignedRouting {
+    /// The routing params for a driving trip, if they differ from the map's.
+    pub fn routing_params(&self, trip: TripID) -> Option<&RoutingParams> {
+        self.trips.get(&trip).map(|idx| &self.costs[*idx])
+    }
+}
+
+#[derive(Default)]
+struct Observations {
+    /// For each road, the total delay experienced beyond the usual cost of crossing it, and the
+    /// number of cars that crossed it
+    delays: BTreeMap<DirectedRoadID, (Duration, usize)>,
+    /// For each driving trip, the original request and the roads actually crossed
+    trips: BTreeMap<TripID, (PathRequest, Vec<DirectedRoadID>)>,
+    /// Trips currently driving, with the road they're on and when they entered it. This isn't
+    /// known for the first road.
+    driving: HashMap<TripID, Option<(DirectedRoadID, Time)>>,
+}
+
+impl Observations {
+    fn handle_event(&mut self, time: Time, ev: Event, map: &Map) {
+        match ev {
+            Event::TripPhaseStarting(trip, _, Some(req), TripPhaseType::Driving)
+                if req.constraints == PathConstraints::Car =>
+            {
+                self.trips.insert(trip, (req, Vec::new()));
+                self.driving.insert(trip, None);
+            }
+            Event::TripPhaseStarting(trip, _, _, phase) => {
+                // The parking phase begins just before the car enters the last road of its route.
+                // Anything crossed afterwards while looking for parking isn't part of the route.
+                if self.driving.remove(&trip).is_some()
+                    && matches!(
+                        phase,
+                        TripPhaseType::Parking | TripPhaseType::SearchingForParking
+                    )
+                {
+                    let (req, roads) = self.trips.get_mut(&trip).unwrap();
+                    let end = map.get_l(req.end.lane()).get_directed_parent();
+                    if roads.last() != Some(&end) {
+                        roads.push(end);
+                    }
+                }
+            }
+            Event::TripFinished { trip, .. } | Event::TripCancelled(trip, _) => {
+                self.driving.remove(&trip);
+            }
+            Event::AgentEntersTraversable(AgentID::Car(car), Some(trip), on, _)
+                if car.vehicle_type == VehicleType::Car =>
+            {
+                let current = match self.driving.get_mut(&trip) {
+                    Some(x) => x,
+                    None => {
+                        return;
+                    }
+                };
+                let roads = &mut self.trips.get_mut(&trip).unwrap().1;
+                match on {
+                    Traversable::Turn(t) => {
+                        // The first road only shows up once the car leaves it
+                        if roads.is_empty() {
+                            roads.push(map.get_l(t.src).get_directed_parent());
+                        }
+                    }
+                    Traversable::Lane(l) => {
+                        let dr = map.get_l(l).get_directed_parent();
+                        if let Some((prev, entered)) = *current {
+                            if let Some(cost) = movement(prev, dr, map).and_then(|mvmnt| {
+                                vehicle_cost(
+                                    prev,
+                                    mvmnt,
+                                    PathConstraints::Car,
+                                    map.routing_params(),
+                                    map,
+                                )
+                            }) {
+                                let entry = self.delays.entry(prev).or_insert((Duration::ZERO, 0));
+                                entry.0 += (time - entered - cost).max(Duration::ZERO);
+                                entry.1 += 1;
+                            }
+                        }
+                        roads.push(dr);
+                        *current = Some((dr, time));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The map's routing costs, plus the average delay observed along each road.
+    fn routing_params(&self, map: &Map) -> RoutingParams {
+        let mut params = map.routing_params().clone();
+        for (dr, (total, count)) in &self.delays {
+            let delay = *total / (*count as f64);
+            if delay >= MIN_DELAY {
+                params.road_delays.insert(*dr, delay);
+            }
+        }
+        params
+    }
+
+    /// Compares the cost of the routes actually taken against the fastest routes, both using the
+    /// same costs.
+    fn relative_gap(&self, params: &RoutingParams, map: &Map) -> f64 {
+        let mut experienced = Duration::ZERO;
+        let mut fastest = Duration::ZERO;
+        for (req, roads) in self.trips.values() {
+            // Only count trips that reached the end of their route
+            let end = map.get_l(req.end.lane()).get_directed_parent();
+            let idx = match roads.iter().position(|dr| *dr == end) {
+                Some(idx) => idx,
+                None => {
+                    continue;
+                }
+            };
+            let cost = match route_cost(&roads[..=idx], params, map) {
+                Some(cost) => cost,
+                None => {
+                    continue;
+                }
+            };
+            if let Ok(path) =
+                map.pathfind_v2_with_params(req.clone(), params, PathfinderCaching::CacheCH)
+            {
+                experienced += cost;
+                fastest += path.get_cost().min(cost);
+            }
+        }
+        if experienced == Duration::ZERO {
+            return 0.0;
+        }
+        (experienced - fastest) / experienced
+    }
+}
+
+/// The movement between two roads, if it exists.
+fn movement(from: DirectedRoadID, to: DirectedRoadID, map: &Map) -> Option<MovementID> {
+    let mvmnt = MovementID {
+        from,
+        to,
+        parent: from.dst_i(map),
+        crosswalk: false,
+    };
+    if map.get_i(mvmnt.parent).movements.contains_key(&mvmnt) {
+        Some(mvmnt)
+    } else {
+        None
+    }
+}
+
+/// The cost of following a sequence of roads, calculated the same way as pathfinding. None if the
+/// sequence includes a movement that's not allowed.
+fn route_cost(roads: &[DirectedRoadID], params: &RoutingParams, map: &Map) -> Option<Duration> {
+    let mut total = Duration::ZERO;
+    for pair in roads.windows(2) {
+        let mvmnt = movement(pair[0], pair[1], map)?;
+        total += vehicle_cost(pair[0], mvmnt, PathConstraints::Car, params, map)?;
+    }
+    Some(total)
+}
@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::Time;
+use map_model::{
+    Map, Path, PathConstraints, PathRequest, PathStep, PathfinderCaching, RoadID, RoutingParams,
+    Traversable, Zone,
+};
+
+use crate::{AgentID, Event, VehicleType};
+
+/// Some zones cap how many motor vehicles may enter them per hour, to model congestion charging
+/// cordons and area licences. Each clock hour, the first vehicles to drive into a capped zone are
+/// let in. Once a zone is full, vehicles planning a route through it have to go around, or give up
+/// if they can't. Buses and trains are exempt, since they follow fixed routes.
+///
+/// A vehicle counts towards the cap when it actually enters the zone, so vehicles that already
+/// planned a route through the zone before it filled up still get in. Starting inside a zone
+/// doesn't count as entering it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct CapSimState {
+    /// For every road in a capped zone, the current hour and how many vehicles have entered the
+    /// zone during it. Zones are recalculated when the map is edited, so counts are kept per road,
+    /// not per zone.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    entered: BTreeMap<RoadID, (usize, usize)>,
+}
+
+impl CapSimState {
+    pub fn new() -> CapSimState {
+        CapSimState {
+            entered: BTreeMap::new(),
+        }
+    }
+
+    /// Pathfinds for a vehicle, routing around capped zones that are already full this hour. Also
+    /// returns the roads through which the usual route would've entered each full zone.
+    pub fn pathfind(
+        &self,
+        req: PathRequest,
+        params: Option<&RoutingParams>,
+        now: Time,
+        map: &Map,
+    ) -> (Result<Path>, Vec<RoadID>) {
+        let path = map.pathfind_with_params_at(
+            req.clone(),
+            params.unwrap_or_else(|| map.routing_params()),
+            now,
+            PathfinderCaching::CacheCH,
+        );
+        let path = match path {
+            Ok(path) => path,
+            Err(err) => {
+                return (Err(err), Vec::new());
+            }
+        };
+        if !matches!(
+            req.constraints,
+            PathConstraints::Car | PathConstraints::Truck
+        ) {
+            return (Ok(path), Vec::new());
+        }
+
+        let full_zones = self.full_zones(&path, now, map);
+        if full_zones.is_empty() {
+            return (Ok(path), Vec::new());
+        }
+        let mut params = params
+            .cloned()
+            .unwrap_or_else(|| map.routing_params().clone());
+        for (zone, _) in &full_zones {
+            // A vehicle that starts inside a zone is already in, and will only have to leave it
+            if !zone.members.contains(&req.start.lane().road) {
+                params.avoid_roads.extend(zone.members.clone());
+            }
+        }
+        // Which zones are full changes every hour, so don't cache a pathfinder for this
+        let rerouted = map
+            .pathfind_with_params_at(req, &params, now, PathfinderCaching::NoCache)
+            .ok()
+            // A zone could've been entered by a different road
+            .filter(|path| self.full_zones(path, now, map).is_empty());
+        let entrances = full_zones.into_iter().map(|(_, r)| r).collect::<Vec<_>>();
+        match rerouted {
+            Some(path) => (Ok(path), entrances),
+            None => {
+                let err = anyhow!(
+                    "can't avoid {} zone(s) that already let in as many vehicles as allowed this \
+                     hour",
+                    entrances.len()
+                );
+                (Err(err), entrances)
+            }
+        }
+    }
+
+    /// Counts vehicles as they enter capped zones.
+    pub fn handle_event(&mut self, now: Time, ev: &Event, map: &Map) {
+        if let Event::AgentEntersTraversable(AgentID::Car(car), _, Traversable::Turn(t), _) = ev {
+            // Emergency vehicles are exempt
+            if !matches!(
+                car.vehicle_type,
+                VehicleType::Car | VehicleType::RideHail | VehicleType::Freight
+            ) {
+                return;
+            }
+            if let Some(zone) = zone_entered(t.src.road, t.dst.road, map) {
+                self.record_entry(zone.members.iter().cloned(), now);
+            }
+        }
+    }
+
+    /// Returns the capped zones that a path enters, and that have already let in as many vehicles
+    /// as allowed this hour. Each zone is paired with the road the path enters it through.
+    fn full_zones<'a>(&self, path: &Path, now: Time, map: &'a Map) -> Vec<(&'a Zone, RoadID)> {
+        entrances(path, map)
+            .into_iter()
+            .filter(|(zone, _)| {
+                self.count(zone.members.iter().cloned(), now)
+                    >= zone.restrictions.cap_vehicles_per_hour.unwrap()
+            })
+            .collect()
+    }
+
+    /// How many vehicles have entered the zone made up of these roads during the current hour. If
+    /// an edit merged zones, use the busiest one.
+    fn count<I: Iterator<Item = RoadID>>(&self, members: I, now: Time) -> usize {
+        members
+            .filter_map(|r| match self.entered.get(&r) {
+                Some((hour, count)) if *hour == now.get_hours() => Some(*count),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn record_entry<I: Iterator<Item = RoadID>>(&mut self, members: I, now: Time) {
+        for r in members {
+            let entry = self.entered.entry(r).or_insert((0, 0));
+            if entry.0 != now.get_hours() {
+                *entry = (now.get_hours(), 0);
+            }
+            entry.1 += 1;
+        }
+    }
+}
+
+/// If moving from one road to another enters a capped zone, returns it.
+fn zone_entered(from: RoadID, to: RoadID, map: &Map) -> Option<&Zone> {
+    if map
+        .get_r(to)
+        .access_restrictions
+        .cap_vehicles_per_hour
+        .is_none()
+    {
+        return None;
+    }
+    let zone = map.get_r(to).get_zone(map)?;
+    if zone.members.contains(&from) {
+        None
+    } else {
+        Some(zone)
+    }
+}
+
+/// The capped zones that a path enters, each with the first road it enters through.
+fn entrances<'a>(path: &Path, map: &'a Map) -> Vec<(&'a Zone, RoadID)> {
+    let mut zones: Vec<(&Zone, RoadID)> = Vec::new();
+    let mut prev: Option<RoadID> = None;
+    for step in path.get_steps() {
+        if let PathStep::Lane(l) = step {
+            if let Some(from) = prev {
+                if let Some(zone) = zone_entered(from, l.road, map) {
+                    if !zones.iter().any(|(z, _)| z.members.contains(&l.road)) {
+                        zones.push((zone, l.road));
+                    }
+                }
+            }
+            prev = Some(l.road);
+        }
+    }
+    zones
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hourly_reset() {
+        let zone = vec![RoadID(1), RoadID(2), RoadID(3)];
+        let mut caps = CapSimState::new();
+        let at = |time: &str| Time::parse(time).unwrap();
+
+        caps.record_entry(zone.iter().cloned(), at("7:10:00"));
+        caps.record_entry(zone.iter().cloned(), at("7:59:59"));
+        assert_eq!(caps.count(zone.iter().cloned(), at("7:59:59")), 2);
+
+        // A new hour starts from scratch
+        assert_eq!(caps.count(zone.iter().cloned(), at("8:00:00")), 0);
+        caps.record_entry(zone.iter().cloned(), at("8:05:00"));
+        assert_eq!(caps.count(zone.iter().cloned(), at("8:30:00")), 1);
+
+        // The same hour on a later day isn't the same hour
+        assert_eq!(caps.count(zone.iter().cloned(), at("32:30:00")), 0);
+
+        // If an edit shrinks the zone, the remaining roads keep the count
+        assert_eq!(
+            caps.count([RoadID(2), RoadID(3)].into_iter(), at("8:40:00")),
+            1
+        );
+        // And if it grows, the new road doesn't reset it
+        assert_eq!(
+            caps.count([RoadID(1), RoadID(4)].into_iter(), at("8:40:00")),
+            1
+        );
+    }
+}
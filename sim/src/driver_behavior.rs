@@ -0,0 +1,191 @@
+use anyhow::Result;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
+
+use abstutil::Timer;
+use geom::Duration;
+
+use crate::CarID;
+
+/// How one driver behaves, independent of the vehicle they're driving.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DriverBehavior {
+    /// Multiplies the speed limit. Above 1 means the driver speeds. A vehicle's own max speed
+    /// still applies.
+    pub desired_speed_factor: f64,
+    /// After being stopped, how long it takes to react and get moving again
+    pub startup_lost_time: Duration,
+    /// Before an unprotected turn, the driver won't start if a vehicle with the right-of-way is
+    /// expected to arrive sooner than this.
+    pub critical_gap: Duration,
+    /// How long to wait at a stop sign before yielding
+    pub stop_sign_wait: Duration,
+}
+
+impl DriverBehavior {
+    /// Everybody behaves this way, unless a DriverBehaviorModel is used.
+    pub const DEFAULT: DriverBehavior = DriverBehavior {
+        desired_speed_factor: 1.0,
+        startup_lost_time: Duration::ZERO,
+        critical_gap: Duration::ZERO,
+        stop_sign_wait: Duration::const_seconds(0.5),
+    };
+}
+
+impl Default for DriverBehavior {
+    fn default() -> DriverBehavior {
+        DriverBehavior::DEFAULT
+    }
+}
+
+/// Distributions that each driver's behavior is sampled from, loaded from a JSON file. Every
+/// vehicle's sample only depends on the seed and its ID, so the same vehicle always behaves the
+/// same way, no matter what order vehicles are created in. Buses and trains aren't affected.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DriverBehaviorModel {
+    pub seed: u64,
+    pub desired_speed_factor: ParamDistribution,
+    /// In seconds
+    pub startup_lost_time: ParamDistribution,
+    /// In seconds
+    pub critical_gap: ParamDistribution,
+    /// In seconds
+    pub stop_sign_wait: ParamDistribution,
+}
+
+/// A distribution of one behavior parameter
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ParamDistribution {
+    Constant(f64),
+    Uniform {
+        min: f64,
+        max: f64,
+    },
+    /// Samples are clamped to the range
+    Normal {
+        mean: f64,
+        std_deviation: f64,
+        min: f64,
+        max: f64,
+    },
+}
+
+impl DriverBehaviorModel {
+    /// Read and validate a model from a JSON file.
+    pub fn load(path: &str) -> Result<DriverBehaviorModel> {
+        let model: DriverBehaviorModel =
+            abstio::maybe_read_json(path.to_string(), &mut Timer::throwaway())?;
+        model.validate()?;
+        Ok(model)
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.desired_speed_factor
+            .validate("desired_speed_factor", 0.1)?;
+        self.startup_lost_time.validate("startup_lost_time", 0.0)?;
+        self.critical_gap.validate("critical_gap", 0.0)?;
+        self.stop_sign_wait.validate("stop_sign_wait", 0.0)
+    }
+
+    pub(crate) fn sample(&self, id: CarID) -> DriverBehavior {
+        let mut rng = XorShiftRng::seed_from_u64(self.seed ^ (id.id as u64));
+        DriverBehavior {
+            desired_speed_factor: self.desired_speed_factor.sample(&mut rng),
+            startup_lost_time: Duration::seconds(self.startup_lost_time.sample(&mut rng)),
+            critical_gap: Duration::seconds(self.critical_gap.sample(&mut rng)),
+            stop_sign_wait: Duration::seconds(self.stop_sign_wait.sample(&mut rng)),
+        }
+    }
+}
+
+impl ParamDistribution {
+    /// Every sample must be at least `lowest`.
+    fn validate(&self, name: &str, lowest: f64) -> Result<()> {
+        let (min, max) = match self {
+            ParamDistribution::Constant(x) => (*x, *x),
+            ParamDistribution::Uniform { min, max } => (*min, *max),
+            ParamDistribution::Normal {
+                std_deviation,
+                min,
+                max,
+                ..
+            } => {
+                if *std_deviation < 0.0 {
+                    bail!("{} has a negative std_deviation", name);
+                }
+                (*min, *max)
+            }
+        };
+        if min > max {
+            bail!("{} has min {} > max {}", name, min, max);
+        }
+        if min < lowest {
+            bail!("{} can't go below {}", name, lowest);
+        }
+        Ok(())
+    }
+
+    fn sample(&self, rng: &mut XorShiftRng) -> f64 {
+        match self {
+            ParamDistribution::Constant(x) => *x,
+            ParamDistribution::Uniform { min, max } => {
+                if min == max {
+                    *min
+                } else {
+                    rng.gen_range(*min..*max)
+                }
+            }
+            ParamDistribution::Normal {
+                mean,
+                std_deviation,
+                min,
+                max,
+            } => Normal::new(*mean, *std_deviation)
+                .unwrap()
+                .sample(rng)
+                .max(*min)
+                .min(*max),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VehicleType;
+
+    #[test]
+    fn deterministic_samples() {
+        let model = DriverBehaviorModel {
+            seed: 42,
+            desired_speed_factor: ParamDistribution::Normal {
+                mean: 1.0,
+                std_deviation: 0.1,
+                min: 0.8,
+                max: 1.2,
+            },
+            startup_lost_time: ParamDistribution::Uniform { min: 1.0, max: 3.0 },
+            critical_gap: ParamDistribution::Constant(4.0),
+            stop_sign_wait: ParamDistribution::Constant(1.0),
+        };
+        let car = |id| CarID {
+            id,
+            vehicle_type: VehicleType::Car,
+        };
+
+        // The same vehicle always gets the same driver
+        assert_eq!(model.sample(car(7)), model.sample(car(7)));
+        for id in 0..100 {
+            let b = model.sample(car(id));
+            assert!((0.8..=1.2).contains(&b.desired_speed_factor));
+            assert!(
+                b.startup_lost_time >= Duration::seconds(1.0)
+                    && b.startup_lost_time < Duration::seconds(3.0)
+            );
+            assert_eq!(b.critical_gap, Duration::seconds(4.0));
+        }
+        assert!(model.validate().is_ok());
+    }
+}
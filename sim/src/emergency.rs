@@ -0,0 +1,99 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
+
+use geom::{Duration, Time};
+use map_model::{Map, PathConstraints, PathRequest, Position};
+
+use crate::{
+    CarID, Command, CreateCar, Router, Scheduler, SimOptions, TripManager, VehicleSpec,
+    VehicleType, EMERGENCY_VEHICLE_LENGTH, SPAWN_DIST,
+};
+
+/// Generates emergency vehicle runs. Each emergency vehicle enters the map at a border sometime
+/// during the day and drives across to another border. Along the way, traffic signals with
+/// preemption switch to let it through.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct EmergencySimState {
+    num_runs: usize,
+}
+
+impl EmergencySimState {
+    pub fn new(opts: &SimOptions) -> EmergencySimState {
+        EmergencySimState {
+            num_runs: opts.emergency_runs,
+        }
+    }
+
+    pub fn seed_runs(
+        &self,
+        map: &Map,
+        rng: &mut XorShiftRng,
+        trips: &mut TripManager,
+        scheduler: &mut Scheduler,
+    ) {
+        if self.num_runs == 0 {
+            return;
+        }
+        let mut entrances = Vec::new();
+        for i in map.all_outgoing_borders() {
+            for l in i.get_outgoing_lanes(map, PathConstraints::Car) {
+                entrances.push(l);
+            }
+        }
+        let mut exits = Vec::new();
+        for i in map.all_incoming_borders() {
+            for l in i.get_incoming_lanes(map, PathConstraints::Car) {
+                exits.push((i.id, l));
+            }
+        }
+        if entrances.is_empty() || exits.is_empty() {
+            warn!("No borders usable by cars; skipping emergency vehicles");
+            return;
+        }
+
+        for _ in 0..self.num_runs {
+            let start = Position::new(*entrances.choose(rng).unwrap(), SPAWN_DIST);
+            let (i, l) = *exits.choose(rng).unwrap();
+            let depart = Time::START_OF_DAY + Duration::seconds(rng.gen_range(0.0..24.0 * 3600.0));
+            // Time windows on zones don't apply to emergency vehicles
+            let path = match map.pathfind(PathRequest::vehicle(
+                start,
+                Position::end(l, map),
+                PathConstraints::Car,
+            )) {
+                Ok(path) => path,
+                Err(_) => {
+                    continue;
+                }
+            };
+            let vehicle = VehicleSpec {
+                vehicle_type: VehicleType::Emergency,
+                length: EMERGENCY_VEHICLE_LENGTH,
+                max_speed: None,
+            }
+            .make(
+                CarID {
+                    id: trips.new_car_id(),
+                    vehicle_type: VehicleType::Emergency,
+                },
+                None,
+            );
+            let router = Router::end_at_border(vehicle.id, path, map.get_l(l).length(), i);
+            scheduler.push(
+                depart,
+                Command::SpawnCar(
+                    CreateCar {
+                        vehicle,
+                        router,
+                        maybe_parked_car: None,
+                        trip_and_person: None,
+                        maybe_route: None,
+                    },
+                    true,
+                ),
+            );
+        }
+    }
+}
@@ -0,0 +1,379 @@
+use std::ops;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use abstutil::Timer;
+use geom::{Distance, Duration, Speed};
+
+use crate::{CarID, Vehicle, VehicleType};
+
+/// Fuel burned, pollutants emitted, and electricity consumed by vehicles. Electric vehicles
+/// recovering energy from regenerative braking can make `energy_kwh` negative over short
+/// intervals.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Emissions {
+    pub fuel_liters: f64,
+    pub co2_grams: f64,
+    pub nox_grams: f64,
+    pub energy_kwh: f64,
+}
+
+impl Emissions {
+    pub const ZERO: Emissions = Emissions {
+        fuel_liters: 0.0,
+        co2_grams: 0.0,
+        nox_grams: 0.0,
+        energy_kwh: 0.0,
+    };
+
+    pub fn is_zero(&self) -> bool {
+        *self == Emissions::ZERO
+    }
+}
+
+impl ops::Add for Emissions {
+    type Output = Emissions;
+
+    fn add(self, other: Emissions) -> Emissions {
+        Emissions {
+            fuel_liters: self.fuel_liters + other.fuel_liters,
+            co2_grams: self.co2_grams + other.co2_grams,
+            nox_grams: self.nox_grams + other.nox_grams,
+            energy_kwh: self.energy_kwh + other.energy_kwh,
+        }
+    }
+}
+
+impl ops::AddAssign for Emissions {
+    fn add_assign(&mut self, other: Emissions) {
+        *self = *self + other;
+    }
+}
+
+impl ops::Mul<f64> for Emissions {
+    type Output = Emissions;
+
+    fn mul(self, scalar: f64) -> Emissions {
+        Emissions {
+            fuel_liters: self.fuel_liters * scalar,
+            co2_grams: self.co2_grams * scalar,
+            nox_grams: self.nox_grams * scalar,
+            energy_kwh: self.energy_kwh * scalar,
+        }
+    }
+}
+
+/// A lookup table of emission rates for one kind of vehicle, sampled at different speeds and
+/// accelerations. Lookups use the nearest sample, without interpolating.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EmissionRates {
+    pub speeds: Vec<Speed>,
+    /// In meters per second squared
+    pub accelerations: Vec<f64>,
+    /// `per_second[i][j]` is how much is emitted per second at `speeds[i]` and
+    /// `accelerations[j]`.
+    pub per_second: Vec<Vec<Emissions>>,
+}
+
+impl EmissionRates {
+    pub fn lookup(&self, speed: Speed, accel: f64) -> Emissions {
+        if self.speeds.is_empty() || self.accelerations.is_empty() {
+            return Emissions::ZERO;
+        }
+        let i = nearest(&self.speeds, |s| (*s - speed).inner_meters_per_second());
+        let j = nearest(&self.accelerations, |a| *a - accel);
+        self.per_second[i][j]
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.per_second.len() != self.speeds.len() {
+            bail!(
+                "{} speeds, but {} rows of rates",
+                self.speeds.len(),
+                self.per_second.len()
+            );
+        }
+        for row in &self.per_second {
+            if row.len() != self.accelerations.len() {
+                bail!(
+                    "{} accelerations, but a row has {} rates",
+                    self.accelerations.len(),
+                    row.len()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn from_powertrain(powertrain: Powertrain) -> EmissionRates {
+        let speeds: Vec<Speed> = (0..=16)
+            .map(|i| Speed::meters_per_second(2.5 * (i as f64)))
+            .collect();
+        let accelerations: Vec<f64> = (-6..=6).map(|i| 0.5 * (i as f64)).collect();
+        let per_second = speeds
+            .iter()
+            .map(|speed| {
+                accelerations
+                    .iter()
+                    .map(|accel| powertrain.per_second(*speed, *accel))
+                    .collect()
+            })
+            .collect();
+        EmissionRates {
+            speeds,
+            accelerations,
+            per_second,
+        }
+    }
+}
+
+fn nearest<T, F: Fn(&T) -> f64>(samples: &[T], diff: F) -> usize {
+    let mut best = 0;
+    for (idx, x) in samples.iter().enumerate() {
+        if diff(x).abs() < diff(&samples[best]).abs() {
+            best = idx;
+        }
+    }
+    best
+}
+
+/// Estimates emissions for every vehicle in the simulation. The built-in model approximates
+/// typical gasoline cars, diesel buses, and electric cars and trains, but tables for a specific
+/// fleet can be loaded from JSON instead.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EmissionsModel {
+    /// This fraction of cars is electric. Which cars are electric is deterministic, based on the
+    /// vehicle's ID.
+    pub electric_car_fraction: f64,
+    pub combustion_car: EmissionRates,
+    pub electric_car: EmissionRates,
+    pub bus: EmissionRates,
+    pub train: EmissionRates,
+}
+
+impl Default for EmissionsModel {
+    fn default() -> EmissionsModel {
+        EmissionsModel {
+            electric_car_fraction: 0.05,
+            combustion_car: EmissionRates::from_powertrain(Powertrain::gasoline_car()),
+            electric_car: EmissionRates::from_powertrain(Powertrain::electric_car()),
+            bus: EmissionRates::from_powertrain(Powertrain::diesel_bus()),
+            train: EmissionRates::from_powertrain(Powertrain::light_rail()),
+        }
+    }
+}
+
+impl EmissionsModel {
+    /// Either "default" for the built-in model, or a path to a JSON file.
+    pub fn load(x: &str) -> Result<EmissionsModel> {
+        if x == "default" {
+            return Ok(EmissionsModel::default());
+        }
+        let model: EmissionsModel =
+            abstio::maybe_read_json(x.to_string(), &mut Timer::throwaway())?;
+        if !(0.0..=1.0).contains(&model.electric_car_fraction) {
+            bail!(
+                "electric_car_fraction {} must be between 0 and 1",
+                model.electric_car_fraction
+            );
+        }
+        for rates in [
+            &model.combustion_car,
+            &model.electric_car,
+            &model.bus,
+            &model.train,
+        ] {
+            rates.validate()?;
+        }
+        Ok(model)
+    }
+
+    pub fn is_electric(&self, car: CarID) -> bool {
+        match car.vehicle_type {
+            VehicleType::Car | VehicleType::RideHail => {
+                // Scramble the ID, so cars spawned around the same time aren't all electric
+                let bucket = (car.id as u64).wrapping_mul(2_654_435_761) % 1000;
+                (bucket as f64) < 1000.0 * self.electric_car_fraction
+            }
+            VehicleType::Train => true,
+            VehicleType::Bus
+            | VehicleType::Bike
+            | VehicleType::Freight
+            | VehicleType::Emergency => false,
+        }
+    }
+
+    /// How much does a vehicle emit while covering some distance over some time? The speed is
+    /// assumed constant, except for the change from `prev_speed` when the movement began.
+    pub fn movement(
+        &self,
+        vehicle: &Vehicle,
+        prev_speed: Speed,
+        dist: Distance,
+        dt: Duration,
+    ) -> Emissions {
+        if dt <= Duration::ZERO {
+            return Emissions::ZERO;
+        }
+        let speed = Speed::from_dist_time(dist, dt);
+        let accel = (speed - prev_speed).inner_meters_per_second() / dt.inner_seconds();
+        let rates = match vehicle.vehicle_type {
+            VehicleType::Car | VehicleType::RideHail => {
+                if self.is_electric(vehicle.id) {
+                    &self.electric_car
+                } else {
+                    &self.combustion_car
+                }
+            }
+            // A diesel delivery truck or ambulance is close enough to a bus
+            VehicleType::Bus | VehicleType::Freight | VehicleType::Emergency => &self.bus,
+            VehicleType::Train => &self.train,
+            VehicleType::Bike => {
+                return Emissions::ZERO;
+            }
+        };
+        rates.lookup(speed, accel) * dt.inner_seconds()
+    }
+}
+
+/// Physical parameters used to generate the default lookup tables.
+struct Powertrain {
+    mass_kg: f64,
+    /// Drag coefficient times frontal area, in square meters
+    drag_area: f64,
+    rolling_resistance: f64,
+    fuel: Option<Fuel>,
+    electric: Option<Electric>,
+}
+
+struct Fuel {
+    idle_liters_per_second: f64,
+    liters_per_joule: f64,
+    co2_grams_per_liter: f64,
+    nox_grams_per_liter: f64,
+}
+
+struct Electric {
+    drivetrain_efficiency: f64,
+    /// The fraction of braking energy recovered
+    regeneration: f64,
+    auxiliary_watts: f64,
+}
+
+impl Powertrain {
+    fn gasoline_car() -> Powertrain {
+        Powertrain {
+            mass_kg: 1500.0,
+            drag_area: 0.7,
+            rolling_resistance: 0.012,
+            fuel: Some(Fuel {
+                idle_liters_per_second: 0.00025,
+                liters_per_joule: 1.25e-7,
+                co2_grams_per_liter: 2310.0,
+                nox_grams_per_liter: 0.8,
+            }),
+            electric: None,
+        }
+    }
+
+    fn diesel_bus() -> Powertrain {
+        Powertrain {
+            mass_kg: 15000.0,
+            drag_area: 6.0,
+            rolling_resistance: 0.008,
+            fuel: Some(Fuel {
+                idle_liters_per_second: 0.0008,
+                liters_per_joule: 7.9e-8,
+                co2_grams_per_liter: 2640.0,
+                nox_grams_per_liter: 10.0,
+            }),
+            electric: None,
+        }
+    }
+
+    fn electric_car() -> Powertrain {
+        Powertrain {
+            mass_kg: 1800.0,
+            drag_area: 0.6,
+            rolling_resistance: 0.010,
+            fuel: None,
+            electric: Some(Electric {
+                drivetrain_efficiency: 0.85,
+                regeneration: 0.6,
+                auxiliary_watts: 300.0,
+            }),
+        }
+    }
+
+    fn light_rail() -> Powertrain {
+        Powertrain {
+            mass_kg: 45000.0,
+            drag_area: 10.0,
+            rolling_resistance: 0.002,
+            fuel: None,
+            electric: Some(Electric {
+                drivetrain_efficiency: 0.85,
+                regeneration: 0.6,
+                auxiliary_watts: 20000.0,
+            }),
+        }
+    }
+
+    fn per_second(&self, speed: Speed, accel: f64) -> Emissions {
+        const GRAVITY: f64 = 9.81;
+        const AIR_DENSITY: f64 = 1.2;
+        const JOULES_PER_KWH: f64 = 3.6e6;
+
+        let v = speed.inner_meters_per_second();
+        // The power needed at the wheels, in watts
+        let power = self.mass_kg * accel * v
+            + self.mass_kg * GRAVITY * self.rolling_resistance * v
+            + 0.5 * AIR_DENSITY * self.drag_area * v.powi(3);
+
+        let mut result = Emissions::ZERO;
+        if let Some(ref fuel) = self.fuel {
+            result.fuel_liters =
+                fuel.idle_liters_per_second + power.max(0.0) * fuel.liters_per_joule;
+            result.co2_grams = result.fuel_liters * fuel.co2_grams_per_liter;
+            result.nox_grams = result.fuel_liters * fuel.nox_grams_per_liter;
+        }
+        if let Some(ref electric) = self.electric {
+            let drawn = if power >= 0.0 {
+                power / electric.drivetrain_efficiency
+            } else {
+                power * electric.regeneration
+            };
+            result.energy_kwh = (drawn + electric.auxiliary_watts) / JOULES_PER_KWH;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_rates() {
+        let model = EmissionsModel::default();
+        let car = model.combustion_car.lookup(Speed::ZERO, 0.0);
+        assert!(car.fuel_liters > 0.0 && car.energy_kwh == 0.0);
+
+        // Cruising burns more fuel than idling, and accelerating burns more than cruising
+        let cruise = model
+            .combustion_car
+            .lookup(Speed::meters_per_second(15.0), 0.0);
+        let accel = model
+            .combustion_car
+            .lookup(Speed::meters_per_second(15.0), 1.0);
+        assert!(cruise.fuel_liters > car.fuel_liters);
+        assert!(accel.fuel_liters > cruise.fuel_liters);
+
+        // Electric vehicles recover energy while braking
+        let braking = model
+            .electric_car
+            .lookup(Speed::meters_per_second(15.0), -2.0);
+        assert!(braking.energy_kwh < 0.0 && braking.co2_grams == 0.0);
+    }
+}
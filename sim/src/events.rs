@@ -1,13 +1,13 @@
 use serde::{Deserialize, Serialize};
 
-use geom::Duration;
+use geom::{Distance, Duration};
 use map_model::{
-    BuildingID, IntersectionID, LaneID, Map, Path, PathRequest, TransitRouteID, TransitStopID,
-    Traversable, TurnID,
+    BuildingID, IntersectionID, LaneID, Map, Path, PathRequest, RoadID, TransitRouteID,
+    TransitStopID, Traversable, TurnID,
 };
 use synthpop::TripMode;
 
-use crate::{AgentID, CarID, ParkingSpot, PedestrianID, PersonID, Problem, TripID};
+use crate::{AgentID, CarID, Emissions, ParkingSpot, PedestrianID, PersonID, Problem, TripID};
 
 /// As a simulation runs, different systems emit Events. This cleanly separates the internal
 /// mechanics of the simulation from consumers that just want to know what's happening.
@@ -23,7 +23,8 @@ pub enum Event {
     CarLeftParkingSpot(CarID, ParkingSpot),
 
     BusArrivedAtStop(CarID, TransitRouteID, TransitStopID),
-    BusDepartedFromStop(CarID, TransitRouteID, TransitStopID),
+    /// How many passengers are aboard, riding to the next stop?
+    BusDepartedFromStop(CarID, TransitRouteID, TransitStopID, usize),
     /// How long waiting at the stop?
     PassengerBoardsTransit(PersonID, CarID, TransitRouteID, TransitStopID, Duration),
     PassengerAlightsTransit(PersonID, CarID, TransitRouteID, TransitStopID),
@@ -60,6 +61,143 @@ pub enum Event {
     PathAmended(Path),
 
     Alert(AlertLocation, String),
+
+    // New events go at the end, so that previously serialized events still deserialize.
+    /// A vehicle moved or idled somewhere along a lane or turn, emitting this much. The TripID is
+    /// None for buses and trains.
+    VehicleEmissions(CarID, Option<TripID>, Traversable, Emissions),
+
+    /// An informed driver switched routes to avoid congestion. Includes the remaining path before
+    /// and after the change.
+    VehicleRerouted(CarID, Option<TripID>, Path, Path),
+
+    /// A ride-hail vehicle stopped in a driving lane to pick up or drop off a passenger.
+    RideHailArrivedAtCurb(CarID, LaneID),
+    /// A ride-hail vehicle finished one leg of driving. The passenger is None when the vehicle was
+    /// empty, either heading to a pickup or returning to its base.
+    RideHailLegFinished(CarID, Option<PersonID>, Distance),
+
+    /// A delivery vehicle stopped along a lane to load or unload at a building for some time. True
+    /// if it double-parked in the driving lane, false if it found room at the curb.
+    DeliveryStop(CarID, BuildingID, LaneID, Duration, bool),
+
+    /// The number of shared scooters and bikes waiting to be picked up in front of a building
+    /// changed.
+    SharedVehiclesAvailable(BuildingID, usize),
+    /// Somebody couldn't rent a shared vehicle near this building, or couldn't find a free dock
+    /// near their destination, so they walked instead.
+    NoSharedVehicleAvailable(TripID, BuildingID),
+
+    /// A vehicle following a timetable departed from a stop. Positive means late, negative early.
+    TransitScheduleDeviation(CarID, TransitRouteID, TransitStopID, Duration),
+    /// How many people waiting for this route couldn't board, because the vehicle was full?
+    PassengersDeniedBoarding(CarID, TransitRouteID, TransitStopID, usize),
+    /// A traffic signal changed when its current stage ends for an approaching bus or train.
+    /// Positive means the stage was extended, negative means it was cut short.
+    SignalPriorityGranted(CarID, IntersectionID, Duration),
+
+    /// A driver paid this many dollars to park in a priced spot for their expected stay.
+    ParkingFeePaid(CarID, ParkingSpot, f64),
+    /// Demand-responsive pricing changed the hourly price of on-street parking along a road.
+    ParkingPriceChanged(RoadID, f64),
+    /// A driver reached a free spot after looking for parking near a building for some time and
+    /// distance.
+    ParkingSearchFinished(TripID, BuildingID, Duration, Distance),
+
+    /// A car's route would've entered a zone, through this road, after the zone already let in as
+    /// many vehicles as allowed this hour. True if the car routed around the zone, false if the
+    /// trip was cancelled.
+    ZoneCapReached(TripID, RoadID, bool),
+}
+
+impl Event {
+    /// The name of this event's variant, useful for filtering a stream of events.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::CarReachedParkingSpot(_, _) => "CarReachedParkingSpot",
+            Event::CarLeftParkingSpot(_, _) => "CarLeftParkingSpot",
+            Event::BusArrivedAtStop(_, _, _) => "BusArrivedAtStop",
+            Event::BusDepartedFromStop(_, _, _, _) => "BusDepartedFromStop",
+            Event::PassengerBoardsTransit(_, _, _, _, _) => "PassengerBoardsTransit",
+            Event::PassengerAlightsTransit(_, _, _, _) => "PassengerAlightsTransit",
+            Event::PersonEntersBuilding(_, _) => "PersonEntersBuilding",
+            Event::PersonLeavesBuilding(_, _) => "PersonLeavesBuilding",
+            Event::PersonLeavesMap(_, _, _) => "PersonLeavesMap",
+            Event::PersonEntersMap(_, _, _) => "PersonEntersMap",
+            Event::PedReachedParkingSpot(_, _) => "PedReachedParkingSpot",
+            Event::BikeStoppedAtSidewalk(_, _) => "BikeStoppedAtSidewalk",
+            Event::ProblemEncountered(_, _) => "ProblemEncountered",
+            Event::AgentEntersTraversable(_, _, _, _) => "AgentEntersTraversable",
+            Event::IntersectionDelayMeasured(_, _, _, _) => "IntersectionDelayMeasured",
+            Event::TripFinished { .. } => "TripFinished",
+            Event::TripCancelled(_, _) => "TripCancelled",
+            Event::TripPhaseStarting(_, _, _, _) => "TripPhaseStarting",
+            Event::PathAmended(_) => "PathAmended",
+            Event::Alert(_, _) => "Alert",
+            Event::VehicleEmissions(_, _, _, _) => "VehicleEmissions",
+            Event::VehicleRerouted(_, _, _, _) => "VehicleRerouted",
+            Event::RideHailArrivedAtCurb(_, _) => "RideHailArrivedAtCurb",
+            Event::RideHailLegFinished(_, _, _) => "RideHailLegFinished",
+            Event::DeliveryStop(_, _, _, _, _) => "DeliveryStop",
+            Event::SharedVehiclesAvailable(_, _) => "SharedVehiclesAvailable",
+            Event::NoSharedVehicleAvailable(_, _) => "NoSharedVehicleAvailable",
+            Event::TransitScheduleDeviation(_, _, _, _) => "TransitScheduleDeviation",
+            Event::PassengersDeniedBoarding(_, _, _, _) => "PassengersDeniedBoarding",
+            Event::SignalPriorityGranted(_, _, _) => "SignalPriorityGranted",
+            Event::ParkingFeePaid(_, _, _) => "ParkingFeePaid",
+            Event::ParkingPriceChanged(_, _) => "ParkingPriceChanged",
+            Event::ParkingSearchFinished(_, _, _, _) => "ParkingSearchFinished",
+            Event::ZoneCapReached(_, _, _) => "ZoneCapReached",
+        }
+    }
+
+    /// If this event happened at a particular intersection, return it.
+    pub fn intersection(&self) -> Option<IntersectionID> {
+        match self {
+            Event::PersonLeavesMap(_, _, i) | Event::PersonEntersMap(_, _, i) => Some(*i),
+            Event::AgentEntersTraversable(_, _, Traversable::Turn(t), _)
+            | Event::IntersectionDelayMeasured(_, t, _, _)
+            | Event::VehicleEmissions(_, _, Traversable::Turn(t), _) => Some(t.parent),
+            Event::ProblemEncountered(_, problem) => match problem {
+                Problem::IntersectionDelay(i, _) | Problem::ComplexIntersectionCrossing(i) => {
+                    Some(*i)
+                }
+                Problem::ArterialIntersectionCrossing(t) => Some(t.parent),
+                Problem::OvertakeDesired(Traversable::Turn(t))
+                | Problem::PedestrianOvercrowding(Traversable::Turn(t)) => Some(t.parent),
+                Problem::OvertakeDesired(Traversable::Lane(_))
+                | Problem::PedestrianOvercrowding(Traversable::Lane(_)) => None,
+            },
+            Event::Alert(AlertLocation::Intersection(i), _)
+            | Event::SignalPriorityGranted(_, i, _) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// If this event happened somewhere along a particular road, return it.
+    pub fn road(&self) -> Option<RoadID> {
+        match self {
+            Event::CarReachedParkingSpot(_, spot)
+            | Event::CarLeftParkingSpot(_, spot)
+            | Event::ParkingFeePaid(_, spot, _)
+            | Event::PedReachedParkingSpot(_, spot) => match spot {
+                ParkingSpot::Onstreet(l, _) => Some(l.road),
+                ParkingSpot::Offstreet(_, _) | ParkingSpot::Lot(_, _) => None,
+            },
+            Event::ParkingPriceChanged(r, _) => Some(*r),
+            Event::BikeStoppedAtSidewalk(_, l)
+            | Event::RideHailArrivedAtCurb(_, l)
+            | Event::DeliveryStop(_, _, l, _, _) => Some(l.road),
+            Event::AgentEntersTraversable(_, _, Traversable::Lane(l), _)
+            | Event::VehicleEmissions(_, _, Traversable::Lane(l), _) => Some(l.road),
+            Event::ProblemEncountered(_, Problem::OvertakeDesired(on))
+            | Event::ProblemEncountered(_, Problem::PedestrianOvercrowding(on)) => match on {
+                Traversable::Lane(l) => Some(l.road),
+                Traversable::Turn(_) => None,
+            },
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
@@ -82,6 +220,11 @@ pub enum TripPhaseType {
     Cancelled,
     Finished,
     DelayedStart,
+    // New phases go at the end, so that previously serialized phases still deserialize.
+    WaitingForRideHail,
+    RidingRideHail(CarID),
+    /// The spot the driver planned on was taken, so they're circling to find another
+    SearchingForParking,
 }
 
 impl TripPhaseType {
@@ -100,6 +243,9 @@ impl TripPhaseType {
             TripPhaseType::Cancelled => "Trip was cancelled due to some bug".to_string(),
             TripPhaseType::Finished => "Trip finished".to_string(),
             TripPhaseType::DelayedStart => "Delayed by a previous trip taking too long".to_string(),
+            TripPhaseType::WaitingForRideHail => "Waiting for a ride-hail pickup".to_string(),
+            TripPhaseType::RidingRideHail(car) => format!("Riding in {}", car),
+            TripPhaseType::SearchingForParking => "Searching for parking".to_string(),
         }
     }
 }
@@ -0,0 +1,328 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Duration, Time};
+use map_model::{
+    BuildingID, BuildingType, IntersectionID, LaneID, Map, PathConstraints, PathRequest, Position,
+    MAX_TRUCK_SPEED,
+};
+
+use crate::sim::Ctx;
+use crate::{
+    CarID, Command, CreateCar, DrivingSimState, Event, ParkingSim, ParkingSpot, Router, Scheduler,
+    SimOptions, TripManager, Vehicle, VehicleSpec, VehicleType, FOLLOWING_DISTANCE, FREIGHT_LENGTH,
+    SPAWN_DIST,
+};
+
+/// Tours start entering the map between these times.
+const FIRST_DEPARTURE: Duration = Duration::const_seconds(6.0 * 3600.0);
+const LAST_DEPARTURE: Duration = Duration::const_seconds(16.0 * 3600.0);
+/// Goods can be carried this far between a free curbside spot and the stop.
+const MAX_CURBSIDE_DIST: Distance = Distance::const_meters(30.0);
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Tour {
+    vehicle: Vehicle,
+    /// Buildings still to visit, in order
+    stops: VecDeque<BuildingID>,
+    /// Where the vehicle leaves the map after the last stop
+    exit: (IntersectionID, LaneID),
+    state: TourState,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+enum TourState {
+    /// Waiting to enter the map here
+    NotStarted(Position),
+    DrivingToStop(BuildingID),
+    /// Loading or unloading with the front of the vehicle at this position
+    AtStop {
+        pos: Position,
+        spot: Option<ParkingSpot>,
+        double_parked: bool,
+    },
+    Leaving,
+}
+
+/// Manages delivery tours. Each delivery vehicle enters the map at a border, visits a series of
+/// commercial buildings, and leaves again. At every stop, the vehicle pulls into a free parking
+/// spot or loading bay near the building if there is one. Otherwise it double-parks, leaving a
+/// static blockage in the driving lane for as long as it takes to load or unload.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct FreightSimState {
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    tours: BTreeMap<CarID, Tour>,
+
+    num_tours: usize,
+    stops_per_tour: usize,
+    dwell_time: Duration,
+
+    events: Vec<Event>,
+}
+
+impl FreightSimState {
+    pub fn new(opts: &SimOptions) -> FreightSimState {
+        FreightSimState {
+            tours: BTreeMap::new(),
+            num_tours: opts.freight_tours,
+            stops_per_tour: opts.freight_stops_per_tour,
+            dwell_time: opts.freight_dwell,
+            events: Vec::new(),
+        }
+    }
+
+    /// Generate tours between commercial buildings and amenities, scheduling each one to start
+    /// sometime during the working day.
+    pub fn seed_tours(
+        &mut self,
+        map: &Map,
+        rng: &mut XorShiftRng,
+        trips: &mut TripManager,
+        scheduler: &mut Scheduler,
+    ) {
+        if self.num_tours == 0 || self.stops_per_tour == 0 {
+            return;
+        }
+        let candidates: Vec<BuildingID> = map
+            .all_buildings()
+            .iter()
+            .filter(|b| {
+                (matches!(
+                    b.bldg_type,
+                    BuildingType::Commercial(_) | BuildingType::ResidentialCommercial(_, _)
+                ) || !b.amenities.is_empty())
+                    && stop_position(b.id, map).is_some()
+            })
+            .map(|b| b.id)
+            .collect();
+        let mut entrances = Vec::new();
+        for i in map.all_outgoing_borders() {
+            for l in i.get_outgoing_lanes(map, PathConstraints::Truck) {
+                entrances.push(l);
+            }
+        }
+        let mut exits = Vec::new();
+        for i in map.all_incoming_borders() {
+            for l in i.get_incoming_lanes(map, PathConstraints::Truck) {
+                exits.push((i.id, l));
+            }
+        }
+        if candidates.is_empty() || entrances.is_empty() || exits.is_empty() {
+            warn!("No commercial buildings or borders usable by trucks; skipping delivery tours");
+            return;
+        }
+
+        for _ in 0..self.num_tours {
+            let start = Position::new(*entrances.choose(rng).unwrap(), SPAWN_DIST);
+            let exit = *exits.choose(rng).unwrap();
+
+            // Visit the stops in a sensible order, always heading to the closest remaining one
+            let mut remaining: Vec<BuildingID> = candidates
+                .choose_multiple(rng, self.stops_per_tour)
+                .cloned()
+                .collect();
+            let mut stops = VecDeque::new();
+            let mut pt = start.pt(map);
+            while !remaining.is_empty() {
+                let idx = (0..remaining.len())
+                    .min_by_key(|idx| map.get_b(remaining[*idx]).polygon.center().dist_to(pt))
+                    .unwrap();
+                let b = remaining.remove(idx);
+                pt = map.get_b(b).polygon.center();
+                stops.push_back(b);
+            }
+
+            let vehicle = VehicleSpec {
+                vehicle_type: VehicleType::Freight,
+                length: FREIGHT_LENGTH,
+                max_speed: Some(MAX_TRUCK_SPEED),
+            }
+            .make(
+                CarID {
+                    id: trips.new_car_id(),
+                    vehicle_type: VehicleType::Freight,
+                },
+                None,
+            );
+            let depart = Time::START_OF_DAY
+                + Duration::seconds(
+                    rng.gen_range(FIRST_DEPARTURE.inner_seconds()..LAST_DEPARTURE.inner_seconds()),
+                );
+            scheduler.push(depart, Command::UpdateFreight(vehicle.id));
+            self.tours.insert(
+                vehicle.id,
+                Tour {
+                    vehicle,
+                    stops,
+                    exit,
+                    state: TourState::NotStarted(start),
+                },
+            );
+        }
+    }
+
+    /// A delivery vehicle is ready to enter the map or leave a stop. Send it to the next stop it
+    /// can reach, or off the map if it's done.
+    pub fn update(&mut self, now: Time, id: CarID, driving: &mut DrivingSimState, ctx: &mut Ctx) {
+        let tour = self.tours.get_mut(&id).unwrap();
+        let from = match std::mem::replace(&mut tour.state, TourState::Leaving) {
+            TourState::NotStarted(start) => start,
+            TourState::AtStop {
+                pos,
+                spot,
+                double_parked,
+            } => {
+                if spot.is_some() {
+                    ctx.parking.unreserve_spot(id);
+                }
+                if double_parked {
+                    driving.clear_double_parking(now, id, pos.lane(), ctx);
+                }
+                pos
+            }
+            TourState::DrivingToStop(_) | TourState::Leaving => unreachable!(),
+        };
+
+        let mut router = None;
+        while let Some(b) = tour.stops.pop_front() {
+            let end = match stop_position(b, ctx.map) {
+                Some(end) => end,
+                None => continue,
+            };
+            // Neighboring buildings served from the same spot were handled in one go
+            if end == from {
+                continue;
+            }
+            if let (Ok(path), _) = ctx.caps.pathfind(
+                PathRequest::vehicle(from, end, PathConstraints::Truck),
+                None,
+                now,
+                ctx.map,
+            ) {
+                tour.state = TourState::DrivingToStop(b);
+                router = Some(Router::deliver(id, path));
+                break;
+            }
+        }
+        if router.is_none() {
+            let (i, l) = tour.exit;
+            if let (Ok(path), _) = ctx.caps.pathfind(
+                PathRequest::vehicle(from, Position::end(l, ctx.map), PathConstraints::Truck),
+                None,
+                now,
+                ctx.map,
+            ) {
+                router = Some(Router::end_at_border(
+                    id,
+                    path,
+                    ctx.map.get_l(l).length(),
+                    i,
+                ));
+            }
+        }
+
+        if let Some(router) = router {
+            ctx.scheduler.push(
+                now,
+                Command::SpawnCar(
+                    CreateCar {
+                        vehicle: tour.vehicle.clone(),
+                        router,
+                        maybe_parked_car: None,
+                        trip_and_person: None,
+                        maybe_route: None,
+                    },
+                    true,
+                ),
+            );
+        } else {
+            // Stuck somewhere after map edits; just give up
+            self.tours.remove(&id);
+        }
+    }
+
+    /// A delivery vehicle reached a stop, with its front at `pos`. Either way, it leaves the
+    /// driving simulation until it's done loading or unloading. Returns true if it has to
+    /// double-park in the driving lane, or false if it pulled over at the curb.
+    pub fn vehicle_arrived(&mut self, now: Time, id: CarID, pos: Position, ctx: &mut Ctx) -> bool {
+        let tour = self.tours.get_mut(&id).unwrap();
+        let b = match tour.state {
+            TourState::DrivingToStop(b) => b,
+            _ => unreachable!(),
+        };
+
+        // Loading bays aren't mapped separately; any free spot nearby will do.
+        let spot = ctx
+            .parking
+            .get_all_free_spots(
+                Position::new(
+                    pos.lane(),
+                    (pos.dist_along() - MAX_CURBSIDE_DIST).max(Distance::ZERO),
+                ),
+                &tour.vehicle,
+                b,
+                ctx.map,
+            )
+            .into_iter()
+            .filter(|(_, spot_pos)| {
+                (spot_pos.dist_along() - pos.dist_along()).abs() <= MAX_CURBSIDE_DIST
+            })
+            .min_by_key(|(spot, spot_pos)| {
+                ((spot_pos.dist_along() - pos.dist_along()).abs(), *spot)
+            })
+            .map(|(spot, _)| spot);
+        if let Some(spot) = spot {
+            ctx.parking.reserve_spot(spot, id);
+        }
+        // Stops are chosen so the vehicle fits along the lane, but lane-changing near the end
+        // might leave it without room for a blockage. Just let it squeeze onto the curb.
+        let double_parked =
+            spot.is_none() && pos.dist_along() >= tour.vehicle.length + FOLLOWING_DISTANCE;
+
+        tour.state = TourState::AtStop {
+            pos,
+            spot,
+            double_parked,
+        };
+        self.events.push(Event::DeliveryStop(
+            id,
+            b,
+            pos.lane(),
+            self.dwell_time,
+            double_parked,
+        ));
+        ctx.scheduler
+            .push(now + self.dwell_time, Command::UpdateFreight(id));
+        double_parked
+    }
+
+    /// A delivery vehicle finished its tour and left the map.
+    pub fn tour_finished(&mut self, id: CarID) {
+        self.tours.remove(&id).unwrap();
+    }
+
+    pub fn collect_events(&mut self) -> Vec<Event> {
+        self.events.drain(..).collect()
+    }
+}
+
+/// Where a delivery vehicle stops to serve a building. The whole vehicle has to fit along the lane,
+/// in case it needs to double-park.
+fn stop_position(b: BuildingID, map: &Map) -> Option<Position> {
+    let pos = map.get_b(b).driving_connection(map)?.0;
+    if PathConstraints::Truck.can_use(map.get_l(pos.lane()), map)
+        && pos.dist_along() >= FREIGHT_LENGTH + FOLLOWING_DISTANCE
+    {
+        Some(pos)
+    } else {
+        None
+    }
+}
@@ -35,34 +35,59 @@ pub use crate::render::{
 };
 
 pub use self::analytics::{Analytics, Problem, ProblemType, SlidingWindow, TripPhase};
-pub(crate) use self::events::Event;
-pub use self::events::{AlertLocation, TripPhaseType};
+pub(crate) use self::assignment::AssignedRouting;
+pub use self::assignment::{AssignmentOptions, TrafficAssignment};
+pub(crate) use self::cap::CapSimState;
+pub use self::driver_behavior::{DriverBehavior, DriverBehaviorModel, ParamDistribution};
+pub(crate) use self::emergency::EmergencySimState;
+pub use self::emissions::{EmissionRates, Emissions, EmissionsModel};
+pub use self::events::{AlertLocation, Event, TripPhaseType};
+pub(crate) use self::freight::FreightSimState;
 pub use self::make::{fork_rng, BorderSpawnOverTime, ScenarioGenerator, SimFlags, SpawnOverTime};
 pub(crate) use self::make::{StartTripArgs, TripSpec};
+pub use self::mechanics::{DetectorReading, SignalDetectors};
 pub(crate) use self::mechanics::{
     DrivingSimState, IntersectionSimState, ParkingSim, ParkingSimState, WalkingSimState,
 };
+pub(crate) use self::micromobility::MicromobilityState;
+pub use self::noise::{
+    BuildingNoise, NoiseEstimate, NoiseExposure, LDEN_THRESHOLD, LNIGHT_THRESHOLD,
+};
 pub(crate) use self::pandemic::PandemicModel;
+pub use self::parking_pricing::{DemandResponsivePricing, ParkingPricing};
 pub use self::prebake::PrebakeSummary;
 pub(crate) use self::recorder::TrafficRecorder;
+pub use self::ridehail::RideHailDispatch;
+pub(crate) use self::ridehail::RideHailSimState;
 pub(crate) use self::router::{ActionAtEnd, Router};
 pub(crate) use self::scheduler::{Command, Scheduler};
 pub use self::sim::{
     count_parked_cars_per_bldg, rand_dist, AgentProperties, AlertHandler, DelayCause, Sim,
     SimCallback, SimOptions,
 };
+pub use self::transit::TransitCapacity;
 pub(crate) use self::transit::TransitSimState;
 pub use self::trips::{CommutersVehiclesCounts, Person, PersonState, TripInfo, TripResult};
 pub(crate) use self::trips::{TripLeg, TripManager};
 
 mod analytics;
+pub mod assignment;
+mod cap;
+mod driver_behavior;
+mod emergency;
+mod emissions;
 mod events;
+mod freight;
 mod make;
 mod mechanics;
+mod micromobility;
+mod noise;
 mod pandemic;
+mod parking_pricing;
 pub mod prebake;
 mod recorder;
 mod render;
+mod ridehail;
 mod router;
 mod scheduler;
 mod sim;
@@ -73,6 +98,10 @@ mod trips;
 pub(crate) const BIKE_LENGTH: Distance = Distance::const_meters(1.8);
 pub(crate) const MIN_CAR_LENGTH: Distance = Distance::const_meters(4.5);
 pub(crate) const MAX_CAR_LENGTH: Distance = Distance::const_meters(6.5);
+// Somewhere between a panel van and a rigid box truck
+pub(crate) const FREIGHT_LENGTH: Distance = Distance::const_meters(8.0);
+/// Ambulances are a bit longer than most cars
+pub(crate) const EMERGENCY_VEHICLE_LENGTH: Distance = Distance::const_meters(7.0);
 // Note this is more than MAX_CAR_LENGTH
 pub(crate) const BUS_LENGTH: Distance = Distance::const_meters(12.5);
 pub(crate) const LIGHT_RAIL_LENGTH: Distance = Distance::const_meters(60.0);
@@ -105,6 +134,9 @@ impl fmt::Display for CarID {
             VehicleType::Bus => write!(f, "Bus #{}", self.id),
             VehicleType::Train => write!(f, "Train #{}", self.id),
             VehicleType::Bike => write!(f, "Bike #{}", self.id),
+            VehicleType::RideHail => write!(f, "Ride-hail #{}", self.id),
+            VehicleType::Freight => write!(f, "Delivery #{}", self.id),
+            VehicleType::Emergency => write!(f, "Emergency #{}", self.id),
         }
     }
 }
@@ -129,6 +161,7 @@ pub enum AgentID {
     Car(CarID),
     Pedestrian(PedestrianID),
     // TODO Rename...
+    /// Somebody riding a bus, train, or ride-hail vehicle
     BusPassenger(PersonID, CarID),
 }
 
@@ -143,7 +176,10 @@ impl AgentID {
     pub fn to_type(self) -> AgentType {
         match self {
             AgentID::Car(c) => match c.vehicle_type {
-                VehicleType::Car => AgentType::Car,
+                VehicleType::Car
+                | VehicleType::RideHail
+                | VehicleType::Freight
+                | VehicleType::Emergency => AgentType::Car,
                 VehicleType::Bike => AgentType::Bike,
                 VehicleType::Bus => AgentType::Bus,
                 VehicleType::Train => AgentType::Train,
@@ -278,6 +314,12 @@ pub enum VehicleType {
     Bus,
     Train,
     Bike,
+    /// Part of a fleet dispatched to carry passengers between curbs
+    RideHail,
+    /// A delivery van or lorry making a tour of stops
+    Freight,
+    /// An ambulance or fire engine, which preempts traffic signals configured for it
+    Emergency,
 }
 
 impl fmt::Display for VehicleType {
@@ -287,6 +329,9 @@ impl fmt::Display for VehicleType {
             VehicleType::Bus => write!(f, "bus"),
             VehicleType::Train => write!(f, "train"),
             VehicleType::Bike => write!(f, "bike"),
+            VehicleType::RideHail => write!(f, "ride-hail vehicle"),
+            VehicleType::Freight => write!(f, "delivery vehicle"),
+            VehicleType::Emergency => write!(f, "emergency vehicle"),
         }
     }
 }
@@ -294,10 +339,13 @@ impl fmt::Display for VehicleType {
 impl VehicleType {
     pub fn to_constraints(self) -> PathConstraints {
         match self {
-            VehicleType::Car => PathConstraints::Car,
+            VehicleType::Car | VehicleType::RideHail | VehicleType::Emergency => {
+                PathConstraints::Car
+            }
             VehicleType::Bus => PathConstraints::Bus,
             VehicleType::Train => PathConstraints::Train,
             VehicleType::Bike => PathConstraints::Bike,
+            VehicleType::Freight => PathConstraints::Truck,
         }
     }
 
@@ -307,6 +355,9 @@ impl VehicleType {
             VehicleType::Bus => true,
             VehicleType::Train => true,
             VehicleType::Bike => false,
+            VehicleType::RideHail => false,
+            VehicleType::Freight => false,
+            VehicleType::Emergency => false,
         }
     }
 }
@@ -318,6 +369,7 @@ pub struct Vehicle {
     pub vehicle_type: VehicleType,
     pub length: Distance,
     pub max_speed: Option<Speed>,
+    pub behavior: DriverBehavior,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -336,6 +388,7 @@ impl VehicleSpec {
             vehicle_type: self.vehicle_type,
             length: self.length,
             max_speed: self.max_speed,
+            behavior: DriverBehavior::DEFAULT,
         }
     }
 }
@@ -366,7 +419,7 @@ impl DrivingGoal {
     pub fn goal_pos(&self, constraints: PathConstraints, map: &Map) -> Option<Position> {
         match self {
             DrivingGoal::ParkNear(b) => match constraints {
-                PathConstraints::Car => {
+                PathConstraints::Car | PathConstraints::Truck => {
                     let driving_lane = map.find_driving_lane_near_building(*b);
                     let sidewalk_pos = map.get_b(*b).sidewalk_pos;
                     if driving_lane.road == sidewalk_pos.lane().road {
@@ -384,13 +437,20 @@ impl DrivingGoal {
         }
     }
 
-    pub fn make_router(&self, owner: CarID, path: Path, map: &Map) -> Router {
+    /// `leave_at` is when a driver parking near a building plans to leave again.
+    pub fn make_router(
+        &self,
+        owner: CarID,
+        path: Path,
+        leave_at: Option<Time>,
+        map: &Map,
+    ) -> Router {
         match self {
             DrivingGoal::ParkNear(b) => {
                 if owner.vehicle_type == VehicleType::Bike {
                     Router::bike_then_stop(owner, path, SidewalkSpot::bike_rack(*b, map).unwrap())
                 } else {
-                    Router::park_near(owner, path, *b)
+                    Router::park_near(owner, path, *b, leave_at)
                 }
             }
             DrivingGoal::Border(i, last_lane) => {
@@ -418,6 +478,8 @@ pub(crate) enum SidewalkPOI {
     Border(IntersectionID),
     /// The bikeable position
     BikeRack(Position),
+    /// Where a ride-hail vehicle stops in the driving lane
+    Curb(Position),
     SuddenlyAppear,
 }
 
@@ -465,6 +527,16 @@ impl SidewalkSpot {
         }
     }
 
+    /// Where somebody meets or leaves a ride-hail vehicle in front of a building.
+    pub fn curb(b: BuildingID, map: &Map) -> Option<SidewalkSpot> {
+        let bldg = map.get_b(b);
+        let (driving_pos, _) = bldg.driving_connection(map)?;
+        Some(SidewalkSpot {
+            sidewalk_pos: driving_pos.equiv_pos(bldg.sidewalk(), map),
+            connection: SidewalkPOI::Curb(driving_pos),
+        })
+    }
+
     // Recall sidewalks are bidirectional.
     pub fn start_at_border(i: IntersectionID, map: &Map) -> Option<SidewalkSpot> {
         Some(SidewalkSpot {
@@ -198,6 +198,135 @@ impl ScenarioGenerator {
         );
         s
     }
+
+    /// Map edits may change how many people live and work in some buildings. Make the scenario
+    /// match: when a building loses residents or workers, cancel the trips of some people living or
+    /// working there. When it gains some, add new people commuting between home and work, like
+    /// `proletariat_robot` does. Everybody else is left alone.
+    pub fn match_building_edits(
+        map: &Map,
+        mut s: Scenario,
+        rng: &mut XorShiftRng,
+        timer: &mut Timer,
+    ) -> Scenario {
+        let edited = &map.get_edits().original_buildings;
+        if edited.is_empty() {
+            return s;
+        }
+        timer.start("match scenario to building edits");
+
+        // Where new people might live or work
+        let mut residences = Vec::new();
+        let mut workplaces = Vec::new();
+        for b in map.all_buildings() {
+            let (residents, workers) = capacity(&b.bldg_type);
+            for _ in 0..residents {
+                residences.push(TripEndpoint::Building(b.id));
+            }
+            for _ in 0..workers {
+                workplaces.push(TripEndpoint::Building(b.id));
+            }
+        }
+        // New people commuting to or from off-map leave and return through the same border, so it
+        // has to work both ways
+        let two_way_borders: Vec<TripEndpoint> = map
+            .all_intersections()
+            .iter()
+            .filter(|i| i.is_incoming_border() && i.is_outgoing_border())
+            .map(|i| TripEndpoint::Border(i.id))
+            .collect();
+
+        let mut num_cancelled = 0;
+        let mut new_people = Vec::new();
+        for (b, orig) in edited {
+            let home = TripEndpoint::Building(*b);
+            let (old_residents, old_workers) = capacity(&orig.bldg_type);
+            let (new_residents, new_workers) = capacity(&map.get_b(*b).bldg_type);
+
+            // Somebody lives in a building if their day starts there
+            num_cancelled +=
+                cancel_people(&mut s, old_residents.saturating_sub(new_residents), |p| {
+                    p.trips.first().map_or(false, |t| t.origin == home)
+                });
+            num_cancelled += cancel_people(&mut s, old_workers.saturating_sub(new_workers), |p| {
+                p.trips
+                    .iter()
+                    .any(|t| t.destination == home && matches!(t.purpose, TripPurpose::Work))
+            });
+
+            for _ in old_residents..new_residents {
+                if let Some(work) = workplaces
+                    .choose(rng)
+                    .or_else(|| two_way_borders.choose(rng))
+                {
+                    new_people.push((home, *work));
+                }
+            }
+            for _ in old_workers..new_workers {
+                if let Some(from) = residences
+                    .choose(rng)
+                    .or_else(|| two_way_borders.choose(rng))
+                {
+                    new_people.push((*from, home));
+                }
+            }
+        }
+
+        let num_tried = new_people.len();
+        let mut num_added = 0;
+        for (home, work) in new_people {
+            match create_prole(home, work, map, rng) {
+                Ok(mut person) => {
+                    for trip in &mut person.trips {
+                        trip.modified = true;
+                    }
+                    s.people.push(person);
+                    num_added += 1;
+                }
+                Err(err) => {
+                    trace!("Unable to create person. error: {}", err);
+                }
+            }
+        }
+
+        info!(
+            "Building edits cancelled {} people's trips and added {} of {} new people",
+            prettyprint_usize(num_cancelled),
+            prettyprint_usize(num_added),
+            prettyprint_usize(num_tried)
+        );
+        timer.stop("match scenario to building edits");
+        s
+    }
+}
+
+/// (residents, workers)
+fn capacity(bldg_type: &BuildingType) -> (usize, usize) {
+    match bldg_type {
+        BuildingType::Residential { num_residents, .. } => (*num_residents, 0),
+        BuildingType::ResidentialCommercial(residents, workers) => (*residents, *workers),
+        BuildingType::Commercial(workers) => (0, *workers),
+        BuildingType::Empty => (0, 0),
+    }
+}
+
+/// Cancel every trip of up to `limit` people matching the predicate. Returns the number of people.
+fn cancel_people<F: Fn(&PersonSpec) -> bool>(s: &mut Scenario, limit: usize, pred: F) -> usize {
+    let mut count = 0;
+    for person in &mut s.people {
+        if count == limit {
+            break;
+        }
+        if person.trips.iter().all(|t| t.cancelled) || !pred(person) {
+            continue;
+        }
+        for trip in &mut person.trips {
+            trip.cancelled = true;
+            trip.modified = true;
+        }
+        count += 1;
+    }
+    count
 }
 
 fn create_prole(
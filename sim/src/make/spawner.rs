@@ -55,6 +55,20 @@ pub(crate) enum TripSpec {
         stop1: TransitStopID,
         maybe_stop2: Option<TransitStopID>,
     },
+    UsingRideHail {
+        start: SidewalkSpot,
+        goal: SidewalkSpot,
+        pickup: SidewalkSpot,
+        dropoff: SidewalkSpot,
+    },
+    UsingSharedVehicle {
+        start: BuildingID,
+        goal: BuildingID,
+        /// Rented from a fleet, not owned by the person
+        vehicle: CarID,
+        pickup: BuildingID,
+        dropoff: BuildingID,
+    },
 }
 
 impl TripSpec {
@@ -201,6 +215,43 @@ impl TripSpec {
                     legs = vec![TripLeg::Walk(walk_to), TripLeg::RideBus(*route, None)];
                 }
             }
+            TripSpec::UsingRideHail {
+                start,
+                goal,
+                pickup,
+                dropoff,
+            } => {
+                if pickup.sidewalk_pos.lane() == dropoff.sidewalk_pos.lane() {
+                    info!(
+                        "Ride-hail trip from {:?} to {:?} will just walk; it's the same sidewalk!",
+                        start.connection, goal.connection
+                    );
+                    return TripSpec::JustWalking {
+                        start: start.clone(),
+                        goal: goal.clone(),
+                    }
+                    .into_plan(map);
+                }
+                legs = vec![
+                    TripLeg::Walk(pickup.clone()),
+                    TripLeg::RideHail(dropoff.clone()),
+                    TripLeg::Walk(goal.clone()),
+                ];
+            }
+            TripSpec::UsingSharedVehicle {
+                goal,
+                vehicle,
+                pickup,
+                dropoff,
+                ..
+            } => {
+                // The rental already checked both ends have a biking connection
+                legs = vec![
+                    TripLeg::Walk(SidewalkSpot::bike_rack(*pickup, map).unwrap()),
+                    TripLeg::Drive(*vehicle, DrivingGoal::ParkNear(*dropoff)),
+                    TripLeg::Walk(SidewalkSpot::building(*goal, map)),
+                ];
+            }
         };
 
         (self, legs)
@@ -288,6 +339,33 @@ impl TripSpec {
                     TripSpec::JustWalking { start, goal }
                 }
             }
+            TripMode::RideHail => {
+                let start = start_sidewalk_spot(from, map)?;
+                let goal = end_sidewalk_spot(to, map)?;
+                // Vehicles only pick up and drop off in front of buildings
+                let curbs = match (from, to) {
+                    (TripEndpoint::Building(b1), TripEndpoint::Building(b2)) => {
+                        SidewalkSpot::curb(b1, map).zip(SidewalkSpot::curb(b2, map))
+                    }
+                    _ => None,
+                };
+                if let Some((pickup, dropoff)) = curbs {
+                    TripSpec::UsingRideHail {
+                        start,
+                        goal,
+                        pickup,
+                        dropoff,
+                    }
+                } else {
+                    TripSpec::JustWalking { start, goal }
+                }
+            }
+            // A shared vehicle is rented right before this, if one is available. This is the
+            // fallback when there isn't.
+            TripMode::Micromobility => TripSpec::JustWalking {
+                start: start_sidewalk_spot(from, map)?,
+                goal: end_sidewalk_spot(to, map)?,
+            },
         })
     }
 }
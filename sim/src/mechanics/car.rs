@@ -2,7 +2,7 @@ use std::collections::{BTreeSet, VecDeque};
 
 use serde::{Deserialize, Serialize};
 
-use geom::{Distance, Duration, PolyLine, Time, EPSILON_DIST};
+use geom::{Distance, Duration, PolyLine, Speed, Time, EPSILON_DIST};
 use map_model::{Direction, LaneID, Map, Traversable};
 
 use crate::{
@@ -29,6 +29,13 @@ pub(crate) struct Car {
     /// Since lane over-taking isn't implemented yet, a vehicle tends to be stuck behind a slow
     /// leader for a while. Avoid duplicate events.
     pub wants_to_overtake: BTreeSet<CarID>,
+
+    /// The average speed over the most recent movement, used to estimate acceleration for the
+    /// emissions model. Zero after idling.
+    pub last_speed: Speed,
+    /// When this vehicle last re-evaluated its route for congestion. Only used with dynamic
+    /// routing.
+    pub last_route_check: Time,
 }
 
 impl Car {
@@ -56,7 +63,7 @@ impl Car {
         start_time: Time,
         map: &Map,
     ) -> CarState {
-        let (speed, percent_incline) = self
+        let (mut speed, percent_incline) = self
             .router
             .get_path()
             .current_step()
@@ -65,6 +72,10 @@ impl Car {
                 self.vehicle.vehicle_type.to_constraints(),
                 map,
             );
+        speed = speed * self.vehicle.behavior.desired_speed_factor;
+        if let Some(max) = self.vehicle.max_speed {
+            speed = speed.min(max);
+        }
         let dt = (dist_int.end - dist_int.start) / speed;
         CarState::Crossing {
             time_int: TimeInterval::new(start_time, start_time + dt),
@@ -73,6 +84,17 @@ impl Car {
         }
     }
 
+    /// A driver who was stopped takes a moment to react and get going again. Spread that over the
+    /// Crossing state they just started.
+    pub fn add_startup_lost_time(&mut self) {
+        if let CarState::Crossing {
+            ref mut time_int, ..
+        } = self.state
+        {
+            time_int.end += self.vehicle.behavior.startup_lost_time;
+        }
+    }
+
     pub fn get_draw_car(
         &self,
         front: Distance,
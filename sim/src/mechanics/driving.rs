@@ -3,20 +3,23 @@ use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_hashmap, serialize_hashmap, FixedMap, IndexableKey};
-use geom::{Distance, Duration, PolyLine, Time};
-use map_model::{DrivingSide, IntersectionID, LaneID, Map, Path, PathStep, Position, Traversable};
+use geom::{Distance, Duration, PolyLine, Speed, Time};
+use map_model::{
+    DrivingSide, IntersectionCluster, IntersectionID, LaneID, Map, Path, PathStep, Position,
+    Traversable,
+};
 
 use crate::mechanics::car::{Car, CarState};
 use crate::mechanics::queue::{Queue, QueueEntry, Queued};
 use crate::sim::Ctx;
 use crate::{
     ActionAtEnd, AgentID, AgentProperties, CarID, CarStatus, Command, CreateCar, DelayCause,
-    DistanceInterval, DrawCarInput, Event, IntersectionSimState, ParkedCar, ParkingSim,
-    ParkingSpot, PersonID, Problem, SimOptions, TimeInterval, TransitSimState, TripID, TripManager,
-    UnzoomedAgent, Vehicle, VehicleType, WalkingSimState, FOLLOWING_DISTANCE, MAX_CAR_LENGTH,
+    DistanceInterval, DrawCarInput, DriverBehaviorModel, EmissionsModel, Event, FreightSimState,
+    IntersectionSimState, ParkedCar, ParkingSim, ParkingSpot, PersonID, Problem, RideHailSimState,
+    SimOptions, TimeInterval, TransitSimState, TripID, TripManager, UnzoomedAgent, Vehicle,
+    VehicleType, WalkingSimState, FOLLOWING_DISTANCE, MAX_CAR_LENGTH,
 };
 
-const TIME_TO_WAIT_AT_BUS_STOP: Duration = Duration::const_seconds(10.0);
 const TIME_TO_CHANGE_LANES: Duration = Duration::const_seconds(1.0);
 
 // TODO Do something else.
@@ -45,6 +48,9 @@ pub(crate) struct DrivingSimState {
 
     recalc_lanechanging: bool,
     handle_uber_turns: bool,
+    emissions: EmissionsModel,
+    dynamic_routing: Option<DynamicRouting>,
+    driver_behavior: Option<DriverBehaviorModel>,
 
     time_to_unpark_onstreet: Duration,
     time_to_park_onstreet: Duration,
@@ -61,6 +67,16 @@ impl DrivingSimState {
             events: Vec::new(),
             recalc_lanechanging: !opts.dont_recalc_lanechanging,
             handle_uber_turns: !opts.dont_handle_uber_turns,
+            emissions: opts.emissions_model.clone(),
+            dynamic_routing: opts.informed_drivers.map(|fraction| DynamicRouting {
+                informed_fraction: fraction,
+                interval: opts.reroute_interval,
+                avoid: IntersectionCluster::find_all(map)
+                    .into_iter()
+                    .flat_map(|ic| ic.members)
+                    .collect(),
+            }),
+            driver_behavior: opts.driver_behavior.clone(),
             waiting_to_spawn: BTreeMap::new(),
 
             time_to_unpark_onstreet: Duration::seconds(10.0),
@@ -191,7 +207,15 @@ impl DrivingSimState {
                 total_blocked_time: Duration::ZERO,
                 trip_and_person: params.trip_and_person,
                 wants_to_overtake: BTreeSet::new(),
+                last_speed: Speed::ZERO,
+                last_route_check: now,
             };
+            // Sampling only depends on the ID, so a vehicle keeps the same driver between trips
+            if let Some(ref model) = self.driver_behavior {
+                if !car.vehicle.vehicle_type.is_transit() {
+                    car.vehicle.behavior = model.sample(car.vehicle.id);
+                }
+            }
             let mut start_crossing = false;
             if let Some(p) = params.maybe_parked_car {
                 let delay = match p.spot {
@@ -233,6 +257,7 @@ impl DrivingSimState {
                 // Have to do this early
                 if car.router.last_step() {
                     match car.router.maybe_handle_end(
+                        now,
                         start_dist,
                         &car.vehicle,
                         ctx.parking,
@@ -324,6 +349,8 @@ impl DrivingSimState {
         trips: &mut TripManager,
         transit: &mut TransitSimState,
         walking: &mut WalkingSimState,
+        ride_hail: &mut RideHailSimState,
+        freight: &mut FreightSimState,
     ) {
         let mut need_distances = {
             let car = &self.cars[&id];
@@ -360,9 +387,9 @@ impl DrivingSimState {
             // checker, temporarily move one of them out of the map.
             let mut car = self.cars.remove(&id).unwrap();
             // Responsibility of update_car_with_distances to manage scheduling stuff!
-            if self
-                .update_car_with_distances(&mut car, &dists, idx, now, ctx, trips, transit, walking)
-            {
+            if self.update_car_with_distances(
+                &mut car, &dists, idx, now, ctx, trips, transit, walking, ride_hail, freight,
+            ) {
                 self.cars.insert(id, car);
             } else {
                 self.delete_car_internal(&mut car, dists, idx, now, ctx);
@@ -381,7 +408,16 @@ impl DrivingSimState {
         transit: &mut TransitSimState,
     ) -> bool {
         match car.state {
-            CarState::Crossing { .. } => {
+            CarState::Crossing {
+                time_int, dist_int, ..
+            } => {
+                record_emissions(
+                    &self.emissions,
+                    &mut self.events,
+                    car,
+                    dist_int.end - dist_int.start,
+                    time_int.end - time_int.start,
+                );
                 car.state = CarState::Queued {
                     blocked_since: now,
                     want_to_change_lanes: None,
@@ -394,6 +430,15 @@ impl DrivingSimState {
                 if queue.is_car_at_front(car.vehicle.id) {
                     // Want to re-run, but no urgency about it happening immediately.
                     car.state = CarState::WaitingToAdvance { blocked_since: now };
+                    if let Some(ref dynamic_routing) = self.dynamic_routing {
+                        dynamic_routing.maybe_reroute(
+                            car,
+                            &self.queues,
+                            &mut self.events,
+                            now,
+                            ctx.map,
+                        );
+                    }
                     if self.recalc_lanechanging {
                         car.router.opportunistically_lanechange(
                             &self.queues,
@@ -470,6 +515,7 @@ impl DrivingSimState {
                     // the next loop will pick that up. Just trigger the side effect of choosing an
                     // end_dist.
                     car.router.maybe_handle_end(
+                        now,
                         front,
                         &car.vehicle,
                         ctx.parking,
@@ -524,7 +570,16 @@ impl DrivingSimState {
                 // We do NOT need to update the follower. If they were Queued, they'll remain that
                 // way, until laggy_head is None.
 
+                record_emissions(
+                    &self.emissions,
+                    &mut self.events,
+                    car,
+                    Distance::ZERO,
+                    now - blocked_since,
+                );
+
                 let last_step = car.router.advance(
+                    now,
                     &car.vehicle,
                     ctx.parking,
                     ctx.map,
@@ -533,6 +588,9 @@ impl DrivingSimState {
                 );
                 car.total_blocked_time += now - blocked_since;
                 car.state = car.crossing_state(Distance::ZERO, now, ctx.map);
+                if now > blocked_since {
+                    car.add_startup_lost_time();
+                }
                 ctx.scheduler
                     .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                 self.events.push(Event::AgentEntersTraversable(
@@ -619,6 +677,8 @@ impl DrivingSimState {
         trips: &mut TripManager,
         transit: &mut TransitSimState,
         walking: &mut WalkingSimState,
+        ride_hail: &mut RideHailSimState,
+        freight: &mut FreightSimState,
     ) -> bool {
         let our_dist = dists[idx].front;
 
@@ -638,14 +698,26 @@ impl DrivingSimState {
                     return true;
                 }
 
-                match car.router.maybe_handle_end(
+                let action = car.router.maybe_handle_end(
+                    now,
                     our_dist,
                     &car.vehicle,
                     ctx.parking,
                     ctx.map,
                     car.trip_and_person,
                     &mut self.events,
-                ) {
+                );
+                if action.is_some() {
+                    // No longer blocked; account for idling while waiting to reach the end
+                    record_emissions(
+                        &self.emissions,
+                        &mut self.events,
+                        car,
+                        Distance::ZERO,
+                        now - blocked_since,
+                    );
+                }
+                match action {
                     Some(ActionAtEnd::VanishAtBorder(i)) => {
                         car.total_blocked_time += now - blocked_since;
                         // Don't do this for buses
@@ -658,6 +730,8 @@ impl DrivingSimState {
                                 car.router.get_path().total_length(),
                                 ctx,
                             );
+                        } else if car.vehicle.vehicle_type == VehicleType::Freight {
+                            freight.tour_finished(car.vehicle.id);
                         }
                         false
                     }
@@ -676,6 +750,13 @@ impl DrivingSimState {
                     }
                     Some(ActionAtEnd::StartParking(spot)) => {
                         car.total_blocked_time += now - blocked_since;
+                        if let (Some((trip, _)), Some((b, duration, dist))) = (
+                            car.trip_and_person,
+                            car.router.parking_search(now, our_dist),
+                        ) {
+                            self.events
+                                .push(Event::ParkingSearchFinished(trip, b, duration, dist));
+                        }
                         let delay = match spot {
                             ParkingSpot::Onstreet(_, _) => self.time_to_park_onstreet,
                             ParkingSpot::Offstreet(_, _) | ParkingSpot::Lot(_, _) => {
@@ -714,19 +795,66 @@ impl DrivingSimState {
                     }
                     Some(ActionAtEnd::BusAtStop) => {
                         car.total_blocked_time += now - blocked_since;
-                        if transit.bus_arrived_at_stop(now, car.vehicle.id, trips, walking, ctx) {
+                        if let Some(dwell) =
+                            transit.bus_arrived_at_stop(now, car.vehicle.id, trips, walking, ctx)
+                        {
+                            let mut depart = now + dwell;
+                            // Early vehicles wait at timepoints
+                            if let Some(t) = transit.hold_until(car.vehicle.id) {
+                                depart = depart.max(t);
+                            }
+                            car.state =
+                                CarState::IdlingAtStop(our_dist, TimeInterval::new(now, depart));
+                            ctx.scheduler
+                                .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                            true
+                        } else {
+                            // Vanishing at a border
+                            false
+                        }
+                    }
+                    Some(ActionAtEnd::RideHailAtCurb) => {
+                        car.total_blocked_time += now - blocked_since;
+                        if let Some(dwell) = ride_hail.vehicle_arrived(
+                            now,
+                            car.vehicle.id,
+                            car.router.get_path().total_length(),
+                            trips,
+                            walking,
+                            ctx,
+                        ) {
+                            // Stop right in the driving lane, blocking anybody behind
                             car.state = CarState::IdlingAtStop(
                                 our_dist,
-                                TimeInterval::new(now, now + TIME_TO_WAIT_AT_BUS_STOP),
+                                TimeInterval::new(now, now + dwell),
                             );
                             ctx.scheduler
                                 .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                             true
                         } else {
-                            // Vanishing at a border
+                            // Waiting off-street for the next request
                             false
                         }
                     }
+                    Some(ActionAtEnd::DeliveryStop) => {
+                        car.total_blocked_time += now - blocked_since;
+                        let pos = Position::new(car.router.head().as_lane(), our_dist);
+                        if freight.vehicle_arrived(now, car.vehicle.id, pos, ctx) {
+                            // Leave a static blockage right behind the vehicle, which takes its
+                            // place once it's removed from the queue. Deleting the vehicle frees
+                            // its own reserved length.
+                            self.queues
+                                .get_mut(&car.router.head())
+                                .unwrap()
+                                .add_static_blockage(
+                                    car.vehicle.id,
+                                    our_dist,
+                                    our_dist - car.vehicle.length,
+                                    idx + 1,
+                                );
+                        }
+                        false
+                    }
                     None => {
                         ctx.scheduler.push(
                             now + BLIND_RETRY_TO_REACH_END_DIST,
@@ -772,8 +900,26 @@ impl DrivingSimState {
                 );
                 false
             }
-            CarState::IdlingAtStop(dist, _) => {
-                car.router = transit.bus_departed_from_stop(car.vehicle.id, ctx.map);
+            CarState::IdlingAtStop(dist, time_int) => {
+                record_emissions(
+                    &self.emissions,
+                    &mut self.events,
+                    car,
+                    Distance::ZERO,
+                    time_int.end - time_int.start,
+                );
+                if car.vehicle.vehicle_type == VehicleType::RideHail {
+                    match ride_hail.vehicle_departed(now, car.vehicle.id, trips, ctx) {
+                        Some(router) => {
+                            car.router = router;
+                        }
+                        None => {
+                            return false;
+                        }
+                    }
+                } else {
+                    car.router = transit.bus_departed_from_stop(now, car.vehicle.id, ctx.map);
+                }
                 self.events
                     .push(Event::PathAmended(car.router.get_path().clone()));
                 car.state = car.crossing_state(dist, now, ctx.map);
@@ -900,7 +1046,17 @@ impl DrivingSimState {
 
                     // Prevent them from jumping forwards.
                     follower.total_blocked_time += now - blocked_since;
+                    record_emissions(
+                        &self.emissions,
+                        &mut self.events,
+                        follower,
+                        Distance::ZERO,
+                        now - blocked_since,
+                    );
                     follower.state = follower.crossing_state(follower_dist, now, ctx.map);
+                    if now > blocked_since {
+                        follower.add_startup_lost_time();
+                    }
                     ctx.scheduler.update(
                         follower.state.get_end_time(),
                         Command::UpdateCar(follower_id),
@@ -908,10 +1064,21 @@ impl DrivingSimState {
                     let follower = &self.cars[&follower_id];
                     self.new_crossing_state(ctx, follower);
                 }
-                CarState::Crossing { .. } => {
+                CarState::Crossing {
+                    time_int, dist_int, ..
+                } => {
                     // If the follower was still Crossing, they might not've been blocked by the
                     // leader yet. But recalculating their Crossing state isn't necessarily a no-op
                     // -- this could prevent them from suddenly warping past a blockage.
+
+                    // The new Crossing state starts from here, so account for the movement so far.
+                    record_emissions(
+                        &self.emissions,
+                        &mut self.events,
+                        follower,
+                        (follower_dist - dist_int.start).max(Distance::ZERO),
+                        now - time_int.start,
+                    );
                     follower.state = follower.crossing_state(follower_dist, now, ctx.map);
                     ctx.scheduler.update(
                         follower.state.get_end_time(),
@@ -1073,6 +1240,17 @@ impl DrivingSimState {
                                     // gets out of the way. So immediately promote them to
                                     // WaitingToAdvance.
                                     follower.state = CarState::WaitingToAdvance { blocked_since };
+                                    if let Some(ref dynamic_routing) = self.dynamic_routing {
+                                        if ctx.handling_live_edits.is_none() {
+                                            dynamic_routing.maybe_reroute(
+                                                follower,
+                                                &self.queues,
+                                                &mut self.events,
+                                                now,
+                                                ctx.map,
+                                            );
+                                        }
+                                    }
                                     if self.recalc_lanechanging && ctx.handling_live_edits.is_none()
                                     {
                                         follower.router.opportunistically_lanechange(
@@ -1301,6 +1479,51 @@ impl DrivingSimState {
                 );
             }
         }
+
+        // Buses, trains, and emergency vehicles check in with traffic signals that give them
+        // priority
+        if !matches!(
+            car.vehicle.vehicle_type,
+            VehicleType::Bus | VehicleType::Train | VehicleType::Emergency
+        ) {
+            return;
+        }
+        if let (
+            Some(Traversable::Turn(turn)),
+            CarState::Crossing {
+                time_int, dist_int, ..
+            },
+        ) = (car.router.maybe_next(), &car.state)
+        {
+            let priority = match ctx
+                .map
+                .maybe_get_traffic_signal(turn.parent)
+                .and_then(|ts| ts.priority.as_ref())
+            {
+                Some(priority) => priority,
+                None => {
+                    return;
+                }
+            };
+            // When will the front of the vehicle reach the detector?
+            let detector = dist_int.end - priority.detection_distance;
+            let check_in = if detector <= dist_int.start {
+                time_int.start
+            } else {
+                time_int.start
+                    + (time_int.end - time_int.start)
+                        * ((detector - dist_int.start) / (dist_int.end - dist_int.start))
+            };
+            ctx.intersections.priority_vehicle_approaching(
+                time_int.start,
+                car.vehicle.id,
+                turn,
+                check_in,
+                time_int.end,
+                ctx.map,
+                ctx.scheduler,
+            );
+        }
     }
 }
 
@@ -1368,18 +1591,40 @@ impl DrivingSimState {
                 queue
                     .get_car_positions(now, &self.cars, &self.queues)
                     .into_iter()
-                    .filter_map(|entry| {
-                        if let Queued::Vehicle(id) = entry.member {
+                    .filter_map(|entry| match entry.member {
+                        Queued::Vehicle(id) => {
                             Some(self.cars[&id].get_draw_car(entry.front, now, map, transit))
-                        } else {
-                            None
                         }
+                        Queued::StaticBlockage { cause, front, back }
+                            if cause.vehicle_type == VehicleType::Freight =>
+                        {
+                            Some(draw_double_parked(cause, queue.id, front, back, map))
+                        }
+                        _ => None,
                     }),
             );
         }
         result
     }
 
+    /// A delivery vehicle finished loading or unloading while double-parked. Clear its static
+    /// blockage, so it can start driving again from the same spot.
+    pub fn clear_double_parking(&mut self, now: Time, id: CarID, lane: LaneID, ctx: &mut Ctx) {
+        let dists =
+            self.queues[&Traversable::Lane(lane)].get_car_positions(now, &self.cars, &self.queues);
+        let idx = dists
+            .iter()
+            .position(
+                |entry| matches!(entry.member, Queued::StaticBlockage { cause, ..} if cause == id),
+            )
+            .unwrap();
+        self.update_follower(idx, &dists, now, ctx);
+        self.queues
+            .get_mut(&Traversable::Lane(lane))
+            .unwrap()
+            .clear_static_blockage(id, idx);
+    }
+
     /// This is about as expensive as get_draw_cars_on.
     pub fn get_single_draw_car(
         &self,
@@ -1409,6 +1654,11 @@ impl DrivingSimState {
                     Queued::Vehicle(id) => {
                         Some(self.cars[&id].get_draw_car(entry.front, now, map, transit))
                     }
+                    Queued::StaticBlockage { cause, front, back }
+                        if cause.vehicle_type == VehicleType::Freight =>
+                    {
+                        Some(draw_double_parked(cause, on, front, back, map))
+                    }
                     // Manually enable to debug exiting driveways and lane-changing
                     Queued::StaticBlockage { cause, front, back } => {
                         if false {
@@ -1601,6 +1851,24 @@ impl DrivingSimState {
         }
     }
 
+    /// Counts vehicles with any part of their body between `start` and `end` along a lane.
+    pub fn count_vehicles_in_zone(
+        &self,
+        now: Time,
+        l: LaneID,
+        start: Distance,
+        end: Distance,
+    ) -> usize {
+        match self.queues.get(&Traversable::Lane(l)) {
+            Some(queue) => queue
+                .get_car_positions(now, &self.cars, &self.queues)
+                .into_iter()
+                .filter(|entry| entry.back < end && entry.front > start)
+                .count(),
+            None => 0,
+        }
+    }
+
     pub fn debug_queue_lengths(&self, l: LaneID) -> Option<(Distance, Distance)> {
         let queue = self.queues.get(&Traversable::Lane(l))?;
         Some((queue.reserved_length, queue.geom_len))
@@ -1694,6 +1962,30 @@ impl DrivingSimState {
     }
 }
 
+/// Records what a vehicle emitted while moving some distance along its current lane or turn, or
+/// idling there if the distance is zero.
+fn record_emissions(
+    model: &EmissionsModel,
+    events: &mut Vec<Event>,
+    car: &mut Car,
+    dist: Distance,
+    dt: Duration,
+) {
+    if dt <= Duration::ZERO {
+        return;
+    }
+    let emissions = model.movement(&car.vehicle, car.last_speed, dist, dt);
+    car.last_speed = Speed::from_dist_time(dist, dt);
+    if !emissions.is_zero() {
+        events.push(Event::VehicleEmissions(
+            car.vehicle.id,
+            car.trip_and_person.map(|(t, _)| t),
+            car.router.head(),
+            emissions,
+        ));
+    }
+}
+
 // This implementation relies on the fact that car IDs are unique just by their number. Vehicle
 // type is also in there, but during lookup, it'll be ignored!
 impl IndexableKey for CarID {
@@ -1701,3 +1993,69 @@ impl IndexableKey for CarID {
         self.id
     }
 }
+
+/// Lets some drivers switch routes mid-trip to avoid congestion.
+#[derive(Serialize, Deserialize, Clone)]
+struct DynamicRouting {
+    informed_fraction: f64,
+    interval: Duration,
+    /// Never reroute through these intersections. They're part of uber-turns or complicated turn
+    /// restrictions, which a simple road-based search doesn't understand.
+    avoid: BTreeSet<IntersectionID>,
+}
+
+impl DynamicRouting {
+    fn maybe_reroute(
+        &self,
+        car: &mut Car,
+        queues: &HashMap<Traversable, Queue>,
+        events: &mut Vec<Event>,
+        now: Time,
+        map: &Map,
+    ) {
+        if car.vehicle.vehicle_type != VehicleType::Car
+            || now - car.last_route_check < self.interval
+        {
+            return;
+        }
+        // Scramble the ID, so cars spawned around the same time aren't all informed. Use a
+        // different multiplier than the emissions model, so informed drivers aren't all in
+        // electric cars.
+        let bucket = (car.vehicle.id.id as u64).wrapping_mul(2_246_822_519) % 1000;
+        if (bucket as f64) >= 1000.0 * self.informed_fraction {
+            return;
+        }
+        car.last_route_check = now;
+        if let Some(old_path) = car
+            .router
+            .reroute_around_congestion(queues, &self.avoid, now, map)
+        {
+            events.push(Event::VehicleRerouted(
+                car.vehicle.id,
+                car.trip_and_person.map(|(t, _)| t),
+                old_path,
+                car.router.get_path().clone(),
+            ));
+        }
+    }
+}
+
+fn draw_double_parked(
+    id: CarID,
+    on: Traversable,
+    front: Distance,
+    back: Distance,
+    map: &Map,
+) -> DrawCarInput {
+    DrawCarInput {
+        id,
+        waiting_for_turn: None,
+        status: CarStatus::Parked,
+        intent: None,
+        on,
+        partly_on: Vec::new(),
+        label: None,
+        person: None,
+        body: on.get_polyline(map).exact_slice(back, front),
+    }
+}
@@ -1,22 +1,30 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use abstutil::{deserialize_btreemap, prettyprint_usize, serialize_btreemap, FixedMap};
-use geom::{Duration, Time};
+use abstutil::{deserialize_btreemap, prettyprint_usize, serialize_btreemap, FixedMap, Timer};
+use geom::{Distance, Duration, Time};
 use map_model::{
-    ControlStopSign, ControlTrafficSignal, Intersection, IntersectionID, LaneID, Map, StageType,
-    Traversable, TurnID, TurnPriority, TurnType, UberTurn,
+    ControlStopSign, ControlTrafficSignal, DirectedRoadID, Intersection, IntersectionID, Lane,
+    LaneID, Map, SignalPriority, Stage, StageType, Traversable, TurnID, TurnPriority, TurnType,
+    UberTurn,
 };
 
 use crate::mechanics::car::{Car, CarState};
 use crate::mechanics::Queue;
 use crate::{
-    AgentID, AlertLocation, CarID, Command, DelayCause, Event, Scheduler, SimOptions, Speed,
+    AgentID, AlertLocation, CarID, Command, DelayCause, DriverBehavior, DrivingSimState, Event,
+    Scheduler, SimOptions, Speed, VehicleType,
 };
 
-const WAIT_AT_STOP_SIGN: Duration = Duration::const_seconds(0.5);
+/// Pedestrians always wait this long. Drivers may have their own DriverBehavior.
+const WAIT_AT_STOP_SIGN: Duration = DriverBehavior::DEFAULT.stop_sign_wait;
 const WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL: Duration = Duration::const_seconds(0.2);
+/// The virtual loop detectors used by actuated traffic signals are this long.
+const DETECTOR_LENGTH: Distance = Distance::const_meters(2.0);
+/// When a green is extended for a bus or train, keep it a little longer than the vehicle needs.
+const PRIORITY_CLEARANCE: Duration = Duration::const_seconds(2.0);
 
 /// Manages conflicts at intersections. When an agent has reached the end of a lane, they call
 /// maybe_start_turn to make a Request. Based on the intersection type (stop sign, traffic signal,
@@ -34,6 +42,9 @@ pub(crate) struct IntersectionSimState {
     break_turn_conflict_cycles: bool,
     handle_uber_turns: bool,
     disable_turn_conflicts: bool,
+    /// If present, traffic signals are run as actuated controllers, with loop detectors placed
+    /// on each approaching lane.
+    actuated_signal_detectors: Option<SignalDetectors>,
     // (x, y) means x is blocked by y. It's a many-to-many relationship. TODO Better data
     // structure.
     blocked_by: BTreeSet<(CarID, CarID)>,
@@ -81,14 +92,102 @@ struct State {
     signal: Option<SignalState>,
 }
 
+impl State {
+    fn new(id: IntersectionID) -> State {
+        State {
+            id,
+            accepted: BTreeSet::new(),
+            waiting: BTreeMap::new(),
+            reserved: BTreeSet::new(),
+            uber_turn_neighbors: Vec::new(),
+            signal: None,
+            leader_eta: BTreeMap::new(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct SignalState {
+    // The timing plan currently in effect. See ControlTrafficSignal::plan_at.
+    plan: usize,
     // The current stage of the signal, zero based
     current_stage: usize,
     // The time when the signal is checked for advancing
     stage_ends_at: Time,
     // The number of times a variable signal has been extended during the current stage.
     extensions_count: usize,
+    // Has signal priority already changed when the current stage ends? Only one adjustment is
+    // made per stage, so a stream of buses can't starve everyone else.
+    priority_adjusted: bool,
+    // A train preempting the signal will make this turn around this time
+    preempted_for: Option<(TurnID, Time)>,
+}
+
+/// The state of a virtual loop detector, used by actuated traffic signals.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DetectorReading {
+    /// The detector is on this lane approaching the intersection
+    pub lane: LaneID,
+    /// The start of the detector along the lane
+    pub dist_along: Distance,
+    /// Is any vehicle over the detector right now?
+    pub occupied: bool,
+    /// How many vehicles are between the start of the detector and the end of the lane?
+    pub vehicles_past_detector: usize,
+}
+
+/// Where the virtual loop detectors of actuated traffic signals are placed
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SignalDetectors {
+    /// How many meters before the stop line detectors are placed, unless `approaches` says
+    /// otherwise
+    pub default_meters: f64,
+    /// Detectors on every lane of these roads are placed this many meters before the stop line
+    /// at the end of the road. Road IDs only apply to the same map.
+    #[serde(
+        default,
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    pub approaches: BTreeMap<DirectedRoadID, f64>,
+}
+
+impl SignalDetectors {
+    /// Either a number of meters for every approach, or a path to a JSON file.
+    pub fn parse(x: &str) -> Result<SignalDetectors> {
+        let detectors = if let Ok(meters) = x.parse::<f64>() {
+            SignalDetectors {
+                default_meters: meters,
+                approaches: BTreeMap::new(),
+            }
+        } else {
+            abstio::maybe_read_json(x.to_string(), &mut Timer::throwaway())?
+        };
+        for meters in
+            std::iter::once(&detectors.default_meters).chain(detectors.approaches.values())
+        {
+            if *meters < 0.0 {
+                bail!("Detector distance {} can't be negative", meters);
+            }
+        }
+        Ok(detectors)
+    }
+
+    /// Where the detector on a lane approaching an intersection starts. It's at the start of a
+    /// lane too short to fit it.
+    fn dist_along(&self, lane: &Lane) -> Distance {
+        let meters = self
+            .approaches
+            .get(&lane.get_directed_parent())
+            .cloned()
+            .unwrap_or(self.default_meters);
+        let len = lane.length();
+        if Distance::meters(meters) > len {
+            Distance::ZERO
+        } else {
+            len - Distance::meters(meters)
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Clone, Debug)]
@@ -107,6 +206,7 @@ impl IntersectionSimState {
             break_turn_conflict_cycles: !opts.dont_break_turn_conflict_cycles,
             handle_uber_turns: !opts.dont_handle_uber_turns,
             disable_turn_conflicts: opts.disable_turn_conflicts,
+            actuated_signal_detectors: opts.actuated_signal_detectors.clone(),
             blocked_by: BTreeSet::new(),
             events: Vec::new(),
 
@@ -119,15 +219,7 @@ impl IntersectionSimState {
         }
 
         for i in map.all_intersections() {
-            let mut state = State {
-                id: i.id,
-                accepted: BTreeSet::new(),
-                waiting: BTreeMap::new(),
-                reserved: BTreeSet::new(),
-                uber_turn_neighbors: Vec::new(),
-                signal: None,
-                leader_eta: BTreeMap::new(),
-            };
+            let mut state = State::new(i.id);
             if i.is_traffic_signal() {
                 state.signal = Some(SignalState::new(i.id, Time::START_OF_DAY, map, scheduler));
             }
@@ -231,8 +323,8 @@ impl IntersectionSimState {
                 protected.push(req);
             }
         } else if let Some(signal) = map.maybe_get_traffic_signal(i) {
-            let current_stage = self.state[&i].signal.as_ref().unwrap().current_stage;
-            let stage = &signal.stages[current_stage];
+            let signal_state = self.state[&i].signal.as_ref().unwrap();
+            let stage = &signal.plan_stages(signal_state.plan)[signal_state.current_stage];
             let reserved = &self.state[&i].reserved;
             let i = map.get_i(i);
             for (req, _, _) in all {
@@ -291,34 +383,72 @@ impl IntersectionSimState {
         id: IntersectionID,
         map: &Map,
         scheduler: &mut Scheduler,
+        driving: &DrivingSimState,
     ) {
+        // Has the time of day come to switch timing plans? Start the new plan wherever its own
+        // offset says it should be right now.
+        let plan = map.get_traffic_signal(id).plan_at(now);
+        if self.state[&id].signal.as_ref().unwrap().plan != plan {
+            self.state.get_mut(&id).unwrap().signal =
+                Some(SignalState::new(id, now, map, scheduler));
+            self.wakeup_waiting(now, id, scheduler, map);
+            return;
+        }
+
+        let signal_state = self.state.get_mut(&id).unwrap().signal.as_mut().unwrap();
+        if let Some((turn, eta)) = signal_state.preempted_for.take() {
+            // Switch to the first stage serving the train, and hold it until the train is through
+            let signal = map.get_traffic_signal(id);
+            let stages = signal.plan_stages(signal_state.plan);
+            let i = map.get_i(id);
+            if let Some(idx) = stages
+                .iter()
+                .position(|s| s.get_priority_of_turn(turn, i) == TurnPriority::Protected)
+            {
+                signal_state.current_stage = idx;
+                signal_state.extensions_count = 0;
+                let end =
+                    (eta + PRIORITY_CLEARANCE).max(now + stages[idx].stage_type.simple_duration());
+                signal_state.stage_ends_at = end_before_next_plan(signal, now, end);
+                signal_state.priority_adjusted = true;
+                scheduler.push(signal_state.stage_ends_at, Command::UpdateIntersection(id));
+                self.wakeup_waiting(now, id, scheduler, map);
+                return;
+            }
+        }
+
+        if self.actuated_signal_detectors.is_some() {
+            self.update_actuated_signal(now, id, map, scheduler, driving);
+            return;
+        }
+
         let i = map.get_i(id);
 
         // trivial function that advances the signal stage and returns duration
         fn advance(
             signal_state: &mut SignalState,
-            signal: &ControlTrafficSignal,
+            stages: &[Stage],
             i: &Intersection,
             allow_crosswalk_skip: bool,
         ) -> Duration {
-            signal_state.current_stage = (signal_state.current_stage + 1) % signal.stages.len();
-            let stage = &signal.stages[signal_state.current_stage];
+            signal_state.current_stage = (signal_state.current_stage + 1) % stages.len();
+            let stage = &stages[signal_state.current_stage];
             // only skip for variable all-walk crosswalk
             if let StageType::Variable(_, _, _) = stage.stage_type {
                 if allow_crosswalk_skip && stage.max_crosswalk_time(i).is_some() {
                     // we can skip this stage, as its all walk and we're allowed to skip (no
                     // pedestrian waiting).
-                    signal_state.current_stage =
-                        (signal_state.current_stage + 1) % signal.stages.len();
+                    signal_state.current_stage = (signal_state.current_stage + 1) % stages.len();
                 }
             }
-            signal.stages[signal_state.current_stage]
+            stages[signal_state.current_stage]
                 .stage_type
                 .simple_duration()
         }
         let state = self.state.get_mut(&id).unwrap();
         let signal_state = state.signal.as_mut().unwrap();
         let signal = map.get_traffic_signal(id);
+        let stages = signal.plan_stages(signal_state.plan);
         let ped_waiting = state.waiting.keys().any(|req| {
             if let AgentID::Pedestrian(_) = req.agent {
                 return true;
@@ -328,10 +458,10 @@ impl IntersectionSimState {
         let duration: Duration;
         // Switch to a new stage?
         assert_eq!(now, signal_state.stage_ends_at);
-        let old_stage = &signal.stages[signal_state.current_stage];
+        let old_stage = &stages[signal_state.current_stage];
         match old_stage.stage_type {
             StageType::Fixed(_) => {
-                duration = advance(signal_state, signal, i, !ped_waiting);
+                duration = advance(signal_state, stages, i, !ped_waiting);
             }
             StageType::Variable(min, delay, additional) => {
                 // test if anyone is waiting in current stage, and if so, extend the signal cycle.
@@ -349,7 +479,7 @@ impl IntersectionSimState {
                             min, delay, additional, signal_state.extensions_count
                         ),
                     ));
-                    duration = advance(signal_state, signal, i, !ped_waiting);
+                    duration = advance(signal_state, stages, i, !ped_waiting);
                     signal_state.extensions_count = 0;
                 } else if state.waiting.keys().all(|req| {
                     if let AgentID::Pedestrian(_) = req.agent {
@@ -360,7 +490,7 @@ impl IntersectionSimState {
                     old_stage.get_priority_of_turn(req.turn, i) != TurnPriority::Protected
                 }) {
                     signal_state.extensions_count = 0;
-                    duration = advance(signal_state, signal, i, !ped_waiting);
+                    duration = advance(signal_state, stages, i, !ped_waiting);
                 } else {
                     signal_state.extensions_count += 1;
                     duration = delay;
@@ -375,11 +505,160 @@ impl IntersectionSimState {
             }
         }
 
-        signal_state.stage_ends_at = now + duration;
+        signal_state.stage_ends_at = end_before_next_plan(signal, now, now + duration);
+        signal_state.priority_adjusted = false;
+        scheduler.push(signal_state.stage_ends_at, Command::UpdateIntersection(id));
+        self.wakeup_waiting(now, id, scheduler, map);
+    }
+
+    /// Like update_intersection, but for actuated signals. Fixed stages behave normally. Variable
+    /// stages are extended by their delay (the passage time) while the detectors for any of their
+    /// protected movements report vehicles approaching, until the additional time is used up
+    /// (max-out), there's a gap in traffic (gap-out), or the stage reaches its force-off point in
+    /// the cycle (see `ActuatedCycle`). When changing stages, variable stages that nobody has
+    /// called for are skipped. If no other stage has a call, the signal rests in the first stage.
+    fn update_actuated_signal(
+        &mut self,
+        now: Time,
+        id: IntersectionID,
+        map: &Map,
+        scheduler: &mut Scheduler,
+        driving: &DrivingSimState,
+    ) {
+        let i = map.get_i(id);
+        let signal = map.get_traffic_signal(id);
+        let (plan, current_stage, extensions_count) = {
+            let signal_state = self.state[&id].signal.as_ref().unwrap();
+            assert_eq!(now, signal_state.stage_ends_at);
+            (
+                signal_state.plan,
+                signal_state.current_stage,
+                signal_state.extensions_count,
+            )
+        };
+        let stages = signal.plan_stages(plan);
+        let cycle = ActuatedCycle::new(stages, signal.plan_offset(plan), now);
+        let stage = &stages[current_stage];
+
+        let extension = match stage.stage_type {
+            // The coordinated stage always ends at its yield point
+            _ if current_stage == 0 => None,
+            StageType::Fixed(_) => None,
+            StageType::Variable(_, passage, additional) => {
+                let passage = std::cmp::max(Duration::const_seconds(1.0), passage);
+                if extensions_count as f64 * passage.inner_seconds() >= additional.inner_seconds()
+                    || passage > cycle.until_force_off(current_stage)
+                {
+                    // Max-out or force-off
+                    None
+                } else if self.vehicles_approaching_stage(now, stage, i, map, driving) {
+                    Some(passage)
+                } else {
+                    // Gap-out
+                    None
+                }
+            }
+        };
+
+        let (next_stage, duration) = if let Some(dt) = extension {
+            (current_stage, dt)
+        } else {
+            // Find the next stage with demand. Fixed stages always run, unless resting in the
+            // first stage pushed them too late in the cycle.
+            let mut next = None;
+            for offset in 1..stages.len() {
+                let idx = (current_stage + offset) % stages.len();
+                if idx == 0 {
+                    next = Some(idx);
+                    break;
+                }
+                let candidate = &stages[idx];
+                let min = std::cmp::max(
+                    Duration::const_seconds(1.0),
+                    candidate.stage_type.simple_duration(),
+                );
+                if min > cycle.until_force_off(idx) {
+                    continue;
+                }
+                if matches!(candidate.stage_type, StageType::Fixed(_))
+                    || self.stage_has_call(now, candidate, i, map, driving)
+                {
+                    next = Some(idx);
+                    break;
+                }
+            }
+            match next {
+                // Hold the coordinated stage until its yield point
+                Some(0) => (0, cycle.until_force_off(0)),
+                Some(idx) => (
+                    idx,
+                    std::cmp::max(
+                        Duration::const_seconds(1.0),
+                        stages[idx].stage_type.simple_duration(),
+                    ),
+                ),
+                // Nobody else wants a green, so rest in this one and check again soon.
+                None => (current_stage, Duration::const_seconds(1.0)),
+            }
+        };
+
+        let signal_state = self.state.get_mut(&id).unwrap().signal.as_mut().unwrap();
+        if extension.is_some() {
+            signal_state.extensions_count += 1;
+        } else {
+            signal_state.extensions_count = 0;
+        }
+        signal_state.current_stage = next_stage;
+        signal_state.stage_ends_at = end_before_next_plan(signal, now, now + duration);
+        signal_state.priority_adjusted = false;
         scheduler.push(signal_state.stage_ends_at, Command::UpdateIntersection(id));
         self.wakeup_waiting(now, id, scheduler, map);
     }
 
+    /// Is there a vehicle between the detector and the stop line of any lane served by a protected
+    /// movement in this stage? A vehicle that has crossed the detector keeps the call until it
+    /// reaches the intersection.
+    fn vehicles_approaching_stage(
+        &self,
+        now: Time,
+        stage: &Stage,
+        i: &Intersection,
+        map: &Map,
+        driving: &DrivingSimState,
+    ) -> bool {
+        let detectors = self.actuated_signal_detectors.as_ref().unwrap();
+        for m in &stage.protected_movements {
+            if m.crosswalk {
+                continue;
+            }
+            for t in &i.movements[m].members {
+                let lane = map.get_l(t.src);
+                let start = detectors.dist_along(lane);
+                if driving.count_vehicles_in_zone(now, t.src, start, lane.length()) > 0 {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Does anybody want to use this stage? Vehicles are detected approaching, and anybody already
+    /// waiting at the intersection (including pedestrians pushing a button) also places a call.
+    fn stage_has_call(
+        &self,
+        now: Time,
+        stage: &Stage,
+        i: &Intersection,
+        map: &Map,
+        driving: &DrivingSimState,
+    ) -> bool {
+        self.state[&i.id]
+            .waiting
+            .keys()
+            .any(|req| stage.get_priority_of_turn(req.turn, i) == TurnPriority::Protected)
+            || self.vehicles_approaching_stage(now, stage, i, map, driving)
+    }
+
     /// For cars: The head car calls this when they're at the end of the lane WaitingToAdvance. If
     /// this returns true, then the head car MUST actually start this turn.
     /// For peds: Likewise -- only called when the ped is at the start of the turn. They must
@@ -441,6 +720,9 @@ impl IntersectionSimState {
             map.get_t(req.turn).turn_type == TurnType::SharedSidewalkCorner;
 
         let readonly_pair = maybe_cars_and_queues.as_ref().map(|(_, c, q)| (*c, &**q));
+        let behavior = maybe_cars_and_queues
+            .as_ref()
+            .map(|(car, _, _)| car.vehicle.behavior);
         let started_uber_turn = |state: &Self, car: &Car| {
             state.handle_uber_turns && car.router.get_path().currently_inside_ut().is_some()
         };
@@ -461,7 +743,9 @@ impl IntersectionSimState {
             // TODO: Consider reenabling alert
             if let Some(signal) = map.maybe_get_traffic_signal(turn.parent) {
                 // Don't pass in the scheduler, aka, don't pause before yielding.
-                if !self.traffic_signal_policy(&req, map, signal, speed, now, None) && false {
+                if !self.traffic_signal_policy(&req, map, signal, speed, behavior, now, None)
+                    && false
+                {
                     self.events.push(Event::Alert(
                         AlertLocation::Intersection(req.turn.parent),
                         format!("Running a red light inside an uber-turn: {:?}", req),
@@ -474,9 +758,9 @@ impl IntersectionSimState {
             // If we made it this far, we don't conflict with an accepted turn
             true
         } else if let Some(signal) = map.maybe_get_traffic_signal(turn.parent) {
-            self.traffic_signal_policy(&req, map, signal, speed, now, Some(scheduler))
+            self.traffic_signal_policy(&req, map, signal, speed, behavior, now, Some(scheduler))
         } else if let Some(sign) = map.maybe_get_stop_sign(turn.parent) {
-            self.stop_sign_policy(&req, map, sign, speed, now, scheduler)
+            self.stop_sign_policy(&req, map, sign, speed, behavior, now, scheduler)
         } else {
             unreachable!()
         };
@@ -604,13 +888,27 @@ impl IntersectionSimState {
         map: &Map,
         scheduler: &mut Scheduler,
     ) {
+        // Map edits can create intersections. Ones that no longer exist are removed later, in
+        // handle_live_edits, after the trips using them are cancelled.
+        for i in map.all_intersections() {
+            self.state.entry(i.id).or_insert_with(|| State::new(i.id));
+        }
+
         for state in self.state.values_mut() {
+            if map.maybe_get_i(state.id).is_none() {
+                continue;
+            }
             match (
                 map.maybe_get_traffic_signal(state.id),
                 state.signal.as_mut(),
             ) {
                 (Some(ts), Some(signal_state)) => {
-                    if signal_state.current_stage >= ts.stages.len() {
+                    if signal_state.plan != ts.plan_at(now) {
+                        // The plans were edited, so a different one is active now
+                        scheduler.cancel(Command::UpdateIntersection(state.id));
+                        state.signal = Some(SignalState::new(state.id, now, map, scheduler));
+                    } else if signal_state.current_stage >= ts.plan_stages(signal_state.plan).len()
+                    {
                         // Just jump back to the first one. Shrug.
                         signal_state.current_stage = 0;
                         println!(
@@ -640,7 +938,7 @@ impl IntersectionSimState {
         }
     }
 
-    pub fn handle_live_edits(&self, map: &Map) {
+    pub fn handle_live_edits(&mut self, map: &Map, scheduler: &mut Scheduler) {
         // Just sanity check that we don't have any references to deleted turns
         let mut errors = Vec::new();
         for state in self.state.values() {
@@ -666,6 +964,89 @@ impl IntersectionSimState {
             }
             panic!("After live map edits, intersection state refers to deleted turns!");
         }
+
+        self.state.retain(|i, state| {
+            if map.maybe_get_i(*i).is_some() {
+                return true;
+            }
+            if state.signal.is_some() {
+                scheduler.cancel(Command::UpdateIntersection(*i));
+            }
+            false
+        });
+    }
+
+    /// A bus, train, or emergency vehicle approaching a traffic signal with priority configured
+    /// will cross the detector at `check_in` and reach the stop line around `eta`, if nothing slows
+    /// it down. See `priority_action` for how the signal reacts. This may be called several times
+    /// for the same vehicle, as its estimates change, but the signal only adjusts once per stage.
+    pub fn priority_vehicle_approaching(
+        &mut self,
+        now: Time,
+        car: CarID,
+        turn: TurnID,
+        check_in: Time,
+        eta: Time,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) {
+        let signal = map.get_traffic_signal(turn.parent);
+        let priority = match signal.priority {
+            Some(ref priority) => priority,
+            None => {
+                return;
+            }
+        };
+        let i = map.get_i(turn.parent);
+        let signal_state = match self.state.get_mut(&turn.parent).unwrap().signal.as_mut() {
+            Some(signal_state) => signal_state,
+            None => {
+                return;
+            }
+        };
+        if signal_state.priority_adjusted {
+            return;
+        }
+        let stages = signal.plan_stages(signal_state.plan);
+        let old_end = signal_state.stage_ends_at;
+        let action = match priority_action(
+            priority,
+            &PriorityRequest {
+                vehicle_type: car.vehicle_type,
+                now,
+                check_in,
+                eta,
+            },
+            stages,
+            signal_state.current_stage,
+            old_end,
+            |idx| stages[idx].get_priority_of_turn(turn, i) == TurnPriority::Protected,
+        ) {
+            Some(action) => action,
+            None => {
+                return;
+            }
+        };
+        signal_state.stage_ends_at = match action {
+            PriorityAction::ExtendGreen(end) | PriorityAction::EarlyGreen(end) => end,
+            PriorityAction::Preempt(end) => {
+                // update_intersection then switches to a stage serving the vehicle
+                signal_state.preempted_for = Some((turn, eta));
+                end
+            }
+        };
+
+        signal_state.stage_ends_at = end_before_next_plan(signal, now, signal_state.stage_ends_at);
+        signal_state.priority_adjusted = true;
+        scheduler.update(
+            signal_state.stage_ends_at,
+            Command::UpdateIntersection(turn.parent),
+        );
+        self.events.push(Event::SignalPriorityGranted(
+            car,
+            turn.parent,
+            signal_state.stage_ends_at - old_end,
+        ));
     }
 
     // Not calling this for pedestrians right now.
@@ -740,6 +1121,45 @@ impl IntersectionSimState {
         candidates
     }
 
+    /// Returns the current state of the virtual loop detectors on every lane approaching a traffic
+    /// signal. Empty unless actuated signals are enabled.
+    pub fn get_signal_detectors(
+        &self,
+        now: Time,
+        id: IntersectionID,
+        map: &Map,
+        driving: &DrivingSimState,
+    ) -> Vec<DetectorReading> {
+        let detectors = match self.actuated_signal_detectors {
+            Some(ref detectors) => detectors,
+            None => return Vec::new(),
+        };
+        if self.state[&id].signal.is_none() {
+            return Vec::new();
+        }
+        let mut results = Vec::new();
+        for l in &map.get_i(id).incoming_lanes {
+            let lane = map.get_l(*l);
+            if !lane.lane_type.is_for_moving_vehicles() {
+                continue;
+            }
+            let len = lane.length();
+            let dist_along = detectors.dist_along(lane);
+            results.push(DetectorReading {
+                lane: *l,
+                dist_along,
+                occupied: driving.count_vehicles_in_zone(
+                    now,
+                    *l,
+                    dist_along,
+                    dist_along + DETECTOR_LENGTH,
+                ) > 0,
+                vehicles_past_detector: driving.count_vehicles_in_zone(now, *l, dist_along, len),
+            });
+        }
+        results
+    }
+
     pub fn current_stage_and_remaining_time(
         &self,
         now: Time,
@@ -842,29 +1262,42 @@ impl IntersectionSimState {
 
 // Stuff to support maybe_start_turn
 impl IntersectionSimState {
+    #[allow(clippy::too_many_arguments)]
     fn stop_sign_policy(
         &mut self,
         req: &Request,
         map: &Map,
         sign: &ControlStopSign,
         speed: Speed,
+        behavior: Option<DriverBehavior>,
         now: Time,
         scheduler: &mut Scheduler,
     ) -> bool {
         let our_priority = sign.get_priority(req.turn, map);
         assert!(our_priority != TurnPriority::Banned);
         let (our_time, _) = self.state[&req.turn.parent].waiting[req];
+        let wait = behavior
+            .map(|b| b.stop_sign_wait)
+            .unwrap_or(WAIT_AT_STOP_SIGN);
 
-        if our_priority == TurnPriority::Yield && now < our_time + WAIT_AT_STOP_SIGN {
+        if our_priority == TurnPriority::Yield && now < our_time + wait {
             // Since we have "ownership" of scheduling for req.agent, don't need to use
             // scheduler.update.
-            scheduler.push(
-                our_time + WAIT_AT_STOP_SIGN,
-                Command::update_agent(req.agent),
-            );
+            scheduler.push(our_time + wait, Command::update_agent(req.agent));
             return false;
         }
 
+        if our_priority == TurnPriority::Yield {
+            if let Some(b) = behavior {
+                if let Some(retry_at) = self.gap_too_small(req, map, b.critical_gap, now, |t| {
+                    sign.get_priority(t, map) == TurnPriority::Protected
+                }) {
+                    scheduler.push(retry_at, Command::update_agent(req.agent));
+                    return false;
+                }
+            }
+        }
+
         // Once upon a time, we'd make sure that this request doesn't conflict with another in
         // self.waiting:
         // 1) Higher-ranking turns get to go first.
@@ -915,12 +1348,14 @@ impl IntersectionSimState {
         true
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn traffic_signal_policy(
         &mut self,
         req: &Request,
         map: &Map,
         signal: &ControlTrafficSignal,
         speed: Speed,
+        behavior: Option<DriverBehavior>,
         now: Time,
         scheduler: Option<&mut Scheduler>,
     ) -> bool {
@@ -928,7 +1363,7 @@ impl IntersectionSimState {
 
         let state = &self.state[&req.turn.parent];
         let signal_state = state.signal.as_ref().unwrap();
-        let stage = &signal.stages[signal_state.current_stage];
+        let stage = &signal.plan_stages(signal_state.plan)[signal_state.current_stage];
         let full_stage_duration = stage.stage_type.simple_duration();
         let remaining_stage_time = signal_state.stage_ends_at - now;
         let (our_time, _) = state.waiting[req];
@@ -953,6 +1388,20 @@ impl IntersectionSimState {
             return false;
         }
 
+        if our_priority == TurnPriority::Yield {
+            if let Some(b) = behavior {
+                let i = map.get_i(state.id);
+                if let Some(retry_at) = self.gap_too_small(req, map, b.critical_gap, now, |t| {
+                    stage.get_priority_of_turn(t, i) == TurnPriority::Protected
+                }) {
+                    if let Some(s) = scheduler {
+                        s.push(retry_at, Command::update_agent(req.agent));
+                    }
+                    return false;
+                }
+            }
+        }
+
         // Previously: A yield loses to a conflicting Priority turn.
         // But similar to the description in stop_sign_policy, this caused unnecessary gridlock.
         // Priority vehicles getting scheduled first just requires a little tweak in
@@ -977,6 +1426,32 @@ impl IntersectionSimState {
         true
     }
 
+    /// Before an unprotected turn, a driver won't start if a vehicle with the right-of-way will
+    /// arrive sooner than `critical_gap`. If so, returns when that vehicle is expected. Vehicles
+    /// that are already late might be stuck, so they're ignored.
+    fn gap_too_small<F: Fn(TurnID) -> bool>(
+        &self,
+        req: &Request,
+        map: &Map,
+        critical_gap: Duration,
+        now: Time,
+        has_right_of_way: F,
+    ) -> Option<Time> {
+        let turn = map.get_t(req.turn);
+        self.state[&req.turn.parent]
+            .leader_eta
+            .values()
+            .filter(|(other, eta)| {
+                other.agent != req.agent
+                    && *eta > now
+                    && *eta < now + critical_gap
+                    && has_right_of_way(other.turn)
+                    && turn.conflicts_with(map.get_t(other.turn))
+            })
+            .map(|(_, eta)| *eta)
+            .max()
+    }
+
     // If true, the request can go.
     fn handle_accepted_conflicts(
         &mut self,
@@ -1105,27 +1580,30 @@ impl IntersectionSimState {
 
 impl SignalState {
     fn new(id: IntersectionID, now: Time, map: &Map, scheduler: &mut Scheduler) -> SignalState {
+        let signal = map.get_traffic_signal(id);
+        let plan = signal.plan_at(now);
         let mut state = SignalState {
+            plan,
             current_stage: 0,
             stage_ends_at: now,
             extensions_count: 0,
+            priority_adjusted: false,
+            preempted_for: None,
         };
 
-        let signal = map.get_traffic_signal(id);
         // What stage are we starting with?
-        let mut offset = (now - Time::START_OF_DAY) + signal.offset;
+        let stages = signal.plan_stages(plan);
+        let mut offset = (now - Time::START_OF_DAY) + signal.plan_offset(plan);
         loop {
-            let dt = signal.stages[state.current_stage]
-                .stage_type
-                .simple_duration();
+            let dt = stages[state.current_stage].stage_type.simple_duration();
             if offset >= dt {
                 offset -= dt;
                 state.current_stage += 1;
-                if state.current_stage == signal.stages.len() {
+                if state.current_stage == stages.len() {
                     state.current_stage = 0;
                 }
             } else {
-                state.stage_ends_at = now + dt - offset;
+                state.stage_ends_at = end_before_next_plan(signal, now, now + dt - offset);
                 break;
             }
         }
@@ -1134,6 +1612,141 @@ impl SignalState {
     }
 }
 
+// Cut a stage short if a different timing plan takes over before it would normally end.
+fn end_before_next_plan(signal: &ControlTrafficSignal, now: Time, end: Time) -> Time {
+    match signal.next_plan_change(now) {
+        Some(t) => end.min(t),
+        None => end,
+    }
+}
+
+/// Actuated signals stay coordinated with their neighbors by following the same cycle as
+/// fixed-time operation, starting from the plan's offset. The first stage is the coordinated one.
+/// It always ends where it would under fixed time (its yield point), and it gets any time the
+/// other stages don't use. The other stages may start early when earlier ones gap out or are
+/// skipped, but they're forced off no later than where they'd end under fixed time.
+struct ActuatedCycle {
+    /// How long it's been since the first stage last reached its yield point
+    since_yield: Duration,
+    /// When each stage must end, measured from the yield point. For the first stage, this is the
+    /// whole cycle.
+    force_offs: Vec<Duration>,
+}
+
+impl ActuatedCycle {
+    fn new(stages: &[Stage], offset: Duration, now: Time) -> ActuatedCycle {
+        let cycle_length: Duration = stages.iter().map(|s| s.stage_type.simple_duration()).sum();
+        let mut force_offs = vec![cycle_length];
+        let mut end = Duration::ZERO;
+        for stage in &stages[1..] {
+            end += stage.stage_type.simple_duration();
+            force_offs.push(end);
+        }
+
+        // Like SignalState::new, the cycle starts with the first stage
+        let since_cycle_start = ((now - Time::START_OF_DAY) + offset) % cycle_length;
+        let mut since_yield = (since_cycle_start + cycle_length
+            - stages[0].stage_type.simple_duration())
+            % cycle_length;
+        // Right at the yield point, rounding might put us at the end of the cycle instead
+        if since_yield >= cycle_length - Duration::EPSILON {
+            since_yield = Duration::ZERO;
+        }
+        ActuatedCycle {
+            since_yield,
+            force_offs,
+        }
+    }
+
+    /// How much longer a stage can run before it must end. Negative if it's already too late.
+    fn until_force_off(&self, stage: usize) -> Duration {
+        self.force_offs[stage] - self.since_yield
+    }
+}
+
+/// What a traffic signal with priority knows about an approaching vehicle
+struct PriorityRequest {
+    vehicle_type: VehicleType,
+    now: Time,
+    /// When the vehicle crosses the detector
+    check_in: Time,
+    /// When the vehicle should reach the stop line
+    eta: Time,
+}
+
+/// How a traffic signal with priority changes when its current stage ends
+#[derive(Debug, PartialEq)]
+enum PriorityAction {
+    /// The vehicle's movement is green, but the stage would end just before it arrives
+    ExtendGreen(Time),
+    /// The vehicle is waiting for the very next stage, so start it sooner
+    EarlyGreen(Time),
+    /// End the current stage now and switch straight to one serving the vehicle, like at a
+    /// railroad crossing or for an ambulance
+    Preempt(Time),
+}
+
+/// Decide how a signal responds to an approaching vehicle. `serves` says whether a stage protects
+/// the vehicle's movement. Buses get limited priority. With preemption configured, trains and
+/// emergency vehicles take over the signal instead. Walk signals are never cut short, except by
+/// preemption.
+fn priority_action(
+    priority: &SignalPriority,
+    req: &PriorityRequest,
+    stages: &[Stage],
+    current_stage: usize,
+    stage_ends_at: Time,
+    serves: impl Fn(usize) -> bool,
+) -> Option<PriorityAction> {
+    let preempt = priority.preemption
+        && matches!(
+            req.vehicle_type,
+            VehicleType::Train | VehicleType::Emergency
+        );
+
+    if serves(current_stage) {
+        // Green extension, only if the vehicle is detected before the green ends
+        let needed = req.eta + PRIORITY_CLEARANCE;
+        if req.check_in > stage_ends_at
+            || needed <= stage_ends_at
+            || (needed - stage_ends_at > priority.max_green_extension && !preempt)
+        {
+            return None;
+        }
+        return Some(PriorityAction::ExtendGreen(needed));
+    }
+
+    if preempt {
+        // End the current stage as soon as the vehicle is detected
+        if !(0..stages.len()).any(&serves) {
+            return None;
+        }
+        let switch_at = req.check_in.max(req.now);
+        if switch_at >= stage_ends_at {
+            return None;
+        }
+        return Some(PriorityAction::Preempt(switch_at));
+    }
+
+    // Early green, only if the very next stage serves the vehicle
+    if !serves((current_stage + 1) % stages.len())
+        || stages[current_stage]
+            .protected_movements
+            .iter()
+            .any(|m| m.crosswalk)
+    {
+        return None;
+    }
+    let new_end = stage_ends_at
+        .clamped_sub(priority.max_early_green)
+        .max(req.check_in)
+        .max(req.now);
+    if new_end >= stage_ends_at {
+        return None;
+    }
+    Some(PriorityAction::EarlyGreen(new_end))
+}
+
 fn allow_block_the_box(i: &Intersection) -> bool {
     // Degenerate intersections are often just artifacts of how roads are split up in OSM. Allow
     // vehicles to get stuck in them, since the only possible thing they could block is pedestrians
@@ -1173,3 +1786,148 @@ fn allow_block_the_box(i: &Intersection) -> bool {
     }
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priority_action() {
+        let at = |time: &str| Time::parse(time).unwrap();
+        let stages = vec![Stage::new(), Stage::new(), Stage::new()];
+        let mut priority = SignalPriority::transit_default();
+        // The vehicle is waiting for the last stage. The first stage ends at 8:01.
+        let stage_ends_at = at("8:01:00");
+        let serves = |idx: usize| idx == 2;
+        let request = |vehicle_type| PriorityRequest {
+            vehicle_type,
+            now: at("8:00:00"),
+            check_in: at("8:00:10"),
+            eta: at("8:00:30"),
+        };
+
+        // Without preemption, nobody can skip the middle stage
+        for vehicle_type in [VehicleType::Bus, VehicleType::Train, VehicleType::Emergency] {
+            assert_eq!(
+                priority_action(
+                    &priority,
+                    &request(vehicle_type),
+                    &stages,
+                    0,
+                    stage_ends_at,
+                    serves
+                ),
+                None
+            );
+        }
+
+        // With preemption, trains and emergency vehicles switch the signal as soon as they're
+        // detected, but buses still can't
+        priority.preemption = true;
+        for vehicle_type in [VehicleType::Train, VehicleType::Emergency] {
+            assert_eq!(
+                priority_action(
+                    &priority,
+                    &request(vehicle_type),
+                    &stages,
+                    0,
+                    stage_ends_at,
+                    serves
+                ),
+                Some(PriorityAction::Preempt(at("8:00:10")))
+            );
+        }
+        assert_eq!(
+            priority_action(
+                &priority,
+                &request(VehicleType::Bus),
+                &stages,
+                0,
+                stage_ends_at,
+                serves
+            ),
+            None
+        );
+
+        // A bus waiting for the next stage gets an early green, limited by max_early_green
+        assert_eq!(
+            priority_action(
+                &priority,
+                &request(VehicleType::Bus),
+                &stages,
+                1,
+                stage_ends_at,
+                serves
+            ),
+            Some(PriorityAction::EarlyGreen(at("8:00:50")))
+        );
+
+        // When the movement is already green, everyone gets an extension. Only preempting
+        // vehicles can go past max_green_extension.
+        let late = |vehicle_type| PriorityRequest {
+            eta: at("8:01:30"),
+            ..request(vehicle_type)
+        };
+        for vehicle_type in [VehicleType::Train, VehicleType::Emergency] {
+            assert_eq!(
+                priority_action(
+                    &priority,
+                    &late(vehicle_type),
+                    &stages,
+                    2,
+                    stage_ends_at,
+                    serves
+                ),
+                Some(PriorityAction::ExtendGreen(at("8:01:32")))
+            );
+        }
+        assert_eq!(
+            priority_action(
+                &priority,
+                &late(VehicleType::Bus),
+                &stages,
+                2,
+                stage_ends_at,
+                serves
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_actuated_cycle() {
+        let at = |time: &str| Time::parse(time).unwrap();
+        let secs = Duration::seconds;
+        let mut stages = vec![Stage::new(), Stage::new(), Stage::new()];
+        stages[0].stage_type = StageType::Fixed(secs(30.0));
+        stages[1].stage_type = StageType::Variable(secs(20.0), secs(2.0), secs(10.0));
+        stages[2].stage_type = StageType::Variable(secs(10.0), secs(2.0), secs(10.0));
+        // With a 5 second offset, the first stage runs from 0:55 to 0:25 of each minute
+        let offset = secs(5.0);
+
+        // At the yield point, each stage may run until its fixed-time end
+        let cycle = ActuatedCycle::new(&stages, offset, at("8:00:25"));
+        assert_eq!(cycle.until_force_off(0), secs(60.0));
+        assert_eq!(cycle.until_force_off(1), secs(20.0));
+        assert_eq!(cycle.until_force_off(2), secs(30.0));
+
+        // If the second stage gapped out early, the third can start early, but still ends on time
+        let cycle = ActuatedCycle::new(&stages, offset, at("8:00:40"));
+        assert_eq!(cycle.until_force_off(1), secs(5.0));
+        assert_eq!(cycle.until_force_off(2), secs(15.0));
+        assert_eq!(cycle.until_force_off(0), secs(45.0));
+
+        // Just before the yield point, it's too late for the other stages
+        let cycle = ActuatedCycle::new(&stages, offset, at("8:00:20"));
+        assert_eq!(cycle.until_force_off(0), secs(5.0));
+        assert_eq!(cycle.until_force_off(1), secs(-35.0));
+    }
+
+    #[test]
+    fn test_signal_detectors() {
+        let detectors = SignalDetectors::parse("30").unwrap();
+        assert_eq!(detectors.default_meters, 30.0);
+        assert!(detectors.approaches.is_empty());
+        assert!(SignalDetectors::parse("-1").is_err());
+    }
+}
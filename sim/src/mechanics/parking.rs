@@ -9,13 +9,19 @@ use abstutil::{
     deserialize_btreemap, deserialize_multimap, serialize_btreemap, serialize_multimap, MultiMap,
     Timer,
 };
-use geom::{Distance, PolyLine, Pt2D};
+use geom::{Distance, Duration, PolyLine, Pt2D, Time};
 use map_model::{
     BuildingID, Lane, LaneID, LaneType, Map, OffstreetParking, ParkingLotID, PathConstraints,
-    PathStep, Position, Traversable, TurnID,
+    PathStep, Position, RoadID, Traversable, TurnID,
 };
 
-use crate::{CarID, CarStatus, DrawCarInput, Event, ParkedCar, ParkingSpot, PersonID, Vehicle};
+use crate::{
+    CarID, CarStatus, DrawCarInput, Event, ParkedCar, ParkingPricing, ParkingSpot, PersonID,
+    Vehicle,
+};
+
+/// When parking is priced, drivers compare spots up to this far away.
+const MAX_PRICED_SEARCH_DIST: Distance = Distance::const_meters(800.0);
 
 /// Manages the state of parked cars. There are two implementations:
 /// - NormalParkingSimState allows only one vehicle per ParkingSpot defined in the map
@@ -87,11 +93,20 @@ pub enum ParkingSimState {
 impl ParkingSimState {
     /// Counterintuitive: any spots located in blackholes are just not represented here. If somebody
     /// tries to drive from a blackholed spot, they couldn't reach most places.
-    pub fn new(map: &Map, infinite: bool, timer: &mut Timer) -> ParkingSimState {
+    ///
+    /// Pricing only applies to normal parking.
+    pub fn new(
+        map: &Map,
+        infinite: bool,
+        pricing: Option<ParkingPricing>,
+        timer: &mut Timer,
+    ) -> ParkingSimState {
         if infinite {
             ParkingSimState::Infinite(InfiniteParkingSimState::new(map))
         } else {
-            ParkingSimState::Normal(NormalParkingSimState::new(map, timer))
+            let mut sim = NormalParkingSimState::new(map, timer);
+            sim.pricing = pricing;
+            ParkingSimState::Normal(sim)
         }
     }
 
@@ -101,6 +116,100 @@ impl ParkingSimState {
             ParkingSimState::Infinite(_) => true,
         }
     }
+
+    /// If parking isn't priced anywhere, drivers just take a free spot close to their destination.
+    pub fn is_priced(&self) -> bool {
+        matches!(self, ParkingSimState::Normal(sim) if sim.pricing.is_some())
+    }
+
+    /// What somebody pays to park in a spot from now until they leave
+    pub fn parking_fee(
+        &self,
+        now: Time,
+        spot: ParkingSpot,
+        leave_at: Option<Time>,
+        map: &Map,
+    ) -> f64 {
+        match self {
+            ParkingSimState::Normal(sim) => match sim.pricing {
+                Some(ref pricing) => {
+                    pricing.fee(now, spot, leave_at, sim.price_adjustment(spot), map)
+                }
+                None => 0.0,
+            },
+            ParkingSimState::Infinite(_) => 0.0,
+        }
+    }
+
+    /// The current price per hour of on-street parking on a block, if it's priced
+    pub fn current_hourly_price(&self, now: Time, r: RoadID) -> Option<f64> {
+        match self {
+            ParkingSimState::Normal(sim) => sim.current_hourly_price(now, r),
+            ParkingSimState::Infinite(_) => None,
+        }
+    }
+
+    /// Like `path_to_free_parking_spot`, but instead of going to the first free spot found, compare
+    /// all free spots within some distance, including the rest of the current lane, and pick the
+    /// one with the lowest overall cost for this driver. The path is empty if the spot is on the
+    /// current lane. Only meaningful if parking is priced.
+    pub fn path_to_cheapest_parking_spot(
+        &self,
+        now: Time,
+        start: Position,
+        vehicle: &Vehicle,
+        target: BuildingID,
+        leave_at: Option<Time>,
+        map: &Map,
+    ) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
+        match self {
+            ParkingSimState::Normal(sim) => {
+                sim.path_to_cheapest_parking_spot(now, start, vehicle, target, leave_at, map)
+            }
+            ParkingSimState::Infinite(_) => None,
+        }
+    }
+
+    /// Like `path_to_free_parking_spot`, but skips spots with a time limit shorter than the
+    /// driver's stay.
+    pub fn path_to_free_parking_spot_for_stay(
+        &self,
+        now: Time,
+        start: LaneID,
+        vehicle: &Vehicle,
+        target: BuildingID,
+        leave_at: Option<Time>,
+        map: &Map,
+    ) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
+        match self {
+            ParkingSimState::Normal(sim) => match sim.pricing {
+                Some(ref pricing) => sim.search_free_spot(start, vehicle, target, map, |spot| {
+                    pricing.allows_stay(now, spot, leave_at, map)
+                }),
+                None => sim.path_to_free_parking_spot(start, vehicle, target, map),
+            },
+            ParkingSimState::Infinite(sim) => {
+                sim.path_to_free_parking_spot(start, vehicle, target, map)
+            }
+        }
+    }
+
+    /// Per block, how many on-street spots are filled or reserved, and how many there are total
+    pub fn onstreet_occupancy_per_block(&self) -> BTreeMap<RoadID, (usize, usize)> {
+        match self {
+            ParkingSimState::Normal(sim) => sim.onstreet_occupancy_per_block(),
+            ParkingSimState::Infinite(_) => BTreeMap::new(),
+        }
+    }
+
+    /// Adjust demand-responsive prices on every block. Returns when to do this next, if prices
+    /// are demand-responsive.
+    pub fn adjust_prices(&mut self, now: Time) -> Option<Duration> {
+        match self {
+            ParkingSimState::Normal(sim) => sim.adjust_prices(now),
+            ParkingSimState::Infinite(_) => None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -147,6 +256,14 @@ pub struct NormalParkingSimState {
     )]
     driving_to_lots: MultiMap<LaneID, ParkingLotID>,
 
+    pricing: Option<ParkingPricing>,
+    /// Demand-responsive changes to the hourly price of on-street parking, per block
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    price_adjustments: BTreeMap<RoadID, f64>,
+
     events: Vec<Event>,
 }
 
@@ -164,6 +281,9 @@ impl NormalParkingSimState {
             num_spots_per_lot: BTreeMap::new(),
             driving_to_lots: MultiMap::new(),
 
+            pricing: None,
+            price_adjustments: BTreeMap::new(),
+
             events: Vec::new(),
         };
         for l in map.all_lanes() {
@@ -211,6 +331,219 @@ impl NormalParkingSimState {
 
         sim
     }
+
+    fn price_adjustment(&self, spot: ParkingSpot) -> f64 {
+        match spot {
+            ParkingSpot::Onstreet(l, _) => {
+                self.price_adjustments.get(&l.road).cloned().unwrap_or(0.0)
+            }
+            ParkingSpot::Offstreet(_, _) | ParkingSpot::Lot(_, _) => 0.0,
+        }
+    }
+
+    fn current_hourly_price(&self, now: Time, r: RoadID) -> Option<f64> {
+        let pricing = self.pricing.as_ref()?;
+        let rate = pricing.blocks.get(&r).or(pricing.onstreet.as_ref())?;
+        let adjustment = self.price_adjustments.get(&r).cloned().unwrap_or(0.0);
+        Some((rate.price_at(now) + adjustment).max(0.0))
+    }
+
+    fn cost(
+        &self,
+        now: Time,
+        spot: ParkingSpot,
+        search: Distance,
+        leave_at: Option<Time>,
+        target: BuildingID,
+        map: &Map,
+    ) -> Option<f64> {
+        self.pricing.as_ref()?.cost(
+            now,
+            spot,
+            self.spot_to_sidewalk_pos(spot, map),
+            search,
+            leave_at,
+            target,
+            self.price_adjustment(spot),
+            map,
+        )
+    }
+
+    fn path_to_cheapest_parking_spot(
+        &self,
+        now: Time,
+        start: Position,
+        vehicle: &Vehicle,
+        target: BuildingID,
+        leave_at: Option<Time>,
+        map: &Map,
+    ) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
+        let mut backrefs: HashMap<LaneID, TurnID> = HashMap::new();
+        // This is a max-heap, so negate all distances
+        let mut queue: BinaryHeap<(Distance, LaneID)> = BinaryHeap::new();
+        queue.push((Distance::ZERO, start.lane()));
+        // (cost, lane, spot, position)
+        let mut best: Option<(f64, LaneID, ParkingSpot, Position)> = None;
+
+        while let Some((dist_so_far, current)) = queue.pop() {
+            let from = if current == start.lane() {
+                start
+            } else {
+                Position::start(current)
+            };
+            for (spot, pos) in self.get_all_free_spots(from, vehicle, target, map) {
+                let search = pos.dist_along() - from.dist_along() - dist_so_far;
+                if let Some(cost) = self.cost(now, spot, search, leave_at, target, map) {
+                    if best.map(|(c, _, _, _)| cost < c).unwrap_or(true) {
+                        best = Some((cost, current, spot, pos));
+                    }
+                }
+            }
+            for turn in map.get_turns_for(current, PathConstraints::Car) {
+                if let Entry::Vacant(e) = backrefs.entry(turn.id.dst) {
+                    let dist = dist_so_far - turn.geom.length() + from.dist_along()
+                        - map.get_l(current).length();
+                    if -dist <= MAX_PRICED_SEARCH_DIST {
+                        e.insert(turn.id);
+                        queue.push((dist, turn.id.dst));
+                    }
+                }
+            }
+        }
+
+        let (_, lane, spot, pos) = best?;
+        let mut steps = Vec::new();
+        let mut current = lane;
+        while current != start.lane() {
+            let turn = backrefs[&current];
+            steps.push(PathStep::Lane(current));
+            steps.push(PathStep::Turn(turn));
+            current = turn.src;
+        }
+        steps.reverse();
+        Some((steps, spot, pos))
+    }
+
+    /// The search behind `path_to_free_parking_spot`, only considering spots that are `allowed`
+    fn search_free_spot(
+        &self,
+        start: LaneID,
+        vehicle: &Vehicle,
+        target: BuildingID,
+        map: &Map,
+        allowed: impl Fn(ParkingSpot) -> bool,
+    ) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
+        let mut backrefs: HashMap<LaneID, TurnID> = HashMap::new();
+        // Don't travel far.
+        // This is a max-heap, so negate all distances. Tie breaker is lane ID, arbitrary but
+        // deterministic.
+        let mut queue: BinaryHeap<(Distance, LaneID)> = BinaryHeap::new();
+        queue.push((Distance::ZERO, start));
+
+        // We need a source of randomness between different cars, but it needs to be deterministic
+        // across repeated runs of the exact same simulation. This also shouldn't be the same
+        // starting seed for one vehicle across different decisions through the simulation, because
+        // then they might always prefer the first or third turn the most or whatever.
+        let mut rng =
+            XorShiftRng::seed_from_u64((vehicle.id.id + start.encode_u32() as usize) as u64);
+
+        while !queue.is_empty() {
+            let (dist_so_far, current) = queue.pop().unwrap();
+            // If the current lane has a spot open, we wouldn't be asking. This can happen if a spot
+            // opens up on the 'start' lane, but behind the car.
+            if current != start {
+                // Pick the closest to the start of the lane, since that's closest to where we came
+                // from
+                if let Some((spot, pos)) = self
+                    .get_all_free_spots(Position::start(current), vehicle, target, map)
+                    .into_iter()
+                    .filter(|(spot, _)| allowed(*spot))
+                    .min_by_key(|(_, pos)| pos.dist_along())
+                {
+                    let mut steps = vec![PathStep::Lane(current)];
+                    let mut current = current;
+                    loop {
+                        if current == start {
+                            // Don't include PathStep::Lane(start)
+                            steps.pop();
+                            steps.reverse();
+                            return Some((steps, spot, pos));
+                        }
+                        let turn = backrefs[&current];
+                        steps.push(PathStep::Turn(turn));
+                        steps.push(PathStep::Lane(turn.src));
+                        current = turn.src;
+                    }
+                }
+            }
+            for turn in map.get_turns_for(current, PathConstraints::Car) {
+                if let Entry::Vacant(e) = backrefs.entry(turn.id.dst) {
+                    let dist_this_step = turn.geom.length() + map.get_l(current).length();
+                    // When vehicles search away from the first lane for a spot, don't all go in
+                    // the same direction! Do this by jittering which turn they explore.
+                    // At worst, they consider a route to be 10% of its true length, so somebody
+                    // might go up to 10x farther than necessary. From some quick tests, these
+                    // worst cases aren't happening -- because it'd be unlikely to roll a higher
+                    // number here many times in a row, and if there are only a few lanes away, it
+                    // doesn't matter that much anyway.
+                    let jitter = rng.gen_range(0.1..0.9);
+                    e.insert(turn.id);
+                    // Remember, keep things negative
+                    queue.push((dist_so_far - jitter * dist_this_step, turn.id.dst));
+                }
+            }
+        }
+
+        None
+    }
+
+    fn onstreet_occupancy_per_block(&self) -> BTreeMap<RoadID, (usize, usize)> {
+        let mut per_block = BTreeMap::new();
+        for lane in self.onstreet_lanes.values() {
+            let spots = lane.spots();
+            let filled = spots.iter().filter(|spot| !self.is_free(**spot)).count();
+            let entry = per_block.entry(lane.parking_lane.road).or_insert((0, 0));
+            entry.0 += filled;
+            entry.1 += spots.len();
+        }
+        per_block
+    }
+
+    fn adjust_prices(&mut self, now: Time) -> Option<Duration> {
+        let pricing = self.pricing.as_ref()?;
+        let demand = pricing.demand_responsive.as_ref()?;
+        let mut changes = Vec::new();
+        for (r, (filled, total)) in self.onstreet_occupancy_per_block() {
+            let base = match pricing.blocks.get(&r).or(pricing.onstreet.as_ref()) {
+                Some(rate) => rate.price_at(now),
+                None => continue,
+            };
+            if total == 0 {
+                continue;
+            }
+            let occupancy = (filled as f64) / (total as f64);
+            let old = self.price_adjustments.get(&r).cloned().unwrap_or(0.0);
+            let new = if occupancy > demand.max_occupancy {
+                old + demand.step
+            } else if occupancy < demand.min_occupancy {
+                old - demand.step
+            } else {
+                continue;
+            };
+            // Keep the price itself between free and the maximum
+            let new = new.max(-base).min(demand.max_price - base);
+            if new != old {
+                changes.push((r, new, (base + new).max(0.0)));
+            }
+        }
+        let interval = demand.interval;
+
+        for (r, adjustment, price) in changes {
+            self.price_adjustments.insert(r, adjustment);
+            self.events.push(Event::ParkingPriceChanged(r, price));
+        }
+        Some(interval)
+    }
 }
 
 impl ParkingSim for NormalParkingSimState {
@@ -565,67 +898,7 @@ impl ParkingSim for NormalParkingSimState {
         target: BuildingID,
         map: &Map,
     ) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
-        let mut backrefs: HashMap<LaneID, TurnID> = HashMap::new();
-        // Don't travel far.
-        // This is a max-heap, so negate all distances. Tie breaker is lane ID, arbitrary but
-        // deterministic.
-        let mut queue: BinaryHeap<(Distance, LaneID)> = BinaryHeap::new();
-        queue.push((Distance::ZERO, start));
-
-        // We need a source of randomness between different cars, but it needs to be deterministic
-        // across repeated runs of the exact same simulation. This also shouldn't be the same
-        // starting seed for one vehicle across different decisions through the simulation, because
-        // then they might always prefer the first or third turn the most or whatever.
-        let mut rng =
-            XorShiftRng::seed_from_u64((vehicle.id.id + start.encode_u32() as usize) as u64);
-
-        while !queue.is_empty() {
-            let (dist_so_far, current) = queue.pop().unwrap();
-            // If the current lane has a spot open, we wouldn't be asking. This can happen if a spot
-            // opens up on the 'start' lane, but behind the car.
-            if current != start {
-                // Pick the closest to the start of the lane, since that's closest to where we came
-                // from
-                if let Some((spot, pos)) = self
-                    .get_all_free_spots(Position::start(current), vehicle, target, map)
-                    .into_iter()
-                    .min_by_key(|(_, pos)| pos.dist_along())
-                {
-                    let mut steps = vec![PathStep::Lane(current)];
-                    let mut current = current;
-                    loop {
-                        if current == start {
-                            // Don't include PathStep::Lane(start)
-                            steps.pop();
-                            steps.reverse();
-                            return Some((steps, spot, pos));
-                        }
-                        let turn = backrefs[&current];
-                        steps.push(PathStep::Turn(turn));
-                        steps.push(PathStep::Lane(turn.src));
-                        current = turn.src;
-                    }
-                }
-            }
-            for turn in map.get_turns_for(current, PathConstraints::Car) {
-                if let Entry::Vacant(e) = backrefs.entry(turn.id.dst) {
-                    let dist_this_step = turn.geom.length() + map.get_l(current).length();
-                    // When vehicles search away from the first lane for a spot, don't all go in
-                    // the same direction! Do this by jittering which turn they explore.
-                    // At worst, they consider a route to be 10% of its true length, so somebody
-                    // might go up to 10x farther than necessary. From some quick tests, these
-                    // worst cases aren't happening -- because it'd be unlikely to roll a higher
-                    // number here many times in a row, and if there are only a few lanes away, it
-                    // doesn't matter that much anyway.
-                    let jitter = rng.gen_range(0.1..0.9);
-                    e.insert(turn.id);
-                    // Remember, keep things negative
-                    queue.push((dist_so_far - jitter * dist_this_step, turn.id.dst));
-                }
-            }
-        }
-
-        None
+        self.search_free_spot(start, vehicle, target, map, |_| true)
     }
 
     fn collect_events(&mut self) -> Vec<Event> {
@@ -14,16 +14,31 @@ use crate::{
     pedestrian_body_radius, AgentID, AgentProperties, Command, CommutersVehiclesCounts,
     CreatePedestrian, DistanceInterval, DrawPedCrowdInput, DrawPedestrianInput, Event, Intent,
     IntersectionSimState, ParkedCar, ParkingSpot, PedCrowdLocation, PedestrianID, PersonID,
-    Problem, Scheduler, SidewalkPOI, SidewalkSpot, TimeInterval, TransitSimState, TripID,
-    TripManager, UnzoomedAgent,
+    Problem, RideHailSimState, Scheduler, SidewalkPOI, SidewalkSpot, SimOptions, TimeInterval,
+    TransitSimState, TripID, TripManager, UnzoomedAgent,
 };
 
 const TIME_TO_START_BIKING: Duration = Duration::const_seconds(30.0);
 const TIME_TO_FINISH_BIKING: Duration = Duration::const_seconds(45.0);
 
+// Based on eyeballing images from
+// https://www.gkstill.com/Support/crowd-density/CrowdDensity-1.html, use a fixed threshold of 1.5
+// people per square meter as "crowded".
+const CROWDED_DENSITY: f64 = 1.5;
+// Parameters for Weidmann's fundamental diagram, in people per square meter
+const JAM_DENSITY: f64 = 5.4;
+const WEIDMANN_GAMMA: f64 = 1.913;
+const MIN_CROWDED_SPEED_FACTOR: f64 = 0.1;
+// When the next sidewalk or crosswalk is jammed, how often to check again, and how long to wait
+// before pushing through anyway
+const SPILLBACK_RETRY: Duration = Duration::const_seconds(5.0);
+const MAX_SPILLBACK_WAIT: Duration = Duration::const_seconds(120.0);
+
 /// Simulates pedestrians. Unlike vehicles, pedestrians can move bidirectionally on sidewalks and
-/// just "ghost" through each other. There's no queueing or slowdown when many people are
-/// overlapping. They're simply grouped together into a DrawPedCrowdInput for rendering.
+/// just "ghost" through each other. By default, there's no queueing and only a crude slowdown when
+/// many people are overlapping. They're simply grouped together into a DrawPedCrowdInput for
+/// rendering. With `SimOptions::pedestrian_crowding`, walking speed instead depends on the density
+/// of people on each sidewalk and crosswalk, and people wait to enter jammed ones.
 #[derive(Serialize, Deserialize, Clone)]
 pub(crate) struct WalkingSimState {
     peds: FixedMap<PedestrianID, Pedestrian>,
@@ -33,14 +48,16 @@ pub(crate) struct WalkingSimState {
     )]
     peds_per_traversable: MultiMap<Traversable, PedestrianID>,
     events: Vec<Event>,
+    crowding: bool,
 }
 
 impl WalkingSimState {
-    pub fn new() -> WalkingSimState {
+    pub fn new(opts: &SimOptions) -> WalkingSimState {
         WalkingSimState {
             peds: FixedMap::new(),
             peds_per_traversable: MultiMap::new(),
             events: Vec::new(),
+            crowding: opts.pedestrian_crowding,
         }
     }
 
@@ -99,6 +116,7 @@ impl WalkingSimState {
                 params.start.sidewalk_pos.dist_along(),
                 now,
                 map,
+                self.crowding,
                 &mut self.events,
             ),
         };
@@ -134,6 +152,7 @@ impl WalkingSimState {
         ctx: &mut Ctx,
         trips: &mut TripManager,
         transit: &mut TransitSimState,
+        ride_hail: &mut RideHailSimState,
     ) {
         let mut ped = self.peds.get_mut(&id).unwrap();
         match ped.state {
@@ -217,6 +236,17 @@ impl WalkingSimState {
                             ctx.scheduler
                                 .push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
                         }
+                        SidewalkPOI::Curb(_) => {
+                            trips.ped_reached_curb(
+                                now,
+                                ped.id,
+                                ped.total_blocked_time,
+                                ped.path.total_length(),
+                                ctx,
+                                ride_hail,
+                            );
+                            ped.state = PedState::WaitingForRide(now);
+                        }
                         SidewalkPOI::SuddenlyAppear => unreachable!(),
                         SidewalkPOI::DeferredParkingSpot => unreachable!(),
                     }
@@ -239,13 +269,15 @@ impl WalkingSimState {
                         ctx.map,
                         ctx.intersections,
                         &mut self.peds_per_traversable,
+                        self.crowding,
                         &mut self.events,
                         ctx.scheduler,
                     ) {
                         ctx.scheduler
                             .push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
                     } else {
-                        // Must've failed because we can't turn yet. Don't schedule a retry here.
+                        // Must've failed because we can't turn yet or the next step is jammed.
+                        // Either way, a retry has already been scheduled.
                         ped.state = PedState::WaitingToTurn(dist, now);
                     }
                 }
@@ -256,18 +288,24 @@ impl WalkingSimState {
                     ctx.map,
                     ctx.intersections,
                     &mut self.peds_per_traversable,
+                    self.crowding,
                     &mut self.events,
                     ctx.scheduler,
                 ) {
                     ctx.scheduler
                         .push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
                     ped.total_blocked_time += now - blocked_since;
-                    self.events.push(Event::IntersectionDelayMeasured(
-                        ped.trip,
-                        ped.path.current_step().as_turn(),
-                        AgentID::Pedestrian(id),
-                        now - blocked_since,
-                    ));
+                    // With crowding, people also wait to leave a crosswalk for a jammed sidewalk.
+                    // That's not a delay at the intersection.
+                    if let PathStep::Turn(t) | PathStep::ContraflowTurn(t) = ped.path.current_step()
+                    {
+                        self.events.push(Event::IntersectionDelayMeasured(
+                            ped.trip,
+                            t,
+                            AgentID::Pedestrian(id),
+                            now - blocked_since,
+                        ));
+                    }
                 }
             }
             PedState::LeavingBuilding(b, _) => {
@@ -276,6 +314,7 @@ impl WalkingSimState {
                     ctx.map.get_b(b).sidewalk_pos.dist_along(),
                     now,
                     ctx.map,
+                    self.crowding,
                     &mut self.events,
                 );
                 ctx.scheduler
@@ -300,6 +339,7 @@ impl WalkingSimState {
                     ctx.map.get_pl(pl).sidewalk_pos.dist_along(),
                     now,
                     ctx.map,
+                    self.crowding,
                     &mut self.events,
                 );
                 ctx.scheduler
@@ -340,19 +380,28 @@ impl WalkingSimState {
                     spot.sidewalk_pos.dist_along(),
                     now,
                     ctx.map,
+                    self.crowding,
                     &mut self.events,
                 );
                 ctx.scheduler
                     .push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
             }
+            PedState::WaitingForRide(_) => {
+                // Nobody was dispatched to pick them up in time
+                ride_hail.request_timed_out(now, id, trips, ctx);
+                self.peds_per_traversable
+                    .remove(ped.path.current_step().as_traversable(), id);
+                self.peds.remove(&id);
+            }
             PedState::WaitingForBus(_, _) => unreachable!(),
         }
     }
 
-    pub fn ped_boarded_bus(&mut self, now: Time, id: PedestrianID) {
+    /// The pedestrian got on a bus, train, or ride-hail vehicle.
+    pub fn ped_boarded_vehicle(&mut self, now: Time, id: PedestrianID) {
         let mut ped = self.peds.remove(&id).unwrap();
         match ped.state {
-            PedState::WaitingForBus(_, blocked_since) => {
+            PedState::WaitingForBus(_, blocked_since) | PedState::WaitingForRide(blocked_since) => {
                 self.peds_per_traversable
                     .remove(ped.path.current_step().as_traversable(), id);
                 ped.total_blocked_time += now - blocked_since;
@@ -413,7 +462,8 @@ impl WalkingSimState {
             | PedState::EnteringBuilding(_, _)
             | PedState::EnteringParkingLot(_, _)
             | PedState::StartingToBike(_, _, _)
-            | PedState::WaitingForBus(_, _) => {
+            | PedState::WaitingForBus(_, _)
+            | PedState::WaitingForRide(_) => {
                 p.path.dist_crossed_from_step(map, &p.path.current_step())
             }
         };
@@ -508,7 +558,8 @@ impl WalkingSimState {
                 }
                 PedState::StartingToBike(_, _, _)
                 | PedState::FinishingBiking(_, _, _)
-                | PedState::WaitingForBus(_, _) => {
+                | PedState::WaitingForBus(_, _)
+                | PedState::WaitingForRide(_) => {
                     // The backwards half of the sidewalk is closer to the road.
                     backwards.push((*id, dist));
                 }
@@ -599,7 +650,9 @@ impl WalkingSimState {
     pub fn populate_commuter_counts(&self, cnts: &mut CommutersVehiclesCounts) {
         for p in self.peds.values() {
             match p.goal.connection {
-                SidewalkPOI::ParkingSpot(_) | SidewalkPOI::DeferredParkingSpot => {
+                SidewalkPOI::ParkingSpot(_)
+                | SidewalkPOI::DeferredParkingSpot
+                | SidewalkPOI::Curb(_) => {
                     cnts.walking_to_from_car += 1;
                 }
                 SidewalkPOI::TransitStop(_) => {
@@ -609,7 +662,9 @@ impl WalkingSimState {
                     cnts.walking_to_from_bike += 1;
                 }
                 _ => match p.start.connection {
-                    SidewalkPOI::ParkingSpot(_) | SidewalkPOI::DeferredParkingSpot => {
+                    SidewalkPOI::ParkingSpot(_)
+                    | SidewalkPOI::DeferredParkingSpot
+                    | SidewalkPOI::Curb(_) => {
                         cnts.walking_to_from_car += 1;
                     }
                     SidewalkPOI::TransitStop(_) => {
@@ -679,6 +734,7 @@ impl Pedestrian {
         start_dist: Distance,
         start_time: Time,
         map: &Map,
+        crowding: bool,
         events: &mut Vec<Event>,
     ) -> PedState {
         let end_dist = if self.path.is_last_step() {
@@ -693,12 +749,18 @@ impl Pedestrian {
             }
         };
 
-        let speed_penalty = crowdedness_penalty(
+        let traversable = self.path.current_step().as_traversable();
+        let density = pedestrian_density(
             map,
-            self.path.current_step().as_traversable(),
-            peds_per_traversable,
+            traversable,
+            peds_per_traversable.get(traversable).len(),
         );
-        if speed_penalty != 1.0 {
+        let speed_penalty = if crowding {
+            fundamental_diagram(density)
+        } else {
+            crowdedness_penalty(density)
+        };
+        if density >= CROWDED_DENSITY {
             events.push(Event::ProblemEncountered(
                 self.trip,
                 Problem::PedestrianOvercrowding(self.path.current_step().as_traversable()),
@@ -738,7 +800,9 @@ impl Pedestrian {
             }
             PedState::StartingToBike(ref spot, _, _) => spot.sidewalk_pos.dist_along(),
             PedState::FinishingBiking(ref spot, _, _) => spot.sidewalk_pos.dist_along(),
-            PedState::WaitingForBus(_, _) => self.goal.sidewalk_pos.dist_along(),
+            PedState::WaitingForBus(_, _) | PedState::WaitingForRide(_) => {
+                self.goal.sidewalk_pos.dist_along()
+            }
         }
     }
 
@@ -843,9 +907,9 @@ impl Pedestrian {
                     .unwrap_or_else(|_| line.pt1()),
                 line.angle(),
             ),
-            PedState::WaitingForBus(_, _) => {
+            PedState::WaitingForBus(_, _) | PedState::WaitingForRide(_) => {
                 let (pt, angle) = self.goal.sidewalk_pos.pt_and_angle(map);
-                // Stand on the far side of the sidewalk (by the bus stop), facing the road
+                // Stand on the far side of the sidewalk (by the bus stop or curb), facing the road
                 (
                     pt.project_away(project_away, angle.rotate_degs(angle_offset)),
                     angle.rotate_degs(-angle_offset),
@@ -857,8 +921,11 @@ impl Pedestrian {
             id: self.id,
             pos,
             facing,
-            waiting_for_turn: match self.state {
-                PedState::WaitingToTurn(_, _) => Some(self.path.next_step().as_turn()),
+            waiting_for_turn: match (&self.state, self.path.next_step()) {
+                (
+                    PedState::WaitingToTurn(_, _),
+                    PathStep::Turn(t) | PathStep::ContraflowTurn(t),
+                ) => Some(t),
                 _ => None,
             },
             intent,
@@ -866,7 +933,10 @@ impl Pedestrian {
                 self.state,
                 PedState::StartingToBike(_, _, _) | PedState::FinishingBiking(_, _, _)
             ),
-            waiting_for_bus: matches!(self.state, PedState::WaitingForBus(_, _)),
+            waiting_for_bus: matches!(
+                self.state,
+                PedState::WaitingForBus(_, _) | PedState::WaitingForRide(_)
+            ),
             on,
             person: self.person,
         }
@@ -879,9 +949,28 @@ impl Pedestrian {
         map: &Map,
         intersections: &mut IntersectionSimState,
         peds_per_traversable: &mut MultiMap<Traversable, PedestrianID>,
+        crowding: bool,
         events: &mut Vec<Event>,
         scheduler: &mut Scheduler,
     ) -> bool {
+        // Don't squeeze onto a jammed sidewalk or crosswalk, unless we've been waiting a while.
+        // Without the escape hatch, two jammed sidewalks feeding each other would gridlock.
+        if crowding {
+            let next = self.path.next_step().as_traversable();
+            let density = pedestrian_density(map, next, peds_per_traversable.get(next).len() + 1);
+            let waited_long = match self.state {
+                PedState::WaitingToTurn(_, blocked_since) => {
+                    now - blocked_since >= MAX_SPILLBACK_WAIT
+                }
+                _ => false,
+            };
+            if density >= JAM_DENSITY && !waited_long {
+                // The intersection may also wake us up, so use update to avoid a duplicate
+                scheduler.update(now + SPILLBACK_RETRY, Command::UpdatePed(self.id));
+                return false;
+            }
+        }
+
         if let PathStep::Turn(t) | PathStep::ContraflowTurn(t) = self.path.next_step() {
             if !intersections.maybe_start_turn(
                 AgentID::Pedestrian(self.id),
@@ -908,7 +997,8 @@ impl Pedestrian {
             PathStep::Turn(_) => Distance::ZERO,
             PathStep::ContraflowTurn(t) => map.get_t(t).geom.length(),
         };
-        self.state = self.crossing_state(peds_per_traversable, start_dist, now, map, events);
+        self.state =
+            self.crossing_state(peds_per_traversable, start_dist, now, map, crowding, events);
         peds_per_traversable.insert(self.path.current_step().as_traversable(), self.id);
         events.push(Event::AgentEntersTraversable(
             AgentID::Pedestrian(self.id),
@@ -928,6 +1018,8 @@ enum PedState {
         steep_uphill: bool,
     },
     /// The Distance is either 0 or the current traversable's length. The Time is blocked_since.
+    /// Usually the next step is a turn that the intersection hasn't allowed yet, but with crowding,
+    /// this is also used to wait at the end of a crosswalk for a jammed sidewalk.
     WaitingToTurn(Distance, Time),
     LeavingBuilding(BuildingID, TimeInterval),
     EnteringBuilding(BuildingID, TimeInterval),
@@ -936,6 +1028,8 @@ enum PedState {
     StartingToBike(SidewalkSpot, Line, TimeInterval),
     FinishingBiking(SidewalkSpot, Line, TimeInterval),
     WaitingForBus(TransitRouteID, Time),
+    /// Waiting at the curb for a ride-hail vehicle since this time
+    WaitingForRide(Time),
 }
 
 impl PedState {
@@ -949,14 +1043,15 @@ impl PedState {
             PedState::EnteringParkingLot(_, ref time_int) => time_int.end,
             PedState::StartingToBike(_, _, ref time_int) => time_int.end,
             PedState::FinishingBiking(_, _, ref time_int) => time_int.end,
-            PedState::WaitingForBus(_, _) => unreachable!(),
+            PedState::WaitingForBus(_, _) | PedState::WaitingForRide(_) => unreachable!(),
         }
     }
 
     fn time_spent_waiting(&self, now: Time) -> Duration {
         match self {
             PedState::WaitingToTurn(_, blocked_since)
-            | PedState::WaitingForBus(_, blocked_since) => now - *blocked_since,
+            | PedState::WaitingForBus(_, blocked_since)
+            | PedState::WaitingForRide(blocked_since) => now - *blocked_since,
             _ => Duration::ZERO,
         }
     }
@@ -1019,24 +1114,32 @@ impl IndexableKey for PedestrianID {
 /// passing people going the opposite direction. But start simple -- keep a fixed speed for the
 /// entire time on a sidewalk, and base the decision on how many people are there when entering the
 /// sidewalk.
-fn crowdedness_penalty(
-    map: &Map,
-    traversable: Traversable,
-    peds_per_traversable: &MultiMap<Traversable, PedestrianID>,
-) -> f64 {
-    let num_people = peds_per_traversable.get(traversable).len();
-    // Assume everyone's equally spread out
-    let people_per_sq_m = (num_people as f64) / area(map, traversable);
-    // Based on eyeballing images from
-    // https://www.gkstill.com/Support/crowd-density/CrowdDensity-1.html, let's use a fixed
-    // threshold of 1.5 people per square meter as "crowded" and slow them down by half.
-    if people_per_sq_m < 1.5 {
+fn crowdedness_penalty(people_per_sq_m: f64) -> f64 {
+    if people_per_sq_m < CROWDED_DENSITY {
         // Plenty of room, no penalty
         return 1.0;
     }
+    // Otherwise slow them down by half
     0.5
 }
 
+/// Like `crowdedness_penalty`, but using Weidmann's fundamental diagram for pedestrians, so speed
+/// smoothly drops as density increases, approaching zero at jam density. The speed is still fixed
+/// for the entire time on a sidewalk or crosswalk.
+fn fundamental_diagram(people_per_sq_m: f64) -> f64 {
+    if people_per_sq_m <= 0.0 {
+        return 1.0;
+    }
+    let factor = 1.0 - (-WEIDMANN_GAMMA * (1.0 / people_per_sq_m - 1.0 / JAM_DENSITY)).exp();
+    // Don't let anybody stop entirely; spillback handles jams.
+    factor.clamp(MIN_CROWDED_SPEED_FACTOR, 1.0)
+}
+
+/// People per square meter on a sidewalk or crosswalk, assuming everyone's equally spread out.
+fn pedestrian_density(map: &Map, traversable: Traversable, num_people: usize) -> f64 {
+    (num_people as f64) / area(map, traversable)
+}
+
 // In m^2
 fn area(map: &Map, traversable: Traversable) -> f64 {
     // The length of the sidewalk or crosswalk
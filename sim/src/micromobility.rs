@@ -0,0 +1,244 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Speed};
+use map_model::{BuildingID, Map, MAX_BIKE_SPEED};
+use synthpop::{MicromobilityFleet, MicromobilityVehicle};
+
+use crate::{CarID, TripID, Vehicle, VehicleSpec, VehicleType, BIKE_LENGTH};
+
+/// Nobody walks further than this, as the crow flies, to pick up or after leaving a shared
+/// vehicle.
+const MAX_WALKING_DIST: Distance = Distance::const_meters(400.0);
+const SCOOTER_LENGTH: Distance = Distance::const_meters(1.2);
+// 15 mph, the usual cap for shared scooters
+const MAX_SCOOTER_SPEED: Speed = Speed::const_meters_per_second(6.7056);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SharedVehicle {
+    vehicle: Vehicle,
+    /// Index into the fleets
+    fleet: usize,
+}
+
+/// Somebody is using a shared vehicle for one trip.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Rental {
+    vehicle: CarID,
+    /// A station with a dock reserved for this vehicle, or the destination for dockless fleets
+    dropoff: BuildingID,
+}
+
+/// Tracks where every shared scooter and bike is through the day. Shared vehicles are ridden like
+/// bikes, but nobody owns them. When a micromobility trip starts, the closest available vehicle in
+/// walking distance is rented. Docked fleets reserve a free dock near the destination at the same
+/// time. If no vehicle or dock is available, the person walks instead, so where vehicles wind up
+/// over the day -- and how they're rebalanced -- matters.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct MicromobilityState {
+    fleets: Vec<MicromobilityFleet>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    vehicles: BTreeMap<CarID, SharedVehicle>,
+    /// Vehicles waiting to be picked up, per location. For dockless fleets, this includes
+    /// buildings that aren't stations.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    parked: BTreeMap<BuildingID, Vec<CarID>>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    rentals: BTreeMap<TripID, Rental>,
+}
+
+impl MicromobilityState {
+    pub fn new() -> MicromobilityState {
+        MicromobilityState {
+            fleets: Vec::new(),
+            vehicles: BTreeMap::new(),
+            parked: BTreeMap::new(),
+            rentals: BTreeMap::new(),
+        }
+    }
+
+    /// Place vehicles at their starting stations. There must be one ID per initial vehicle.
+    pub fn add_fleet(&mut self, fleet: MicromobilityFleet, ids: Vec<usize>) {
+        let idx = self.fleets.len();
+        let spec = match fleet.vehicle {
+            MicromobilityVehicle::Scooter => VehicleSpec {
+                vehicle_type: VehicleType::Bike,
+                length: SCOOTER_LENGTH,
+                max_speed: Some(MAX_SCOOTER_SPEED),
+            },
+            MicromobilityVehicle::Bike => VehicleSpec {
+                vehicle_type: VehicleType::Bike,
+                length: BIKE_LENGTH,
+                max_speed: Some(MAX_BIKE_SPEED),
+            },
+        };
+        let mut ids = ids.into_iter();
+        for station in &fleet.stations {
+            for _ in 0..station.initial_vehicles {
+                let vehicle = spec.clone().make(
+                    CarID {
+                        id: ids.next().unwrap(),
+                        vehicle_type: VehicleType::Bike,
+                    },
+                    None,
+                );
+                self.parked
+                    .entry(station.building)
+                    .or_insert_with(Vec::new)
+                    .push(vehicle.id);
+                self.vehicles.insert(
+                    vehicle.id,
+                    SharedVehicle {
+                        vehicle,
+                        fleet: idx,
+                    },
+                );
+            }
+        }
+        assert!(ids.next().is_none());
+        self.fleets.push(fleet);
+    }
+
+    /// Rent the closest vehicle to the start that can be left near the destination. Returns the
+    /// vehicle, where to pick it up, and where to leave it.
+    pub fn start_rental(
+        &mut self,
+        trip: TripID,
+        start: BuildingID,
+        end: BuildingID,
+        map: &Map,
+    ) -> Option<(CarID, BuildingID, BuildingID)> {
+        let start_pt = map.get_b(start).polygon.center();
+        let mut pickups: Vec<(Distance, BuildingID)> = self
+            .parked
+            .iter()
+            .filter(|(_, vehicles)| !vehicles.is_empty())
+            .map(|(b, _)| (map.get_b(*b).polygon.center().dist_to(start_pt), *b))
+            .filter(|(dist, _)| *dist <= MAX_WALKING_DIST)
+            .collect();
+        pickups.sort();
+
+        for (_, pickup) in pickups {
+            for idx in 0..self.parked[&pickup].len() {
+                let id = self.parked[&pickup][idx];
+                let dropoff = match self.find_dropoff(self.vehicles[&id].fleet, pickup, end, map) {
+                    Some(b) => b,
+                    None => continue,
+                };
+                self.parked.get_mut(&pickup).unwrap().remove(idx);
+                self.rentals.insert(
+                    trip,
+                    Rental {
+                        vehicle: id,
+                        dropoff,
+                    },
+                );
+                return Some((id, pickup, dropoff));
+            }
+        }
+        None
+    }
+
+    /// If the trip rented a vehicle, leave it at the dropoff. Cancelled trips warp the vehicle
+    /// there, as if the trip had finished. Returns the dropoff.
+    pub fn end_rental(&mut self, trip: TripID) -> Option<BuildingID> {
+        let rental = self.rentals.remove(&trip)?;
+        self.parked
+            .entry(rental.dropoff)
+            .or_insert_with(Vec::new)
+            .push(rental.vehicle);
+        Some(rental.dropoff)
+    }
+
+    pub fn get_vehicle(&self, id: CarID) -> Option<Vehicle> {
+        self.vehicles.get(&id).map(|v| v.vehicle.clone())
+    }
+
+    pub fn num_available(&self, b: BuildingID) -> usize {
+        self.parked.get(&b).map(|v| v.len()).unwrap_or(0)
+    }
+
+    /// How many vehicles are available at every station and dockless location
+    pub fn all_available(&self) -> BTreeMap<BuildingID, usize> {
+        self.parked
+            .iter()
+            .map(|(b, vehicles)| (*b, vehicles.len()))
+            .collect()
+    }
+
+    pub fn get_fleets(&self) -> &Vec<MicromobilityFleet> {
+        &self.fleets
+    }
+
+    fn find_dropoff(
+        &self,
+        fleet: usize,
+        pickup: BuildingID,
+        end: BuildingID,
+        map: &Map,
+    ) -> Option<BuildingID> {
+        if self.fleets[fleet].dockless {
+            return Some(end).filter(|b| worth_riding(pickup, *b, map));
+        }
+
+        let end_pt = map.get_b(end).polygon.center();
+        let mut stations: Vec<(Distance, BuildingID, usize)> = self.fleets[fleet]
+            .stations
+            .iter()
+            .map(|s| {
+                (
+                    map.get_b(s.building).polygon.center().dist_to(end_pt),
+                    s.building,
+                    s.capacity,
+                )
+            })
+            .filter(|(dist, _, _)| *dist <= MAX_WALKING_DIST)
+            .collect();
+        stations.sort();
+        stations
+            .into_iter()
+            .find(|(_, b, capacity)| {
+                // Vehicles on their way to a dock have already claimed it
+                let occupied = self
+                    .parked
+                    .get(b)
+                    .into_iter()
+                    .flatten()
+                    .chain(
+                        self.rentals
+                            .values()
+                            .filter(|r| r.dropoff == *b)
+                            .map(|r| &r.vehicle),
+                    )
+                    .filter(|id| self.vehicles[id].fleet == fleet)
+                    .count();
+                occupied < *capacity && worth_riding(pickup, *b, map)
+            })
+            .map(|(_, b, _)| b)
+    }
+}
+
+/// Riding between two buildings on the same sidewalk isn't worth it, and both buildings must be
+/// connected to somewhere bikes can go.
+fn worth_riding(pickup: BuildingID, dropoff: BuildingID, map: &Map) -> bool {
+    match (
+        map.get_b(pickup).biking_connection(map),
+        map.get_b(dropoff).biking_connection(map),
+    ) {
+        (Some((bike1, sidewalk1)), Some((bike2, sidewalk2))) => {
+            bike1.lane() != bike2.lane() && sidewalk1.lane() != sidewalk2.lane()
+        }
+        _ => false,
+    }
+}
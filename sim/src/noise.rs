@@ -0,0 +1,224 @@
+//! Rough estimates of road traffic noise, derived from the number and type of vehicles passing
+//! along each road every hour. This follows the general shape of standard road traffic noise
+//! methods -- an emission level per passing vehicle depending on its type and speed, summed over
+//! an hour, then attenuated by distance from the road -- but ignores screening by buildings,
+//! ground absorption, and road surfaces. Levels are A-weighted decibels.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use geom::{Distance, FindClosest, Speed};
+use map_model::{Building, BuildingID, BuildingType, Map, RoadID};
+
+use crate::{AgentType, Analytics};
+
+/// Roads further than this from a building don't contribute to its noise level.
+const MAX_DISTANCE: Distance = Distance::const_meters(200.0);
+/// Emission levels are defined at this distance from the road's center.
+const REFERENCE_DISTANCE: Distance = Distance::const_meters(10.0);
+/// Even without any traffic, a building isn't totally silent.
+const BACKGROUND_LEVEL: f64 = 35.0;
+/// Residents exposed to more than this day-evening-night level are reported, matching the
+/// reporting threshold of the EU Environmental Noise Directive.
+pub const LDEN_THRESHOLD: f64 = 55.0;
+/// Likewise for the night-time level.
+pub const LNIGHT_THRESHOLD: f64 = 50.0;
+
+/// Noise levels along roads and at buildings.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NoiseEstimate {
+    /// For each road with any motorized traffic, the equivalent continuous level for each hour
+    /// since midnight (index 0 to 23), measured 10m from the road's center. Hours without
+    /// traffic are `None`.
+    pub roads: BTreeMap<RoadID, Vec<Option<f64>>>,
+    /// Levels at every building near a road with traffic
+    pub buildings: BTreeMap<BuildingID, BuildingNoise>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BuildingNoise {
+    /// The equivalent continuous level for each hour since midnight
+    pub hourly: Vec<f64>,
+    /// The day-evening-night level, with 5dB and 10dB penalties for evening (19:00 to 23:00) and
+    /// night (23:00 to 07:00) hours
+    pub lden: f64,
+    /// The night-time level, from 23:00 to 07:00
+    pub lnight: f64,
+    pub residents: usize,
+}
+
+/// How many residents are exposed to noise?
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NoiseExposure {
+    pub residents: usize,
+    pub residents_above_lden_threshold: usize,
+    pub residents_above_lnight_threshold: usize,
+    /// Average over all residents of the Lden at their home
+    pub mean_lden_per_resident: f64,
+}
+
+impl NoiseEstimate {
+    /// Estimates noise from all the traffic recorded so far. Vehicles are assumed to travel at the
+    /// speed limit. Traffic after midnight wraps around to the same hours of the day.
+    pub fn new(map: &Map, analytics: &Analytics) -> NoiseEstimate {
+        // For every road, hour, and kind of vehicle, sum the energy of each passing vehicle
+        let mut energy_per_road: BTreeMap<RoadID, Vec<f64>> = BTreeMap::new();
+        for ((r, agent_type, hour), count) in &analytics.road_thruput.counts {
+            if let Some(level) = pass_by_level(*agent_type, map.get_r(*r).speed_limit) {
+                energy_per_road.entry(*r).or_insert_with(|| vec![0.0; 24])[hour % 24] +=
+                    (*count as f64) * to_energy(level);
+            }
+        }
+        let roads: BTreeMap<RoadID, Vec<Option<f64>>> = energy_per_road
+            .into_iter()
+            .map(|(r, hourly)| {
+                let levels = hourly
+                    .into_iter()
+                    .map(|energy| {
+                        if energy > 0.0 {
+                            // Spread the exposure from all vehicles over the hour
+                            Some(to_level(energy / 3600.0))
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+                (r, levels)
+            })
+            .collect();
+
+        let mut closest = FindClosest::new(map.get_bounds());
+        for r in roads.keys() {
+            closest.add(*r, map.get_r(*r).center_pts.points());
+        }
+
+        let mut buildings = BTreeMap::new();
+        for b in map.all_buildings() {
+            let nearby = closest.all_close_pts(b.polygon.center(), MAX_DISTANCE);
+            if nearby.is_empty() {
+                continue;
+            }
+            let mut energy = vec![to_energy(BACKGROUND_LEVEL); 24];
+            for (r, _, dist) in nearby {
+                // Roads are line sources, so the level drops by 3dB every time the distance
+                // doubles
+                let attenuation =
+                    10.0 * (dist.max(REFERENCE_DISTANCE) / REFERENCE_DISTANCE).log10();
+                for (hour, level) in roads[&r].iter().enumerate() {
+                    if let Some(level) = level {
+                        energy[hour] += to_energy(level - attenuation);
+                    }
+                }
+            }
+            let hourly: Vec<f64> = energy.into_iter().map(to_level).collect();
+            buildings.insert(
+                b.id,
+                BuildingNoise {
+                    lden: lden(&hourly),
+                    lnight: lnight(&hourly),
+                    hourly,
+                    residents: num_residents(b),
+                },
+            );
+        }
+
+        NoiseEstimate { roads, buildings }
+    }
+
+    /// Summarizes exposure per resident. Residents of buildings too far from any road with
+    /// traffic are counted at the background level.
+    pub fn exposure(&self, map: &Map) -> NoiseExposure {
+        let mut residents = 0;
+        let mut above_lden = 0;
+        let mut above_lnight = 0;
+        let mut sum_lden = 0.0;
+        for b in map.all_buildings() {
+            let n = num_residents(b);
+            if n == 0 {
+                continue;
+            }
+            residents += n;
+            if let Some(noise) = self.buildings.get(&b.id) {
+                if noise.lden > LDEN_THRESHOLD {
+                    above_lden += n;
+                }
+                if noise.lnight > LNIGHT_THRESHOLD {
+                    above_lnight += n;
+                }
+                sum_lden += (n as f64) * noise.lden;
+            } else {
+                sum_lden += (n as f64) * BACKGROUND_LEVEL;
+            }
+        }
+        NoiseExposure {
+            residents,
+            residents_above_lden_threshold: above_lden,
+            residents_above_lnight_threshold: above_lnight,
+            mean_lden_per_resident: if residents == 0 {
+                0.0
+            } else {
+                sum_lden / (residents as f64)
+            },
+        }
+    }
+}
+
+fn num_residents(b: &Building) -> usize {
+    match b.bldg_type {
+        BuildingType::Residential { num_residents, .. } => num_residents,
+        BuildingType::ResidentialCommercial(residents, _) => residents,
+        BuildingType::Commercial(_) | BuildingType::Empty => 0,
+    }
+}
+
+/// The sound exposure level of one vehicle passing by, measured 10m away. None for agents that
+/// don't make meaningful noise.
+fn pass_by_level(agent_type: AgentType, speed_limit: Speed) -> Option<f64> {
+    // Below this, engine noise dominates and doesn't depend much on speed
+    let kmph = (speed_limit.inner_meters_per_second() * 3.6).max(30.0);
+    let ratio = (kmph / 50.0).log10();
+    match agent_type {
+        AgentType::Car => Some(71.0 + 30.0 * ratio),
+        AgentType::Bus => Some(80.0 + 25.0 * ratio),
+        AgentType::Train => Some(82.0 + 20.0 * ratio),
+        AgentType::Bike | AgentType::Pedestrian | AgentType::TransitRider => None,
+    }
+}
+
+fn to_energy(level: f64) -> f64 {
+    10.0_f64.powf(level / 10.0)
+}
+
+fn to_level(energy: f64) -> f64 {
+    10.0 * energy.log10()
+}
+
+fn lden(hourly: &[f64]) -> f64 {
+    let mut energy = 0.0;
+    for (hour, level) in hourly.iter().enumerate() {
+        let penalty = if is_night(hour) {
+            10.0
+        } else if (19..23).contains(&hour) {
+            5.0
+        } else {
+            0.0
+        };
+        energy += to_energy(level + penalty);
+    }
+    to_level(energy / 24.0)
+}
+
+fn lnight(hourly: &[f64]) -> f64 {
+    let mut energy = 0.0;
+    for (hour, level) in hourly.iter().enumerate() {
+        if is_night(hour) {
+            energy += to_energy(*level);
+        }
+    }
+    to_level(energy / 8.0)
+}
+
+fn is_night(hour: usize) -> bool {
+    hour >= 23 || hour < 7
+}
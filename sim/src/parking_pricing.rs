@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap, Timer};
+use geom::{Distance, Duration, Speed, Time};
+use map_model::{BuildingID, Map, OffstreetParking, ParkingRate, Position, RoadID};
+
+use crate::ParkingSpot;
+
+/// If a driver has no more trips planned, assume they'll stay this long.
+const UNKNOWN_STAY: Duration = Duration::const_seconds(2.0 * 3600.0);
+/// For estimating how long it takes to walk between a spot and the destination
+const WALKING_SPEED: Speed = Speed::const_meters_per_second(1.34);
+/// For estimating how long it takes to drive to a spot farther away. Drivers looking for parking
+/// go slowly.
+const CRUISING_SPEED: Speed = Speed::const_meters_per_second(6.7);
+
+/// Prices and time limits for on-street parking, loaded from a JSON file. Parking lots and public
+/// garages charge the rates attached to them in the map. When parking is priced, drivers don't
+/// just take the free spot closest to their destination. They pick the spot with the lowest
+/// overall cost, adding up the fee for their stay and the value of the time spent driving to the
+/// spot and walking to and from the destination. Private parking is always free.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ParkingPricing {
+    /// How many dollars drivers would pay to save an hour of walking or searching for parking
+    pub value_of_time: f64,
+    /// Applies to all on-street parking, except for blocks listed in `blocks`
+    pub onstreet: Option<ParkingRate>,
+    /// On-street parking along these roads has its own rate. Road IDs only apply to the same map.
+    #[serde(
+        default,
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    pub blocks: BTreeMap<RoadID, ParkingRate>,
+    /// Periodically adjust the price of on-street parking on every block based on how full it is
+    pub demand_responsive: Option<DemandResponsivePricing>,
+}
+
+/// Every interval, raise the price on blocks that are too full and lower it on blocks with plenty
+/// of free spots, steering drivers towards a target occupancy.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DemandResponsivePricing {
+    pub interval: Duration,
+    /// Raise the price on blocks with more than this fraction of spots taken
+    pub max_occupancy: f64,
+    /// Lower the price on blocks with less than this fraction of spots taken
+    pub min_occupancy: f64,
+    /// How many dollars per hour to change the price by each time
+    pub step: f64,
+    /// The price is never raised above this many dollars per hour
+    pub max_price: f64,
+}
+
+impl ParkingPricing {
+    /// Read and validate pricing from a JSON file.
+    pub fn load(path: &str) -> Result<ParkingPricing> {
+        let pricing: ParkingPricing =
+            abstio::maybe_read_json(path.to_string(), &mut Timer::throwaway())?;
+        if pricing.value_of_time < 0.0 {
+            bail!("value_of_time {} can't be negative", pricing.value_of_time);
+        }
+        for rate in pricing.onstreet.iter().chain(pricing.blocks.values()) {
+            rate.validate()?;
+        }
+        if let Some(ref demand) = pricing.demand_responsive {
+            if demand.interval <= Duration::ZERO {
+                bail!("demand_responsive interval must be positive");
+            }
+            if !(0.0 <= demand.min_occupancy
+                && demand.min_occupancy <= demand.max_occupancy
+                && demand.max_occupancy <= 1.0)
+            {
+                bail!(
+                    "demand_responsive needs 0 <= min_occupancy ({}) <= max_occupancy ({}) <= 1",
+                    demand.min_occupancy,
+                    demand.max_occupancy
+                );
+            }
+            if demand.step <= 0.0 || demand.max_price < 0.0 {
+                bail!("demand_responsive step must be positive, and max_price can't be negative");
+            }
+        }
+        Ok(pricing)
+    }
+
+    /// The rate charged for a spot, or None if it's free
+    pub(crate) fn rate(&self, spot: ParkingSpot, map: &Map) -> Option<&ParkingRate> {
+        match spot {
+            ParkingSpot::Onstreet(l, _) => self.blocks.get(&l.road).or(self.onstreet.as_ref()),
+            ParkingSpot::Lot(pl, _) => map.get_pl(pl).rate.as_ref(),
+            ParkingSpot::Offstreet(b, _) => {
+                let b = map.get_b(b);
+                match b.parking {
+                    OffstreetParking::PublicGarage(_, _) => b.garage_rate.as_ref(),
+                    OffstreetParking::Private(_, _) => None,
+                }
+            }
+        }
+    }
+
+    /// The fee for parking in a spot from `now` until `leave_at`, adding `adjustment` dollars to
+    /// every hourly price.
+    pub(crate) fn fee(
+        &self,
+        now: Time,
+        spot: ParkingSpot,
+        leave_at: Option<Time>,
+        adjustment: f64,
+        map: &Map,
+    ) -> f64 {
+        match self.rate(spot, map) {
+            Some(rate) => rate.fee(now, now + expected_stay(now, leave_at), adjustment),
+            None => 0.0,
+        }
+    }
+
+    /// Does the spot's time limit, if any, allow staying from `now` until `leave_at`?
+    pub(crate) fn allows_stay(
+        &self,
+        now: Time,
+        spot: ParkingSpot,
+        leave_at: Option<Time>,
+        map: &Map,
+    ) -> bool {
+        match self.rate(spot, map).and_then(|rate| rate.max_stay) {
+            Some(max_stay) => expected_stay(now, leave_at) <= max_stay,
+            None => true,
+        }
+    }
+
+    /// The overall cost in dollars of parking in a spot, or None if the driver would stay too
+    /// long. `search` is how much farther the driver has to go to reach the spot.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn cost(
+        &self,
+        now: Time,
+        spot: ParkingSpot,
+        sidewalk_pos: Position,
+        search: Distance,
+        leave_at: Option<Time>,
+        target: BuildingID,
+        adjustment: f64,
+        map: &Map,
+    ) -> Option<f64> {
+        if !self.allows_stay(now, spot, leave_at, map) {
+            return None;
+        }
+        let fee = self.fee(now, spot, leave_at, adjustment, map);
+        // Walk there and back, as the crow flies
+        let walk = 2.0
+            * sidewalk_pos
+                .pt(map)
+                .dist_to(map.get_b(target).sidewalk_pos.pt(map));
+        let time = walk / WALKING_SPEED + search / CRUISING_SPEED;
+        Some(fee + self.value_of_time * time.inner_seconds() / 3600.0)
+    }
+}
+
+fn expected_stay(now: Time, leave_at: Option<Time>) -> Duration {
+    match leave_at {
+        Some(t) if t > now => t - now,
+        Some(_) => Duration::ZERO,
+        None => UNKNOWN_STAY,
+    }
+}
+
+/// Which block a parking spot belongs to, for pricing and reporting. Lots and garages belong to
+/// the road their driveway connects to.
+pub(crate) fn parking_block(spot: ParkingSpot, map: &Map) -> RoadID {
+    match spot {
+        ParkingSpot::Onstreet(l, _) => l.road,
+        ParkingSpot::Lot(pl, _) => map.get_pl(pl).driving_pos.lane().road,
+        ParkingSpot::Offstreet(b, _) => map.get_b(b).sidewalk_pos.lane().road,
+    }
+}
@@ -1,26 +1,30 @@
 use serde::Serialize;
 
-use crate::{AlertHandler, Sim, SimFlags, SimOptions};
+use crate::{AlertHandler, ScenarioGenerator, Sim, SimFlags, SimOptions};
 use abstutil::{prettyprint_usize, Timer};
 use geom::{Duration, Time};
 use map_model::Map;
 use synthpop::Scenario;
 
 /// Simulate a curated list of scenarios to completion, and save the analytics as "prebaked
-/// results," to later compare simulation metrics against the baseline without map edits.
+/// results," to later compare simulation metrics against the baseline without map edits. If the
+/// map does have edits to buildings, the scenario is first changed to match them.
 pub fn prebake(map: &Map, scenario: Scenario, timer: &mut Timer) -> PrebakeSummary {
     timer.start(format!(
         "prebake for {} / {}",
         scenario.map_name.describe(),
         scenario.scenario_name
     ));
+    let scenario = ScenarioGenerator::match_building_edits(
+        map,
+        scenario,
+        &mut SimFlags::for_test("prebaked").make_rng(),
+        timer,
+    );
 
     let mut opts = SimOptions::new("prebaked");
     opts.alerts = AlertHandler::Silence;
-    let mut sim = Sim::new(map, opts);
-    // Bit of an abuse of this, but just need to fix the rng seed.
-    let mut rng = SimFlags::for_test("prebaked").make_rng();
-    sim.instantiate(&scenario, map, &mut rng, timer);
+    let mut sim = instantiate(map, &scenario, opts, timer);
 
     // Run until a few hours after the end of the day. Some trips start close to midnight, and we
     // want prebaked data for them too.
@@ -53,6 +57,21 @@ pub fn prebake(map: &Map, scenario: Scenario, timer: &mut Timer) -> PrebakeSumma
     PrebakeSummary::new(&sim, &scenario)
 }
 
+/// Creates a simulation of a scenario, always seeding the RNG the same way. Repeated runs of the
+/// same scenario on the same map will have matching trips and vehicles.
+pub(crate) fn instantiate(
+    map: &Map,
+    scenario: &Scenario,
+    opts: SimOptions,
+    timer: &mut Timer,
+) -> Sim {
+    let mut sim = Sim::new(map, opts);
+    // Bit of an abuse of this, but just need to fix the rng seed.
+    let mut rng = SimFlags::for_test("prebaked").make_rng();
+    sim.instantiate(scenario, map, &mut rng, timer);
+    sim
+}
+
 #[derive(Debug, Serialize)]
 pub struct PrebakeSummary {
     pub map: String,
@@ -110,6 +110,7 @@ impl TrafficRecorder {
                 })
                 .collect::<Vec<_>>(),
             only_seed_buses: None,
+            micromobility: Vec::new(),
         }
         .save();
     }
@@ -0,0 +1,372 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Duration, Time};
+use map_model::{Map, Path, PathConstraints, PathRequest, Position};
+
+use crate::sim::Ctx;
+use crate::{
+    AgentID, CarID, Command, CreateCar, Event, PedestrianID, PersonID, Router, SimOptions, TripID,
+    TripManager, TripPhaseType, Vehicle, VehicleSpec, VehicleType, WalkingSimState, MAX_CAR_LENGTH,
+    MIN_CAR_LENGTH,
+};
+
+/// How to choose which idle vehicle serves a ride request.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RideHailDispatch {
+    /// The idle vehicle closest to the pickup, as the crow flies
+    Nearest,
+    /// The vehicle that's been idle for the longest time, to spread work evenly over the fleet
+    LongestIdle,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct RideRequest {
+    ped: PedestrianID,
+    trip: TripID,
+    person: PersonID,
+    /// Where the vehicle stops in the driving lane
+    pickup: Position,
+    dropoff: Position,
+    requested_at: Time,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct FleetVehicle {
+    vehicle: Vehicle,
+    base: Position,
+    state: FleetState,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+enum FleetState {
+    /// Where the vehicle is waiting, and since when
+    Idle(Position, Time),
+    DrivingToPickup(RideRequest),
+    AtPickup(RideRequest),
+    DrivingToDropoff(RideRequest),
+    AtDropoff(Position),
+    ReturningToBase,
+}
+
+/// Manages a fleet of ride-hail vehicles. When somebody reaches the curb, the nearest or longest
+/// idle vehicle is dispatched to them. The vehicle stops in the driving lane to pick them up,
+/// blocking traffic behind it, drives them to the curb near their destination, and then either
+/// returns to its base or waits there for the next request. Idle vehicles are assumed to wait
+/// off-street, so they're removed from the simulation until they're dispatched again. If no
+/// vehicle is dispatched to somebody in time, they give up on their trip.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct RideHailSimState {
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    vehicles: BTreeMap<CarID, FleetVehicle>,
+    /// Requests with no vehicle assigned yet, in the order they were made
+    unassigned: VecDeque<RideRequest>,
+
+    fleet_size: usize,
+    policy: RideHailDispatch,
+    dwell_time: Duration,
+    return_to_base: bool,
+    max_wait: Duration,
+
+    events: Vec<Event>,
+}
+
+impl RideHailSimState {
+    pub fn new(opts: &SimOptions) -> RideHailSimState {
+        RideHailSimState {
+            vehicles: BTreeMap::new(),
+            unassigned: VecDeque::new(),
+            fleet_size: opts.ride_hail_fleet_size,
+            policy: opts.ride_hail_dispatch,
+            dwell_time: opts.ride_hail_dwell,
+            return_to_base: opts.ride_hail_return_to_base,
+            max_wait: opts.ride_hail_max_wait,
+            events: Vec::new(),
+        }
+    }
+
+    /// The fleet is only created once somebody first requests a ride, so that simulations without
+    /// any ride-hail trips don't have vehicle IDs shifted.
+    fn create_fleet(&mut self, now: Time, trips: &mut TripManager, map: &Map) {
+        // Spread the bases evenly over lanes long enough to comfortably spawn on
+        let lanes: Vec<_> = map
+            .all_lanes()
+            .filter(|l| PathConstraints::Car.can_use(l, map) && l.length() > MAX_CAR_LENGTH * 3.0)
+            .map(|l| (l.id, l.length()))
+            .collect();
+        if lanes.is_empty() {
+            return;
+        }
+        for idx in 0..self.fleet_size {
+            let (lane, length) = lanes[idx * lanes.len() / self.fleet_size];
+            let base = Position::new(lane, length / 2.0);
+            let vehicle = VehicleSpec {
+                vehicle_type: VehicleType::RideHail,
+                length: MIN_CAR_LENGTH,
+                max_speed: None,
+            }
+            .make(
+                CarID {
+                    id: trips.new_car_id(),
+                    vehicle_type: VehicleType::RideHail,
+                },
+                None,
+            );
+            self.vehicles.insert(
+                vehicle.id,
+                FleetVehicle {
+                    vehicle,
+                    base,
+                    state: FleetState::Idle(base, now),
+                },
+            );
+        }
+    }
+
+    /// Somebody is waiting at the curb for a ride. Unless a vehicle is dispatched sooner, the
+    /// pedestrian is woken up when they run out of patience.
+    pub fn request_ride(
+        &mut self,
+        now: Time,
+        ped: PedestrianID,
+        trip: TripID,
+        person: PersonID,
+        pickup: Position,
+        dropoff: Position,
+        trips: &mut TripManager,
+        ctx: &mut Ctx,
+    ) {
+        if self.vehicles.is_empty() {
+            self.create_fleet(now, trips, ctx.map);
+        }
+        self.unassigned.push_back(RideRequest {
+            ped,
+            trip,
+            person,
+            pickup,
+            dropoff,
+            requested_at: now,
+        });
+        ctx.scheduler
+            .push(now + self.max_wait, Command::UpdatePed(ped));
+        self.dispatch(now, trips, ctx);
+    }
+
+    /// Nobody was dispatched to this pedestrian in time, so they give up on their trip.
+    pub fn request_timed_out(
+        &mut self,
+        now: Time,
+        ped: PedestrianID,
+        trips: &mut TripManager,
+        ctx: &mut Ctx,
+    ) {
+        let idx = self
+            .unassigned
+            .iter()
+            .position(|req| req.ped == ped)
+            .unwrap();
+        let req = self.unassigned.remove(idx).unwrap();
+        trips.ride_hail_request_timed_out(
+            now,
+            req.ped,
+            format!("no ride-hail vehicle came within {}", self.max_wait),
+            ctx,
+        );
+    }
+
+    /// Assign idle vehicles to waiting requests, first-come first-served. Requests that no idle
+    /// vehicle can reach keep waiting.
+    fn dispatch(&mut self, now: Time, trips: &TripManager, ctx: &mut Ctx) {
+        let mut still_waiting = VecDeque::new();
+        while let Some(req) = self.unassigned.pop_front() {
+            // The trip may have been cancelled while waiting
+            if trips.agent_to_trip(AgentID::Pedestrian(req.ped)) != Some(req.trip) {
+                continue;
+            }
+            if let Some((id, path)) = self.find_vehicle(&req, now, ctx) {
+                // They'll wait as long as it takes now
+                ctx.scheduler.cancel(Command::UpdatePed(req.ped));
+                let fleet_vehicle = self.vehicles.get_mut(&id).unwrap();
+                fleet_vehicle.state = FleetState::DrivingToPickup(req);
+                ctx.scheduler.push(
+                    now,
+                    Command::SpawnCar(
+                        CreateCar {
+                            vehicle: fleet_vehicle.vehicle.clone(),
+                            router: Router::ride_hail(id, path),
+                            maybe_parked_car: None,
+                            trip_and_person: None,
+                            maybe_route: None,
+                        },
+                        true,
+                    ),
+                );
+            } else {
+                still_waiting.push_back(req);
+            }
+        }
+        self.unassigned = still_waiting;
+    }
+
+    fn find_vehicle(&self, req: &RideRequest, now: Time, ctx: &Ctx) -> Option<(CarID, Path)> {
+        let map = ctx.map;
+        let mut candidates = Vec::new();
+        for (id, fleet_vehicle) in &self.vehicles {
+            if let FleetState::Idle(pos, since) = fleet_vehicle.state {
+                // A one-step path has to start before the pickup
+                if pos.lane() == req.pickup.lane() && pos.dist_along() >= req.pickup.dist_along() {
+                    continue;
+                }
+                candidates.push((*id, pos, since));
+            }
+        }
+        match self.policy {
+            RideHailDispatch::Nearest => {
+                let pickup = req.pickup.pt(map);
+                candidates.sort_by_key(|(id, pos, _)| (pos.pt(map).dist_to(pickup), *id));
+            }
+            RideHailDispatch::LongestIdle => {
+                candidates.sort_by_key(|(id, _, since)| (*since, *id));
+            }
+        }
+        for (id, pos, _) in candidates {
+            if let (Ok(path), _) = ctx.caps.pathfind(
+                PathRequest::vehicle(pos, req.pickup, PathConstraints::Car),
+                None,
+                now,
+                map,
+            ) {
+                return Some((id, path));
+            }
+        }
+        None
+    }
+
+    /// A fleet vehicle reached the end of its route. If it should wait at the curb, returns how
+    /// long. Otherwise, it vanishes.
+    pub fn vehicle_arrived(
+        &mut self,
+        now: Time,
+        id: CarID,
+        distance: Distance,
+        trips: &mut TripManager,
+        walking: &mut WalkingSimState,
+        ctx: &mut Ctx,
+    ) -> Option<Duration> {
+        let fleet_vehicle = self.vehicles.get_mut(&id).unwrap();
+        let state = std::mem::replace(&mut fleet_vehicle.state, FleetState::ReturningToBase);
+        match state {
+            FleetState::DrivingToPickup(req) => {
+                self.events
+                    .push(Event::RideHailLegFinished(id, None, distance));
+                if trips.agent_to_trip(AgentID::Pedestrian(req.ped)) != Some(req.trip) {
+                    // Nobody's waiting anymore
+                    fleet_vehicle.state = FleetState::Idle(req.pickup, now);
+                    self.dispatch(now, trips, ctx);
+                    return None;
+                }
+                self.events
+                    .push(Event::RideHailArrivedAtCurb(id, req.pickup.lane()));
+                trips.ped_picked_up(now, req.ped, id, now - req.requested_at, walking);
+                self.events.push(Event::TripPhaseStarting(
+                    req.trip,
+                    req.person,
+                    Some(PathRequest::vehicle(
+                        req.pickup,
+                        req.dropoff,
+                        PathConstraints::Car,
+                    )),
+                    TripPhaseType::RidingRideHail(id),
+                ));
+                fleet_vehicle.state = FleetState::AtPickup(req);
+                Some(self.dwell_time)
+            }
+            FleetState::DrivingToDropoff(req) => {
+                self.events
+                    .push(Event::RideHailLegFinished(id, Some(req.person), distance));
+                self.events
+                    .push(Event::RideHailArrivedAtCurb(id, req.dropoff.lane()));
+                trips.person_dropped_off(now, req.person, id, ctx);
+                fleet_vehicle.state = FleetState::AtDropoff(req.dropoff);
+                Some(self.dwell_time)
+            }
+            FleetState::ReturningToBase => {
+                self.events
+                    .push(Event::RideHailLegFinished(id, None, distance));
+                fleet_vehicle.state = FleetState::Idle(fleet_vehicle.base, now);
+                self.dispatch(now, trips, ctx);
+                None
+            }
+            FleetState::Idle(_, _) | FleetState::AtPickup(_) | FleetState::AtDropoff(_) => {
+                unreachable!()
+            }
+        }
+    }
+
+    /// A fleet vehicle finished waiting at the curb. Returns the route for its next leg, or None
+    /// if it should vanish and wait off-street.
+    pub fn vehicle_departed(
+        &mut self,
+        now: Time,
+        id: CarID,
+        trips: &mut TripManager,
+        ctx: &mut Ctx,
+    ) -> Option<Router> {
+        let fleet_vehicle = self.vehicles.get_mut(&id).unwrap();
+        let state = std::mem::replace(&mut fleet_vehicle.state, FleetState::ReturningToBase);
+        match state {
+            FleetState::AtPickup(req) => {
+                match ctx
+                    .caps
+                    .pathfind(
+                        PathRequest::vehicle(req.pickup, req.dropoff, PathConstraints::Car),
+                        None,
+                        now,
+                        ctx.map,
+                    )
+                    .0
+                {
+                    Ok(path) => {
+                        fleet_vehicle.state = FleetState::DrivingToDropoff(req);
+                        Some(Router::ride_hail(id, path))
+                    }
+                    Err(err) => {
+                        fleet_vehicle.state = FleetState::Idle(req.pickup, now);
+                        trips.ride_hail_cancelled(now, req.person, id, err.to_string(), ctx);
+                        self.dispatch(now, trips, ctx);
+                        None
+                    }
+                }
+            }
+            FleetState::AtDropoff(pos) => {
+                if self.return_to_base && pos != fleet_vehicle.base {
+                    if let (Ok(path), _) = ctx.caps.pathfind(
+                        PathRequest::vehicle(pos, fleet_vehicle.base, PathConstraints::Car),
+                        None,
+                        now,
+                        ctx.map,
+                    ) {
+                        // Stay in ReturningToBase
+                        return Some(Router::ride_hail(id, path));
+                    }
+                }
+                fleet_vehicle.state = FleetState::Idle(pos, now);
+                self.dispatch(now, trips, ctx);
+                None
+            }
+            FleetState::Idle(_, _)
+            | FleetState::DrivingToPickup(_)
+            | FleetState::DrivingToDropoff(_)
+            | FleetState::ReturningToBase => unreachable!(),
+        }
+    }
+
+    pub fn collect_events(&mut self) -> Vec<Event> {
+        self.events.drain(..).collect()
+    }
+}
@@ -1,14 +1,17 @@
 //! For vehicles only, not pedestrians. Follows a Path from map_model, but can opportunistically
-//! lane-change to avoid a slow lane, can can handle re-planning to look for available parking.
+//! lane-change to avoid a slow lane, can can handle re-planning to look for available parking or to
+//! avoid congestion.
 
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap};
 
 use serde::{Deserialize, Serialize};
 
-use geom::Distance;
+use geom::{Distance, Duration, Time};
+use map_model::connectivity::vehicle_cost;
 use map_model::{
-    BuildingID, IntersectionID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep,
-    Position, Traversable, Turn, TurnID,
+    BuildingID, DirectedRoadID, IntersectionID, LaneID, Map, MovementID, Path, PathConstraints,
+    PathRequest, PathStep, PathV2, Position, Traversable, Turn, TurnID, Zone,
 };
 
 use crate::mechanics::Queue;
@@ -25,6 +28,13 @@ pub(crate) struct Router {
     owner: CarID,
 }
 
+/// When estimating congestion along a road, assume each queued vehicle holds up the ones behind it
+/// for this long.
+const QUEUE_HEADWAY: Duration = Duration::const_seconds(2.0);
+/// Only switch routes if the new one is estimated to save at least this much time. Drivers don't
+/// change their plans over small differences, and this keeps them from flip-flopping.
+const MIN_REROUTE_SAVINGS: Duration = Duration::const_seconds(30.0);
+
 #[derive(Debug)]
 pub(crate) enum ActionAtEnd {
     VanishAtBorder(IntersectionID),
@@ -32,6 +42,8 @@ pub(crate) enum ActionAtEnd {
     GotoLaneEnd,
     StopBiking(SidewalkSpot),
     BusAtStop,
+    RideHailAtCurb,
+    DeliveryStop,
     GiveUpOnParking,
 }
 
@@ -43,7 +55,12 @@ enum Goal {
         spot: Option<(ParkingSpot, Distance)>,
         /// No parking available at all!
         stuck_end_dist: Option<Distance>,
-        started_looking: bool,
+        /// Once the driver starts looking for a spot, when that happened and how far they've
+        /// driven since
+        started_looking: Option<(Time, Distance)>,
+        /// When the driver plans to leave again, if they have another trip. Used to estimate the
+        /// cost of priced parking.
+        leave_at: Option<Time>,
     },
     EndAtBorder {
         end_dist: Distance,
@@ -55,6 +72,15 @@ enum Goal {
     FollowTransitRoute {
         end_dist: Distance,
     },
+    /// A ride-hail vehicle pulling over to pick up or drop off a passenger, or returning to its
+    /// base.
+    StopAtCurb {
+        end_dist: Distance,
+    },
+    /// A delivery vehicle stopping to load or unload
+    DeliveryStop {
+        end_dist: Distance,
+    },
 }
 
 impl Router {
@@ -71,14 +97,15 @@ impl Router {
         }
     }
 
-    pub fn park_near(owner: CarID, path: Path, bldg: BuildingID) -> Router {
+    pub fn park_near(owner: CarID, path: Path, bldg: BuildingID, leave_at: Option<Time>) -> Router {
         Router {
             path,
             goal: Goal::ParkNearBuilding {
                 target: bldg,
                 spot: None,
                 stuck_end_dist: None,
-                started_looking: false,
+                started_looking: None,
+                leave_at,
             },
             owner,
         }
@@ -102,6 +129,26 @@ impl Router {
         }
     }
 
+    pub fn ride_hail(owner: CarID, path: Path) -> Router {
+        Router {
+            goal: Goal::StopAtCurb {
+                end_dist: path.get_req().end.dist_along(),
+            },
+            path,
+            owner,
+        }
+    }
+
+    pub fn deliver(owner: CarID, path: Path) -> Router {
+        Router {
+            goal: Goal::DeliveryStop {
+                end_dist: path.get_req().end.dist_along(),
+            },
+            path,
+            owner,
+        }
+    }
+
     pub fn head(&self) -> Traversable {
         self.path.current_step().as_traversable()
     }
@@ -130,6 +177,8 @@ impl Router {
             } => stuck_end_dist.unwrap_or_else(|| spot.unwrap().1),
             Goal::BikeThenStop { ref goal } => goal.sidewalk_pos.dist_along(),
             Goal::FollowTransitRoute { end_dist } => end_dist,
+            Goal::StopAtCurb { end_dist } => end_dist,
+            Goal::DeliveryStop { end_dist } => end_dist,
         }
     }
 
@@ -140,6 +189,7 @@ impl Router {
     /// Returns the step just finished
     pub fn advance(
         &mut self,
+        now: Time,
         vehicle: &Vehicle,
         parking: &ParkingSimState,
         map: &Map,
@@ -147,9 +197,17 @@ impl Router {
         events: &mut Vec<Event>,
     ) -> Traversable {
         let prev = self.path.shift(map).as_traversable();
+        if let Goal::ParkNearBuilding {
+            started_looking: Some((_, ref mut searched)),
+            ..
+        } = self.goal
+        {
+            *searched += prev.get_polyline(map).length();
+        }
         if self.last_step() {
             // Do this to trigger the side-effect of looking for parking.
             self.maybe_handle_end(
+                now,
                 Distance::ZERO,
                 vehicle,
                 parking,
@@ -177,6 +235,7 @@ impl Router {
     /// step.
     pub fn maybe_handle_end(
         &mut self,
+        now: Time,
         front: Distance,
         vehicle: &Vehicle,
         parking: &ParkingSimState,
@@ -200,6 +259,7 @@ impl Router {
                 ref mut stuck_end_dist,
                 target,
                 ref mut started_looking,
+                leave_at,
             } => {
                 if let Some(d) = stuck_end_dist {
                     if *d == front {
@@ -214,16 +274,36 @@ impl Router {
                     None => true,
                 };
                 if need_new_spot {
-                    *started_looking = true;
+                    if started_looking.is_none() {
+                        // Count distance from here, not from the start of the lane
+                        *started_looking = Some((now, -front));
+                    }
                     let current_lane = self.path.current_step().as_lane();
-                    let candidates = parking.get_all_free_spots(
-                        Position::new(current_lane, front),
-                        vehicle,
-                        target,
-                        map,
-                    );
-                    let best =
-                        if let Some((driving_pos, _)) = map.get_b(target).driving_connection(map) {
+                    // When parking is priced, a cheaper spot elsewhere might be worth the drive
+                    let (best, elsewhere) = if parking.is_priced() {
+                        match parking.path_to_cheapest_parking_spot(
+                            now,
+                            Position::new(current_lane, front),
+                            vehicle,
+                            target,
+                            leave_at,
+                            map,
+                        ) {
+                            Some((steps, new_spot, new_pos)) if steps.is_empty() => {
+                                (Some((new_spot, new_pos)), None)
+                            }
+                            elsewhere => (None, elsewhere),
+                        }
+                    } else {
+                        let candidates = parking.get_all_free_spots(
+                            Position::new(current_lane, front),
+                            vehicle,
+                            target,
+                            map,
+                        );
+                        let best = if let Some((driving_pos, _)) =
+                            map.get_b(target).driving_connection(map)
+                        {
                             if driving_pos.lane() == current_lane {
                                 let target_dist = driving_pos.dist_along();
                                 // Closest to the building
@@ -242,6 +322,8 @@ impl Router {
                                 .into_iter()
                                 .min_by_key(|(_, pos)| pos.dist_along())
                         };
+                        (best, None)
+                    };
                     if let Some((new_spot, new_pos)) = best {
                         if let Some((t, p)) = trip_and_person {
                             events.push(Event::TripPhaseStarting(
@@ -259,9 +341,16 @@ impl Router {
                         assert!(new_pos.dist_along() >= front);
                         *spot = Some((new_spot, new_pos.dist_along()));
                     } else {
-                        if let Some((new_path_steps, new_spot, new_pos)) =
-                            parking.path_to_free_parking_spot(current_lane, vehicle, target, map)
-                        {
+                        if let Some((new_path_steps, new_spot, new_pos)) = elsewhere.or_else(|| {
+                            parking.path_to_free_parking_spot_for_stay(
+                                now,
+                                current_lane,
+                                vehicle,
+                                target,
+                                leave_at,
+                                map,
+                            )
+                        }) {
                             assert!(!new_path_steps.is_empty());
                             for step in new_path_steps {
                                 self.path.add(step, map);
@@ -278,7 +367,7 @@ impl Router {
                                         new_pos,
                                         PathConstraints::Car,
                                     )),
-                                    TripPhaseType::Parking,
+                                    TripPhaseType::SearchingForParking,
                                 ));
                             }
                         } else {
@@ -318,6 +407,20 @@ impl Router {
                     None
                 }
             }
+            Goal::StopAtCurb { end_dist } => {
+                if end_dist == front {
+                    Some(ActionAtEnd::RideHailAtCurb)
+                } else {
+                    None
+                }
+            }
+            Goal::DeliveryStop { end_dist } => {
+                if end_dist == front {
+                    Some(ActionAtEnd::DeliveryStop)
+                } else {
+                    None
+                }
+            }
         }
     }
 
@@ -452,6 +555,148 @@ impl Router {
         }
     }
 
+    /// If the rest of the route has become congested, switch to a faster one. Each road costs the
+    /// usual time to cross it, plus a delay estimated from the vehicles currently queued there.
+    /// This should only be called when the vehicle is at the front of its queue, before starting
+    /// the next turn. Routes never pass through intersections in `avoid`, and respect the zone
+    /// time windows in effect `now`. If the route changes, returns the remaining path before the
+    /// change.
+    pub fn reroute_around_congestion(
+        &mut self,
+        queues: &HashMap<Traversable, Queue>,
+        avoid: &BTreeSet<IntersectionID>,
+        now: Time,
+        map: &Map,
+    ) -> Option<Path> {
+        // Once a driver starts looking for parking, they're committed to the area.
+        match self.goal {
+            Goal::EndAtBorder { .. } => {}
+            Goal::ParkNearBuilding {
+                started_looking: None,
+                ..
+            } => {}
+            _ => {
+                return None;
+            }
+        }
+        if self.path.currently_inside_ut().is_some() || self.path.about_to_start_ut().is_some() {
+            return None;
+        }
+        // There has to be at least one intersection between the next road and the end to choose
+        // something different.
+        let steps = self.path.get_steps();
+        if steps.len() < 5 {
+            return None;
+        }
+        let current_lane = match steps[0] {
+            PathStep::Lane(l) => l,
+            _ => {
+                return None;
+            }
+        };
+        let end = self.path.get_req().end;
+        if self.path.last_step() != PathStep::Lane(end.lane()) {
+            return None;
+        }
+        let constraints = self.owner.vehicle_type.to_constraints();
+        let mut params = map.routing_params().clone();
+        params.access_restrictions_at = Zone::restrictions_changed_at(map, now);
+        let params = &params;
+        let delay = |dr: DirectedRoadID| queue_delay(dr, constraints, queues, map);
+        let start_dr = map.get_l(current_lane).get_directed_parent();
+        let end_dr = map.get_l(end.lane()).get_directed_parent();
+        if start_dr == end_dr {
+            return None;
+        }
+
+        let mut current_cost = Duration::ZERO;
+        for step in steps {
+            if let PathStep::Turn(t) = step {
+                let mvmnt = t.to_movement(map);
+                match vehicle_cost(mvmnt.from, mvmnt, constraints, params, map) {
+                    Some(cost) => {
+                        current_cost += cost + delay(mvmnt.to);
+                    }
+                    // The route may have become illegal after a live edit. Let the usual
+                    // handling deal with that.
+                    None => {
+                        return None;
+                    }
+                }
+            }
+        }
+
+        // Dijkstra's algorithm over directed roads. The first movement has to be possible from
+        // the current lane, since there's no room left to change lanes.
+        let first_movements: BTreeSet<MovementID> = map
+            .get_turns_for(current_lane, constraints)
+            .into_iter()
+            .map(|t| t.id.to_movement(map))
+            .collect();
+        // Each entry also remembers the road it was reached from
+        let mut queue: BinaryHeap<Reverse<(Duration, DirectedRoadID, DirectedRoadID)>> =
+            BinaryHeap::new();
+        let mut prev: HashMap<DirectedRoadID, DirectedRoadID> = HashMap::new();
+        let mut new_cost = None;
+        queue.push(Reverse((Duration::ZERO, start_dr, start_dr)));
+        while let Some(Reverse((cost, dr, from))) = queue.pop() {
+            if prev.contains_key(&dr) {
+                continue;
+            }
+            prev.insert(dr, from);
+            if dr == end_dr {
+                new_cost = Some(cost);
+                break;
+            }
+            let movements = if dr == start_dr {
+                first_movements.iter().cloned().collect()
+            } else {
+                map.get_movements_for(dr, constraints)
+            };
+            for mvmnt in movements {
+                if avoid.contains(&mvmnt.parent) || prev.contains_key(&mvmnt.to) {
+                    continue;
+                }
+                if let Some(step_cost) = vehicle_cost(dr, mvmnt, constraints, params, map) {
+                    let total = cost + step_cost + delay(mvmnt.to);
+                    // Don't bother exploring anything that can't beat the current route
+                    if total + MIN_REROUTE_SAVINGS <= current_cost {
+                        queue.push(Reverse((total, mvmnt.to, dr)));
+                    }
+                }
+            }
+        }
+        let new_cost = new_cost?;
+
+        let mut roads = vec![end_dr];
+        while roads.last() != Some(&start_dr) {
+            roads.push(prev[roads.last().unwrap()]);
+        }
+        roads.reverse();
+        let req = PathRequest::vehicle(
+            Position::new(current_lane, map.get_l(current_lane).length()),
+            end,
+            constraints,
+        );
+        let new_path = match PathV2::from_roads(roads, req, new_cost, Vec::new(), map).into_v1(map)
+        {
+            Ok(path) => path,
+            Err(err) => {
+                warn!("{} couldn't reroute: {}", self.owner, err);
+                return None;
+            }
+        };
+        // The lane-based path might start from a different lane on the same road, but it's too
+        // late to change lanes.
+        if new_path.current_step() != PathStep::Lane(current_lane) {
+            return None;
+        }
+
+        let old_path = self.path.clone();
+        self.path.reroute(new_path, map);
+        Some(old_path)
+    }
+
     pub fn can_lanechange(&self, from: LaneID, to: LaneID, map: &Map) -> bool {
         let steps = self.path.get_steps();
         if steps.len() < 3 {
@@ -487,11 +732,28 @@ impl Router {
         match self.goal {
             Goal::ParkNearBuilding {
                 started_looking, ..
-            } => started_looking,
+            } => started_looking.is_some(),
             _ => false,
         }
     }
 
+    /// If the driver has been looking for parking, returns the destination and how long and how
+    /// far they've searched so far. `front` is the current distance along the last step.
+    pub fn parking_search(
+        &self,
+        now: Time,
+        front: Distance,
+    ) -> Option<(BuildingID, Duration, Distance)> {
+        match self.goal {
+            Goal::ParkNearBuilding {
+                target,
+                started_looking: Some((since, searched)),
+                ..
+            } => Some((target, now - since, searched + front)),
+            _ => None,
+        }
+    }
+
     pub fn get_parking_spot_goal(&self) -> Option<&ParkingSpot> {
         match self.goal {
             Goal::ParkNearBuilding { ref spot, .. } => spot.as_ref().map(|(s, _)| s),
@@ -499,3 +761,24 @@ impl Router {
         }
     }
 }
+
+/// Estimates how long it'll take for the vehicles currently on a road to clear out, beyond the
+/// usual time to cross it.
+fn queue_delay(
+    dr: DirectedRoadID,
+    constraints: PathConstraints,
+    queues: &HashMap<Traversable, Queue>,
+    map: &Map,
+) -> Duration {
+    let lanes = dr.lanes(constraints, map);
+    if lanes.is_empty() {
+        return Duration::ZERO;
+    }
+    let mut vehicles = 0;
+    for l in &lanes {
+        if let Some(queue) = queues.get(&Traversable::Lane(*l)) {
+            vehicles += queue.target_lane_penalty().0;
+        }
+    }
+    QUEUE_HEADWAY * (vehicles as f64) / (lanes.len() as f64)
+}
@@ -27,6 +27,10 @@ pub(crate) enum Command {
     Pandemic(pandemic::Cmd),
     /// The Time is redundant, just used to dedupe commands
     StartBus(TransitRouteID, Time),
+    /// A delivery vehicle is ready to start its tour or leave a stop
+    UpdateFreight(CarID),
+    /// Adjust demand-responsive parking prices
+    UpdateParkingPrices,
 }
 
 impl Command {
@@ -50,6 +54,8 @@ impl Command {
             Command::Callback(_) => CommandType::Callback,
             Command::Pandemic(ref p) => CommandType::Pandemic(p.clone()),
             Command::StartBus(r, t) => CommandType::StartBus(*r, *t),
+            Command::UpdateFreight(id) => CommandType::Freight(*id),
+            Command::UpdateParkingPrices => CommandType::ParkingPrices,
         }
     }
 
@@ -65,6 +71,8 @@ impl Command {
             Command::Callback(_) => SimpleCommandType::Callback,
             Command::Pandemic(_) => SimpleCommandType::Pandemic,
             Command::StartBus(_, _) => SimpleCommandType::StartBus,
+            Command::UpdateFreight(_) => SimpleCommandType::Freight,
+            Command::UpdateParkingPrices => SimpleCommandType::ParkingPrices,
         }
     }
 }
@@ -81,6 +89,8 @@ enum CommandType {
     Callback,
     Pandemic(pandemic::Cmd),
     StartBus(TransitRouteID, Time),
+    Freight(CarID),
+    ParkingPrices,
 }
 
 /// A more compressed form of CommandType, just used for keeping stats on event processing.
@@ -94,6 +104,8 @@ enum SimpleCommandType {
     Callback,
     Pandemic,
     StartBus,
+    Freight,
+    ParkingPrices,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -22,11 +22,14 @@ pub use self::queries::{AgentProperties, DelayCause};
 // TODO Super weird for both of these to wind up here
 pub use self::scenario::{count_parked_cars_per_bldg, rand_dist};
 use crate::{
-    AgentID, AlertLocation, Analytics, CarID, Command, CreateCar, DrivingSimState, Event,
-    IntersectionSimState, PandemicModel, ParkedCar, ParkingSim, ParkingSimState, ParkingSpot,
-    Person, PersonID, Router, Scheduler, SidewalkPOI, SidewalkSpot, StartTripArgs, TrafficRecorder,
-    TransitSimState, TripID, TripInfo, TripManager, TripPhaseType, Vehicle, VehicleSpec,
-    VehicleType, WalkingSimState, BUS_LENGTH, LIGHT_RAIL_LENGTH, MIN_CAR_LENGTH,
+    AgentID, AlertLocation, Analytics, CapSimState, CarID, Command, CreateCar, DriverBehavior,
+    DriverBehaviorModel, DrivingSimState, EmergencySimState, EmissionsModel, Event,
+    FreightSimState, IntersectionSimState, PandemicModel, ParkedCar, ParkingPricing, ParkingSim,
+    ParkingSimState, ParkingSpot, Person, PersonID, RideHailDispatch, RideHailSimState, Router,
+    Scheduler, SidewalkPOI, SidewalkSpot, SignalDetectors, StartTripArgs, TrafficAssignment,
+    TrafficRecorder, TransitCapacity, TransitSimState, TripID, TripInfo, TripManager,
+    TripPhaseType, Vehicle, VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH,
+    LIGHT_RAIL_LENGTH, MIN_CAR_LENGTH,
 };
 
 mod queries;
@@ -43,6 +46,10 @@ pub struct Sim {
     walking: WalkingSimState,
     intersections: IntersectionSimState,
     transit: TransitSimState,
+    ride_hail: RideHailSimState,
+    freight: FreightSimState,
+    emergency: EmergencySimState,
+    caps: CapSimState,
     trips: TripManager,
     #[serde(skip_serializing, skip_deserializing)]
     pandemic: Option<PandemicModel>,
@@ -62,6 +69,10 @@ pub struct Sim {
     // This is created interactively, and there's no reason to preserve one for savestates.
     #[serde(skip_serializing, skip_deserializing)]
     recorder: Option<TrafficRecorder>,
+    /// If present, every event is also copied here, so that callers outside the sim can observe
+    /// them.
+    #[serde(skip_serializing, skip_deserializing)]
+    captured_events: Option<Vec<(Time, Event)>>,
 
     #[serde(skip_serializing, skip_deserializing)]
     alerts: AlertHandler,
@@ -71,6 +82,7 @@ pub(crate) struct Ctx<'a> {
     pub parking: &'a mut ParkingSimState,
     pub intersections: &'a mut IntersectionSimState,
     pub scheduler: &'a mut Scheduler,
+    pub caps: &'a CapSimState,
     pub map: &'a Map,
     /// If present, live map edits are being processed, and the agents specified are in the process
     /// of being deleted. Some regular work should maybe be skipped.
@@ -127,6 +139,89 @@ pub struct SimOptions {
     /// quickly.
     #[structopt(long)]
     pub skip_analytics: bool,
+    /// Run the variable stages of traffic signals as actuated controllers, using virtual loop
+    /// detectors on every approaching lane. Either a number of meters before the stop line for
+    /// every approach, or a path to a JSON file that can also set the distance per approach. A
+    /// stage is extended while vehicles are detected, ends early when there's a gap in traffic,
+    /// and is skipped when no vehicle or pedestrian has called for it. Signals keep the cycle and
+    /// offset of their timing plan, so they stay coordinated with each other.
+    #[structopt(long, parse(try_from_str = SignalDetectors::parse))]
+    pub actuated_signal_detectors: Option<SignalDetectors>,
+    /// How to estimate the fuel, CO2, NOx, and electricity used by vehicles. Either "default" for
+    /// a built-in model of typical gasoline cars, diesel buses, and electric vehicles, or a path
+    /// to a JSON file with custom lookup tables.
+    #[structopt(long, parse(try_from_str = EmissionsModel::load), default_value = "default")]
+    pub emissions_model: EmissionsModel,
+    /// Let this fraction of drivers (between 0 and 1) react to live congestion. When an informed
+    /// driver reaches the end of a road, they periodically re-evaluate the rest of their route
+    /// using the vehicles currently queued on each road, and switch to a faster route if there is
+    /// one. Which drivers are informed is deterministic, based on the vehicle's ID.
+    #[structopt(long, parse(try_from_str = parse_fraction))]
+    pub informed_drivers: Option<f64>,
+    /// How often informed drivers re-evaluate their route, such as "1:00" for every minute.
+    #[structopt(long, parse(try_from_str = Duration::parse), default_value = "1:00")]
+    pub reroute_interval: Duration,
+    /// Route driving trips using the congestion costs found by an equilibrium traffic assignment
+    /// (see the `assign-traffic` command). This is a path to the saved assignment, which only
+    /// applies to the same map and scenario.
+    #[structopt(long, parse(try_from_str = TrafficAssignment::load))]
+    pub traffic_assignment: Option<TrafficAssignment>,
+    /// Slow pedestrians down based on the density of people on each sidewalk and crosswalk, using
+    /// a fundamental diagram and the width of the sidewalk. People wait to enter a jammed sidewalk
+    /// or crosswalk, so crowds can spill back. Without this, pedestrians pass through each other,
+    /// and are only slowed down by a fixed amount when it's crowded.
+    #[structopt(long)]
+    pub pedestrian_crowding: bool,
+    /// How many vehicles serve ride-hail trips. The fleet is spread evenly around the map when the
+    /// first ride is requested.
+    #[structopt(long, default_value = "50")]
+    pub ride_hail_fleet_size: usize,
+    /// How to pick an idle vehicle for each ride request: "nearest" or "longest-idle".
+    #[structopt(long, parse(try_from_str = parse_ride_hail_dispatch), default_value = "nearest")]
+    pub ride_hail_dispatch: RideHailDispatch,
+    /// How long a ride-hail vehicle stops in the driving lane to pick up or drop off a passenger.
+    #[structopt(long, parse(try_from_str = Duration::parse), default_value = "0:30")]
+    pub ride_hail_dwell: Duration,
+    /// After dropping off a passenger, ride-hail vehicles drive back to where they started.
+    /// Otherwise they wait off-street near the dropoff.
+    #[structopt(long)]
+    pub ride_hail_return_to_base: bool,
+    /// How long somebody waits at the curb for a ride-hail vehicle to be dispatched before giving
+    /// up on their trip.
+    #[structopt(long, parse(try_from_str = Duration::parse), default_value = "15:00")]
+    pub ride_hail_max_wait: Duration,
+    /// How many delivery tours to generate. Each delivery vehicle enters from a border sometime
+    /// during the working day, stops at commercial buildings, and leaves again.
+    #[structopt(long, default_value = "0")]
+    pub freight_tours: usize,
+    /// How many buildings each delivery tour visits.
+    #[structopt(long, default_value = "6")]
+    pub freight_stops_per_tour: usize,
+    /// How long a delivery vehicle spends loading or unloading at each stop.
+    #[structopt(long, parse(try_from_str = Duration::parse), default_value = "5:00")]
+    pub freight_dwell: Duration,
+    /// How many emergency vehicles to generate. Each one enters from a border sometime during the
+    /// day and drives across the map to another border, preempting traffic signals configured for
+    /// it.
+    #[structopt(long, default_value = "0")]
+    pub emergency_runs: usize,
+    /// How many passengers fit in a bus, as "seated+standing".
+    #[structopt(long, parse(try_from_str = TransitCapacity::parse), default_value = "40+30")]
+    pub bus_capacity: TransitCapacity,
+    /// How many passengers fit in a train, as "seated+standing".
+    #[structopt(long, parse(try_from_str = TransitCapacity::parse), default_value = "70+130")]
+    pub train_capacity: TransitCapacity,
+    /// Charge for public parking and limit how long people may stay, using prices from this JSON
+    /// file. Drivers choose where to park by weighing the fee against walking and searching time.
+    /// Has no effect with --infinite-parking.
+    #[structopt(long, parse(try_from_str = ParkingPricing::load))]
+    pub parking_pricing: Option<ParkingPricing>,
+    /// Vary how each driver behaves -- how fast they like to go relative to the speed limit, how
+    /// quickly they get moving after stopping, what gap they accept before an unprotected turn,
+    /// and how long they wait at stop signs -- by sampling from distributions in this JSON file.
+    /// Otherwise, all drivers behave the same.
+    #[structopt(long, parse(try_from_str = DriverBehaviorModel::load))]
+    pub driver_behavior: Option<DriverBehaviorModel>,
 }
 
 impl SimOptions {
@@ -143,6 +238,25 @@ impl SimOptions {
             infinite_parking: false,
             disable_turn_conflicts: false,
             skip_analytics: false,
+            actuated_signal_detectors: None,
+            emissions_model: EmissionsModel::default(),
+            informed_drivers: None,
+            reroute_interval: Duration::minutes(1),
+            traffic_assignment: None,
+            pedestrian_crowding: false,
+            ride_hail_fleet_size: 50,
+            ride_hail_dispatch: RideHailDispatch::Nearest,
+            ride_hail_dwell: Duration::seconds(30.0),
+            ride_hail_return_to_base: false,
+            ride_hail_max_wait: Duration::minutes(15),
+            freight_tours: 0,
+            freight_stops_per_tour: 6,
+            freight_dwell: Duration::minutes(5),
+            emergency_runs: 0,
+            bus_capacity: TransitCapacity::DEFAULT_BUS,
+            train_capacity: TransitCapacity::DEFAULT_TRAIN,
+            parking_pricing: None,
+            driver_behavior: None,
         }
     }
 }
@@ -158,6 +272,25 @@ fn parse_rng(x: &str) -> Result<XorShiftRng> {
     Ok(XorShiftRng::seed_from_u64(seed))
 }
 
+fn parse_fraction(x: &str) -> Result<f64> {
+    let fraction: f64 = x.parse()?;
+    if !(0.0..=1.0).contains(&fraction) {
+        bail!("{} must be between 0 and 1", x);
+    }
+    Ok(fraction)
+}
+
+fn parse_ride_hail_dispatch(x: &str) -> Result<RideHailDispatch> {
+    match x {
+        "nearest" => Ok(RideHailDispatch::Nearest),
+        "longest-idle" => Ok(RideHailDispatch::LongestIdle),
+        _ => bail!(
+            "Bad --ride-hail-dispatch={}. Must be nearest|longest-idle",
+            x
+        ),
+    }
+}
+
 #[derive(Clone)]
 pub enum AlertHandler {
     /// Just print the alert to STDOUT
@@ -201,18 +334,54 @@ impl Sim {
             opts.infinite_parking = true;
         }
 
+        if let Some(ref assignment) = opts.traffic_assignment {
+            if &assignment.map_name != map.get_name() {
+                panic!(
+                    "The traffic assignment is for {}, not {}",
+                    assignment.map_name.describe(),
+                    map.get_name().describe()
+                );
+            }
+        }
+
         // Hack around simulation bugs to get a Tehran map running.
         if map.get_name() == &MapName::new("ir", "tehran", "parliament") {
             opts.allow_block_the_box = true;
         }
 
+        if let Some(demand) = opts
+            .parking_pricing
+            .as_ref()
+            .and_then(|p| p.demand_responsive.as_ref())
+        {
+            if !opts.infinite_parking {
+                scheduler.push(
+                    Time::START_OF_DAY + demand.interval,
+                    Command::UpdateParkingPrices,
+                );
+            }
+        }
+
         Sim {
             driving: DrivingSimState::new(map, &opts),
-            parking: ParkingSimState::new(map, opts.infinite_parking, &mut timer),
-            walking: WalkingSimState::new(),
+            parking: ParkingSimState::new(
+                map,
+                opts.infinite_parking,
+                opts.parking_pricing.clone(),
+                &mut timer,
+            ),
+            walking: WalkingSimState::new(&opts),
             intersections: IntersectionSimState::new(map, &mut scheduler, &opts),
-            transit: TransitSimState::new(map),
-            trips: TripManager::new(),
+            transit: TransitSimState::new(map, &opts),
+            ride_hail: RideHailSimState::new(&opts),
+            freight: FreightSimState::new(&opts),
+            emergency: EmergencySimState::new(&opts),
+            caps: CapSimState::new(),
+            trips: TripManager::new(
+                opts.traffic_assignment
+                    .as_ref()
+                    .map(|a| a.assigned_routing()),
+            ),
             pandemic: opts.enable_pandemic_model.map(PandemicModel::new),
             scheduler,
             time: Time::START_OF_DAY,
@@ -226,6 +395,7 @@ impl Sim {
 
             analytics: Analytics::new(!opts.skip_analytics),
             recorder: None,
+            captured_events: None,
         }
     }
 
@@ -287,6 +457,7 @@ impl Sim {
             vehicle_type: VehicleType::Car,
             length: MIN_CAR_LENGTH,
             max_speed: None,
+            behavior: DriverBehavior::DEFAULT,
         };
         let driving_lane = map.find_driving_lane_near_building(b);
 
@@ -334,7 +505,7 @@ impl Sim {
         }
     }
 
-    fn start_bus(&mut self, route: &TransitRoute, map: &Map) {
+    fn start_bus(&mut self, route: &TransitRoute, spawn_time: Time, map: &Map) {
         // Spawn one bus for the first leg.
         let path = self.transit.create_empty_route(route, map);
 
@@ -357,6 +528,9 @@ impl Sim {
             },
             None,
         );
+        if let Some(run) = route.get_run(spawn_time) {
+            self.transit.bus_scheduled(vehicle.id, run.stops.clone());
+        }
 
         self.scheduler.push(
             self.time,
@@ -439,6 +613,7 @@ impl Sim {
             parking: &mut self.parking,
             intersections: &mut self.intersections,
             scheduler: &mut self.scheduler,
+            caps: &self.caps,
             map,
             handling_live_edits: None,
         };
@@ -470,13 +645,17 @@ impl Sim {
                     }
                 }
                 if !ok {
-                    self.trips.cancel_trip(
-                        self.time,
-                        create_car.trip_and_person.unwrap().0,
-                        "path is no longer valid after map edits".to_string(),
-                        Some(create_car.vehicle),
-                        &mut ctx,
-                    );
+                    // Emergency vehicles are scheduled long before they spawn, without a trip.
+                    // They just don't appear.
+                    if let Some((trip, _)) = create_car.trip_and_person {
+                        self.trips.cancel_trip(
+                            self.time,
+                            trip,
+                            "path is no longer valid after map edits".to_string(),
+                            Some(create_car.vehicle),
+                            &mut ctx,
+                        );
+                    }
                 } else {
                     // create_car contains a Path, which is expensive to clone. We need different
                     // parts of create_car after attempting start_car_on_lane.
@@ -600,6 +779,8 @@ impl Sim {
                     &mut self.trips,
                     &mut self.transit,
                     &mut self.walking,
+                    &mut self.ride_hail,
+                    &mut self.freight,
                 );
             }
             Command::UpdateLaggyHead(car) => {
@@ -612,11 +793,17 @@ impl Sim {
                     &mut ctx,
                     &mut self.trips,
                     &mut self.transit,
+                    &mut self.ride_hail,
                 );
             }
             Command::UpdateIntersection(i) => {
-                self.intersections
-                    .update_intersection(self.time, i, map, &mut self.scheduler);
+                self.intersections.update_intersection(
+                    self.time,
+                    i,
+                    map,
+                    &mut self.scheduler,
+                    &self.driving,
+                );
             }
             Command::Callback(frequency) => {
                 self.scheduler
@@ -631,8 +818,18 @@ impl Sim {
                     .unwrap()
                     .handle_cmd(self.time, cmd, &mut self.scheduler);
             }
-            Command::StartBus(r, _) => {
-                self.start_bus(map.get_tr(r), map);
+            Command::StartBus(r, t) => {
+                self.start_bus(map.get_tr(r), t, map);
+            }
+            Command::UpdateFreight(id) => {
+                self.freight
+                    .update(self.time, id, &mut self.driving, &mut ctx);
+            }
+            Command::UpdateParkingPrices => {
+                if let Some(interval) = self.parking.adjust_prices(self.time) {
+                    self.scheduler
+                        .push(self.time + interval, Command::UpdateParkingPrices);
+                }
             }
         }
 
@@ -645,17 +842,23 @@ impl Sim {
     fn dispatch_events(&mut self, mut events: Vec<Event>, map: &Map) {
         events.extend(self.trips.collect_events());
         events.extend(self.transit.collect_events());
+        events.extend(self.ride_hail.collect_events());
+        events.extend(self.freight.collect_events());
         events.extend(self.driving.collect_events());
         events.extend(self.walking.collect_events());
         events.extend(self.intersections.collect_events());
         events.extend(self.parking.collect_events());
         for ev in events {
+            self.caps.handle_event(self.time, &ev, map);
             if let Some(ref mut m) = self.pandemic {
                 m.handle_event(self.time, &ev, &mut self.scheduler);
             }
             if let Some(ref mut r) = self.recorder {
                 r.handle_event(self.time, &ev, map, &self.driving);
             }
+            if let Some(ref mut list) = self.captured_events {
+                list.push((self.time, ev.clone()));
+            }
 
             self.analytics.event(ev, self.time, map);
         }
@@ -852,6 +1055,7 @@ impl Sim {
             parking: &mut self.parking,
             intersections: &mut self.intersections,
             scheduler: &mut self.scheduler,
+            caps: &self.caps,
             map,
             handling_live_edits: Some(affected_agents),
         };
@@ -886,7 +1090,8 @@ impl Sim {
         }
 
         self.driving.handle_live_edits(map);
-        self.intersections.handle_live_edits(map);
+        self.intersections
+            .handle_live_edits(map, &mut self.scheduler);
 
         (num_trips_cancelled, num_parked_cars)
     }
@@ -903,7 +1108,10 @@ impl Sim {
 
         {
             // Find every active trip whose path crosses a modified lane or intersection
-            let (edited_lanes, _) = map.get_edits().changed_lanes(map);
+            let (mut edited_lanes, edited_roads) = map.get_edits().changed_lanes(map);
+            for r in edited_roads {
+                edited_lanes.extend(map.get_r(r).lanes.iter().map(|l| l.id));
+            }
             let mut closed_intersections = HashSet::new();
             for i in map.get_edits().original_intersections.keys() {
                 if map.get_i(*i).is_closed() {
@@ -916,9 +1124,13 @@ impl Sim {
                         .get_steps()
                         .iter()
                         .any(|step| match step.as_traversable() {
-                            Traversable::Lane(l) => edited_lanes.contains(&l),
+                            // Deleting or splitting a road removes lanes and turns
+                            Traversable::Lane(l) => {
+                                edited_lanes.contains(&l) || map.maybe_get_l(l).is_none()
+                            }
                             Traversable::Turn(t) => {
-                                closed_intersections.contains(&t.parent)
+                                map.maybe_get_t(t).is_none()
+                                    || closed_intersections.contains(&t.parent)
                                     || edited_lanes.contains(&t.src)
                                     || edited_lanes.contains(&t.dst)
                             }
@@ -967,6 +1179,7 @@ impl Sim {
                 parking: &mut self.parking,
                 intersections: &mut self.intersections,
                 scheduler: &mut self.scheduler,
+                caps: &self.caps,
                 map,
                 handling_live_edits: None,
             };
@@ -1025,6 +1238,29 @@ impl Sim {
     }
 }
 
+// Capturing events
+impl Sim {
+    /// Start copying every event that occurs, until `stop_capturing_events` is called. Does nothing
+    /// if events are already being captured.
+    pub fn start_capturing_events(&mut self) {
+        if self.captured_events.is_none() {
+            self.captured_events = Some(Vec::new());
+        }
+    }
+
+    pub fn stop_capturing_events(&mut self) {
+        self.captured_events = None;
+    }
+
+    /// Returns all events captured since the last call, in the order they occurred.
+    pub fn drain_captured_events(&mut self) -> Vec<(Time, Event)> {
+        self.captured_events
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+}
+
 // Managing highlighted people
 impl Sim {
     pub fn set_highlighted_people(&mut self, people: BTreeSet<PersonID>) {
@@ -14,9 +14,10 @@ use synthpop::{OrigPersonID, Scenario, TripMode};
 
 use crate::analytics::SlidingWindow;
 use crate::{
-    AgentID, AgentType, Analytics, CarID, CommutersVehiclesCounts, DrawCarInput, DrawPedCrowdInput,
-    DrawPedestrianInput, PandemicModel, ParkedCar, ParkingSim, PedestrianID, Person, PersonID,
-    PersonState, Sim, TripEndpoint, TripID, TripInfo, TripResult, UnzoomedAgent, VehicleType,
+    AgentID, AgentType, Analytics, CarID, CommutersVehiclesCounts, DetectorReading, DrawCarInput,
+    DrawPedCrowdInput, DrawPedestrianInput, PandemicModel, ParkedCar, ParkingSim, PedestrianID,
+    Person, PersonID, PersonState, Sim, TransitCapacity, TripEndpoint, TripID, TripInfo,
+    TripResult, UnzoomedAgent, VehicleType,
 };
 
 // TODO Many of these just delegate to an inner piece. This is unorganized and hard to maintain.
@@ -100,6 +101,11 @@ impl Sim {
         self.transit.get_passengers(car).len()
     }
 
+    /// How many passengers fit in this type of vehicle. None if it doesn't carry transit riders.
+    pub fn transit_capacity(&self, vehicle_type: VehicleType) -> Option<TransitCapacity> {
+        self.transit.capacity(vehicle_type)
+    }
+
     pub fn bus_route_id(&self, maybe_bus: CarID) -> Option<TransitRouteID> {
         if maybe_bus.vehicle_type == VehicleType::Bus
             || maybe_bus.vehicle_type == VehicleType::Train
@@ -204,6 +210,9 @@ impl Sim {
             VehicleType::Bike,
             VehicleType::Bus,
             VehicleType::Train,
+            VehicleType::RideHail,
+            VehicleType::Freight,
+            VehicleType::Emergency,
         ] {
             let id = CarID {
                 id: idx,
@@ -324,6 +333,11 @@ impl Sim {
         self.trips.bldg_to_people(b)
     }
 
+    /// How many shared scooters and bikes are waiting in front of each building
+    pub fn get_micromobility_availability(&self) -> BTreeMap<BuildingID, usize> {
+        self.trips.micromobility_availability()
+    }
+
     pub fn get_pandemic_model(&self) -> Option<&PandemicModel> {
         self.pandemic.as_ref()
     }
@@ -338,6 +352,12 @@ impl Sim {
             .max(Time::START_OF_DAY + Duration::hours(24))
     }
 
+    /// Only returns something for traffic signals when actuated signals are enabled.
+    pub fn get_signal_detectors(&self, map: &Map, i: IntersectionID) -> Vec<DetectorReading> {
+        self.intersections
+            .get_signal_detectors(self.time, i, map, &self.driving)
+    }
+
     pub fn current_stage_and_remaining_time(&self, i: IntersectionID) -> (usize, Duration) {
         self.intersections
             .current_stage_and_remaining_time(self.time, i)
@@ -394,6 +414,17 @@ impl Sim {
         self.parking.is_infinite()
     }
 
+    /// Per block, how many on-street parking spots are filled or reserved, and how many there are
+    /// total
+    pub fn parking_occupancy_per_block(&self) -> BTreeMap<RoadID, (usize, usize)> {
+        self.parking.onstreet_occupancy_per_block()
+    }
+
+    /// The current hourly price of on-street parking along a road, if it's priced
+    pub fn current_parking_price(&self, r: RoadID) -> Option<f64> {
+        self.parking.current_hourly_price(self.time, r)
+    }
+
     pub fn all_waiting_people(&self) -> BTreeMap<PersonID, Duration> {
         let mut delays = BTreeMap::new();
         self.walking.all_waiting_people(self.time, &mut delays);
@@ -427,7 +458,7 @@ impl Sim {
                 let max_speed = match info.mode {
                     TripMode::Walk | TripMode::Transit => Some(person.ped_speed),
                     // TODO We should really search the vehicles and grab it from there
-                    TripMode::Drive => None,
+                    TripMode::Drive | TripMode::RideHail => None,
                     // Assume just one bike
                     TripMode::Bike => {
                         person
@@ -437,6 +468,8 @@ impl Sim {
                             .unwrap()
                             .max_speed
                     }
+                    // Assume the fastest shared vehicle, since this is a lower bound
+                    TripMode::Micromobility => None,
                 };
                 Ok(path.estimate_duration(map, max_speed))
             }
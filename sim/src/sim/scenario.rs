@@ -36,6 +36,16 @@ impl Sim {
         retry_if_no_room: bool,
         timer: &mut Timer,
     ) {
+        // The assigned routes are keyed by trip IDs, which only line up with the same scenario
+        if let Some(name) = self.trips.assigned_scenario() {
+            if name != scenario.scenario_name {
+                panic!(
+                    "The traffic assignment is for the {} scenario, not {}",
+                    name, scenario.scenario_name
+                );
+            }
+        }
+
         // Any case where map edits could change the calls to the RNG, we have to fork.
         self.set_run_name(scenario.scenario_name.clone());
 
@@ -97,6 +107,21 @@ impl Sim {
         // parked_cars is stable over map edits, so don't fork.
         parked_cars.shuffle(rng);
         seed_parked_cars(parked_cars, self, map, rng, timer);
+        for fleet in &scenario.micromobility {
+            self.trips.add_micromobility_fleet(fleet.clone());
+        }
+        self.freight.seed_tours(
+            map,
+            &mut fork_rng(rng),
+            &mut self.trips,
+            &mut self.scheduler,
+        );
+        self.emergency.seed_runs(
+            map,
+            &mut fork_rng(rng),
+            &mut self.trips,
+            &mut self.scheduler,
+        );
 
         self.spawn_trips(schedule_trips, map, timer);
         timer.stop(format!("Instantiating {}", scenario.scenario_name));
@@ -122,7 +147,10 @@ fn get_vehicles(
     // TODO If the trip is cancelled, this should be affected...
     for trip in &person.trips {
         let use_for_trip = match trip.mode {
-            TripMode::Walk | TripMode::Transit => None,
+            // Ride-hail and shared vehicles belong to a fleet, not the person
+            TripMode::Walk | TripMode::Transit | TripMode::RideHail | TripMode::Micromobility => {
+                None
+            }
             TripMode::Bike => {
                 if bike_idx.is_none() {
                     bike_idx = Some(vehicle_specs.len());
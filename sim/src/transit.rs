@@ -1,20 +1,70 @@
 use std::collections::{BTreeMap, BTreeSet};
 
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::Time;
-use map_model::{Map, Path, TransitRoute, TransitRouteID, TransitStopID};
+use geom::{Duration, Time};
+use map_model::{Map, Path, ScheduledStop, TransitRoute, TransitRouteID, TransitStopID};
 
 use crate::sim::Ctx;
 use crate::{
-    AgentID, CarID, DrivingSimState, Event, PedestrianID, PersonID, Router, TripID, TripManager,
-    TripPhaseType, UnzoomedAgent, VehicleType, WalkingSimState,
+    AgentID, CarID, DrivingSimState, Event, PedestrianID, PersonID, Router, SimOptions, TripID,
+    TripManager, TripPhaseType, UnzoomedAgent, VehicleType, WalkingSimState,
 };
 
 // These index stops along a route, not stops along a single sidewalk.
 type StopIdx = usize;
 
+/// Vehicles always wait at least this long at a stop, to open and close doors
+const MIN_DWELL_TIME: Duration = Duration::const_seconds(10.0);
+const BOARDING_TIME: Duration = Duration::const_seconds(3.0);
+const ALIGHTING_TIME: Duration = Duration::const_seconds(2.0);
+/// Boarding takes longer when people have to squeeze past standing passengers
+const CROWDED_BOARDING_TIME: Duration = Duration::const_seconds(3.5);
+
+/// How many passengers fit in one transit vehicle
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransitCapacity {
+    pub seated: usize,
+    pub standing: usize,
+}
+
+impl TransitCapacity {
+    /// A standard 40-foot bus
+    pub const DEFAULT_BUS: TransitCapacity = TransitCapacity {
+        seated: 40,
+        standing: 30,
+    };
+    /// A single light rail car
+    pub const DEFAULT_TRAIN: TransitCapacity = TransitCapacity {
+        seated: 70,
+        standing: 130,
+    };
+
+    /// Parses "seated+standing", like "40+30".
+    pub fn parse(x: &str) -> Result<TransitCapacity> {
+        if let Some((seated, standing)) = x.split_once('+') {
+            let capacity = TransitCapacity {
+                seated: seated.trim().parse()?,
+                standing: standing.trim().parse()?,
+            };
+            if capacity.total() == 0 {
+                bail!("A transit vehicle must carry at least one passenger");
+            }
+            return Ok(capacity);
+        }
+        bail!(
+            "Transit capacity {} should look like \"seated+standing\"",
+            x
+        )
+    }
+
+    pub fn total(self) -> usize {
+        self.seated + self.standing
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 struct Route {
     // Entry i is the path to drive to stop i. The very last path is to drive from the last step to
@@ -31,6 +81,8 @@ struct Bus {
     /// Where does each passenger want to deboard?
     passengers: Vec<(PersonID, Option<TransitStopID>)>,
     state: BusState,
+    /// Lines up with the route's stops, if this vehicle follows a timetable
+    schedule: Option<Vec<ScheduledStop>>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -63,12 +115,20 @@ pub(crate) struct TransitSimState {
     )]
     peds_waiting:
         BTreeMap<TransitStopID, Vec<(PedestrianID, TransitRouteID, Option<TransitStopID>, Time)>>,
+    /// Timetables for vehicles about to spawn
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    pending_schedules: BTreeMap<CarID, Vec<ScheduledStop>>,
+    bus_capacity: TransitCapacity,
+    train_capacity: TransitCapacity,
 
     events: Vec<Event>,
 }
 
 impl TransitSimState {
-    pub fn new(map: &Map) -> TransitSimState {
+    pub fn new(map: &Map, opts: &SimOptions) -> TransitSimState {
         // Keep this filled out always so get_passengers can return &Vec without a hassle
         let mut peds_waiting = BTreeMap::new();
         for ts in map.all_transit_stops().keys() {
@@ -79,10 +139,26 @@ impl TransitSimState {
             buses: BTreeMap::new(),
             routes: BTreeMap::new(),
             peds_waiting,
+            pending_schedules: BTreeMap::new(),
+            bus_capacity: opts.bus_capacity,
+            train_capacity: opts.train_capacity,
             events: Vec::new(),
         }
     }
 
+    /// How many passengers fit in this type of vehicle. None if it doesn't carry transit riders.
+    pub fn capacity(&self, vehicle_type: VehicleType) -> Option<TransitCapacity> {
+        match vehicle_type {
+            VehicleType::Bus => Some(self.bus_capacity),
+            VehicleType::Train => Some(self.train_capacity),
+            VehicleType::Car
+            | VehicleType::Bike
+            | VehicleType::RideHail
+            | VehicleType::Freight
+            | VehicleType::Emergency => None,
+        }
+    }
+
     /// Returns the path for the first leg.
     pub fn create_empty_route(&mut self, bus_route: &TransitRoute, map: &Map) -> Path {
         self.routes
@@ -105,6 +181,11 @@ impl TransitSimState {
         self.routes[&bus_route.id].paths[0].clone()
     }
 
+    /// The vehicle will follow this timetable once it's created.
+    pub fn bus_scheduled(&mut self, bus: CarID, schedule: Vec<ScheduledStop>) {
+        self.pending_schedules.insert(bus, schedule);
+    }
+
     pub fn bus_created(&mut self, bus: CarID, r: TransitRouteID) {
         let route = self.routes.get_mut(&r).unwrap();
         route.active_vehicles.insert(bus);
@@ -115,11 +196,13 @@ impl TransitSimState {
                 route: r,
                 passengers: Vec::new(),
                 state: BusState::DrivingToStop(0),
+                schedule: self.pending_schedules.remove(&bus),
             },
         );
     }
 
-    /// If true, the bus is idling. If false, the bus actually arrived at a border and should now
+    /// If this returns a duration, the bus idles at the stop that long, depending on how many
+    /// people board and alight. If None, the bus actually arrived at a border and should now
     /// vanish.
     ///
     /// TODO Misnomer -- callback from Router::follow_bus_route
@@ -130,7 +213,8 @@ impl TransitSimState {
         trips: &mut TripManager,
         walking: &mut WalkingSimState,
         ctx: &mut Ctx,
-    ) -> bool {
+    ) -> Option<Duration> {
+        let capacity = self.capacity(id.vehicle_type).unwrap();
         let mut bus = self.buses.get_mut(&id).unwrap();
         match bus.state {
             BusState::DrivingToStop(stop_idx) => {
@@ -140,9 +224,11 @@ impl TransitSimState {
                     .push(Event::BusArrivedAtStop(id, bus.route, stop1));
 
                 // Deboard existing passengers.
+                let mut dwell = MIN_DWELL_TIME;
                 let mut still_riding = Vec::new();
                 for (person, maybe_stop2) in bus.passengers.drain(..) {
                     if Some(stop1) == maybe_stop2 {
+                        dwell += ALIGHTING_TIME;
                         trips.person_left_bus(now, person, bus.car, ctx);
                         self.events.push(Event::PassengerAlightsTransit(
                             person, bus.car, bus.route, stop1,
@@ -153,12 +239,21 @@ impl TransitSimState {
                 }
                 bus.passengers = still_riding;
 
-                // Board new passengers.
+                // Board new passengers, as long as there's room.
                 let mut still_waiting = Vec::new();
+                let mut denied = 0;
                 for (ped, route, maybe_stop2, started_waiting) in
                     self.peds_waiting.remove(&stop1).unwrap()
                 {
-                    if bus.route == route {
+                    if bus.route == route && bus.passengers.len() >= capacity.total() {
+                        denied += 1;
+                        still_waiting.push((ped, route, maybe_stop2, started_waiting));
+                    } else if bus.route == route {
+                        dwell += if bus.passengers.len() >= capacity.seated {
+                            CROWDED_BOARDING_TIME
+                        } else {
+                            BOARDING_TIME
+                        };
                         let (trip, person) = trips.ped_boarded_bus(
                             now,
                             ped,
@@ -189,7 +284,12 @@ impl TransitSimState {
                     }
                 }
                 self.peds_waiting.insert(stop1, still_waiting);
-                true
+                if denied > 0 {
+                    self.events.push(Event::PassengersDeniedBoarding(
+                        id, bus.route, stop1, denied,
+                    ));
+                }
+                Some(dwell)
             }
             BusState::DrivingOffMap => {
                 self.routes
@@ -208,13 +308,24 @@ impl TransitSimState {
                     trips.transit_rider_reached_border(now, person, id, ctx);
                 }
                 bus.state = BusState::Finished;
-                false
+                None
             }
             BusState::AtStop(_) | BusState::Finished => unreachable!(),
         }
     }
 
-    pub fn bus_departed_from_stop(&mut self, id: CarID, _: &Map) -> Router {
+    /// If the vehicle is early at a timepoint, it should wait here until its scheduled departure.
+    pub fn hold_until(&self, id: CarID) -> Option<Time> {
+        let bus = &self.buses[&id];
+        match (&bus.state, &bus.schedule) {
+            (BusState::AtStop(idx), Some(schedule)) if schedule[*idx].timepoint => {
+                Some(schedule[*idx].departure)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn bus_departed_from_stop(&mut self, now: Time, id: CarID, _: &Map) -> Router {
         let mut bus = self.buses.get_mut(&id).unwrap();
         let route = self.routes.get_mut(&bus.route).unwrap();
         match bus.state {
@@ -223,7 +334,16 @@ impl TransitSimState {
                     id,
                     bus.route,
                     route.stops[stop_idx],
+                    bus.passengers.len(),
                 ));
+                if let Some(ref schedule) = bus.schedule {
+                    self.events.push(Event::TransitScheduleDeviation(
+                        id,
+                        bus.route,
+                        route.stops[stop_idx],
+                        now - schedule[stop_idx].departure,
+                    ));
+                }
 
                 if stop_idx == route.stops.len() - 1 {
                     bus.state = BusState::DrivingOffMap;
@@ -251,10 +371,19 @@ impl TransitSimState {
         _: &Map,
     ) -> Option<CarID> {
         assert!(Some(stop1) != maybe_stop2);
+        // Even if several full vehicles are at the stop, this person only counts as one denied
+        // boarding
+        let mut full_vehicle = None;
         if let Some(route) = self.routes.get(&route_id) {
             for bus in &route.active_vehicles {
                 if let BusState::AtStop(idx) = self.buses[bus].state {
                     if route.stops[idx] == stop1 {
+                        if self.buses[bus].passengers.len()
+                            >= self.capacity(bus.vehicle_type).unwrap().total()
+                        {
+                            full_vehicle = Some(*bus);
+                            continue;
+                        }
                         self.buses
                             .get_mut(bus)
                             .unwrap()
@@ -278,6 +407,10 @@ impl TransitSimState {
             );
         }
 
+        if let Some(bus) = full_vehicle {
+            self.events
+                .push(Event::PassengersDeniedBoarding(bus, route_id, stop1, 1));
+        }
         self.peds_waiting
             .get_mut(&stop1)
             .unwrap()
@@ -379,3 +512,20 @@ impl TransitSimState {
         results
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_capacity() {
+        assert_eq!(
+            TransitCapacity::parse("40+30").unwrap(),
+            TransitCapacity::DEFAULT_BUS
+        );
+        assert_eq!(TransitCapacity::parse(" 70 + 130 ").unwrap().total(), 200);
+        assert!(TransitCapacity::parse("70").is_err());
+        assert!(TransitCapacity::parse("0+0").is_err());
+        assert!(TransitCapacity::parse("-1+5").is_err());
+    }
+}
@@ -1,23 +1,26 @@
 use std::collections::{BTreeMap, VecDeque};
 
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
 use abstutil::{deserialize_btreemap, serialize_btreemap, Counter};
 use geom::{Distance, Duration, Speed, Time};
 use map_model::{
-    BuildingID, IntersectionID, Map, PathConstraints, PathRequest, Position, TransitRouteID,
+    BuildingID, IntersectionID, Map, Path, PathConstraints, PathRequest, Position, TransitRouteID,
     TransitStopID,
 };
 use synthpop::{
-    IndividTrip, OrigPersonID, PersonSpec, Scenario, TripEndpoint, TripMode, TripPurpose,
+    IndividTrip, MicromobilityFleet, OrigPersonID, PersonSpec, Scenario, TripEndpoint, TripMode,
+    TripPurpose,
 };
 
 use crate::sim::Ctx;
 use crate::{
-    AgentID, AgentType, AlertLocation, CarID, Command, CreateCar, CreatePedestrian, DrivingGoal,
-    Event, ParkedCar, ParkingSim, ParkingSpot, PedestrianID, PersonID, SidewalkPOI, SidewalkSpot,
-    StartTripArgs, TransitSimState, TripID, TripPhaseType, TripSpec, Vehicle, VehicleSpec,
-    VehicleType, WalkingSimState,
+    AgentID, AgentType, AlertLocation, AssignedRouting, CarID, Command, CreateCar,
+    CreatePedestrian, DrivingGoal, Event, MicromobilityState, ParkedCar, ParkingSim, ParkingSpot,
+    PedestrianID, PersonID, RideHailSimState, SidewalkPOI, SidewalkSpot, StartTripArgs,
+    TransitSimState, TripID, TripPhaseType, TripSpec, Vehicle, VehicleSpec, VehicleType,
+    WalkingSimState,
 };
 
 /// Manages people, each of which executes some trips through the day. Each trip is further broken
@@ -39,19 +42,23 @@ pub(crate) struct TripManager {
     unfinished_trips: usize,
 
     car_id_counter: usize,
+    assigned_routing: Option<AssignedRouting>,
+    micromobility: MicromobilityState,
 
     events: Vec<Event>,
 }
 
 // Initialization
 impl TripManager {
-    pub fn new() -> TripManager {
+    pub fn new(assigned_routing: Option<AssignedRouting>) -> TripManager {
         TripManager {
             trips: Vec::new(),
             people: Vec::new(),
             active_trip_mode: BTreeMap::new(),
             unfinished_trips: 0,
             car_id_counter: 0,
+            assigned_routing,
+            micromobility: MicromobilityState::new(),
             events: Vec::new(),
         }
     }
@@ -95,6 +102,13 @@ impl TripManager {
         id
     }
 
+    /// Place the shared scooters or bikes of a fleet at their starting stations.
+    pub fn add_micromobility_fleet(&mut self, fleet: MicromobilityFleet) {
+        let num_vehicles = fleet.stations.iter().map(|s| s.initial_vehicles).sum();
+        let ids = (0..num_vehicles).map(|_| self.new_car_id()).collect();
+        self.micromobility.add_fleet(fleet, ids);
+    }
+
     pub fn new_trip(&mut self, person: PersonID, info: TripInfo) -> TripID {
         let id = TripID(self.trips.len());
         let trip = Trip {
@@ -160,19 +174,51 @@ impl TripManager {
         self.trips[trip.0].started = true;
 
         let info = &self.trips[trip.0].info;
-        let spec = match TripSpec::maybe_new(
-            info.start,
-            info.end,
-            info.mode,
-            args.use_vehicle,
-            args.retry_if_no_room,
-            ctx.map,
-        ) {
-            Ok(spec) => spec,
-            Err(error) => TripSpec::SpawningFailure {
-                use_vehicle: args.use_vehicle,
-                error: error.to_string(),
-            },
+        // Shared vehicles have to be found right as the trip starts, since earlier trips move
+        // them around
+        let mut rental = None;
+        if let (
+            TripMode::Micromobility,
+            TripEndpoint::Building(start),
+            TripEndpoint::Building(end),
+        ) = (info.mode, info.start, info.end)
+        {
+            if let Some((vehicle, pickup, dropoff)) =
+                self.micromobility.start_rental(trip, start, end, ctx.map)
+            {
+                self.events.push(Event::SharedVehiclesAvailable(
+                    pickup,
+                    self.micromobility.num_available(pickup),
+                ));
+                rental = Some(TripSpec::UsingSharedVehicle {
+                    start,
+                    goal: end,
+                    vehicle,
+                    pickup,
+                    dropoff,
+                });
+            } else {
+                self.events
+                    .push(Event::NoSharedVehicleAvailable(trip, start));
+            }
+        }
+        let spec = if let Some(spec) = rental {
+            spec
+        } else {
+            match TripSpec::maybe_new(
+                info.start,
+                info.end,
+                info.mode,
+                args.use_vehicle,
+                args.retry_if_no_room,
+                ctx.map,
+            ) {
+                Ok(spec) => spec,
+                Err(error) => TripSpec::SpawningFailure {
+                    use_vehicle: args.use_vehicle,
+                    error: error.to_string(),
+                },
+            }
         };
         // to_plan might actually change the TripSpec
         let (spec, legs) = spec.into_plan(ctx.map);
@@ -208,9 +254,10 @@ impl TripManager {
                 );
                 let person = person.id;
 
-                match ctx.map.pathfind(req) {
+                match self.pathfind_driving(trip, req, now, ctx) {
                     Ok(path) => {
-                        let router = goal.make_router(vehicle.id, path, ctx.map);
+                        let router =
+                            goal.make_router(vehicle.id, path, self.next_departure(trip), ctx.map);
                         ctx.scheduler.push(
                             now,
                             Command::SpawnCar(
@@ -241,7 +288,7 @@ impl TripManager {
                     let walking_goal =
                         SidewalkSpot::parking_spot(parked_car.spot, ctx.map, ctx.parking);
                     let req = PathRequest::walking(start.sidewalk_pos, walking_goal.sidewalk_pos);
-                    match ctx.map.pathfind(req) {
+                    match ctx.map.pathfind_at(req, now) {
                         Ok(path) => {
                             ctx.scheduler.push(
                                 now,
@@ -309,7 +356,7 @@ impl TripManager {
                 person.state = PersonState::Trip(trip);
 
                 let req = PathRequest::walking(start.sidewalk_pos, goal.sidewalk_pos);
-                match ctx.map.pathfind(req) {
+                match ctx.map.pathfind_at(req, now) {
                     Ok(path) => {
                         ctx.scheduler.push(
                             now,
@@ -338,7 +385,7 @@ impl TripManager {
                         SidewalkSpot::building(start, ctx.map).sidewalk_pos,
                         walk_to.sidewalk_pos,
                     );
-                    match ctx.map.pathfind(req) {
+                    match ctx.map.pathfind_at(req, now) {
                         Ok(path) => {
                             // Where we start biking may have slightly changed due to live map
                             // edits!
@@ -412,7 +459,7 @@ impl TripManager {
 
                 let walk_to = SidewalkSpot::bus_stop(stop1, ctx.map);
                 let req = PathRequest::walking(start.sidewalk_pos, walk_to.sidewalk_pos);
-                match ctx.map.pathfind(req) {
+                match ctx.map.pathfind_at(req, now) {
                     Ok(path) => {
                         ctx.scheduler.push(
                             now,
@@ -432,6 +479,63 @@ impl TripManager {
                     }
                 }
             }
+            TripSpec::UsingSharedVehicle { start, pickup, .. } => {
+                assert_eq!(person.state, PersonState::Inside(start));
+                person.state = PersonState::Trip(trip);
+
+                let start = SidewalkSpot::building(start, ctx.map);
+                let walk_to = SidewalkSpot::bike_rack(pickup, ctx.map).unwrap();
+                let req = PathRequest::walking(start.sidewalk_pos, walk_to.sidewalk_pos);
+                match ctx.map.pathfind_at(req, now) {
+                    Ok(path) => {
+                        ctx.scheduler.push(
+                            now,
+                            Command::SpawnPed(CreatePedestrian {
+                                id: person.ped,
+                                speed: person.ped_speed,
+                                start,
+                                goal: walk_to,
+                                path,
+                                trip,
+                                person: person.id,
+                            }),
+                        );
+                    }
+                    Err(err) => {
+                        self.cancel_trip(now, trip, err.to_string(), None, ctx);
+                    }
+                }
+            }
+            TripSpec::UsingRideHail { start, pickup, .. } => {
+                match start.connection {
+                    SidewalkPOI::Building(b) => {
+                        assert_eq!(person.state, PersonState::Inside(b));
+                    }
+                    _ => unreachable!(),
+                }
+                person.state = PersonState::Trip(trip);
+
+                let req = PathRequest::walking(start.sidewalk_pos, pickup.sidewalk_pos);
+                match ctx.map.pathfind_at(req, now) {
+                    Ok(path) => {
+                        ctx.scheduler.push(
+                            now,
+                            Command::SpawnPed(CreatePedestrian {
+                                id: person.ped,
+                                speed: person.ped_speed,
+                                start,
+                                goal: pickup,
+                                path,
+                                trip,
+                                person: person.id,
+                            }),
+                        );
+                    }
+                    Err(err) => {
+                        self.cancel_trip(now, trip, err.to_string(), None, ctx);
+                    }
+                }
+            }
         }
     }
 
@@ -461,7 +565,15 @@ impl TripManager {
         distance_crossed: Distance,
         ctx: &mut Ctx,
     ) {
-        let trip = &mut self.trips[self.active_trip_mode.remove(&AgentID::Car(car)).unwrap().0];
+        let id = self.active_trip_mode.remove(&AgentID::Car(car)).unwrap();
+        let fee = ctx
+            .parking
+            .parking_fee(now, spot, self.next_departure(id), ctx.map);
+        if fee > 0.0 {
+            self.events.push(Event::ParkingFeePaid(car, spot, fee));
+        }
+
+        let trip = &mut self.trips[id.0];
         trip.total_blocked_time += blocked_time;
         trip.total_distance += distance_crossed;
 
@@ -547,9 +659,14 @@ impl TripManager {
 
         let person = trip.person;
         let trip = trip.id;
-        match ctx.map.pathfind(req) {
+        match self.pathfind_driving(trip, req, now, ctx) {
             Ok(path) => {
-                let router = drive_to.make_router(parked_car.vehicle.id, path, ctx.map);
+                let router = drive_to.make_router(
+                    parked_car.vehicle.id,
+                    path,
+                    self.next_departure(trip),
+                    ctx.map,
+                );
                 ctx.scheduler.push(
                     now,
                     Command::SpawnCar(
@@ -617,7 +734,7 @@ impl TripManager {
         } else {
             ctx.map
                 .pathfind(req)
-                .map(|path| drive_to.make_router(bike, path, ctx.map))
+                .map(|path| drive_to.make_router(bike, path, None, ctx.map))
         };
         match maybe_router {
             Ok(router) => {
@@ -625,7 +742,10 @@ impl TripManager {
                     now,
                     Command::SpawnCar(
                         CreateCar::for_appearing(
-                            self.people[trip.person.0].get_vehicle(bike),
+                            // Shared vehicles belong to a fleet, not the person
+                            self.micromobility
+                                .get_vehicle(bike)
+                                .unwrap_or_else(|| self.people[trip.person.0].get_vehicle(bike)),
                             router,
                             trip.id,
                             trip.person,
@@ -666,6 +786,7 @@ impl TripManager {
         };
 
         let id = trip.id;
+        self.return_shared_vehicle(id);
         self.spawn_ped(now, id, bike_rack, ctx);
     }
 
@@ -768,7 +889,7 @@ impl TripManager {
         // No distance crossed between waiting for a bus and boarding
 
         trip.legs.pop_front();
-        walking.ped_boarded_bus(now, ped);
+        walking.ped_boarded_vehicle(now, ped);
         self.active_trip_mode
             .insert(AgentID::BusPassenger(trip.person, bus), trip.id);
         self.people[trip.person.0].on_bus = Some(bus);
@@ -795,6 +916,122 @@ impl TripManager {
         self.spawn_ped(now, id, start, ctx);
     }
 
+    /// A ride-hail vehicle picked up somebody waiting at the curb.
+    pub fn ped_picked_up(
+        &mut self,
+        now: Time,
+        ped: PedestrianID,
+        car: CarID,
+        blocked_time: Duration,
+        walking: &mut WalkingSimState,
+    ) {
+        let trip = &mut self.trips[self
+            .active_trip_mode
+            .remove(&AgentID::Pedestrian(ped))
+            .unwrap()
+            .0];
+        trip.total_blocked_time += blocked_time;
+
+        match trip.legs.pop_front() {
+            Some(TripLeg::Walk(SidewalkSpot {
+                connection: SidewalkPOI::Curb(_),
+                ..
+            })) => {}
+            _ => unreachable!(),
+        }
+        walking.ped_boarded_vehicle(now, ped);
+        self.active_trip_mode
+            .insert(AgentID::BusPassenger(trip.person, car), trip.id);
+        self.people[trip.person.0].on_bus = Some(car);
+    }
+
+    /// A ride-hail vehicle dropped off its passenger, who walks the rest of the way.
+    pub fn person_dropped_off(&mut self, now: Time, person: PersonID, car: CarID, ctx: &mut Ctx) {
+        let trip = &mut self.trips[self
+            .active_trip_mode
+            .remove(&AgentID::BusPassenger(person, car))
+            .unwrap()
+            .0];
+        let start = match trip.legs.pop_front() {
+            Some(TripLeg::RideHail(dropoff)) => dropoff,
+            _ => unreachable!(),
+        };
+        self.people[person.0].on_bus.take().unwrap();
+
+        let id = trip.id;
+        self.spawn_ped(now, id, start, ctx);
+    }
+
+    /// The pedestrian waits at the curb until a ride-hail vehicle arrives.
+    pub fn ped_reached_curb(
+        &mut self,
+        now: Time,
+        ped: PedestrianID,
+        blocked_time: Duration,
+        distance_crossed: Distance,
+        ctx: &mut Ctx,
+        ride_hail: &mut RideHailSimState,
+    ) {
+        let trip = &mut self.trips[self.active_trip_mode[&AgentID::Pedestrian(ped)].0];
+        trip.total_blocked_time += blocked_time;
+        trip.total_distance += distance_crossed;
+
+        let pickup = match trip.legs[0] {
+            TripLeg::Walk(SidewalkSpot {
+                connection: SidewalkPOI::Curb(pos),
+                ..
+            }) => pos,
+            _ => unreachable!(),
+        };
+        let dropoff = match trip.legs[1] {
+            TripLeg::RideHail(SidewalkSpot {
+                connection: SidewalkPOI::Curb(pos),
+                ..
+            }) => pos,
+            _ => unreachable!(),
+        };
+        let (id, person) = (trip.id, trip.person);
+        self.events.push(Event::TripPhaseStarting(
+            id,
+            person,
+            None,
+            TripPhaseType::WaitingForRideHail,
+        ));
+        ride_hail.request_ride(now, ped, id, person, pickup, dropoff, self, ctx);
+    }
+
+    /// The ride-hail vehicle couldn't reach the passenger's destination.
+    pub fn ride_hail_cancelled(
+        &mut self,
+        now: Time,
+        person: PersonID,
+        car: CarID,
+        reason: String,
+        ctx: &mut Ctx,
+    ) {
+        let trip = self
+            .active_trip_mode
+            .remove(&AgentID::BusPassenger(person, car))
+            .unwrap();
+        self.people[person.0].on_bus.take().unwrap();
+        self.cancel_trip(now, trip, reason, None, ctx);
+    }
+
+    /// Nobody came to pick up somebody waiting at the curb in time.
+    pub fn ride_hail_request_timed_out(
+        &mut self,
+        now: Time,
+        ped: PedestrianID,
+        reason: String,
+        ctx: &mut Ctx,
+    ) {
+        let trip = self
+            .active_trip_mode
+            .remove(&AgentID::Pedestrian(ped))
+            .unwrap();
+        self.cancel_trip(now, trip, reason, None, ctx);
+    }
+
     pub fn ped_reached_border(
         &mut self,
         now: Time,
@@ -937,7 +1174,7 @@ impl TripManager {
         };
 
         let req = PathRequest::walking(start.sidewalk_pos, walk_to.sidewalk_pos);
-        match ctx.map.pathfind(req) {
+        match ctx.map.pathfind_at(req, now) {
             Ok(path) => {
                 let person = &self.people[trip.person.0];
                 ctx.scheduler.push(
@@ -958,6 +1195,33 @@ impl TripManager {
             }
         }
     }
+
+    /// Driving trips rerouted by a traffic assignment use its congestion costs. Zones with time
+    /// windows are routed around using the restrictions in effect when the trip starts; the path
+    /// isn't revisited if a window opens or closes midway. Cars must also route around zones that
+    /// have reached their hourly cap.
+    fn pathfind_driving(
+        &mut self,
+        trip: TripID,
+        req: PathRequest,
+        now: Time,
+        ctx: &Ctx,
+    ) -> Result<Path> {
+        let map = ctx.map;
+        let assigned = if req.constraints == PathConstraints::Car {
+            self.assigned_routing
+                .as_ref()
+                .and_then(|a| a.routing_params(trip))
+        } else {
+            None
+        };
+        let (result, full_zones) = ctx.caps.pathfind(req, assigned, now, map);
+        for r in full_zones {
+            self.events
+                .push(Event::ZoneCapReached(trip, r, result.is_ok()));
+        }
+        result
+    }
 }
 
 // Cancelling trips
@@ -1064,9 +1328,26 @@ impl TripManager {
             }
         }
 
+        self.return_shared_vehicle(id);
         self.start_delayed_trip(now, person, ctx);
     }
 
+    fn return_shared_vehicle(&mut self, trip: TripID) {
+        if let Some(b) = self.micromobility.end_rental(trip) {
+            self.events.push(Event::SharedVehiclesAvailable(
+                b,
+                self.micromobility.num_available(b),
+            ));
+        }
+    }
+
+    /// When the person doing this trip plans to start their next one, if they have one
+    fn next_departure(&self, trip: TripID) -> Option<Time> {
+        let trips = &self.people[self.trips[trip.0].person.0].trips;
+        let idx = trips.iter().position(|t| *t == trip)?;
+        trips.get(idx + 1).map(|t| self.trips[t.0].info.departure)
+    }
+
     pub fn trip_abruptly_cancelled(&mut self, trip: TripID, agent: AgentID) {
         assert_eq!(self.active_trip_mode.remove(&agent), Some(trip));
     }
@@ -1083,6 +1364,12 @@ impl TripManager {
     pub fn num_active_agents(&self) -> usize {
         self.active_trip_mode.len()
     }
+    /// The scenario that driving trips were assigned routes for, if any
+    pub fn assigned_scenario(&self) -> Option<&str> {
+        self.assigned_routing
+            .as_ref()
+            .map(|a| a.scenario_name.as_str())
+    }
 
     pub fn trip_to_agent(&self, id: TripID) -> TripResult<AgentID> {
         if id.0 >= self.trips.len() {
@@ -1104,7 +1391,9 @@ impl TripManager {
         let a = match &trip.legs[0] {
             TripLeg::Walk(_) => AgentID::Pedestrian(person.ped),
             TripLeg::Drive(c, _) => AgentID::Car(*c),
-            TripLeg::RideBus(_, _) => AgentID::BusPassenger(person.id, person.on_bus.unwrap()),
+            TripLeg::RideBus(_, _) | TripLeg::RideHail(_) => {
+                AgentID::BusPassenger(person.id, person.on_bus.unwrap())
+            }
         };
         if self.active_trip_mode.get(&a) == Some(&id) {
             TripResult::Ok(a)
@@ -1164,6 +1453,7 @@ impl TripManager {
             trains,
             bus_riders: 0,
             train_riders: 0,
+            ride_hail_riders: 0,
         };
 
         for a in self.active_trip_mode.keys() {
@@ -1175,7 +1465,11 @@ impl TripManager {
                     VehicleType::Bike => {
                         cnt.cyclists += 1;
                     }
-                    VehicleType::Bus | VehicleType::Train => unreachable!(),
+                    VehicleType::Bus
+                    | VehicleType::Train
+                    | VehicleType::RideHail
+                    | VehicleType::Freight
+                    | VehicleType::Emergency => unreachable!(),
                 },
                 AgentID::BusPassenger(_, c) => match c.vehicle_type {
                     VehicleType::Bus => {
@@ -1184,7 +1478,13 @@ impl TripManager {
                     VehicleType::Train => {
                         cnt.train_riders += 1;
                     }
-                    VehicleType::Car | VehicleType::Bike => unreachable!(),
+                    VehicleType::RideHail => {
+                        cnt.ride_hail_riders += 1;
+                    }
+                    VehicleType::Car
+                    | VehicleType::Bike
+                    | VehicleType::Freight
+                    | VehicleType::Emergency => unreachable!(),
                 },
                 // These're counted separately
                 AgentID::Pedestrian(_) => {}
@@ -1243,6 +1543,11 @@ impl TripManager {
         people
     }
 
+    /// How many shared scooters and bikes are waiting in front of each building
+    pub fn micromobility_availability(&self) -> BTreeMap<BuildingID, usize> {
+        self.micromobility.all_available()
+    }
+
     pub fn get_person(&self, p: PersonID) -> Option<&Person> {
         self.people.get(p.0)
     }
@@ -1270,6 +1575,10 @@ impl TripManager {
                         // TODO Not true for long. People will be able to spawn at borders already
                         // on a bus.
                         TripMode::Transit => AgentType::Pedestrian,
+                        // Ride-hail trips always start at buildings
+                        TripMode::RideHail => AgentType::Pedestrian,
+                        // Shared vehicles can't be rented at a border, so these trips walk
+                        TripMode::Micromobility => AgentType::Pedestrian,
                     };
                     times.push((t.info.departure, agent_type));
                 }
@@ -1302,6 +1611,7 @@ impl TripManager {
                     .collect(),
             });
         }
+        scenario.micromobility = self.micromobility.get_fleets().clone();
         scenario
     }
 }
@@ -1352,6 +1662,8 @@ pub(crate) enum TripLeg {
     Drive(CarID, DrivingGoal),
     /// Maybe get off at a stop, maybe ride off-map
     RideBus(TransitRouteID, Option<TransitStopID>),
+    /// Where the ride-hail vehicle drops off the passenger
+    RideHail(SidewalkSpot),
 }
 
 pub enum TripResult<T> {
@@ -1427,4 +1739,5 @@ pub struct CommutersVehiclesCounts {
     pub trains: usize,
     pub bus_riders: usize,
     pub train_riders: usize,
+    pub ride_hail_riders: usize,
 }
@@ -20,7 +20,10 @@ pub use self::counts::TrafficCounts;
 pub use self::endpoint::TripEndpoint;
 pub use self::external::{ExternalPerson, ExternalTrip, ExternalTripEndpoint};
 pub use self::modifier::ScenarioModifier;
-pub use self::scenario::{IndividTrip, PersonSpec, Scenario, TripPurpose};
+pub use self::scenario::{
+    IndividTrip, MicromobilityFleet, MicromobilityStation, MicromobilityVehicle, PersonSpec,
+    Scenario, TripPurpose,
+};
 
 mod borders;
 mod counts;
@@ -39,6 +42,10 @@ pub enum TripMode {
     Bike,
     Transit,
     Drive,
+    /// Get picked up and dropped off by a taxi or ride-hail vehicle
+    RideHail,
+    /// Walk to a shared scooter or bike, ride it, and leave it at a dock or near the destination
+    Micromobility,
 }
 
 impl TripMode {
@@ -48,6 +55,8 @@ impl TripMode {
             TripMode::Bike,
             TripMode::Transit,
             TripMode::Drive,
+            TripMode::RideHail,
+            TripMode::Micromobility,
         ]
     }
 
@@ -57,6 +66,8 @@ impl TripMode {
             TripMode::Bike => "bike",
             TripMode::Transit => "use transit",
             TripMode::Drive => "drive",
+            TripMode::RideHail => "take a ride-hail",
+            TripMode::Micromobility => "ride a shared scooter or bike",
         }
     }
 
@@ -67,6 +78,8 @@ impl TripMode {
             TripMode::Bike => "biking",
             TripMode::Transit => "using transit",
             TripMode::Drive => "driving",
+            TripMode::RideHail => "riding in a ride-hail",
+            TripMode::Micromobility => "riding a shared scooter or bike",
         }
     }
 
@@ -76,16 +89,18 @@ impl TripMode {
             TripMode::Bike => "Bike",
             TripMode::Transit => "Bus",
             TripMode::Drive => "Car",
+            TripMode::RideHail => "Ride-hail",
+            TripMode::Micromobility => "Micromobility",
         }
     }
 
     pub fn to_constraints(self) -> PathConstraints {
         match self {
             TripMode::Walk => PathConstraints::Pedestrian,
-            TripMode::Bike => PathConstraints::Bike,
+            TripMode::Bike | TripMode::Micromobility => PathConstraints::Bike,
             // TODO WRONG
             TripMode::Transit => PathConstraints::Bus,
-            TripMode::Drive => PathConstraints::Car,
+            TripMode::Drive | TripMode::RideHail => PathConstraints::Car,
         }
     }
 
@@ -95,7 +110,7 @@ impl TripMode {
             PathConstraints::Bike => TripMode::Bike,
             // TODO The bijection breaks down... transit rider vs train vs bus...
             PathConstraints::Bus | PathConstraints::Train => TripMode::Transit,
-            PathConstraints::Car => TripMode::Drive,
+            PathConstraints::Car | PathConstraints::Truck => TripMode::Drive,
         }
     }
 }
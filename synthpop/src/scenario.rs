@@ -7,7 +7,7 @@ use serde::{Deserialize, Serialize};
 use abstio::{CityName, MapName};
 use abstutil::prettyprint_usize;
 use geom::Time;
-use map_model::Map;
+use map_model::{BuildingID, Map};
 
 use crate::{OrigPersonID, TripEndpoint, TripMode};
 
@@ -20,6 +20,10 @@ pub struct Scenario {
     pub people: Vec<PersonSpec>,
     /// None means seed all buses. Otherwise the route name must be present here.
     pub only_seed_buses: Option<BTreeSet<String>>,
+    /// Shared scooters and bikes available to people making micromobility trips.
+    ///
+    /// This changed the scenario format, so scenarios generated before it must be regenerated.
+    pub micromobility: Vec<MicromobilityFleet>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -64,6 +68,34 @@ impl IndividTrip {
     }
 }
 
+/// A shared scooter or bike operator. Riders pick up any available vehicle from the fleet within
+/// walking distance.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct MicromobilityFleet {
+    pub name: String,
+    pub vehicle: MicromobilityVehicle,
+    /// Riders can leave vehicles near their destination. Otherwise, they have to return them to a
+    /// station with a free dock.
+    pub dockless: bool,
+    /// Where vehicles are at the start of the day
+    pub stations: Vec<MicromobilityStation>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum MicromobilityVehicle {
+    Scooter,
+    Bike,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct MicromobilityStation {
+    /// Vehicles are picked up and left in front of this building
+    pub building: BuildingID,
+    /// The number of docks. Dockless fleets can leave more vehicles here.
+    pub capacity: usize,
+    pub initial_vehicles: usize,
+}
+
 /// Lifted from Seattle's Soundcast model, but seems general enough to use anyhere.
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub enum TripPurpose {
@@ -117,6 +149,7 @@ impl Scenario {
             map_name: map.get_name().clone(),
             people: Vec::new(),
             only_seed_buses: Some(BTreeSet::new()),
+            micromobility: Vec::new(),
         }
     }
 
@@ -1,5 +1,6 @@
 //! Integration tests
 
+use std::collections::BTreeMap;
 use std::io::Write;
 
 use anyhow::{bail, Result};
@@ -7,22 +8,42 @@ use fs_err::File;
 use rand::seq::SliceRandom;
 
 use abstio::{CityName, MapName};
-use abstutil::Timer;
-use geom::{Duration, Time};
-use map_model::{IntersectionID, LaneType, Map, Perimeter, RoadID};
-use sim::{AlertHandler, PrebakeSummary, Sim, SimFlags, SimOptions};
-use synthpop::{IndividTrip, PersonSpec, Scenario, TripEndpoint, TripMode, TripPurpose};
+use abstutil::{Tags, Timer};
+use geom::{Distance, Duration, Speed, Time};
+use map_model::{
+    AccessRestrictions, BuildingID, BuildingType, EditCmd, EditIntersection, Intersection,
+    IntersectionID, LaneID, LaneType, Map, PathConstraints, PathRequest, PathStep, PathStepV2,
+    PathfinderCaching, Perimeter, PermanentMapEdits, Position, RoadID,
+};
+use sim::{
+    AlertHandler, Event, PrebakeSummary, ScenarioGenerator, SignalDetectors, Sim, SimFlags,
+    SimOptions, TransitCapacity,
+};
+use synthpop::{
+    IndividTrip, MicromobilityFleet, MicromobilityStation, MicromobilityVehicle, PersonSpec,
+    Scenario, TripEndpoint, TripMode, TripPurpose,
+};
 
 fn main() -> Result<()> {
     abstutil::logger::setup();
     test_blockfinding()?;
-    test_lane_changing(&import_map(abstio::path(
-        "../tests/input/lane_selection.osm",
-    )))?;
+    let lane_selection = import_map(abstio::path("../tests/input/lane_selection.osm"));
+    test_lane_changing(&lane_selection)?;
+    test_pedestrian_crowding(&lane_selection)?;
     test_map_importer()?;
     check_proposals()?;
     ab_test_spurious_diff()?;
+    test_path_reroute()?;
+    test_informed_drivers()?;
+    test_truck_routing()?;
+    test_actuated_signals()?;
+    test_micromobility_docks()?;
+    test_ride_hail()?;
+    test_turn_restriction_edits()?;
+    test_road_network_edits()?;
+    test_building_edits()?;
     bus_test()?;
+    test_transit_capacity()?;
     bus_route_test()?;
     smoke_test()?;
     Ok(())
@@ -101,23 +122,47 @@ fn smoke_test() -> Result<()> {
 /// Verify all edits under version control can be correctly apply to their map.
 fn check_proposals() -> Result<()> {
     let mut timer = Timer::new("check all proposals");
+    // Everything allowed through before trucks existed
+    let unrestricted = PathConstraints::Pedestrian
+        | PathConstraints::Car
+        | PathConstraints::Bike
+        | PathConstraints::Bus
+        | PathConstraints::Train;
     for name in abstio::list_all_objects(abstio::path("system/proposals")) {
-        match abstio::maybe_read_json::<map_model::PermanentMapEdits>(
-            abstio::path(format!("system/proposals/{}.json", name)),
-            &mut timer,
-        ) {
-            Ok(perma) => {
-                let map = map_model::Map::load_synchronously(perma.map_name.path(), &mut timer);
-                if let Err(err) = perma.clone().into_edits(&map) {
-                    abstio::write_json(
-                        "repair_attempt.json".to_string(),
-                        &perma.into_edits_permissive(&map).to_permanent(&map),
-                    );
-                    anyhow::bail!("{} is out-of-date: {}", name, err);
-                }
+        let path = abstio::path(format!("system/proposals/{}.json", name));
+        let value: serde_json::Value = match serde_json::from_slice(&abstio::slurp_file(path)?) {
+            Ok(value) => value,
+            Err(err) => {
+                bail!("{} JSON is broken: {}", name, err);
             }
+        };
+        let map_name: MapName = serde_json::from_value(value["map_name"].clone())?;
+        let map = Map::load_synchronously(map_name.path(), &mut timer);
+        let perma = PermanentMapEdits::from_json(value, &map)?;
+        let edits = match perma.clone().into_edits(&map) {
+            Ok(edits) => edits,
             Err(err) => {
-                anyhow::bail!("{} JSON is broken: {}", name, err);
+                abstio::write_json(
+                    "repair_attempt.json".to_string(),
+                    &perma.into_edits_permissive(&map).to_permanent(&map),
+                );
+                bail!("{} is out-of-date: {}", name, err);
+            }
+        };
+
+        // Older versions of the format don't know about trucks. Roads that allowed everything
+        // then should still be unrestricted.
+        for cmd in &edits.commands {
+            if let EditCmd::ChangeRoad { r, old, new } = cmd {
+                for access in [&old.access_restrictions, &new.access_restrictions] {
+                    if access.allow_through_traffic.is_superset(unrestricted)
+                        && access.time_windows.is_empty()
+                        && access.cap_vehicles_per_hour.is_none()
+                        && access != &AccessRestrictions::new()
+                    {
+                        bail!("{} has {} restricted after upgrading", name, r);
+                    }
+                }
             }
         }
     }
@@ -198,6 +243,62 @@ fn test_lane_changing(map: &Map) -> Result<()> {
     Ok(())
 }
 
+/// With pedestrian crowding enabled, send a crowd big enough to jam the sidewalks and crosswalks
+/// through one intersection, and make sure everybody still gets through.
+fn test_pedestrian_crowding(map: &Map) -> Result<()> {
+    let north = IntersectionID(7);
+    let south = IntersectionID(0);
+
+    let mut scenario = Scenario::empty(map, "pedestrian_crowding");
+    for idx in 0..2000 {
+        scenario.people.push(PersonSpec {
+            orig_id: None,
+            trips: vec![IndividTrip::new(
+                // Everybody shows up within a minute
+                Time::START_OF_DAY + Duration::seconds((idx % 60) as f64),
+                TripPurpose::Recreation,
+                TripEndpoint::Border(north),
+                TripEndpoint::Border(south),
+                TripMode::Walk,
+            )],
+        });
+    }
+
+    let mut opts = sim::SimOptions::new("test_pedestrian_crowding");
+    opts.alerts = sim::AlertHandler::Silence;
+    opts.pedestrian_crowding = true;
+    let mut sim = sim::Sim::new(map, opts);
+    let mut rng = sim::SimFlags::for_test("test_pedestrian_crowding").make_rng();
+    sim.instantiate(&scenario, map, &mut rng, &mut Timer::throwaway());
+    let limit = Time::START_OF_DAY + Duration::hours(3);
+    while !sim.is_done() && sim.time() < limit {
+        sim.tiny_step(map, &mut None);
+    }
+
+    if !sim.is_done() {
+        bail!("The pedestrian crowd is still walking at {}", sim.time());
+    }
+    let analytics = sim.get_analytics();
+    if analytics
+        .finished_trips
+        .iter()
+        .any(|(_, _, _, duration)| duration.is_none())
+    {
+        bail!("Some pedestrians in the crowd cancelled their trip");
+    }
+    // Make sure the crowd was dense enough to matter
+    if !analytics
+        .problems_per_trip
+        .values()
+        .flatten()
+        .any(|(_, problem)| matches!(problem, sim::Problem::PedestrianOvercrowding(_)))
+    {
+        bail!("The pedestrian crowd never got crowded; the test is somehow broken");
+    }
+
+    Ok(())
+}
+
 /// Generate single blocks and merged LTN-style blocks for some maps, counting the number of
 /// failures. Store in a goldenfile, so somebody can manually do a visual diff if anything changes.
 fn test_blockfinding() -> Result<()> {
@@ -304,6 +405,707 @@ fn ab_test_spurious_diff() -> Result<()> {
     Ok(())
 }
 
+/// Swap the rest of a route for a detour, and make sure the path matches the detour exactly,
+/// including its length.
+fn test_path_reroute() -> Result<()> {
+    let mut timer = Timer::new("test Path::reroute");
+    let map = map_model::Map::load_synchronously(MapName::seattle("montlake").path(), &mut timer);
+    let driving_lanes: Vec<LaneID> = map
+        .all_lanes()
+        .filter(|l| l.is_driving())
+        .map(|l| l.id)
+        .collect();
+    let mut rng = SimFlags::for_test("test_path_reroute").make_rng();
+
+    let mut num_detours = 0;
+    for _ in 0..100 {
+        let start = *driving_lanes.choose(&mut rng).unwrap();
+        let end = *driving_lanes.choose(&mut rng).unwrap();
+        let req = PathRequest::vehicle(
+            Position::start(start),
+            Position::end(end, &map),
+            PathConstraints::Car,
+        );
+        let mut path = match map.pathfind(req.clone()) {
+            Ok(path) => path,
+            Err(_) => continue,
+        };
+
+        // Steps alternate between lanes and turns, so this is a lane in the middle of the route
+        let idx = path.get_steps().len() / 2;
+        let avoid = match path.get_steps()[idx - idx % 2] {
+            PathStep::Lane(l) => l.road,
+            _ => unreachable!(),
+        };
+        if avoid == start.road || avoid == end.road {
+            continue;
+        }
+        let mut params = map.routing_params().clone();
+        params.avoid_roads.insert(avoid);
+        let detour = match map.pathfind_with_params(req, &params, PathfinderCaching::NoCache) {
+            Ok(detour) => detour,
+            Err(_) => continue,
+        };
+
+        path.reroute(detour.clone(), &map);
+        if path.get_steps() != detour.get_steps() {
+            bail!(
+                "Rerouting from {} to {} didn't follow the detour",
+                start,
+                end
+            );
+        }
+        if (path.total_length() - detour.total_length()).abs() > Distance::meters(0.1) {
+            bail!(
+                "Rerouting from {} to {} has length {}, but the detour is {}",
+                start,
+                end,
+                path.total_length(),
+                detour.total_length()
+            );
+        }
+        num_detours += 1;
+    }
+    if num_detours == 0 {
+        bail!("No detours found; the test is somehow broken");
+    }
+    Ok(())
+}
+
+/// Trucks are routed on a graph built only when they're first needed. Their routes must avoid
+/// roads closed to heavy goods vehicles, and can't be faster than a car's.
+fn test_truck_routing() -> Result<()> {
+    let mut timer = Timer::new("test truck routing");
+    let map = map_model::Map::load_synchronously(MapName::seattle("montlake").path(), &mut timer);
+    let driving_lanes: Vec<LaneID> = map
+        .all_lanes()
+        .filter(|l| PathConstraints::Truck.can_use(l, &map))
+        .map(|l| l.id)
+        .collect();
+    let mut rng = SimFlags::for_test("test_truck_routing").make_rng();
+
+    let mut num_paths = 0;
+    for _ in 0..100 {
+        let start = *driving_lanes.choose(&mut rng).unwrap();
+        let end = *driving_lanes.choose(&mut rng).unwrap();
+        let truck_req = PathRequest::vehicle(
+            Position::start(start),
+            Position::end(end, &map),
+            PathConstraints::Truck,
+        );
+        let truck = match map.pathfind_v2(truck_req) {
+            Ok(path) => path,
+            Err(_) => continue,
+        };
+        for step in truck.get_steps() {
+            if let PathStepV2::Along(dr) = step {
+                if map.get_r(dr.road).osm_tags.is("hgv", "no") {
+                    bail!("Truck route from {} to {} uses {}", start, end, dr.road);
+                }
+            }
+        }
+
+        let car_req = PathRequest::vehicle(
+            Position::start(start),
+            Position::end(end, &map),
+            PathConstraints::Car,
+        );
+        let car = map.pathfind_v2(car_req)?;
+        if truck.get_cost() < car.get_cost() {
+            bail!(
+                "Truck route from {} to {} costs {}, but a car's only costs {}",
+                start,
+                end,
+                truck.get_cost(),
+                car.get_cost()
+            );
+        }
+        num_paths += 1;
+    }
+    if num_paths == 0 {
+        bail!("No truck routes found; the test is somehow broken");
+    }
+    Ok(())
+}
+
+/// Let every driver react to live congestion through the morning, and make sure some of them
+/// switch routes without changing where they're going.
+fn test_informed_drivers() -> Result<()> {
+    let mut timer = Timer::new("test informed drivers");
+    let map = map_model::Map::load_synchronously(MapName::seattle("montlake").path(), &mut timer);
+    let scenario: Scenario =
+        abstio::read_binary(abstio::path_scenario(map.get_name(), "weekday"), &mut timer);
+
+    let mut opts = SimOptions::new("test_informed_drivers");
+    opts.alerts = AlertHandler::Silence;
+    opts.informed_drivers = Some(1.0);
+    let mut sim = Sim::new(&map, opts);
+    let mut rng = SimFlags::for_test("test_informed_drivers").make_rng();
+    sim.instantiate(&scenario, &map, &mut rng, &mut timer);
+    sim.start_capturing_events();
+    sim.timed_step(&map, Duration::hours(9), &mut None, &mut timer);
+
+    let mut num_reroutes = 0;
+    for (_, ev) in sim.drain_captured_events() {
+        if let Event::VehicleRerouted(car, _, old_path, new_path) = ev {
+            if old_path.current_step() != new_path.current_step()
+                || old_path.last_step() != new_path.last_step()
+            {
+                bail!(
+                    "{} switched to a route that doesn't start and end at the same place",
+                    car
+                );
+            }
+            num_reroutes += 1;
+        }
+    }
+    if num_reroutes == 0 {
+        bail!("Nobody rerouted around congestion; the test is somehow broken");
+    }
+    Ok(())
+}
+
+/// Run actuated signals through part of the morning. The detectors on one approach are moved
+/// further back than the rest. Every signal's first stage keeps ending at the same point in its
+/// fixed-time cycle, so neighboring signals stay coordinated.
+fn test_actuated_signals() -> Result<()> {
+    let mut timer = Timer::new("test actuated signals");
+    let map = map_model::Map::load_synchronously(MapName::seattle("montlake").path(), &mut timer);
+    let scenario: Scenario =
+        abstio::read_binary(abstio::path_scenario(map.get_name(), "weekday"), &mut timer);
+
+    // Timing plans and priority can legitimately interrupt the cycle, so skip those signals
+    let signals: Vec<IntersectionID> = map
+        .all_intersections()
+        .iter()
+        .filter(|i| {
+            i.is_traffic_signal() && {
+                let signal = map.get_traffic_signal(i.id);
+                signal.plans.is_empty() && signal.priority.is_none()
+            }
+        })
+        .map(|i| i.id)
+        .collect();
+    if signals.is_empty() {
+        bail!("No traffic signals to test; the test is somehow broken");
+    }
+    let approach = map
+        .get_i(signals[0])
+        .incoming_lanes
+        .iter()
+        .map(|l| map.get_l(*l))
+        .find(|l| l.is_driving())
+        .unwrap()
+        .get_directed_parent();
+    let mut approaches = BTreeMap::new();
+    approaches.insert(approach, 60.0);
+
+    let mut opts = SimOptions::new("test_actuated_signals");
+    opts.alerts = AlertHandler::Silence;
+    opts.actuated_signal_detectors = Some(SignalDetectors {
+        default_meters: 20.0,
+        approaches,
+    });
+    let mut sim = Sim::new(&map, opts);
+    let mut rng = SimFlags::for_test("test_actuated_signals").make_rng();
+    sim.instantiate(&scenario, &map, &mut rng, &mut timer);
+    sim.timed_step(&map, Duration::hours(7), &mut None, &mut timer);
+
+    for reading in sim.get_signal_detectors(&map, signals[0]) {
+        let lane = map.get_l(reading.lane);
+        let meters = Distance::meters(if lane.get_directed_parent() == approach {
+            60.0
+        } else {
+            20.0
+        });
+        let expected = if meters > lane.length() {
+            Distance::ZERO
+        } else {
+            lane.length() - meters
+        };
+        if reading.dist_along != expected {
+            bail!(
+                "The detector on {} is {} along, but should be {} along",
+                reading.lane,
+                reading.dist_along,
+                expected
+            );
+        }
+    }
+
+    for _ in 0..600 {
+        sim.timed_step(&map, Duration::seconds(1.0), &mut None, &mut timer);
+        for i in &signals {
+            let (stage, remaining) = sim.current_stage_and_remaining_time(*i);
+            // When nobody else wants a green, the signal rests in the first stage one second at
+            // a time
+            if stage != 0 || remaining <= Duration::seconds(1.0) {
+                continue;
+            }
+            let signal = map.get_traffic_signal(*i);
+            let cycle_length: Duration = signal
+                .stages
+                .iter()
+                .map(|s| s.stage_type.simple_duration())
+                .sum();
+            // Like SignalState::new, the cycle starts with the first stage, so it yields after
+            // that stage's fixed duration
+            let since_yield =
+                ((sim.time() + remaining - Time::START_OF_DAY) + signal.offset + cycle_length
+                    - signal.stages[0].stage_type.simple_duration())
+                    % cycle_length;
+            if since_yield.min(cycle_length - since_yield) > Duration::seconds(0.01) {
+                bail!(
+                    "At {}, {} will end its first stage {} after its yield point",
+                    sim.time(),
+                    i,
+                    since_yield
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Three people want to ride shared bikes between the same two stations, but only one dock is free
+/// at the destination. The first rental reserves it, so the others walk instead.
+fn test_micromobility_docks() -> Result<()> {
+    let mut timer = Timer::new("test micromobility docks");
+    let map = map_model::Map::load_synchronously(MapName::seattle("montlake").path(), &mut timer);
+    let bikeable: Vec<_> = map
+        .all_buildings()
+        .iter()
+        .filter(|b| b.biking_connection(&map).is_some())
+        .collect();
+    let start = bikeable[0];
+    let end = bikeable
+        .iter()
+        .max_by_key(|b| b.polygon.center().dist_to(start.polygon.center()))
+        .unwrap();
+
+    let mut scenario = Scenario::empty(&map, "micromobility_docks");
+    scenario.micromobility.push(MicromobilityFleet {
+        name: "test fleet".to_string(),
+        vehicle: MicromobilityVehicle::Bike,
+        dockless: false,
+        stations: vec![
+            MicromobilityStation {
+                building: start.id,
+                capacity: 3,
+                initial_vehicles: 3,
+            },
+            MicromobilityStation {
+                building: end.id,
+                capacity: 1,
+                initial_vehicles: 0,
+            },
+        ],
+    });
+    for idx in 0..3 {
+        scenario.people.push(PersonSpec {
+            orig_id: None,
+            trips: vec![IndividTrip::new(
+                Time::START_OF_DAY + Duration::seconds(idx as f64),
+                TripPurpose::Recreation,
+                TripEndpoint::Building(start.id),
+                TripEndpoint::Building(end.id),
+                TripMode::Micromobility,
+            )],
+        });
+    }
+
+    let mut opts = SimOptions::new("test_micromobility_docks");
+    opts.alerts = AlertHandler::Silence;
+    let mut sim = Sim::new(&map, opts);
+    let mut rng = SimFlags::for_test("test_micromobility_docks").make_rng();
+    sim.instantiate(&scenario, &map, &mut rng, &mut timer);
+    sim.start_capturing_events();
+    let limit = Time::START_OF_DAY + Duration::hours(3);
+    while !sim.is_done() && sim.time() < limit {
+        sim.tiny_step(&map, &mut None);
+    }
+    if !sim.is_done() {
+        bail!("Micromobility trips are still going at {}", sim.time());
+    }
+
+    let num_walked = sim
+        .drain_captured_events()
+        .into_iter()
+        .filter(|(_, ev)| matches!(ev, Event::NoSharedVehicleAvailable(_, _)))
+        .count();
+    if num_walked != 2 {
+        bail!(
+            "{} people walked, but only the first should've rented",
+            num_walked
+        );
+    }
+    let available = sim.get_micromobility_availability();
+    let at = |b| available.get(&b).cloned().unwrap_or(0);
+    if at(start.id) != 2 || at(end.id) != 1 {
+        bail!(
+            "After the trips, {} bikes are at the start and {} at the end, not 2 and 1",
+            at(start.id),
+            at(end.id)
+        );
+    }
+    Ok(())
+}
+
+/// Three people ask for a ride from the same building, but the fleet only has one vehicle. The
+/// first person is driven to their destination. The vehicle is still busy when the others run out
+/// of patience, so their trips are cancelled.
+fn test_ride_hail() -> Result<()> {
+    let mut timer = Timer::new("test ride-hail");
+    let map = map_model::Map::load_synchronously(MapName::seattle("montlake").path(), &mut timer);
+    let curbside: Vec<_> = map
+        .all_buildings()
+        .iter()
+        .filter(|b| b.driving_connection(&map).is_some())
+        .collect();
+    let start = curbside[0];
+    let end = curbside
+        .iter()
+        .max_by_key(|b| b.polygon.center().dist_to(start.polygon.center()))
+        .unwrap();
+
+    let mut scenario = Scenario::empty(&map, "ride_hail");
+    for _ in 0..3 {
+        scenario.people.push(PersonSpec {
+            orig_id: None,
+            trips: vec![IndividTrip::new(
+                Time::START_OF_DAY,
+                TripPurpose::Recreation,
+                TripEndpoint::Building(start.id),
+                TripEndpoint::Building(end.id),
+                TripMode::RideHail,
+            )],
+        });
+    }
+
+    let mut opts = SimOptions::new("test_ride_hail");
+    opts.alerts = AlertHandler::Silence;
+    opts.ride_hail_fleet_size = 1;
+    opts.ride_hail_max_wait = Duration::minutes(1);
+    let mut sim = Sim::new(&map, opts);
+    let mut rng = SimFlags::for_test("test_ride_hail").make_rng();
+    sim.instantiate(&scenario, &map, &mut rng, &mut timer);
+    sim.start_capturing_events();
+    let limit = Time::START_OF_DAY + Duration::hours(3);
+    while !sim.is_done() && sim.time() < limit {
+        sim.tiny_step(&map, &mut None);
+    }
+    if !sim.is_done() {
+        bail!("Ride-hail trips are still going at {}", sim.time());
+    }
+
+    let (mut finished, mut cancelled, mut empty_legs, mut full_legs) = (0, 0, 0, 0);
+    for (_, ev) in sim.drain_captured_events() {
+        match ev {
+            Event::TripFinished { mode, .. } => {
+                if mode != TripMode::RideHail {
+                    bail!(
+                        "A {:?} trip finished, but everybody should've taken a ride",
+                        mode
+                    );
+                }
+                finished += 1;
+            }
+            Event::TripCancelled(trip, _) => {
+                let reason = sim.trip_info(trip).cancellation_reason.unwrap();
+                if !reason.starts_with("no ride-hail vehicle came") {
+                    bail!("{} was cancelled because {}", trip, reason);
+                }
+                cancelled += 1;
+            }
+            Event::RideHailLegFinished(_, None, _) => {
+                empty_legs += 1;
+            }
+            Event::RideHailLegFinished(_, Some(_), _) => {
+                full_legs += 1;
+            }
+            _ => {}
+        }
+    }
+    if finished != 1 || cancelled != 2 {
+        bail!(
+            "{} ride-hail trips finished and {} were cancelled, not 1 and 2",
+            finished,
+            cancelled
+        );
+    }
+    if empty_legs != 1 || full_legs != 1 {
+        bail!(
+            "The vehicle drove {} legs empty and {} with a passenger, not 1 and 1",
+            empty_legs,
+            full_legs
+        );
+    }
+    Ok(())
+}
+
+/// Ban a movement at a traffic signal with custom timing, then undo that. The signal should keep
+/// its timing throughout, and the edits should survive a round trip through the permanent format.
+fn test_turn_restriction_edits() -> Result<()> {
+    let mut timer = Timer::new("test turn restriction edits");
+    let mut map =
+        map_model::Map::load_synchronously(MapName::seattle("montlake").path(), &mut timer);
+    let (i, from, to) = map
+        .all_intersections()
+        .iter()
+        .filter(|i| i.is_traffic_signal())
+        .find_map(|i| {
+            i.movements
+                .keys()
+                .find(|m| !m.crosswalk && m.from.road != m.to.road)
+                .map(|m| (i.id, m.from.road, m.to.road))
+        })
+        .unwrap();
+    let offset = Duration::seconds(7.0);
+
+    let mut signal = map.get_traffic_signal(i).clone();
+    signal.offset = offset;
+    let mut edits = map.get_edits().clone();
+    edits.commands.push(EditCmd::ChangeIntersection {
+        i,
+        old: map.get_i_edit(i),
+        new: EditIntersection::TrafficSignal(signal.export(&map)),
+    });
+    let old = map.get_i_turn_restrictions_edit(i);
+    let mut new = old.clone();
+    new.0.insert((from, to));
+    edits.commands.push(EditCmd::ChangeTurnRestrictions {
+        i,
+        old: old.clone(),
+        new: new.clone(),
+    });
+    map.must_apply_edits(edits, &mut timer);
+
+    let check_signal = |map: &Map, banned: bool| -> Result<()> {
+        let signal = map.get_traffic_signal(i);
+        if signal.offset != offset {
+            bail!(
+                "Changing turn restrictions at {} reset the signal timing",
+                i
+            );
+        }
+        signal.validate(map.get_i(i))?;
+        let has_movement = map
+            .get_i(i)
+            .movements
+            .keys()
+            .any(|m| !m.crosswalk && m.from.road == from && m.to.road == to);
+        if has_movement == banned {
+            bail!(
+                "After changing turn restrictions at {}, the movement from {} to {} exists: {}",
+                i,
+                from,
+                to,
+                has_movement
+            );
+        }
+        Ok(())
+    };
+    check_signal(&map, true)?;
+
+    let mut fresh =
+        map_model::Map::load_synchronously(MapName::seattle("montlake").path(), &mut timer);
+    let perma = map.get_edits().to_permanent(&map);
+    fresh.must_apply_edits(perma.into_edits(&fresh)?, &mut timer);
+    if fresh.get_i_turn_restrictions_edit(i) != new {
+        bail!("Turn restrictions at {} didn't survive a round trip", i);
+    }
+    check_signal(&fresh, true)?;
+
+    let mut compressed = map.get_edits().clone();
+    compressed.compress(&map);
+    let mut fresh =
+        map_model::Map::load_synchronously(MapName::seattle("montlake").path(), &mut timer);
+    fresh.must_apply_edits(compressed, &mut timer);
+    if fresh.get_i_turn_restrictions_edit(i) != new {
+        bail!(
+            "Turn restrictions at {} didn't survive compressing the edits",
+            i
+        );
+    }
+    check_signal(&fresh, true)?;
+
+    // Undo the ban
+    let mut edits = map.get_edits().clone();
+    edits.commands.pop().unwrap();
+    map.must_apply_edits(edits, &mut timer);
+    if map.get_i_turn_restrictions_edit(i) != old {
+        bail!(
+            "Undoing the turn restriction edit at {} didn't restore it",
+            i
+        );
+    }
+    check_signal(&map, false)?;
+
+    Ok(())
+}
+
+fn test_road_network_edits() -> Result<()> {
+    let mut timer = Timer::new("test road network edits");
+    let mut map =
+        map_model::Map::load_synchronously(MapName::seattle("montlake").path(), &mut timer);
+    let num_roads = map.all_roads().len();
+    let num_intersections = map.all_intersections().len();
+
+    // Split one basemap road and delete another, far enough apart not to touch
+    let split = map
+        .all_roads()
+        .iter()
+        .find(|r| map.split_road_cmd(r.id, r.length() / 2.0).is_ok())
+        .unwrap();
+    let (split, orig_split_length) = (split.id, split.untrimmed_center_pts.length());
+    let delete = map
+        .all_roads()
+        .iter()
+        .find(|r| {
+            let ends = [r.src_i, r.dst_i];
+            let split = map.get_r(split);
+            !ends.contains(&split.src_i)
+                && !ends.contains(&split.dst_i)
+                && map.delete_road_cmd(r.id).is_ok()
+        })
+        .unwrap();
+    let (delete, orig_delete_lanes) = (delete.id, delete.lanes.len());
+    // And connect two nearby intersections that aren't already connected
+    let create_road_cmd = |map: &Map, i1, i2| {
+        let mut tags = Tags::empty();
+        tags.insert("highway", "residential");
+        map.create_road_cmd(i1, i2, Vec::new(), tags, Speed::miles_per_hour(25.0))
+    };
+    let untouched = |i: &&Intersection| !i.roads.contains(&split) && !i.roads.contains(&delete);
+    let (src_i, dst_i) = map
+        .all_intersections()
+        .iter()
+        .filter(untouched)
+        .flat_map(|i1| {
+            map.all_intersections()
+                .iter()
+                .filter(untouched)
+                .map(move |i2| (i1, i2))
+        })
+        .find(|(i1, i2)| {
+            i1.id < i2.id
+                && i1.polygon.center().dist_to(i2.polygon.center()) < Distance::meters(150.0)
+                && !i1.roads.iter().any(|r| i2.roads.contains(r))
+                && create_road_cmd(&map, i1.id, i2.id).is_ok()
+        })
+        .map(|(i1, i2)| (i1.id, i2.id))
+        .unwrap();
+
+    let mut edits = map.get_edits().clone();
+    edits
+        .commands
+        .push(map.split_road_cmd(split, map.get_r(split).length() / 2.0)?);
+    edits.commands.push(map.delete_road_cmd(delete)?);
+    map.must_apply_edits(edits, &mut timer);
+    // The new road's ID depends on the earlier commands, so ask for it last
+    let mut edits = map.get_edits().clone();
+    edits.commands.push(create_road_cmd(&map, src_i, dst_i)?);
+    map.must_apply_edits(edits, &mut timer);
+
+    let check = |map: &Map| -> Result<()> {
+        if map.all_roads().len() != num_roads + 2
+            || map.all_intersections().len() != num_intersections + 1
+        {
+            bail!(
+                "After splitting and creating a road, there are {} roads and {} intersections",
+                map.all_roads().len(),
+                map.all_intersections().len()
+            );
+        }
+        let new_i = IntersectionID(num_intersections);
+        let (first, second) = (map.get_r(split), map.get_r(RoadID(num_roads)));
+        if first.dst_i != new_i
+            || second.src_i != new_i
+            || map.get_i(new_i).roads.len() != 2
+            || second.lanes.len() != first.lanes.len()
+        {
+            bail!(
+                "Splitting {} didn't connect the two halves at {}",
+                split,
+                new_i
+            );
+        }
+        if !map.get_r(delete).is_deleted()
+            || map.get_i(map.get_r(delete).src_i).roads.contains(&delete)
+        {
+            bail!("{} wasn't deleted", delete);
+        }
+        let created = map.get_r(RoadID(num_roads + 1));
+        if created.src_i != src_i || created.dst_i != dst_i || created.lanes.is_empty() {
+            bail!(
+                "{} wasn't created between {} and {}",
+                created.id,
+                src_i,
+                dst_i
+            );
+        }
+        Ok(())
+    };
+    check(&map)?;
+
+    let mut fresh =
+        map_model::Map::load_synchronously(MapName::seattle("montlake").path(), &mut timer);
+    let perma = map.get_edits().to_permanent(&map);
+    fresh.must_apply_edits(perma.into_edits(&fresh)?, &mut timer);
+    check(&fresh)?;
+    if fresh.get_r(RoadID(num_roads)).center_pts != map.get_r(RoadID(num_roads)).center_pts {
+        bail!("Splitting {} didn't survive a round trip", split);
+    }
+
+    // Compressing keeps the road network changes and any edits to the roads involved, and still
+    // compresses edits elsewhere
+    let other = map
+        .all_roads()
+        .iter()
+        .find(|r| r.id != split && r.id != delete && !r.is_deleted() && r.id.0 < num_roads)
+        .unwrap()
+        .id;
+    let speed_limit = Speed::miles_per_hour(15.0);
+    let mut edits = map.get_edits().clone();
+    for r in [split, other] {
+        edits.commands.push(map.edit_road_cmd(r, |new| {
+            new.speed_limit = speed_limit;
+        }));
+    }
+    map.must_apply_edits(edits, &mut timer);
+    let mut compressed = map.get_edits().clone();
+    compressed.compress(&map);
+    let mut fresh =
+        map_model::Map::load_synchronously(MapName::seattle("montlake").path(), &mut timer);
+    fresh.must_apply_edits(compressed, &mut timer);
+    check(&fresh)?;
+    for r in [split, other] {
+        if fresh.get_r(r).speed_limit != speed_limit {
+            bail!("Editing {} didn't survive compressing the edits", r);
+        }
+    }
+
+    // Undo everything
+    let mut edits = map.get_edits().clone();
+    edits.commands.clear();
+    map.must_apply_edits(edits, &mut timer);
+    if map.all_roads().len() != num_roads || map.all_intersections().len() != num_intersections {
+        bail!("Undoing didn't remove the new roads and intersections");
+    }
+    if (map.get_r(split).untrimmed_center_pts.length() - orig_split_length).abs()
+        > Distance::meters(0.1)
+    {
+        bail!("Undoing didn't restore the geometry of {}", split);
+    }
+    if map.get_r(delete).lanes.len() != orig_delete_lanes
+        || !map.get_i(map.get_r(delete).src_i).roads.contains(&delete)
+    {
+        bail!("Undoing didn't restore {}", delete);
+    }
+
+    Ok(())
+}
+
 fn run_sim(map: &Map, scenario: &Scenario, timer: &mut Timer) -> PrebakeSummary {
     let mut opts = SimOptions::new("prebaked");
     opts.alerts = AlertHandler::Silence;
@@ -378,3 +1180,128 @@ fn bus_test() -> Result<()> {
     }
     Ok(())
 }
+
+/// With tiny buses, riders should be left behind at stops, and no bus should ever carry more
+/// people than fit.
+fn test_transit_capacity() -> Result<()> {
+    let mut timer = Timer::new("test transit capacity");
+    let map = map_model::Map::load_synchronously(MapName::seattle("arboretum").path(), &mut timer);
+    let scenario: Scenario =
+        abstio::read_binary(abstio::path_scenario(map.get_name(), "weekday"), &mut timer);
+
+    let mut opts = SimOptions::new("test_transit_capacity");
+    opts.alerts = AlertHandler::Silence;
+    opts.bus_capacity = TransitCapacity::parse("1+1")?;
+    let mut sim = Sim::new(&map, opts);
+    let mut rng = SimFlags::for_test("test_transit_capacity").make_rng();
+    sim.instantiate(&scenario, &map, &mut rng, &mut timer);
+    sim.start_capturing_events();
+    sim.timed_step(&map, Duration::hours(9), &mut None, &mut timer);
+
+    let mut num_denied = 0;
+    for (_, ev) in sim.drain_captured_events() {
+        match ev {
+            Event::BusDepartedFromStop(bus, _, stop, load) => {
+                if load > sim.transit_capacity(bus.vehicle_type).unwrap().total() {
+                    bail!("{} left {} with {} passengers", bus, stop, load);
+                }
+            }
+            Event::PassengersDeniedBoarding(_, _, _, count) => {
+                num_denied += count;
+            }
+            _ => {}
+        }
+    }
+    if num_denied == 0 {
+        bail!("Nobody was left behind by a full bus; the test is somehow broken");
+    }
+    Ok(())
+}
+
+/// Empty out a building where some people live, and add jobs to another. Matching the scenario to
+/// those edits should cancel the residents' trips and add new commuters.
+fn test_building_edits() -> Result<()> {
+    let mut timer = Timer::new("test building edits");
+    let mut map =
+        map_model::Map::load_synchronously(MapName::seattle("montlake").path(), &mut timer);
+    let scenario: Scenario =
+        abstio::read_binary(abstio::path_scenario(map.get_name(), "weekday"), &mut timer);
+
+    let lives_in = |s: &Scenario, b: BuildingID| {
+        s.people
+            .iter()
+            .filter(|p| {
+                p.trips.iter().any(|t| !t.cancelled)
+                    && p.trips[0].origin == TripEndpoint::Building(b)
+            })
+            .count()
+    };
+    let (home, num_residents) = map
+        .all_buildings()
+        .iter()
+        .find_map(|b| match b.bldg_type {
+            BuildingType::Residential { num_residents, .. } if lives_in(&scenario, b.id) > 0 => {
+                Some((b.id, num_residents))
+            }
+            _ => None,
+        })
+        .unwrap();
+    let office = map
+        .all_buildings()
+        .iter()
+        .find(|b| b.id != home && matches!(b.bldg_type, BuildingType::Commercial(_)))
+        .unwrap()
+        .id;
+    let num_new_jobs = 5;
+
+    let mut edits = map.get_edits().clone();
+    let old = map.get_b_edit(home);
+    let mut new = old.clone();
+    new.bldg_type = BuildingType::Empty;
+    edits
+        .commands
+        .push(EditCmd::ChangeBuilding { b: home, old, new });
+    let old = map.get_b_edit(office);
+    let mut new = old.clone();
+    if let BuildingType::Commercial(ref mut workers) = new.bldg_type {
+        *workers += num_new_jobs;
+    }
+    edits.commands.push(EditCmd::ChangeBuilding {
+        b: office,
+        old,
+        new,
+    });
+    map.must_apply_edits(edits, &mut timer);
+
+    let mut rng = SimFlags::for_test("test_building_edits").make_rng();
+    let matched =
+        ScenarioGenerator::match_building_edits(&map, scenario.clone(), &mut rng, &mut timer);
+
+    let expected = lives_in(&scenario, home).saturating_sub(num_residents);
+    if lives_in(&matched, home) != expected {
+        bail!(
+            "{} people still live in the emptied {}, but there should be {}",
+            lives_in(&matched, home),
+            home,
+            expected
+        );
+    }
+    let new_people = &matched.people[scenario.people.len()..];
+    if new_people.is_empty() || new_people.len() > num_new_jobs {
+        bail!(
+            "{} new people were added for {} new jobs",
+            new_people.len(),
+            num_new_jobs
+        );
+    }
+    for person in new_people {
+        if !person
+            .trips
+            .iter()
+            .any(|t| t.destination == TripEndpoint::Building(office))
+        {
+            bail!("Somebody new doesn't work at {}", office);
+        }
+    }
+    Ok(())
+}
@@ -15,6 +15,25 @@ pub struct TrafficSignal {
     /// order of ascending `start_time_seconds`, the first plan must begin at `0` (midnight), and
     /// the last plan must not start after 24 hours.
     pub plans: Vec<Plan>,
+    /// If present, the signal gives priority to approaching buses, trains, and emergency vehicles.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<SignalPriority>,
+}
+
+/// Transit signal priority and preemption settings.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SignalPriority {
+    /// Vehicles are detected this many meters before the stop line.
+    pub detection_distance_meters: usize,
+    /// A green may be held up to this many seconds longer, so a detected vehicle can make it
+    /// through.
+    pub max_green_extension_seconds: usize,
+    /// The current stage may end up to this many seconds early, when a detected vehicle is
+    /// waiting for the next stage.
+    pub max_early_green_seconds: usize,
+    /// Rail and emergency vehicles immediately switch the signal to a stage that lets them
+    /// through, instead of getting limited priority.
+    pub preemption: bool,
 }
 
 /// A plan describes how a traffic signal is configured during some period of time. Multiple plans